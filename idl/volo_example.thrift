@@ -10,8 +10,32 @@ struct RaftReply {
     2: required string error
 }
 
+// 分块数据不经过 raft 日志，节点间直接传输
+struct ChunkRequest {
+    1: required string name,
+    2: required binary data,
+}
+
+struct ChunkReply {
+    1: required binary data,
+    2: required string error
+}
+
+struct ShardRequest {
+    1: required string name,
+    2: required bool headers_only,
+}
+
+struct ShardReply {
+    1: required list<binary> shards,
+    2: required string error
+}
+
 service RaftService {
     RaftReply Vote (1: RaftRequest req),
     RaftReply Append (1: RaftRequest req),
     RaftReply Snapshot (1: RaftRequest req),
+    ChunkReply PutChunk (1: ChunkRequest req),
+    ChunkReply GetChunk (1: ChunkRequest req),
+    ShardReply GetShards (1: ShardRequest req),
 }
@@ -1,37 +1,46 @@
 use crate::err::AppError;
 use crate::err::AppError::BadRequest;
-use crate::fs::DecompressStream;
+use crate::fs::{DecompressStream, Metadata, ObjectChecksum};
 use crate::model::{
-    Bucket, BucketWrapper, CompleteMultipartUploadResult, Content, HeadNotFoundResp,
-    InitiateMultipartUploadResult, ListBucketResp, ListBucketResult, Owner,
+    Bucket, BucketWrapper, Checksum, CommonPrefix, CompleteMultipartUpload, CompleteMultipartUploadResult,
+    Content, GetObjectAttributesResponse, HeadNotFoundResp, InitiateMultipartUploadResult,
+    ListBucketResp, ListBucketResult, ListPartsResult, ObjectPartAttributes, ObjectParts, Owner,
+    Part, SelectObjectContentRequest, ServerSideEncryptionConfiguration, WebsiteConfiguration,
 };
+use crate::quota::UsageChange;
 use crate::raft::app::App;
+use crate::raft::network::chunk;
+use crate::raft::store;
 use crate::raft::store::Request::{
-    CombineChunk, CopyFile, CreateBucket, DeleteBucket, DeleteFile, InitChunk, UploadChunk,
-    UploadFile,
+    AbortUpload, CombineChunk, CommitPart, CopyFile, CreateBucket, DeleteBucket, DeleteFile,
+    InitChunk,
 };
+use crate::layout::metadata_path;
+use crate::object_index::ListQuery;
+use crate::select::{SelectEngine, SelectRequest, SelectStream};
+use crate::util::checksum::ChecksumAlgorithm;
 use crate::util::cry;
 use crate::util::date::date_format_to_second;
-use crate::{fs, HandlerResponse};
+use crate::{bucket, checksum, fs, sse, upload, website, HandlerResponse};
 use anyhow::{anyhow, Context};
 use futures::future::ok;
 use futures::stream::once;
 use futures::StreamExt;
 use log::info;
-use ntex::util::{Bytes, BytesMut};
+use ntex::util::Bytes;
 use ntex::web;
 use ntex::web::types::Query;
 use ntex::web::HttpResponse;
 use quick_xml::se::to_string;
 use serde::Deserialize;
 use std::fs::read_dir;
-use std::path::PathBuf;
-use tokio::sync::OnceCell;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
-use zstd::zstd_safe::WriteBuf;
 
-pub(crate) static DATA_DIR: OnceCell<String> = OnceCell::const_new();
-pub(crate) const BASIC_PATH_SUFFIX: &str = "buckets";
+// ListObjects 单次返回的最大数量
+const MAX_KEYS: u32 = 1000;
+// GetObjectAttributes 选择返回属性的请求头
+const OBJECT_ATTRIBUTES_HEADER: &str = "x-amz-object-attributes";
 
 pub fn rest(cfg: &mut web::ServiceConfig) {
     cfg
@@ -90,10 +99,190 @@ fn get_path_param(req: &web::HttpRequest, name: &str) -> Result<String, AppError
     Ok(param)
 }
 
+// 判断请求是否带有子资源参数，如 ?encryption
+fn has_sub_resource(req: &web::HttpRequest, name: &str) -> bool {
+    url::form_urlencoded::parse(req.query_string().as_bytes()).any(|(key, _)| key == name)
+}
+
+// 读取完整请求体
+async fn read_payload(mut body: web::types::Payload) -> Result<Vec<u8>, AppError> {
+    let mut bytes = Vec::new();
+    while let Some(item) = body.next().await {
+        let item = item.map_err(|err| anyhow!(err.to_string()))?;
+        bytes.extend_from_slice(&item);
+    }
+    Ok(bytes)
+}
+
+// 读取查询参数
+fn query_param(req: &web::HttpRequest, name: &str) -> Option<String> {
+    url::form_urlencoded::parse(req.query_string().as_bytes())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+// 分片分页参数 (max-parts, part-number-marker)，GetObjectAttributes 通过 x-amz- 前缀的请求头传递
+fn parts_pagination(req: &web::HttpRequest) -> (u32, u32) {
+    let param = |name: &str| {
+        query_param(req, name)
+            .or_else(|| {
+                req.headers()
+                    .get(format!("x-amz-{}", name))
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+            })
+            .and_then(|value| value.parse::<u32>().ok())
+    };
+    (
+        param("max-parts").unwrap_or(1000).min(1000),
+        param("part-number-marker").unwrap_or(0),
+    )
+}
+
+// 对象路径 {buckets}/{bucket}/{key} 对应的元数据，对象不存在时为 None
+fn object_metadata(state: &App, file_path: &Path) -> anyhow::Result<Option<Metadata>> {
+    let path = format!("{}.meta", file_path.to_string_lossy());
+    match state.layout.object_of(&path) {
+        Some((bucket_name, key)) => state.objects.load(&bucket_name, &key),
+        None => Ok(None),
+    }
+}
+
+// 解析 x-amz-copy-source，返回源桶名和对象名
+pub fn parse_copy_source(copy_source: &str) -> Option<(String, String)> {
+    let copy_source = copy_source.split('?').next().unwrap_or_default();
+    let copy_source = percent_decode(copy_source)?;
+    let (bucket_name, object_key) = copy_source.trim_start_matches('/').split_once('/')?;
+    if bucket_name.is_empty() || object_key.is_empty() {
+        return None;
+    }
+    Some((bucket_name.to_string(), object_key.to_string()))
+}
+
+// 复制加密对象时能否沿用来源的分块和数据密钥，只需用目标的密钥重新包装数据密钥
+// 密钥为 None 表示 SSE-S3 的主密钥，Some 为 SSE-C 的客户密钥；加密方式或密钥不同时
+// 需用新的数据密钥重新加密，否则目标的分块仍可用来源的密钥解密
+pub fn reuses_source_chunks(source_key: Option<&[u8; 32]>, dest_key: Option<&[u8; 32]>) -> bool {
+    source_key == dest_key
+}
+
+// URL百分号解码
+pub(crate) fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = input.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+// GetObjectAttributes 请求返回的属性
+#[derive(Debug, Default, PartialEq)]
+pub struct ObjectAttributes {
+    pub etag: bool,
+    pub checksum: bool,
+    pub object_parts: bool,
+    pub storage_class: bool,
+    pub object_size: bool,
+}
+
+// 解析 x-amz-object-attributes，属性名以逗号分隔，为空或包含未知属性时返回 None
+pub fn parse_object_attributes(value: &str) -> Option<ObjectAttributes> {
+    let mut attributes = ObjectAttributes::default();
+    for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        match name {
+            "ETag" => attributes.etag = true,
+            "Checksum" => attributes.checksum = true,
+            "ObjectParts" => attributes.object_parts = true,
+            "StorageClass" => attributes.storage_class = true,
+            "ObjectSize" => attributes.object_size = true,
+            _ => return None,
+        }
+    }
+    if attributes == ObjectAttributes::default() {
+        return None;
+    }
+    Some(attributes)
+}
+
+pub(crate) fn no_such_bucket() -> AppError {
+    AppError::s3(404, "NoSuchBucket", "The specified bucket does not exist")
+}
+
+fn no_such_upload() -> AppError {
+    AppError::s3(404, "NoSuchUpload", "The specified upload does not exist")
+}
+
+fn malformed_xml() -> AppError {
+    AppError::s3(
+        400,
+        "MalformedXML",
+        "The XML you provided was not well-formed or did not validate against our published schema.",
+    )
+}
+
+fn no_such_key() -> AppError {
+    AppError::s3(404, "NoSuchKey", "The specified key does not exist.")
+}
+
+fn quota_exceeded() -> AppError {
+    AppError::s3(403, "QuotaExceeded", "The bucket quota has been exceeded.")
+}
+
+// 提交前检查写入后是否超出桶配额，覆盖已有对象时只计算大小的差值
+async fn check_quota(
+    state: &App,
+    bucket_name: &str,
+    object_key: &str,
+    size: u64,
+) -> Result<(), AppError> {
+    let previous = state
+        .objects
+        .load(bucket_name, object_key)
+        .ok()
+        .flatten()
+        .map(|metadata| metadata.size);
+    check_usage(state, bucket_name, UsageChange::put(previous, size)).await
+}
+
+// 检查用量按 change 变化后是否仍在桶配额内
+async fn check_usage(state: &App, bucket_name: &str, change: UsageChange) -> Result<(), AppError> {
+    let kvs = state.key_values.read().await;
+    if !bucket::quota_allows(&kvs, bucket_name, change) {
+        return Err(quota_exceeded());
+    }
+    Ok(())
+}
+
+// 提交对象或分片写入，并发写入时状态机应用阶段仍可能因超出配额拒绝，写入失败时返回错误
+async fn write_object(state: &App, request: store::Request) -> Result<(), AppError> {
+    let res = state
+        .raft
+        .client_write(request)
+        .await
+        .map_err(|err| anyhow!(err.to_string()))?;
+    match res.data.value.as_deref() {
+        Some(bucket::QUOTA_EXCEEDED) => Err(quota_exceeded()),
+        Some(value) if value.starts_with(bucket::WRITE_FAILED) => Err(AppError::s3(
+            500,
+            "InternalError",
+            &value[bucket::WRITE_FAILED.len()..],
+        )),
+        _ => Ok(()),
+    }
+}
+
 // 获取所有桶的列表
-pub async fn list_bucket() -> HandlerResponse {
-    let dir_path = PathBuf::from(DATA_DIR.get().unwrap()).join(BASIC_PATH_SUFFIX);
-    let dir_path = dir_path.as_path();
+pub async fn list_bucket(state: web::types::State<App>) -> HandlerResponse {
+    let dir_path = state.layout.buckets.as_path();
     if dir_path.is_dir() {
         let mut res = Vec::new();
         if let Ok(dir) = read_dir(dir_path) {
@@ -140,57 +329,75 @@ pub async fn list_bucket() -> HandlerResponse {
 #[derive(Deserialize)]
 pub struct GetBucketQueryParams {
     pub prefix: Option<String>,
+    pub delimiter: Option<String>,
+    pub marker: Option<String>,
+    #[serde(rename = "max-keys")]
+    pub max_keys: Option<u32>,
 }
 // 获取桶的数据
 pub async fn get_bucket(
     req: web::HttpRequest,
     Query(query): Query<GetBucketQueryParams>,
+    state: web::types::State<App>,
 ) -> HandlerResponse {
     let bucket_name: String = get_path_param(&req, "bucket")?;
+    if has_sub_resource(&req, "encryption") {
+        return get_bucket_encryption(&bucket_name, &state).await;
+    }
+    if has_sub_resource(&req, "website") {
+        return get_bucket_website(&bucket_name, &state).await;
+    }
+    if !state.layout.bucket(&bucket_name).is_dir() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
 
-    let bucket_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(&bucket_name);
-    if let Ok(files) = std::fs::read_dir(&bucket_path) {
-        let mut contents = Vec::new();
-        for file in files.flatten() {
-            if !file.file_type().unwrap().is_dir()
-                && file.file_name().to_string_lossy().ends_with(".meta")
-            {
-                let meta_file_path = bucket_path.clone();
-                let meta_file_path = meta_file_path.join(&file.file_name());
-                let meta_file_path = meta_file_path.to_str().unwrap();
-                info!("{}", &meta_file_path);
-                let metadata = fs::load_metadata(meta_file_path)?;
-                contents.push(Content {
-                    size: metadata.size as i64,
-                    key: metadata.name,
-                    last_modified: metadata.time,
-                });
-            }
-        }
+    let max_keys = query.max_keys.unwrap_or(MAX_KEYS).min(MAX_KEYS);
+    let list_query = ListQuery {
+        prefix: query.prefix.clone().unwrap_or_default(),
+        delimiter: query.delimiter.clone(),
+        marker: query.marker.clone(),
+        max_keys: max_keys as usize,
+    };
+    let listing = state.objects.list(&bucket_name, &list_query)?;
+    let mut contents = Vec::new();
+    for (key, value) in listing.objects {
+        let metadata = state.objects.decode(&value)?;
+        contents.push(Content {
+            size: metadata.size as i64,
+            key,
+            last_modified: metadata.time,
+        });
+    }
+    let common_prefixes = listing
+        .common_prefixes
+        .into_iter()
+        .map(|prefix| CommonPrefix { prefix })
+        .collect();
 
-        let result = ListBucketResult {
-            name: bucket_name,
-            prefix: query.prefix.unwrap_or_else(|| "".to_string()),
-            is_truncated: false,
-            max_keys: 100000,
-            contents,
-        };
+    let result = ListBucketResult {
+        name: bucket_name,
+        prefix: list_query.prefix,
+        marker: list_query.marker.unwrap_or_default(),
+        // 未指定分隔符时 S3 不返回 NextMarker，客户端以最后一个键作为下一页的 marker
+        next_marker: listing.next_marker.filter(|_| list_query.delimiter.is_some()),
+        delimiter: list_query.delimiter,
+        max_keys,
+        is_truncated: listing.is_truncated,
+        contents,
+        common_prefixes,
+    };
 
-        let xml = to_string(&result).context("序列化失败")?;
-        Ok(HttpResponse::Ok().content_type("application/xml").body(xml))
-    } else {
-        Ok(HttpResponse::NotFound().finish())
-    }
+    let xml = to_string(&result).context("序列化失败")?;
+    Ok(HttpResponse::Ok().content_type("application/xml").body(xml))
 }
 
 // 查询桶是否存在
-pub async fn head_bucket(req: web::HttpRequest) -> HandlerResponse {
+pub async fn head_bucket(
+    req: web::HttpRequest,
+    state: web::types::State<App>,
+) -> HandlerResponse {
     let bucket_name: String = get_path_param(&req, "bucket")?;
-    let file_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(bucket_name);
+    let file_path = state.layout.bucket(&bucket_name);
     if file_path.as_path().is_dir() {
         Ok(HttpResponse::Ok().content_type("application/xml").finish())
     } else {
@@ -203,17 +410,19 @@ pub async fn head_bucket(req: web::HttpRequest) -> HandlerResponse {
 // 创建桶
 pub async fn create_bucket(
     req: web::HttpRequest,
+    body: web::types::Payload,
     state: web::types::State<App>,
 ) -> HandlerResponse {
     let bucket_name: String = get_path_param(&req, "bucket")?;
-    let file_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(bucket_name);
+    if has_sub_resource(&req, "encryption") {
+        return put_bucket_encryption(&bucket_name, body, &state).await;
+    }
+    if has_sub_resource(&req, "website") {
+        return put_bucket_website(&bucket_name, body, &state).await;
+    }
     state
         .raft
-        .client_write(CreateBucket {
-            bucket_name: file_path.to_string_lossy().to_string(),
-        })
+        .client_write(CreateBucket { bucket_name })
         .await
         .map_err(|err| anyhow!(err.to_string()))?;
     Ok(HttpResponse::Ok().finish())
@@ -225,21 +434,138 @@ pub async fn delete_bucket(
     state: web::types::State<App>,
 ) -> HandlerResponse {
     let bucket_name: String = get_path_param(&req, "bucket")?;
-    let file_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(bucket_name);
-
+    if has_sub_resource(&req, "encryption") {
+        return delete_bucket_encryption(&bucket_name, &state).await;
+    }
+    if has_sub_resource(&req, "website") {
+        return delete_bucket_website(&bucket_name, &state).await;
+    }
     state
         .raft
-        .client_write(DeleteBucket {
-            bucket_name: file_path.to_string_lossy().to_string(),
-        })
+        .client_write(DeleteBucket { bucket_name })
         .await
         .map_err(|err| anyhow!(err.to_string()))?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
+// 设置桶默认加密配置
+async fn put_bucket_encryption(
+    bucket_name: &str,
+    body: web::types::Payload,
+    state: &App,
+) -> HandlerResponse {
+    if !state.layout.bucket(bucket_name).is_dir() {
+        return Err(no_such_bucket());
+    }
+    let bytes = read_payload(body).await?;
+    let body = std::str::from_utf8(&bytes).map_err(|err| anyhow!(err))?;
+    let config: ServerSideEncryptionConfiguration = quick_xml::de::from_str(body)
+        .map_err(|_| AppError::s3(400, "MalformedXML", "The XML you provided was not well-formed"))?;
+    sse::validate_config(&config)?;
+    bucket::put_config(state, bucket_name, bucket::ENCRYPTION_CONFIG, Some(&config)).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+// 获取桶默认加密配置
+async fn get_bucket_encryption(bucket_name: &str, state: &App) -> HandlerResponse {
+    if !state.layout.bucket(bucket_name).is_dir() {
+        return Err(no_such_bucket());
+    }
+    let config: Option<ServerSideEncryptionConfiguration> =
+        bucket::get_config(state, bucket_name, bucket::ENCRYPTION_CONFIG).await?;
+    match config {
+        Some(config) => {
+            let xml = to_string(&config).context("序列化失败")?;
+            Ok(HttpResponse::Ok().content_type("application/xml").body(xml))
+        }
+        None => Err(AppError::s3(
+            404,
+            "ServerSideEncryptionConfigurationNotFoundError",
+            "The server side encryption configuration was not found",
+        )),
+    }
+}
+
+// 删除桶默认加密配置
+async fn delete_bucket_encryption(bucket_name: &str, state: &App) -> HandlerResponse {
+    if !state.layout.bucket(bucket_name).is_dir() {
+        return Err(no_such_bucket());
+    }
+    bucket::put_config::<ServerSideEncryptionConfiguration>(
+        state,
+        bucket_name,
+        bucket::ENCRYPTION_CONFIG,
+        None,
+    )
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// 设置桶的静态网站配置
+async fn put_bucket_website(
+    bucket_name: &str,
+    body: web::types::Payload,
+    state: &App,
+) -> HandlerResponse {
+    if !state.layout.bucket(bucket_name).is_dir() {
+        return Err(no_such_bucket());
+    }
+    let bytes = read_payload(body).await?;
+    let body = std::str::from_utf8(&bytes).map_err(|_| malformed_xml())?;
+    let config: WebsiteConfiguration =
+        quick_xml::de::from_str(body).map_err(|_| malformed_xml())?;
+    website::routing::validate_config(&config)?;
+    bucket::put_config(state, bucket_name, bucket::WEBSITE_CONFIG, Some(&config)).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+// 获取桶的静态网站配置
+async fn get_bucket_website(bucket_name: &str, state: &App) -> HandlerResponse {
+    if !state.layout.bucket(bucket_name).is_dir() {
+        return Err(no_such_bucket());
+    }
+    let config: Option<WebsiteConfiguration> =
+        bucket::get_config(state, bucket_name, bucket::WEBSITE_CONFIG).await?;
+    match config {
+        Some(config) => {
+            let xml = to_string(&config).context("序列化失败")?;
+            Ok(HttpResponse::Ok().content_type("application/xml").body(xml))
+        }
+        None => Err(AppError::s3(
+            404,
+            "NoSuchWebsiteConfiguration",
+            "The specified bucket does not have a website configuration",
+        )),
+    }
+}
+
+// 删除桶的静态网站配置
+async fn delete_bucket_website(bucket_name: &str, state: &App) -> HandlerResponse {
+    if !state.layout.bucket(bucket_name).is_dir() {
+        return Err(no_such_bucket());
+    }
+    bucket::put_config::<WebsiteConfiguration>(state, bucket_name, bucket::WEBSITE_CONFIG, None)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// 读取请求中对象的网站重定向地址
+fn website_redirect_location(req: &web::HttpRequest) -> Result<Option<String>, AppError> {
+    let Some(value) = req.headers().get(website::REDIRECT_LOCATION_HEADER) else {
+        return Ok(None);
+    };
+    let location = value.to_str().map_err(|_| {
+        AppError::s3(
+            400,
+            "InvalidArgument",
+            "The website redirect location is not valid.",
+        )
+    })?;
+    website::routing::validate_redirect_location(location)?;
+    Ok(Some(location.to_string()))
+}
+
 #[derive(Deserialize)]
 pub struct InitChunkOrCombineQuery {
     #[serde(rename = "uploadId")]
@@ -249,66 +575,19 @@ pub struct InitChunkOrCombineQuery {
 // 初始化分片上传 & 完成分片上传
 pub async fn init_chunk_or_combine_chunk(
     req: web::HttpRequest,
-    mut body: web::types::Payload,
+    body: web::types::Payload,
     Query(query): Query<InitChunkOrCombineQuery>,
     state: web::types::State<App>,
 ) -> HandlerResponse {
     let bucket_name: String = get_path_param(&req, "bucket")?;
     let object_name: String = get_path_param(&req, "object")?;
-    if let Some(upload_id) = query.upload_id {
-        let mut bytes = BytesMut::new();
-        while let Some(item) = body.next().await {
-            let item = item.context("")?;
-            bytes.extend_from_slice(&item);
-        }
-        let body = std::str::from_utf8(bytes.as_slice()).map_err(|err| anyhow!(err))?;
-        state
-            .raft
-            .client_write(CombineChunk {
-                bucket_name: bucket_name.clone(),
-                object_key: object_name.clone(),
-                upload_id: upload_id.clone(),
-                cmu: body.to_string(),
-            })
-            .await
-            .map_err(|err| anyhow!(err.to_string()))?;
-
-        let e_tag = cry::encrypt_by_md5(&format!("{}/{}", &bucket_name, &object_name));
-        let res = CompleteMultipartUploadResult {
-            bucket_name: bucket_name.to_string(),
-            object_key: object_name.to_string(),
-            etag: e_tag,
-        };
-        let xml = to_string(&res).map_err(|err| anyhow!(err))?;
-        Ok(HttpResponse::Ok().content_type("application/xml").body(xml))
-    } else {
-        let guid = Uuid::new_v4();
-        let upload_id = guid.to_string();
-        info!("gen upload_id: {}", &upload_id);
-        state
-            .raft
-            .client_write(InitChunk {
-                bucket_name: bucket_name.clone(),
-                object_key: object_name.clone(),
-                upload_id: upload_id.clone(),
-            })
-            .await
-            .map_err(|err| anyhow!(err.to_string()))?;
-        info!("init chunk upload done: {}", &upload_id);
-        let resp = InitiateMultipartUploadResult {
-            bucket: bucket_name,
-            object_key: object_name,
-            upload_id,
-        };
-        let xml = to_string(&resp).map_err(|err| anyhow!(err))?;
-        Ok(HttpResponse::Ok().content_type("application/xml").body(xml))
-    }
+    do_init_chunk_or_combine_chunk(&req, body, query, &state, bucket_name, object_name).await
 }
 
 // 对长路径的初始化分片上传或完成分片上传
 pub async fn init_chunk_or_combine_chunk_longpath(
     req: web::HttpRequest,
-    mut body: web::types::Payload,
+    body: web::types::Payload,
     Query(query): Query<InitChunkOrCombineQuery>,
     state: web::types::State<App>,
 ) -> HandlerResponse {
@@ -319,65 +598,207 @@ pub async fn init_chunk_or_combine_chunk_longpath(
         .join(&object_suffix)
         .to_string_lossy()
         .to_string();
+    do_init_chunk_or_combine_chunk(&req, body, query, &state, bucket_name, object_key).await
+}
+
+// 校验完成分片上传请求中的分片，返回对象大小和组合校验和
+fn verify_complete_parts(
+    state: &App,
+    bucket_name: &str,
+    object_key: &str,
+    upload_id: &str,
+    body: &str,
+) -> Result<(u64, Option<(ChecksumAlgorithm, String)>), AppError> {
+    let tmp_metadata_path = state.layout.upload_metadata(bucket_name, object_key, upload_id);
+    let keyring = state.objects.keyring()?;
+    let tmp_metadata =
+        fs::load_metadata(tmp_metadata_path, &keyring).map_err(|_| no_such_upload())?;
+    let mut cmu: CompleteMultipartUpload =
+        quick_xml::de::from_str(body).map_err(|_| malformed_xml())?;
+    cmu.part_etags.sort_by_key(|p| p.part_number);
+    let invalid_part = || {
+        AppError::s3(
+            400,
+            "InvalidPart",
+            "One or more of the specified parts could not be found.",
+        )
+    };
+    let parts = store::load_parts(&state.layout, upload_id, &cmu.part_etags)
+        .map_err(|_| invalid_part())?;
+    let size = parts.iter().map(|part| part.size).sum();
+    let algorithm = match tmp_metadata
+        .checksum
+        .and_then(|checksum| ChecksumAlgorithm::parse(&checksum.algorithm))
+    {
+        Some(algorithm) => algorithm,
+        None => return Ok((size, None)),
+    };
+    for (part_etag, part) in cmu.part_etags.iter().zip(&parts) {
+        let part_checksum = part.checksum.as_ref().ok_or_else(|| {
+            AppError::s3(
+                400,
+                "InvalidRequest",
+                format!("Part {} was uploaded without a checksum", part.part_number),
+            )
+        })?;
+        if checksum::part_checksum(part_etag, algorithm).is_some_and(|value| value != part_checksum)
+        {
+            return Err(checksum::bad_digest(algorithm));
+        }
+    }
+    let composite = store::composite_checksum(algorithm.name(), &parts)?;
+    Ok((size, Some((algorithm, composite))))
+}
+
+// 使用 SQL 查询对象内容 (SelectObjectContent)，结果以 event stream 格式流式返回
+async fn select_object_content(
+    req: &web::HttpRequest,
+    body: web::types::Payload,
+    state: &App,
+    bucket_name: String,
+    object_key: String,
+) -> HandlerResponse {
+    if query_param(req, "select-type").is_some_and(|select_type| select_type != "2") {
+        return Err(AppError::s3(
+            400,
+            "InvalidArgument",
+            "The select-type parameter must be 2.",
+        ));
+    }
+    let bytes = read_payload(body).await?;
+    let body = std::str::from_utf8(&bytes).map_err(|_| malformed_xml())?;
+    let request: SelectObjectContentRequest =
+        quick_xml::de::from_str(body).map_err(|_| malformed_xml())?;
+    let request = SelectRequest::from_model(&request)?;
+    let file_path = state.layout.object(&bucket_name, &object_key);
+    let metainfo = object_metadata(state, &file_path)
+        .ok()
+        .flatten()
+        .ok_or_else(no_such_key)?;
+    let customer_key = sse::CustomerKey::from_request(req, false)?;
+    let data_key = sse::data_key(
+        &state.master_key,
+        metainfo.encryption.as_ref(),
+        customer_key.as_ref(),
+    )?;
+    let engine = SelectEngine::new(request)?;
+    let source = DecompressStream::object(
+        state.chunk_gc.store,
+        state.chunk_gc.cache.clone(),
+        metainfo,
+        data_key,
+        chunk::peers(state),
+        state.read_ahead,
+    );
+    let stream = SelectStream::new(source, engine);
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .streaming(stream))
+}
+
+// 初始化分片上传 & 完成分片上传逻辑
+async fn do_init_chunk_or_combine_chunk(
+    req: &web::HttpRequest,
+    body: web::types::Payload,
+    query: InitChunkOrCombineQuery,
+    state: &App,
+    bucket_name: String,
+    object_key: String,
+) -> HandlerResponse {
+    if has_sub_resource(req, "select") {
+        return select_object_content(req, body, state, bucket_name, object_key).await;
+    }
     if let Some(upload_id) = query.upload_id {
         info!("uploadId: {}", upload_id);
-        let mut bytes = BytesMut::new();
-        while let Some(item) = body.next().await {
-            let item = item.context("")?;
-            bytes.extend_from_slice(&item);
-        }
-        let body = std::str::from_utf8(bytes.as_slice()).map_err(|err| anyhow!(err))?;
-        state
-            .raft
-            .client_write(CombineChunk {
+        let bytes = read_payload(body).await?;
+        let body = std::str::from_utf8(&bytes).map_err(|err| anyhow!(err))?;
+        let (size, checksum) =
+            verify_complete_parts(state, &bucket_name, &object_key, &upload_id, body)?;
+        check_quota(state, &bucket_name, &object_key, size).await?;
+        write_object(
+            state,
+            CombineChunk {
                 bucket_name: bucket_name.clone(),
                 object_key: object_key.clone(),
                 upload_id: upload_id.clone(),
                 cmu: body.to_string(),
-            })
-            .await
-            .map_err(|err| anyhow!(err.to_string()))?;
-        let e_tag = cry::encrypt_by_md5(&format!("{}/{}", &bucket_name, &object_key));
+            },
+        )
+        .await?;
+        // 返回提交后对象的 ETag，与 HEAD/GET 返回的一致
+        let e_tag = state
+            .objects
+            .load(&bucket_name, &object_key)?
+            .ok_or_else(no_such_key)?
+            .etag();
+        let [checksum_crc32, checksum_crc32c, checksum_sha1, checksum_sha256] = checksum::fields(
+            checksum.as_ref().map(|(algorithm, _)| *algorithm),
+            checksum.map(|(_, value)| value),
+        );
         let res = CompleteMultipartUploadResult {
             bucket_name: bucket_name.to_string(),
             object_key: object_key.to_string(),
             etag: e_tag,
+            checksum_crc32,
+            checksum_crc32c,
+            checksum_sha1,
+            checksum_sha256,
         };
         let xml = to_string(&res).map_err(|err| anyhow!(err))?;
         Ok(HttpResponse::Ok().content_type("application/xml").body(xml))
     } else {
         let guid = Uuid::new_v4();
         let upload_id = guid.to_string();
+        info!("gen upload_id: {}", &upload_id);
+        let sse = sse::from_request(req, state, &bucket_name).await?;
+        let checksum_algorithm = checksum::requested_algorithm(req)?;
+        let website_redirect_location = website_redirect_location(req)?;
+        let mut response = HttpResponse::Ok();
+        if let Some(algorithm) = checksum_algorithm {
+            response.header(checksum::CHECKSUM_ALGORITHM_HEADER, algorithm.name());
+        }
+        if let Some(sse) = &sse {
+            for (name, value) in
+                sse::response_headers(&sse.encryption, sse.customer_key_md5.as_deref())
+            {
+                response.header(name, value);
+            }
+        }
+        let encryption = sse.map(|sse| sse.encryption);
         state
             .raft
             .client_write(InitChunk {
                 bucket_name: bucket_name.clone(),
                 object_key: object_key.clone(),
                 upload_id: upload_id.clone(),
+                encryption,
+                checksum_algorithm: checksum_algorithm
+                    .map(|algorithm| algorithm.name().to_string()),
+                website_redirect_location,
             })
             .await
             .map_err(|err| anyhow!(err.to_string()))?;
-
+        info!("init chunk upload done: {}", &upload_id);
         let resp = InitiateMultipartUploadResult {
             bucket: bucket_name,
             object_key,
             upload_id,
         };
         let xml = to_string(&resp).map_err(|err| anyhow!(err))?;
-        Ok(HttpResponse::Ok().content_type("application/xml").body(xml))
+        Ok(response.content_type("application/xml").body(xml))
     }
 }
 
 // 查询对象信息
-pub async fn head_object(req: web::HttpRequest) -> HandlerResponse {
+pub async fn head_object(
+    req: web::HttpRequest,
+    state: web::types::State<App>,
+) -> HandlerResponse {
     let bucket_name: String = get_path_param(&req, "bucket")?;
     let object_name: String = get_path_param(&req, "object")?;
-    let file_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(bucket_name)
-        .join(object_name);
+    let file_path = state.layout.object(&bucket_name, &object_name);
 
-    do_head_object(file_path).await
+    do_head_object(&req, &state, file_path).await
 }
 
 #[derive(Deserialize)]
@@ -391,87 +812,264 @@ pub struct UploadFileOrChunkQuery {
 // 上传文件 & 上传文件分片
 pub async fn upload_file_or_upload_chunk(
     req: web::HttpRequest,
-    mut body: web::types::Payload,
+    body: web::types::Payload,
     Query(query): Query<UploadFileOrChunkQuery>,
     state: web::types::State<App>,
 ) -> HandlerResponse {
     let bucket_name: String = get_path_param(&req, "bucket")?;
     let object_name: String = get_path_param(&req, "object")?;
-    let file_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(&bucket_name)
-        .join(&object_name);
+    do_upload_file_or_upload_chunk(&req, body, query, &state, bucket_name, object_name).await
+}
+
+// 上传文件 & 上传文件分片逻辑
+async fn do_upload_file_or_upload_chunk(
+    req: &web::HttpRequest,
+    body: web::types::Payload,
+    query: UploadFileOrChunkQuery,
+    state: &App,
+    bucket_name: String,
+    object_key: String,
+) -> HandlerResponse {
     match (query.upload_id, query.part_number) {
         (Some(upload_id), Some(part_number)) => {
-            let mut bytes = Vec::new();
-            bytes.reserve_exact(8 << 20);
-            while let Some(item) = body.next().await {
-                let item = item.map_err(|err| anyhow!(err.to_string()))?;
-                bytes.extend_from_slice(&item);
+            let tmp_metadata_path = state
+                .layout
+                .upload_metadata(&bucket_name, &object_key, &upload_id);
+            let keyring = state.objects.keyring()?;
+            let tmp_metadata =
+                fs::load_metadata(&tmp_metadata_path, &keyring).map_err(|_| no_such_upload())?;
+            let upload_algorithm = tmp_metadata
+                .checksum
+                .as_ref()
+                .and_then(|checksum| ChecksumAlgorithm::parse(&checksum.algorithm));
+            let checksum_request = checksum::from_request(req, upload_algorithm)?;
+            let customer_key = sse::CustomerKey::from_request(req, false)?;
+            let data_key = sse::data_key(
+                &state.master_key,
+                tmp_metadata.encryption.as_ref(),
+                customer_key.as_ref(),
+            )?;
+            // 未完成的分片不计入用量，但桶的剩余空间放不下声明的长度时拒绝上传
+            if let Some(size) = checksum::declared_content_length(req) {
+                check_usage(state, &bucket_name, UsageChange::part(size)).await?;
             }
-            let hash = fs::sum_sha256(&bytes).await;
-            state
-                .raft
-                .client_write(UploadChunk {
-                    part_number,
+            let policy = bucket::write_policy(state, &bucket_name, &object_key)
+                .await?
+                .without_inline();
+            let staged = upload::stage_payload(
+                req,
+                body,
+                state,
+                &policy,
+                data_key.as_deref(),
+                checksum_request.as_ref(),
+            )
+            .await?;
+            let mut response = HttpResponse::Ok();
+            if let Some(request) = &checksum_request {
+                if let Some(value) = &staged.checksum {
+                    response.header(request.algorithm.header_name(), value.as_str());
+                }
+            }
+            // 加密分片的 ETag 由数据密钥派生，不暴露明文的 sha256
+            let etag = match (&tmp_metadata.encryption, &data_key) {
+                (Some(encryption), Some(data_key)) => {
+                    let key_md5 = customer_key.as_ref().map(|key| key.key_md5());
+                    for (name, value) in sse::response_headers(encryption, key_md5) {
+                        response.header(name, value);
+                    }
+                    fs::sealed_chunk_name(data_key, &staged.sha256)?
+                }
+                _ => staged.sha256.clone(),
+            };
+            // 分片信息写入失败时返回错误，不返回 ETag
+            write_object(
+                state,
+                CommitPart {
                     upload_id,
-                    hash: hash.clone(),
-                    body: bytes.to_vec(),
-                })
-                .await
-                .map_err(|err| anyhow!(err.to_string()))?;
-            Ok(HttpResponse::Ok().header("ETag", &hash).finish())
+                    part_number,
+                    size: staged.size,
+                    etag: etag.clone(),
+                    checksum: staged.checksum,
+                    chunks: staged.chunks,
+                    chunk_sizes: staged.chunk_sizes,
+                    erasure: staged.erasure,
+                },
+            )
+            .await?;
+            Ok(response.header("ETag", &etag).finish())
         }
         _ => {
             if let Some(copy_source) = req.headers().get("x-amz-copy-source") {
-                state
-                    .raft
-                    .client_write(CopyFile {
-                        copy_source: copy_source.to_str().unwrap().to_string(),
-                        dest_bucket: bucket_name,
-                        dest_object: object_name,
-                    })
-                    .await
-                    .map_err(|err| anyhow!(err.to_string()))?;
-                Ok(HttpResponse::Ok().finish())
+                let copy_source = copy_source.to_str().map_err(|_| BadRequest)?.to_string();
+                do_copy_object(req, state, copy_source, bucket_name, object_key).await
             } else {
-                let mut bytes = Vec::new();
-                bytes.reserve_exact(8 << 20);
-                while let Some(item) = body.next().await {
-                    let item = item.map_err(|err| anyhow!(err.to_string()))?;
-                    bytes.extend_from_slice(&item);
+                let sse = sse::from_request(req, state, &bucket_name).await?;
+                let checksum_request = checksum::from_request(req, None)?;
+                let website_redirect_location = website_redirect_location(req)?;
+                let policy = bucket::write_policy(state, &bucket_name, &object_key).await?;
+                // 按声明的长度预先检查配额，写入时状态机按实际长度再次检查
+                if let Some(size) = checksum::declared_content_length(req) {
+                    check_quota(state, &bucket_name, &object_key, size).await?;
                 }
+                let mut staged = upload::stage_payload(
+                    req,
+                    body,
+                    state,
+                    &policy,
+                    sse.as_ref().map(|sse| sse.data_key.as_slice()),
+                    checksum_request.as_ref(),
+                )
+                .await?;
+                let mut response = HttpResponse::Ok();
+                let checksum = match (&checksum_request, staged.checksum.take()) {
+                    (Some(request), Some(value)) => {
+                        response.header(request.algorithm.header_name(), value.as_str());
+                        Some(ObjectChecksum {
+                            algorithm: request.algorithm.name().to_string(),
+                            value,
+                        })
+                    }
+                    _ => None,
+                };
+                let encryption = sse.map(|sse| {
+                    for (name, value) in
+                        sse::response_headers(&sse.encryption, sse.customer_key_md5.as_deref())
+                    {
+                        response.header(name, value);
+                    }
+                    sse.encryption
+                });
 
-                let mut metainfo_file_path = file_path.clone().to_string_lossy().to_string();
-                metainfo_file_path.push_str(".meta");
-                state
-                    .raft
-                    .client_write(UploadFile {
-                        file_path: metainfo_file_path,
-                        body: bytes.to_vec(),
-                    })
-                    .await
-                    .map_err(|err| anyhow!(err.to_string()))?;
-                Ok(HttpResponse::Ok().finish())
+                let file_path = metadata_path(&bucket_name, &object_key);
+                let request =
+                    staged.commit(file_path, encryption, checksum, website_redirect_location);
+                write_object(state, request).await?;
+                Ok(response.finish())
             }
         }
     }
 }
 
+// 拷贝对象逻辑
+// 源对象和目标对象加密方式一致时只重新包装数据密钥，共享数据块；否则在本节点解密后重新写入
+async fn do_copy_object(
+    req: &web::HttpRequest,
+    state: &App,
+    copy_source: String,
+    bucket_name: String,
+    object_key: String,
+) -> HandlerResponse {
+    let (src_bucket_name, src_object_key) = parse_copy_source(&copy_source).ok_or_else(|| {
+        AppError::s3(400, "InvalidArgument", "Copy Source must mention the source bucket and key")
+    })?;
+    let src_metadata = state
+        .objects
+        .load(&src_bucket_name, &src_object_key)
+        .ok()
+        .flatten()
+        .ok_or_else(no_such_key)?;
+    check_quota(state, &bucket_name, &object_key, src_metadata.size).await?;
+    let src_customer_key = sse::CustomerKey::from_request(req, true)?;
+    let src_data_key = sse::data_key(
+        &state.master_key,
+        src_metadata.encryption.as_ref(),
+        src_customer_key.as_ref(),
+    )?;
+    let dest = sse::requested(req, state, &bucket_name).await?;
+    let website_redirect_location = website_redirect_location(req)?;
+    let policy = bucket::write_policy(state, &bucket_name, &object_key).await?;
+    // 重新写入数据时分片信息丢失，只保留整个对象的校验和
+    let checksum = src_metadata
+        .checksum
+        .clone()
+        .filter(|_| src_metadata.parts.is_empty());
+
+    let metainfo_file_path = metadata_path(&bucket_name, &object_key);
+    let mut response = HttpResponse::Ok();
+    match (src_data_key, dest) {
+        (None, None) => {
+            write_object(
+                state,
+                CopyFile {
+                    copy_source,
+                    dest_bucket: bucket_name,
+                    dest_object: object_key,
+                    encryption: None,
+                    website_redirect_location,
+                },
+            )
+            .await?;
+        }
+        (Some(data_key), Some(dest))
+            if reuses_source_chunks(
+                src_customer_key.as_ref().map(|key| key.key()),
+                dest.customer_key(),
+            ) =>
+        {
+            let encryption = dest.wrap(&state.master_key, &data_key)?;
+            for (name, value) in sse::response_headers(&encryption, dest.customer_key_md5()) {
+                response.header(name, value);
+            }
+            write_object(
+                state,
+                CopyFile {
+                    copy_source,
+                    dest_bucket: bucket_name,
+                    dest_object: object_key,
+                    encryption: Some(encryption),
+                    website_redirect_location,
+                },
+            )
+            .await?;
+        }
+        (src_data_key, dest) => {
+            let source = DecompressStream::object(
+                state.chunk_gc.store,
+                state.chunk_gc.cache.clone(),
+                src_metadata,
+                src_data_key,
+                chunk::peers(state),
+                state.read_ahead,
+            );
+            let data_key = dest.as_ref().map(|_| cry::gen_aes_256_key());
+            let encryption = match (&dest, &data_key) {
+                (Some(dest), Some(data_key)) => {
+                    let encryption = dest.wrap(&state.master_key, data_key)?;
+                    let headers = sse::response_headers(&encryption, dest.customer_key_md5());
+                    for (name, value) in headers {
+                        response.header(name, value);
+                    }
+                    Some(encryption)
+                }
+                _ => None,
+            };
+            let staged = upload::stage_stream(
+                state,
+                &policy,
+                data_key.as_ref().map(|key| &key[..]),
+                source,
+            )
+            .await?;
+            let request =
+                staged.commit(metainfo_file_path, encryption, checksum, website_redirect_location);
+            write_object(state, request).await?;
+        }
+    }
+    Ok(response.finish())
+}
+
 // 删除文件
 pub async fn delete_file(req: web::HttpRequest, state: web::types::State<App>) -> HandlerResponse {
     let bucket_name: String = get_path_param(&req, "bucket")?;
     let object_name: String = get_path_param(&req, "object")?;
-    let file_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(bucket_name)
-        .join(object_name);
-    let mut metainfo_file_path = file_path.clone().to_string_lossy().to_string();
-    metainfo_file_path.push_str(".meta");
+    if let Some(upload_id) = query_param(&req, "uploadId") {
+        return abort_multipart_upload(&state, bucket_name, object_name, upload_id).await;
+    }
     state
         .raft
         .client_write(DeleteFile {
-            file_path: metainfo_file_path,
+            file_path: metadata_path(&bucket_name, &object_name),
         })
         .await
         .map_err(|err| anyhow!(err.to_string()))?;
@@ -479,24 +1077,27 @@ pub async fn delete_file(req: web::HttpRequest, state: web::types::State<App>) -
 }
 
 // 长路径获取对象信息
-pub async fn head_object_longpath(req: web::HttpRequest) -> HandlerResponse {
+pub async fn head_object_longpath(
+    req: web::HttpRequest,
+    state: web::types::State<App>,
+) -> HandlerResponse {
     let bucket_name: String = get_path_param(&req, "bucket")?;
     let object_name: String = get_path_param(&req, "object")?;
     let object_suffix: String = get_path_param(&req, "objectSuffix")?;
-    let file_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(bucket_name)
-        .join(object_name)
+    let file_path = state
+        .layout
+        .object(&bucket_name, &object_name)
         .join(object_suffix);
-    do_head_object(file_path).await
+    do_head_object(&req, &state, file_path).await
 }
 
 // 获取对象信息逻辑
-async fn do_head_object(file_path: PathBuf) -> HandlerResponse {
-    let mut metainfo_file_path = file_path.clone().to_string_lossy().to_string();
-    metainfo_file_path.push_str(".meta");
-    info!("{}", metainfo_file_path);
-    if std::fs::metadata(&metainfo_file_path).is_err() {
+async fn do_head_object(
+    req: &web::HttpRequest,
+    state: &App,
+    file_path: PathBuf,
+) -> HandlerResponse {
+    let Some(metainfo) = object_metadata(state, &file_path)? else {
         let resp = HeadNotFoundResp {
             no_exist: "1".to_string(),
         };
@@ -504,18 +1105,39 @@ async fn do_head_object(file_path: PathBuf) -> HandlerResponse {
         return Ok(web::HttpResponse::NotFound()
             .content_type("application/xml")
             .body(xml));
-    }
-    let metainfo = fs::load_metadata(&metainfo_file_path)?;
+    };
 
     let body = once(ok::<_, web::Error>(Bytes::new()));
     let last_modified = date_format_to_second(metainfo.time);
-    Ok(web::HttpResponse::Ok()
+    let customer_key = sse::CustomerKey::from_request(req, false)?;
+    sse::data_key(
+        &state.master_key,
+        metainfo.encryption.as_ref(),
+        customer_key.as_ref(),
+    )?;
+    let mut response = web::HttpResponse::Ok();
+    if let Some(encryption) = &metainfo.encryption {
+        let key_md5 = customer_key.as_ref().map(|key| key.key_md5());
+        for (name, value) in sse::response_headers(encryption, key_md5) {
+            response.header(name, value);
+        }
+    }
+    if let (true, Some(object_checksum)) = (checksum::mode_enabled(req), &metainfo.checksum) {
+        for (name, value) in checksum::response_headers(object_checksum) {
+            response.header(name, value);
+        }
+    }
+    if let Some(location) = &metainfo.website_redirect_location {
+        response.header(website::REDIRECT_LOCATION_HEADER, location.as_str());
+    }
+    Ok(response
         .content_type(metainfo.file_type)
         .header(
             "Content-Disposition",
             format!("attachment; filename=\"{}\"", metainfo.name),
         )
         .header("Last-Modified", last_modified)
+        .header("ETag", metainfo.etag())
         .content_length(metainfo.size as u64)
         .no_chunking()
         .streaming(body))
@@ -524,77 +1146,18 @@ async fn do_head_object(file_path: PathBuf) -> HandlerResponse {
 // 长路径上传文件 & 上传文件分片
 pub async fn upload_file_or_upload_chunk_longpath(
     req: web::HttpRequest,
-    mut body: web::types::Payload,
+    body: web::types::Payload,
     Query(query): Query<UploadFileOrChunkQuery>,
     state: web::types::State<App>,
 ) -> HandlerResponse {
     let bucket_name: String = get_path_param(&req, "bucket")?;
     let object_name: String = get_path_param(&req, "object")?;
     let object_suffix: String = get_path_param(&req, "objectSuffix")?;
-    let file_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(&bucket_name)
-        .join(&object_name)
-        .join(&object_suffix);
     let object_key = PathBuf::from(&object_name)
         .join(&object_suffix)
         .to_string_lossy()
         .to_string();
-    match (query.upload_id, query.part_number) {
-        (Some(upload_id), Some(part_number)) => {
-            info!("long path");
-            let mut bytes = Vec::new();
-            bytes.reserve_exact(8 << 20);
-            while let Some(item) = body.next().await {
-                let item = item.map_err(|err| anyhow!(err.to_string()))?;
-                bytes.extend_from_slice(&item);
-            }
-            let hash = fs::sum_sha256(&bytes).await;
-            state
-                .raft
-                .client_write(UploadChunk {
-                    part_number,
-                    upload_id,
-                    hash: hash.clone(),
-                    body: bytes.to_vec(),
-                })
-                .await
-                .map_err(|err| anyhow!(err.to_string()))?;
-            Ok(HttpResponse::Ok().header("ETag", &hash).finish())
-        }
-        _ => {
-            if let Some(copy_source) = req.headers().get("x-amz-copy-source") {
-                state
-                    .raft
-                    .client_write(CopyFile {
-                        copy_source: copy_source.to_str().unwrap().to_string(),
-                        dest_bucket: bucket_name,
-                        dest_object: object_key,
-                    })
-                    .await
-                    .map_err(|err| anyhow!(err.to_string()))?;
-                Ok(HttpResponse::Ok().finish())
-            } else {
-                let mut bytes = Vec::new();
-                bytes.reserve_exact(8 << 20);
-                while let Some(item) = body.next().await {
-                    let item = item.map_err(|err| anyhow!(err.to_string()))?;
-                    bytes.extend_from_slice(&item);
-                }
-                let mut metainfo_file_path = file_path.clone().to_string_lossy().to_string();
-                metainfo_file_path.push_str(".meta");
-                state
-                    .raft
-                    .client_write(UploadFile {
-                        file_path: metainfo_file_path,
-                        body: bytes.to_vec(),
-                    })
-                    .await
-                    .map_err(|err| anyhow!(err.to_string()))?;
-                Ok(HttpResponse::Ok().finish())
-            }
-        }
-    }
+    do_upload_file_or_upload_chunk(&req, body, query, &state, bucket_name, object_key).await
 }
 
 // 长路径删除文件
@@ -605,15 +1168,21 @@ pub async fn delete_file_longpath(
     let bucket_name: String = get_path_param(&req, "bucket")?;
     let object_name: String = get_path_param(&req, "object")?;
     let object_suffix: String = get_path_param(&req, "objectSuffix")?;
-    let file_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(bucket_name)
-        .join(object_name)
-        .join(object_suffix);
+    if let Some(upload_id) = query_param(&req, "uploadId") {
+        let object_key = PathBuf::from(&object_name)
+            .join(&object_suffix)
+            .to_string_lossy()
+            .to_string();
+        return abort_multipart_upload(&state, bucket_name, object_key, upload_id).await;
+    }
+    let object_key = PathBuf::from(&object_name)
+        .join(&object_suffix)
+        .to_string_lossy()
+        .to_string();
     state
         .raft
         .client_write(DeleteFile {
-            file_path: file_path.to_string_lossy().to_string(),
+            file_path: metadata_path(&bucket_name, &object_key),
         })
         .await
         .map_err(|err| anyhow!(err.to_string()))?;
@@ -621,40 +1190,284 @@ pub async fn delete_file_longpath(
 }
 
 // 产路径删除文件
-pub async fn download_file_longpath(req: web::HttpRequest) -> HandlerResponse {
+pub async fn download_file_longpath(
+    req: web::HttpRequest,
+    state: web::types::State<App>,
+) -> HandlerResponse {
     let bucket_name: String = get_path_param(&req, "bucket")?;
     let object_name: String = get_path_param(&req, "object")?;
     let object_suffix: String = get_path_param(&req, "objectSuffix")?;
-    let file_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(bucket_name)
-        .join(object_name)
+    if let Some(upload_id) = query_param(&req, "uploadId") {
+        let object_key = PathBuf::from(&object_name)
+            .join(&object_suffix)
+            .to_string_lossy()
+            .to_string();
+        return list_parts(&req, &state, bucket_name, object_key, upload_id).await;
+    }
+    let file_path = state
+        .layout
+        .object(&bucket_name, &object_name)
         .join(object_suffix);
-    do_download_file(file_path).await
+    if has_sub_resource(&req, "attributes") {
+        return get_object_attributes(&req, &state, file_path).await;
+    }
+    do_download_file(&req, &state, file_path).await
 }
 
 // 下载文件
-pub async fn download_file(req: web::HttpRequest) -> HandlerResponse {
+pub async fn download_file(
+    req: web::HttpRequest,
+    state: web::types::State<App>,
+) -> HandlerResponse {
     let bucket_name: String = get_path_param(&req, "bucket")?;
     let object_name: String = get_path_param(&req, "object")?;
-    let file_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(bucket_name)
-        .join(object_name);
-    do_download_file(file_path).await
+    if let Some(upload_id) = query_param(&req, "uploadId") {
+        return list_parts(&req, &state, bucket_name, object_name, upload_id).await;
+    }
+    let file_path = state.layout.object(&bucket_name, &object_name);
+    if has_sub_resource(&req, "attributes") {
+        return get_object_attributes(&req, &state, file_path).await;
+    }
+    do_download_file(&req, &state, file_path).await
+}
+
+// 中止分片上传
+async fn abort_multipart_upload(
+    state: &App,
+    bucket_name: String,
+    object_key: String,
+    upload_id: String,
+) -> HandlerResponse {
+    if upload_id.contains('/')
+        || !state
+            .layout
+            .upload_metadata(&bucket_name, &object_key, &upload_id)
+            .exists()
+    {
+        return Err(no_such_upload());
+    }
+    state
+        .raft
+        .client_write(AbortUpload {
+            bucket_name,
+            object_key,
+            upload_id,
+        })
+        .await
+        .map_err(|err| anyhow!(err.to_string()))?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// 列出分片上传中已上传的分片
+async fn list_parts(
+    req: &web::HttpRequest,
+    state: &App,
+    bucket_name: String,
+    object_key: String,
+    upload_id: String,
+) -> HandlerResponse {
+    let tmp_metadata_path = state
+        .layout
+        .upload_metadata(&bucket_name, &object_key, &upload_id);
+    let keyring = state.objects.keyring()?;
+    let tmp_metadata =
+        fs::load_metadata(tmp_metadata_path, &keyring).map_err(|_| no_such_upload())?;
+    let algorithm = tmp_metadata
+        .checksum
+        .as_ref()
+        .and_then(|checksum| ChecksumAlgorithm::parse(&checksum.algorithm));
+    let (max_parts, part_number_marker) = parts_pagination(req);
+
+    let upload_dir = state.layout.upload_dir(&upload_id);
+    let mut part_numbers: Vec<u32> = read_dir(&upload_dir)
+        .map_err(|_| no_such_upload())?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|part_number| *part_number > part_number_marker)
+        .collect();
+    part_numbers.sort_unstable();
+    let is_truncated = part_numbers.len() > max_parts as usize;
+    part_numbers.truncate(max_parts as usize);
+
+    let mut parts = Vec::with_capacity(part_numbers.len());
+    for part_number in &part_numbers {
+        let part_path = upload_dir.join(part_number.to_string());
+        let info = fs::load_part_info(&part_path)?;
+        let last_modified = std::fs::metadata(&part_path)
+            .and_then(|metadata| metadata.modified())
+            .context("读取分片信息失败")?;
+        let [checksum_crc32, checksum_crc32c, checksum_sha1, checksum_sha256] =
+            checksum::fields(algorithm, info.checksum);
+        parts.push(Part {
+            part_number: *part_number,
+            last_modified: last_modified.into(),
+            etag: info.etag,
+            size: info.size,
+            checksum_crc32,
+            checksum_crc32c,
+            checksum_sha1,
+            checksum_sha256,
+        });
+    }
+    let res = ListPartsResult {
+        bucket: bucket_name,
+        key: object_key,
+        upload_id,
+        part_number_marker,
+        next_part_number_marker: part_numbers.last().copied().unwrap_or(part_number_marker),
+        max_parts,
+        is_truncated,
+        checksum_algorithm: algorithm.map(|algorithm| algorithm.name().to_string()),
+        parts,
+    };
+    let xml = to_string(&res).map_err(|err| anyhow!(err))?;
+    Ok(HttpResponse::Ok().content_type("application/xml").body(xml))
+}
+
+// 获取对象属性，返回 x-amz-object-attributes 中选择的属性
+async fn get_object_attributes(
+    req: &web::HttpRequest,
+    state: &App,
+    file_path: PathBuf,
+) -> HandlerResponse {
+    let selector = req
+        .headers()
+        .get_all(OBJECT_ATTRIBUTES_HEADER)
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+    let attributes = parse_object_attributes(&selector).ok_or_else(|| {
+        AppError::s3(
+            400,
+            "InvalidArgument",
+            "Invalid attribute name specified in x-amz-object-attributes.",
+        )
+    })?;
+    let metainfo = object_metadata(state, &file_path)
+        .ok()
+        .flatten()
+        .ok_or_else(no_such_key)?;
+    let customer_key = sse::CustomerKey::from_request(req, false)?;
+    sse::data_key(
+        &state.master_key,
+        metainfo.encryption.as_ref(),
+        customer_key.as_ref(),
+    )?;
+
+    let algorithm = metainfo
+        .checksum
+        .as_ref()
+        .and_then(|checksum| ChecksumAlgorithm::parse(&checksum.algorithm));
+    let object_checksum = match (attributes.checksum, &metainfo.checksum) {
+        (true, Some(object_checksum)) => {
+            let [checksum_crc32, checksum_crc32c, checksum_sha1, checksum_sha256] =
+                checksum::fields(algorithm, Some(object_checksum.value.clone()));
+            Some(Checksum {
+                checksum_crc32,
+                checksum_crc32c,
+                checksum_sha1,
+                checksum_sha256,
+                checksum_type: checksum::checksum_type(object_checksum).to_string(),
+            })
+        }
+        _ => None,
+    };
+    // 只有分片上传的对象有分片列表
+    let object_parts = if attributes.object_parts && !metainfo.parts.is_empty() {
+        let (max_parts, part_number_marker) = parts_pagination(req);
+        let remaining: Vec<_> = metainfo
+            .parts
+            .iter()
+            .filter(|part| part.part_number > part_number_marker)
+            .collect();
+        let is_truncated = remaining.len() > max_parts as usize;
+        let parts: Vec<_> = remaining
+            .into_iter()
+            .take(max_parts as usize)
+            .map(|part| {
+                let [checksum_crc32, checksum_crc32c, checksum_sha1, checksum_sha256] =
+                    checksum::fields(algorithm, part.checksum.clone());
+                ObjectPartAttributes {
+                    part_number: part.part_number,
+                    size: part.size,
+                    checksum_crc32,
+                    checksum_crc32c,
+                    checksum_sha1,
+                    checksum_sha256,
+                }
+            })
+            .collect();
+        Some(ObjectParts {
+            parts_count: metainfo.parts.len() as u32,
+            part_number_marker,
+            next_part_number_marker: parts
+                .last()
+                .map(|part| part.part_number)
+                .unwrap_or(part_number_marker),
+            max_parts,
+            is_truncated,
+            parts,
+        })
+    } else {
+        None
+    };
+    let res = GetObjectAttributesResponse {
+        etag: attributes.etag.then(|| metainfo.etag()),
+        checksum: object_checksum,
+        object_parts,
+        storage_class: attributes.storage_class.then(|| "STANDARD".to_string()),
+        object_size: attributes.object_size.then_some(metainfo.size),
+    };
+    let xml = to_string(&res).map_err(|err| anyhow!(err))?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/xml")
+        .header("Last-Modified", date_format_to_second(metainfo.time))
+        .body(xml))
 }
 
 // 下载文件逻辑
-async fn do_download_file(file_path: PathBuf) -> HandlerResponse {
-    let mut metainfo_file_path = file_path.clone().to_string_lossy().to_string();
-    metainfo_file_path.push_str(".meta");
-    let meta_info = fs::load_metadata(&metainfo_file_path)?;
-    let body = DecompressStream::new(meta_info.chunks);
+async fn do_download_file(
+    req: &web::HttpRequest,
+    state: &App,
+    file_path: PathBuf,
+) -> HandlerResponse {
+    let meta_info = object_metadata(state, &file_path)?.context("对象不存在")?;
+    let customer_key = sse::CustomerKey::from_request(req, false)?;
+    let data_key = sse::data_key(
+        &state.master_key,
+        meta_info.encryption.as_ref(),
+        customer_key.as_ref(),
+    )?;
+    let mut response = web::HttpResponse::Ok();
+    if let Some(encryption) = &meta_info.encryption {
+        let key_md5 = customer_key.as_ref().map(|key| key.key_md5());
+        for (name, value) in sse::response_headers(encryption, key_md5) {
+            response.header(name, value);
+        }
+    }
+    if let (true, Some(object_checksum)) = (checksum::mode_enabled(req), &meta_info.checksum) {
+        for (name, value) in checksum::response_headers(object_checksum) {
+            response.header(name, value);
+        }
+    }
+    if let Some(location) = &meta_info.website_redirect_location {
+        response.header(website::REDIRECT_LOCATION_HEADER, location.as_str());
+    }
+    let etag = meta_info.etag();
     let content_disposition = format!("attachment; filename=\"{}\"", meta_info.name);
-    Ok(web::HttpResponse::Ok()
+    let (size, time) = (meta_info.size, meta_info.time);
+    let body = DecompressStream::object(
+        state.chunk_gc.store,
+        state.chunk_gc.cache.clone(),
+        meta_info,
+        data_key,
+        chunk::peers(state),
+        state.read_ahead,
+    );
+    Ok(response
         .header("Content-Type", "application/octet-stream")
-        .header("Content-Length", meta_info.size)
-        .header("Last-Modified", date_format_to_second(meta_info.time))
+        .header("Content-Length", size)
+        .header("Last-Modified", date_format_to_second(time))
+        .header("ETag", etag)
         .header("Content-Disposition", content_disposition)
         .streaming(body))
 }
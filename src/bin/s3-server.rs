@@ -4,9 +4,13 @@ static ALLOC: MiMalloc = MiMalloc;
 
 use clap::Parser;
 use mimalloc::MiMalloc;
+use rs_s3_local::layout::Layout;
 use rs_s3_local::start_example_raft_node;
 use std::path::PathBuf;
 
+// 内联对象随元数据保存在索引和 raft 日志中，限制其大小
+const MAX_INLINE_THRESHOLD: usize = 64 * 1024;
+
 #[derive(Parser, Clone, Debug)]
 #[clap(author, version, about, long_about = None)]
 pub struct Opt {
@@ -30,6 +34,120 @@ pub struct Opt {
 
     #[clap(long, default_value_t = String::from("minioadmin"))]
     pub secret_key: String,
+
+    /// File holding the hex encoded 256-bit master key used for server-side encryption.
+    /// Every node of a cluster must use the same key.
+    #[clap(long, env = "S3_MASTER_KEY_FILE")]
+    pub master_key_file: Option<String>,
+
+    /// Run a standalone node that never joins a cluster. Only then are missing master and
+    /// metadata keys generated under --fs-root; otherwise startup fails unless both keys are
+    /// given explicitly.
+    #[clap(long)]
+    pub single_node: bool,
+
+    /// Hex encoded 256-bit keys used to encrypt object metadata, separated by commas.
+    /// The first key encrypts, the others are only used to decrypt. Takes precedence over
+    /// `--metadata-key-file`.
+    #[clap(long, env = "S3_METADATA_KEY", hide_env_values = true)]
+    pub metadata_key: Option<String>,
+
+    /// File holding the metadata keys, one hex encoded key per line, newest first.
+    /// It is read again when the key is rotated.
+    #[clap(long, env = "S3_METADATA_KEY_FILE")]
+    pub metadata_key_file: Option<String>,
+
+    /// Accept metadata written by older versions with the built-in AES-256-CBC key, which is
+    /// not authenticated, and re-encrypt it with the metadata key at startup. Afterwards the
+    /// node records that no such metadata is left and rejects it even with this flag.
+    #[clap(long)]
+    pub metadata_legacy_cbc: bool,
+
+    /// Address of the static website endpoint. Disabled when not set.
+    #[clap(long)]
+    pub website_addr: Option<String>,
+
+    /// Domain of the website endpoint: a request for `{bucket}.{domain}` serves `bucket`.
+    /// Other host names are used as the bucket name as they are.
+    #[clap(long)]
+    pub website_domain: Option<String>,
+
+    /// Seconds between two runs of the garbage collector that deletes unreferenced chunks.
+    #[clap(long, default_value_t = 600)]
+    pub gc_interval: u64,
+
+    /// Seconds a chunk must stay unreferenced before it is deleted, so that downloads in
+    /// progress can still read it.
+    #[clap(long, default_value_t = 3600)]
+    pub gc_grace: u64,
+
+    /// Seconds after which unfinished multipart uploads are aborted. 0 keeps them forever.
+    #[clap(long, default_value_t = 7 * 24 * 3600)]
+    pub upload_expiry: u64,
+
+    /// Seconds between two scans that re-encode the missing erasure-coded shards of chunks,
+    /// e.g. after a node was replaced. 0 disables the repair.
+    #[clap(long, default_value_t = 3600)]
+    pub repair_interval: u64,
+
+    /// Directory holding chunk data, usually one per disk. Repeat it to use several disks;
+    /// chunks are spread over them by hash, weighted by the capacity of each disk.
+    /// Defaults to `data/file` under --fs-root.
+    #[clap(long = "data-dir")]
+    pub data_dirs: Vec<String>,
+
+    /// Seconds between two checks that bring failed data directories back online and move
+    /// chunks to the directory their hash prefers, e.g. after a disk was added.
+    #[clap(long, default_value_t = 60)]
+    pub disk_check_interval: u64,
+
+    /// Seconds between two scrub passes that read every local chunk, check it against its
+    /// hash and repair corrupt chunks from peer replicas. 0 disables the scrubber.
+    #[clap(long, default_value_t = 604800)]
+    pub scrub_interval: u64,
+
+    /// Maximum rate in MiB/s at which the scrubber reads chunk data. 0 means unlimited.
+    #[clap(long, default_value_t = 16)]
+    pub scrub_rate: u64,
+
+    /// Number of chunks read and decompressed ahead of the one being sent in a download.
+    #[clap(long, default_value_t = 2)]
+    pub read_ahead: usize,
+
+    /// Objects of at most this many bytes are stored inside their metadata instead of as
+    /// chunks, so reading them needs a single lookup. At most 64 KiB, 0 disables inline
+    /// storage.
+    #[clap(long, default_value_t = 4096)]
+    pub inline_threshold: usize,
+
+    /// Memory budget in MiB for caching decompressed chunks and parsed object metadata, so
+    /// popular objects are served without reading and decoding them again. An eighth of it
+    /// goes to metadata. Encrypted chunks are not cached. 0 disables the cache.
+    #[clap(long, default_value_t = 256)]
+    pub cache_size: usize,
+
+    /// Where chunks are kept: "disk" stores them under the data directories, "pack" appends
+    /// small chunks to pack files in --pack-dir and stores the others under the data
+    /// directories, "memory" keeps them in memory only, so they are lost on restart and
+    /// refetched from peer replicas.
+    #[clap(long, default_value = "disk")]
+    pub chunk_store: String,
+
+    /// Directory of the pack files and their index when --chunk-store is "pack".
+    /// Defaults to `data/pack` under --fs-root.
+    #[clap(long)]
+    pub pack_dir: Option<String>,
+
+    /// Chunks smaller than this many KiB are appended to pack files when --chunk-store is
+    /// "pack".
+    #[clap(long, default_value_t = 128)]
+    pub pack_threshold: usize,
+
+    /// How writes are made durable. Files are always written to a temporary file and renamed
+    /// into place. "data" also fsyncs the file and the raft log before a write is acknowledged,
+    /// "full" additionally fsyncs the directory, "none" leaves flushing to the OS.
+    #[clap(long, default_value = "full")]
+    pub durability: String,
 }
 
 #[ntex::main]
@@ -39,19 +157,47 @@ async fn main() -> anyhow::Result<()> {
     // 创建一个新的 HTTP 服务器实例。
     // Parse the parameters passed by arguments.
     let options = Opt::parse();
+    if options.inline_threshold > MAX_INLINE_THRESHOLD {
+        anyhow::bail!(
+            "--inline-threshold 不能超过 {} 字节，当前为 {}",
+            MAX_INLINE_THRESHOLD,
+            options.inline_threshold
+        );
+    }
+    let data_dirs = options.data_dirs.iter().map(PathBuf::from).collect();
+    let mut layout = Layout::new(&options.fs_root, options.id).with_chunk_dirs(data_dirs);
+    if let Some(pack_dir) = &options.pack_dir {
+        layout = layout.with_pack_dir(pack_dir);
+    }
 
     start_example_raft_node(
         options.id,
-        PathBuf::from(options.fs_root.clone())
-            .join(format!("{}-db", options.id))
-            .to_string_lossy()
-            .to_string(),
+        layout,
         options.http_addr,
         options.rpc_addr,
-        options.fs_root,
         options.access_key,
         options.secret_key,
         options.leader_http_addr,
+        options.master_key_file,
+        options.single_node,
+        options.metadata_key,
+        options.metadata_key_file,
+        options.metadata_legacy_cbc,
+        options.website_addr,
+        options.website_domain,
+        options.gc_interval,
+        options.gc_grace,
+        options.upload_expiry,
+        options.repair_interval,
+        options.disk_check_interval,
+        options.scrub_interval,
+        options.scrub_rate * 1024 * 1024,
+        options.read_ahead,
+        options.inline_threshold,
+        options.cache_size << 20,
+        options.chunk_store,
+        options.pack_threshold * 1024,
+        options.durability,
     )
     .await?;
     Ok(())
@@ -0,0 +1,155 @@
+use crate::quota::{self, BucketQuota, BucketUsage, UsageChange};
+use crate::raft::app::App;
+use crate::raft::store::Request::SetBucketConfig;
+use crate::util::chunker::ChunkingConfig;
+use crate::util::codec::CompressionConfig;
+use crate::util::erasure::StoragePolicy;
+use anyhow::anyhow;
+use mime_guess::MimeGuess;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// 桶配置保存在状态机的键值表中，随快照一起复制到所有节点
+
+// 默认加密配置
+pub(crate) const ENCRYPTION_CONFIG: &str = "encryption";
+// 分块方式
+pub(crate) const CHUNKING_CONFIG: &str = "chunking";
+// 分块存储方式
+pub(crate) const STORAGE_CONFIG: &str = "storage";
+// 分块压缩方式
+pub(crate) const COMPRESSION_CONFIG: &str = "compression";
+// 静态网站配置
+pub(crate) const WEBSITE_CONFIG: &str = "website";
+// 桶配额
+pub(crate) const QUOTA_CONFIG: &str = "quota";
+// 桶用量，只由状态机在应用写入时更新
+pub(crate) const USAGE: &str = "usage";
+// 状态机应用写入时发现超出配额的响应值
+pub(crate) const QUOTA_EXCEEDED: &str = "QuotaExceeded";
+// 状态机应用写入失败时响应值的前缀，其后为错误信息
+pub(crate) const WRITE_FAILED: &str = "WriteFailed: ";
+
+// 桶配置在状态机中的键前缀
+pub(crate) fn config_prefix(bucket_name: &str) -> String {
+    format!("bucket/{}/", bucket_name)
+}
+
+// 桶配置在状态机中的键
+pub(crate) fn config_key(bucket_name: &str, kind: &str) -> String {
+    format!("{}{}", config_prefix(bucket_name), kind)
+}
+
+// 读取桶配置
+pub(crate) async fn get_config<T: DeserializeOwned>(
+    state: &App,
+    bucket_name: &str,
+    kind: &str,
+) -> anyhow::Result<Option<T>> {
+    let kvs = state.key_values.read().await;
+    match kvs.get(&config_key(bucket_name, kind)) {
+        Some(value) => Ok(Some(serde_json::from_str(value)?)),
+        None => Ok(None),
+    }
+}
+
+// 通过raft写入桶配置，config为None时删除配置
+pub(crate) async fn put_config<T: Serialize>(
+    state: &App,
+    bucket_name: &str,
+    kind: &str,
+    config: Option<&T>,
+) -> anyhow::Result<()> {
+    let value = config.map(serde_json::to_string).transpose()?;
+    state
+        .raft
+        .client_write(SetBucketConfig {
+            bucket_name: bucket_name.to_string(),
+            kind: kind.to_string(),
+            value,
+        })
+        .await
+        .map_err(|err| anyhow!(err.to_string()))?;
+    Ok(())
+}
+
+// 读取桶的分块方式，未配置时使用固定大小分块
+pub(crate) async fn chunking(state: &App, bucket_name: &str) -> anyhow::Result<ChunkingConfig> {
+    Ok(get_config(state, bucket_name, CHUNKING_CONFIG)
+        .await?
+        .unwrap_or_default())
+}
+
+// 读取桶的分块存储方式，未配置时每个节点保存完整分块
+pub(crate) async fn storage(state: &App, bucket_name: &str) -> anyhow::Result<StoragePolicy> {
+    Ok(get_config(state, bucket_name, STORAGE_CONFIG)
+        .await?
+        .unwrap_or_default())
+}
+
+// 读取桶的分块压缩方式，未配置时使用 zstd 默认级别并跳过不可压缩的分块
+pub(crate) async fn compression(
+    state: &App,
+    bucket_name: &str,
+) -> anyhow::Result<CompressionConfig> {
+    Ok(get_config(state, bucket_name, COMPRESSION_CONFIG)
+        .await?
+        .unwrap_or_default())
+}
+
+// 写入对象时使用的桶配置
+pub(crate) struct WritePolicy {
+    pub chunking: ChunkingConfig,
+    pub storage: StoragePolicy,
+    // 已按对象的 MIME 类型调整
+    pub compression: CompressionConfig,
+    // 不超过该长度的对象保存在元数据中，0 表示不内联
+    pub inline_threshold: usize,
+}
+
+impl WritePolicy {
+    // 分片上传的分片总是保存为分块，完成上传时按分片拼接
+    pub(crate) fn without_inline(mut self) -> Self {
+        self.inline_threshold = 0;
+        self
+    }
+}
+
+// 读取写入对象时使用的桶配置，对象的 MIME 类型按名称推断
+pub(crate) async fn write_policy(
+    state: &App,
+    bucket_name: &str,
+    object_key: &str,
+) -> anyhow::Result<WritePolicy> {
+    let content_type = MimeGuess::from_path(object_key)
+        .first_or_text_plain()
+        .to_string();
+    Ok(WritePolicy {
+        chunking: chunking(state, bucket_name).await?,
+        storage: storage(state, bucket_name).await?,
+        compression: compression(state, bucket_name)
+            .await?
+            .for_content_type(&content_type),
+        inline_threshold: state.inline_threshold,
+    })
+}
+
+// 读取桶用量，没有记录时为零
+pub(crate) async fn get_usage(state: &App, bucket_name: &str) -> anyhow::Result<BucketUsage> {
+    let kvs = state.key_values.read().await;
+    Ok(quota::usage(&kvs, bucket_name).unwrap_or_default())
+}
+
+// 检查写入是否在桶配额内，leader 提交前和状态机应用时使用同一份判断
+pub(crate) fn quota_allows(
+    kvs: &BTreeMap<String, String>,
+    bucket_name: &str,
+    change: UsageChange,
+) -> bool {
+    let quota: BucketQuota = match kvs.get(&config_key(bucket_name, QUOTA_CONFIG)) {
+        Some(value) => serde_json::from_str(value).unwrap_or_default(),
+        None => return true,
+    };
+    quota.allows(&quota::usage(kvs, bucket_name).unwrap_or_default(), change)
+}
@@ -0,0 +1,151 @@
+use crate::fs::Metadata;
+use crate::metrics::{self, Counter};
+use lru::LruCache;
+use ntex::util::Bytes;
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+// 解压后的分块明文，键为分块名称
+pub type ChunkCache = Cache<String, Bytes>;
+// 解密并解析后的对象元数据，键为索引中的 {bucket}/{key}
+pub type MetadataCache = Cache<Vec<u8>, Metadata>;
+
+// 按占用的字节数限制大小的 LRU 缓存，超出容量时淘汰最久未使用的条目
+// 克隆后共享同一份数据，容量为0时不缓存
+pub struct Cache<K: Hash + Eq, V: Clone> {
+    entries: Arc<Mutex<Entries<K, V>>>,
+    capacity: usize,
+    hits: &'static Counter,
+    misses: &'static Counter,
+}
+
+struct Entries<K: Hash + Eq, V> {
+    lru: LruCache<K, (V, usize)>,
+    // 全部条目的字节数
+    size: usize,
+}
+
+impl<K: Hash + Eq, V: Clone> Cache<K, V> {
+    pub fn new(capacity: usize, hits: &'static Counter, misses: &'static Counter) -> Self {
+        Cache {
+            entries: Arc::new(Mutex::new(Entries {
+                lru: LruCache::unbounded(),
+                size: 0,
+            })),
+            capacity,
+            hits,
+            misses,
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        match entries.lru.get(key) {
+            Some((value, _)) => {
+                self.hits.inc();
+                Some(value.clone())
+            }
+            None => {
+                self.misses.inc();
+                None
+            }
+        }
+    }
+
+    // 放入条目，size 为条目占用的字节数，超过整个缓存容量的条目不缓存
+    pub fn insert(&self, key: K, value: V, size: usize) {
+        self.insert_if(key, value, size, || true)
+    }
+
+    // 在缓存的锁内确认 valid 后才放入条目
+    // 写入方先更新数据再 remove，读取方与之并发时不会把读到的旧值放入缓存
+    pub fn insert_if(&self, key: K, value: V, size: usize, valid: impl FnOnce() -> bool) {
+        if size > self.capacity {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if !valid() {
+            return;
+        }
+        if let Some((_, (_, replaced))) = entries.lru.push(key, (value, size)) {
+            entries.size -= replaced;
+        }
+        entries.size += size;
+        while entries.size > self.capacity {
+            match entries.lru.pop_lru() {
+                Some((_, (_, evicted))) => entries.size -= evicted,
+                None => break,
+            }
+        }
+    }
+
+    pub fn remove(&self, key: &K) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if let Some((_, size)) = entries.lru.pop(key) {
+            entries.size -= size;
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.lru.clear();
+        entries.size = 0;
+    }
+
+    // 已缓存的条目数和字节数
+    pub fn usage(&self) -> (usize, usize) {
+        let entries = self.entries.lock().unwrap();
+        (entries.lru.len(), entries.size)
+    }
+}
+
+impl<K: Hash + Eq, V: Clone> Clone for Cache<K, V> {
+    fn clone(&self) -> Self {
+        Cache {
+            entries: self.entries.clone(),
+            capacity: self.capacity,
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+}
+
+impl<K: Hash + Eq, V: Clone> Debug for Cache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (len, size) = self.usage();
+        f.debug_struct("Cache")
+            .field("capacity", &self.capacity)
+            .field("len", &len)
+            .field("size", &size)
+            .finish()
+    }
+}
+
+// 缓存的内存预算，八分之一用于元数据，其余用于分块明文
+pub fn split_budget(budget: usize) -> (usize, usize) {
+    let metadata = budget / 8;
+    (budget - metadata, metadata)
+}
+
+pub fn chunk_cache(capacity: usize) -> ChunkCache {
+    Cache::new(
+        capacity,
+        &metrics::CHUNK_CACHE_HITS,
+        &metrics::CHUNK_CACHE_MISSES,
+    )
+}
+
+pub fn metadata_cache(capacity: usize) -> MetadataCache {
+    Cache::new(
+        capacity,
+        &metrics::METADATA_CACHE_HITS,
+        &metrics::METADATA_CACHE_MISSES,
+    )
+}
@@ -0,0 +1,235 @@
+use crate::err::AppError;
+use crate::fs::ObjectChecksum;
+use crate::model::PartETag;
+use crate::util::checksum::{ChecksumAlgorithm, Checksummer};
+use ntex::web;
+
+pub(crate) const CHECKSUM_ALGORITHM_HEADER: &str = "x-amz-checksum-algorithm";
+// 新版 SDK 使用尾部字段时通过该请求头声明算法
+const SDK_CHECKSUM_ALGORITHM_HEADER: &str = "x-amz-sdk-checksum-algorithm";
+const CHECKSUM_MODE_HEADER: &str = "x-amz-checksum-mode";
+const CHECKSUM_TYPE_HEADER: &str = "x-amz-checksum-type";
+const TRAILER_HEADER: &str = "x-amz-trailer";
+
+// 请求要求计算的附加校验和
+pub(crate) struct ChecksumRequest {
+    pub algorithm: ChecksumAlgorithm,
+    // 请求头中提供的校验和
+    expected: Option<String>,
+    // 校验和在 aws-chunked 请求体的尾部字段中提供
+    trailer: bool,
+}
+
+impl ChecksumRequest {
+    // 增量计算请求体的校验和
+    pub(crate) fn checksummer(&self) -> Checksummer {
+        Checksummer::new(self.algorithm)
+    }
+
+    // 检查计算出的校验和，与客户端提供的值不一致时返回 BadDigest
+    pub(crate) fn verify(
+        &self,
+        actual: String,
+        trailers: &[(String, String)],
+    ) -> Result<String, AppError> {
+        let expected = match (&self.expected, self.trailer) {
+            (Some(expected), _) => Some(expected.as_str()),
+            (None, true) => Some(
+                trailers
+                    .iter()
+                    .find(|(name, _)| name == self.algorithm.header_name())
+                    .map(|(_, value)| value.as_str())
+                    .ok_or_else(|| {
+                        AppError::s3(
+                            400,
+                            "InvalidRequest",
+                            format!("Missing trailer {}", self.algorithm.header_name()),
+                        )
+                    })?,
+            ),
+            (None, false) => None,
+        };
+        match expected {
+            Some(expected) if expected != actual => Err(bad_digest(self.algorithm)),
+            _ => Ok(actual),
+        }
+    }
+}
+
+// 读取请求声明的校验和算法
+pub(crate) fn requested_algorithm(
+    req: &web::HttpRequest,
+) -> Result<Option<ChecksumAlgorithm>, AppError> {
+    let value = req
+        .headers()
+        .get(CHECKSUM_ALGORITHM_HEADER)
+        .or_else(|| req.headers().get(SDK_CHECKSUM_ALGORITHM_HEADER));
+    match value {
+        Some(value) => {
+            let name = value.to_str().map_err(|_| invalid_algorithm())?;
+            Ok(Some(
+                ChecksumAlgorithm::parse(name).ok_or_else(invalid_algorithm)?,
+            ))
+        }
+        None => Ok(None),
+    }
+}
+
+// 根据请求头确定需要计算的校验和，default 为分片上传初始化时指定的算法
+pub(crate) fn from_request(
+    req: &web::HttpRequest,
+    default: Option<ChecksumAlgorithm>,
+) -> Result<Option<ChecksumRequest>, AppError> {
+    let declared = requested_algorithm(req)?;
+    let mut provided = None;
+    for algorithm in ChecksumAlgorithm::ALL {
+        if let Some(value) = req.headers().get(algorithm.header_name()) {
+            if provided.is_some() {
+                return Err(AppError::s3(
+                    400,
+                    "InvalidRequest",
+                    "Expecting a single x-amz-checksum- header. Multiple checksum Types are not allowed.",
+                ));
+            }
+            let value = value.to_str().map_err(|_| bad_digest(algorithm))?;
+            provided = Some((algorithm, value.to_string()));
+        }
+    }
+    let trailer = match req.headers().get(TRAILER_HEADER) {
+        Some(value) => {
+            let name = value.to_str().map_err(|_| invalid_algorithm())?.trim();
+            Some(
+                ChecksumAlgorithm::ALL
+                    .into_iter()
+                    .find(|algorithm| algorithm.header_name().eq_ignore_ascii_case(name))
+                    .ok_or_else(invalid_algorithm)?,
+            )
+        }
+        None => None,
+    };
+    let (algorithm, expected, trailer) = match (provided, trailer) {
+        (Some((algorithm, value)), _) => (algorithm, Some(value), false),
+        (None, Some(algorithm)) => (algorithm, None, true),
+        (None, None) => match declared.or(default) {
+            Some(algorithm) => (algorithm, None, false),
+            None => return Ok(None),
+        },
+    };
+    for expected_algorithm in [declared, default].into_iter().flatten() {
+        if expected_algorithm != algorithm {
+            return Err(AppError::s3(
+                400,
+                "InvalidRequest",
+                format!(
+                    "Checksum Type mismatch occurred, expected checksum Type: {}, actual checksum Type: {}",
+                    expected_algorithm.name().to_lowercase(),
+                    algorithm.name().to_lowercase()
+                ),
+            ));
+        }
+    }
+    Ok(Some(ChecksumRequest {
+        algorithm,
+        expected,
+        trailer,
+    }))
+}
+
+// 请求体是否使用 aws-chunked 编码
+pub(crate) fn is_aws_chunked(req: &web::HttpRequest) -> bool {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+    header("content-encoding").contains("aws-chunked")
+        || header("x-amz-content-sha256").starts_with("STREAMING-")
+}
+
+// 请求声明的对象长度，aws-chunked 编码时为解码后的长度
+pub(crate) fn declared_content_length(req: &web::HttpRequest) -> Option<u64> {
+    let name = if is_aws_chunked(req) {
+        "x-amz-decoded-content-length"
+    } else {
+        "content-length"
+    };
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+// 下载时是否返回校验和
+pub(crate) fn mode_enabled(req: &web::HttpRequest) -> bool {
+    req.headers()
+        .get(CHECKSUM_MODE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("ENABLED"))
+}
+
+// 对象校验和对应的响应头
+pub(crate) fn response_headers(checksum: &ObjectChecksum) -> Vec<(&'static str, String)> {
+    match ChecksumAlgorithm::parse(&checksum.algorithm) {
+        Some(algorithm) => vec![
+            (algorithm.header_name(), checksum.value.clone()),
+            (CHECKSUM_TYPE_HEADER, checksum_type(checksum).to_string()),
+        ],
+        None => vec![],
+    }
+}
+
+// 校验和类型，分片上传对象的组合校验和带有分片数后缀
+pub(crate) fn checksum_type(checksum: &ObjectChecksum) -> &'static str {
+    if checksum.value.contains('-') {
+        "COMPOSITE"
+    } else {
+        "FULL_OBJECT"
+    }
+}
+
+// 按算法拆分为 CRC32、CRC32C、SHA1、SHA256 四个字段，用于组装 XML 响应
+pub(crate) fn fields(
+    algorithm: Option<ChecksumAlgorithm>,
+    value: Option<String>,
+) -> [Option<String>; 4] {
+    let mut fields = [None, None, None, None];
+    if let (Some(algorithm), Some(value)) = (algorithm, value) {
+        let idx = ChecksumAlgorithm::ALL
+            .iter()
+            .position(|it| *it == algorithm)
+            .unwrap_or_default();
+        fields[idx] = Some(value);
+    }
+    fields
+}
+
+// 完成分片上传请求中为分片提供的校验和
+pub(crate) fn part_checksum(part: &PartETag, algorithm: ChecksumAlgorithm) -> Option<&String> {
+    match algorithm {
+        ChecksumAlgorithm::Crc32 => part.checksum_crc32.as_ref(),
+        ChecksumAlgorithm::Crc32c => part.checksum_crc32c.as_ref(),
+        ChecksumAlgorithm::Sha1 => part.checksum_sha1.as_ref(),
+        ChecksumAlgorithm::Sha256 => part.checksum_sha256.as_ref(),
+    }
+}
+
+pub(crate) fn bad_digest(algorithm: ChecksumAlgorithm) -> AppError {
+    AppError::s3(
+        400,
+        "BadDigest",
+        format!(
+            "The {} you specified did not match the calculated checksum.",
+            algorithm.header_name()
+        ),
+    )
+}
+
+fn invalid_algorithm() -> AppError {
+    AppError::s3(
+        400,
+        "InvalidRequest",
+        "Value for x-amz-checksum-algorithm header is invalid.",
+    )
+}
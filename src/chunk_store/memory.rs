@@ -0,0 +1,49 @@
+use super::ChunkStore;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+// 分块保存在内存中，节点重启后丢失，用于测试
+#[derive(Debug, Default)]
+pub struct MemoryChunkStore {
+    chunks: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+#[async_trait]
+impl ChunkStore for MemoryChunkStore {
+    async fn put(&self, name: &str, data: &[u8]) -> anyhow::Result<()> {
+        self.chunks
+            .write()
+            .unwrap()
+            .insert(name.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    async fn get(&self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.chunks.read().unwrap().get(name).cloned())
+    }
+
+    async fn exists(&self, name: &str) -> bool {
+        self.chunks.read().unwrap().contains_key(name)
+    }
+
+    async fn delete(&self, name: &str) -> anyhow::Result<()> {
+        self.chunks.write().unwrap().remove(name);
+        Ok(())
+    }
+
+    async fn list(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.chunks.read().unwrap().keys().cloned().collect())
+    }
+
+    async fn shards(&self, hash: &str) -> anyhow::Result<Vec<String>> {
+        let prefix = format!("{}.", hash);
+        let chunks = self.chunks.read().unwrap();
+        Ok(chunks
+            .range(prefix.clone()..)
+            .map(|(name, _)| name)
+            .take_while(|name| name.starts_with(&prefix))
+            .cloned()
+            .collect())
+    }
+}
@@ -0,0 +1,55 @@
+use async_trait::async_trait;
+use std::fmt::Debug;
+
+mod memory;
+mod pack;
+
+pub use memory::MemoryChunkStore;
+pub use pack::PackChunkStore;
+
+// 分块的持久化方式，名称为分块或纠删码分片的名称，数据为压缩(和加密)后保存的内容
+// 默认实现是数据目录(disk::Disks)，小分块可打包保存(PackChunkStore)，测试可使用内存实现
+// 每个节点在启动时创建自己的分块存储，经 ChunkGc 传给各组件
+#[async_trait]
+pub trait ChunkStore: Send + Sync + Debug {
+    // 保存分块，已存在时覆盖
+    async fn put(&self, name: &str, data: &[u8]) -> anyhow::Result<()>;
+
+    // 保存分块，同名分块已存在时跳过；分块名称由内容决定，内容相同
+    async fn put_if_absent(&self, name: &str, data: &[u8]) -> anyhow::Result<()> {
+        if !self.exists(name).await {
+            self.put(name, data).await?;
+        }
+        Ok(())
+    }
+
+    // 读取分块，不存在时返回 None
+    async fn get(&self, name: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    async fn exists(&self, name: &str) -> bool;
+
+    // 删除分块，不存在时忽略
+    async fn delete(&self, name: &str) -> anyhow::Result<()>;
+
+    // 列出全部分块和分片的名称
+    async fn list(&self) -> anyhow::Result<Vec<String>>;
+
+    // 列出分块的纠删码分片名称
+    async fn shards(&self, hash: &str) -> anyhow::Result<Vec<String>> {
+        let prefix = format!("{}.", hash);
+        let mut names = self.list().await?;
+        names.retain(|name| name.starts_with(&prefix));
+        Ok(names)
+    }
+
+    // 移走损坏的分块，返回隔离后的位置；不支持隔离时直接删除
+    async fn quarantine(&self, name: &str) -> anyhow::Result<Option<String>> {
+        self.delete(name).await?;
+        Ok(None)
+    }
+
+    // 回收已删除分块占用的空间，返回回收的字节数
+    async fn compact(&self) -> anyhow::Result<u64> {
+        Ok(0)
+    }
+}
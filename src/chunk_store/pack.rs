@@ -0,0 +1,439 @@
+use super::ChunkStore;
+use crate::util::durable::{self, Durability};
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+#[cfg(not(unix))]
+use std::io::{Read, Seek, SeekFrom};
+#[cfg(unix)]
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+// 单个包文件的大小上限，写满后换新的包文件
+const PACK_SIZE: u64 = 256 << 20;
+// 已删除的数据超过包文件大小的这一比例时整理
+const DEAD_RATIO: f64 = 0.5;
+// 索引的目录
+const INDEX_DIR: &str = "index";
+// 隔离损坏分块的目录
+const QUARANTINE_DIR: &str = "quarantine";
+// 索引项: 包文件编号(8) 数据偏移(8) 数据长度(4)
+const ENTRY_LEN: usize = 20;
+
+// 分块在包文件中的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    pack: u64,
+    offset: u64,
+    len: u32,
+}
+
+impl Entry {
+    fn encode(&self) -> [u8; ENTRY_LEN] {
+        let mut res = [0; ENTRY_LEN];
+        res[..8].copy_from_slice(&self.pack.to_be_bytes());
+        res[8..16].copy_from_slice(&self.offset.to_be_bytes());
+        res[16..].copy_from_slice(&self.len.to_be_bytes());
+        res
+    }
+
+    fn decode(value: &[u8]) -> anyhow::Result<Self> {
+        let value: &[u8; ENTRY_LEN] = value.try_into().context("索引项格式错误")?;
+        Ok(Entry {
+            pack: u64::from_be_bytes(value[..8].try_into().unwrap()),
+            offset: u64::from_be_bytes(value[8..16].try_into().unwrap()),
+            len: u32::from_be_bytes(value[16..].try_into().unwrap()),
+        })
+    }
+}
+
+// 记录在包文件中占用的字节数: 名称长度(2) 名称 数据长度(4) 数据
+fn record_len(name: &str, len: u32) -> u64 {
+    (2 + name.len() + 4) as u64 + len as u64
+}
+
+fn pack_name(id: u64) -> String {
+    format!("{:016}.pack", id)
+}
+
+// 从包文件的指定位置读满缓冲区
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    file.read_exact_at(buf, offset)
+}
+
+// 每次读取都单独打开包文件，移动读取位置不影响其他读取
+#[cfg(not(unix))]
+fn read_exact_at(mut file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+// 包文件的大小和其中已删除的字节数
+#[derive(Debug, Default)]
+struct PackStat {
+    size: u64,
+    dead: u64,
+}
+
+#[derive(Debug)]
+struct Active {
+    id: u64,
+    file: File,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    // 正在追加的包文件，首次写入时创建
+    active: Option<Active>,
+    next_id: u64,
+    packs: BTreeMap<u64, PackStat>,
+}
+
+// 小于阈值的分块追加到包文件中，索引记录分块所在的包文件和位置，
+// 其余分块保存在内层的分块存储中，避免大量小分块耗尽 inode
+// 包文件只追加，删除分块只删除索引项，由 compact 整理
+#[derive(Debug)]
+pub struct PackChunkStore {
+    threshold: usize,
+    inner: &'static dyn ChunkStore,
+    packs: Arc<Packs>,
+}
+
+// 包文件和索引，读写包文件在阻塞线程池中进行
+#[derive(Debug)]
+struct Packs {
+    dir: PathBuf,
+    index: sled::Db,
+    state: Mutex<State>,
+    durability: Durability,
+    // 读取持有读锁，删除包文件持有写锁，读取期间包文件不会被整理删除
+    files: RwLock<()>,
+}
+
+impl PackChunkStore {
+    // 打开包文件目录，按索引统计各包文件中已删除的字节数
+    // 上次未写完的记录没有索引项，计为已删除；
+    // 指向不存在的包文件或超出包文件末尾的索引项(如包文件被截断)无法读取，删除
+    pub fn open(
+        dir: impl AsRef<Path>,
+        threshold: usize,
+        inner: &'static dyn ChunkStore,
+        durability: Durability,
+    ) -> anyhow::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).with_context(|| format!("创建包文件目录 {:?} 失败", dir))?;
+        let index = sled::open(dir.join(INDEX_DIR))
+            .with_context(|| format!("打开包文件索引 {:?} 失败", dir))?;
+        let mut packs = BTreeMap::new();
+        for entry in fs::read_dir(&dir)?.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(id) = name.strip_suffix(".pack") else {
+                continue;
+            };
+            let Ok(id) = id.parse::<u64>() else {
+                continue;
+            };
+            let size = entry.metadata()?.len();
+            packs.insert(id, PackStat { size, dead: size });
+        }
+        let mut lost = Vec::new();
+        for item in index.iter() {
+            let (key, value) = item?;
+            let entry = Entry::decode(&value)?;
+            let name = String::from_utf8_lossy(&key);
+            match packs.get_mut(&entry.pack) {
+                Some(stat) if entry.offset + entry.len as u64 <= stat.size => {
+                    stat.dead = stat.dead.saturating_sub(record_len(&name, entry.len));
+                }
+                Some(_) => {
+                    warn!("分块 {} 超出包文件 {} 的末尾，删除索引项", name, entry.pack);
+                    lost.push(key);
+                }
+                None => {
+                    warn!(
+                        "分块 {} 所在的包文件 {} 不存在，删除索引项",
+                        name, entry.pack
+                    );
+                    lost.push(key);
+                }
+            }
+        }
+        for key in lost {
+            index.remove(key)?;
+        }
+        index.flush()?;
+        let next_id = packs.keys().next_back().map_or(0, |id| id + 1);
+        info!("包文件目录 {:?}，{} 个包文件", dir, packs.len());
+        Ok(PackChunkStore {
+            threshold,
+            inner,
+            packs: Arc::new(Packs {
+                dir,
+                index,
+                state: Mutex::new(State {
+                    active: None,
+                    next_id,
+                    packs,
+                }),
+                durability,
+                files: RwLock::new(()),
+            }),
+        })
+    }
+
+    // 在阻塞线程池中读写包文件，不阻塞处理请求的线程
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Packs) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let packs = self.packs.clone();
+        tokio::task::spawn_blocking(move || f(&packs))
+            .await
+            .context("读写包文件的任务异常退出")?
+    }
+}
+
+impl Packs {
+    fn entry(&self, name: &str) -> anyhow::Result<Option<Entry>> {
+        match self.index.get(name)? {
+            Some(value) => Ok(Some(Entry::decode(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    // 追加一条记录，当前包文件写满时换新的包文件
+    fn append(&self, name: &str, data: &[u8]) -> anyhow::Result<Entry> {
+        let len = u32::try_from(data.len()).context("分块过大")?;
+        let name_len = u16::try_from(name.len()).context("分块名称过长")?;
+        let size = record_len(name, len);
+        let mut state = self.state.lock().unwrap();
+        let full = match &state.active {
+            Some(active) => state.packs[&active.id].size + size > PACK_SIZE,
+            None => true,
+        };
+        if full {
+            let id = state.next_id;
+            let file = OpenOptions::new()
+                .create_new(true)
+                .append(true)
+                .open(self.dir.join(pack_name(id)))?;
+            if self.durability == Durability::Full {
+                durable::sync_dir(&self.dir)?;
+            }
+            state.next_id += 1;
+            state.packs.insert(id, PackStat::default());
+            state.active = Some(Active { id, file });
+        }
+        let State { active, packs, .. } = &mut *state;
+        let active = active.as_mut().unwrap();
+        let stat = packs.get_mut(&active.id).unwrap();
+        let mut record = Vec::with_capacity(size as usize);
+        record.extend_from_slice(&name_len.to_be_bytes());
+        record.extend_from_slice(name.as_bytes());
+        record.extend_from_slice(&len.to_be_bytes());
+        record.extend_from_slice(data);
+        // 记录落盘后才写入索引项，断电后索引不会指向未写完的记录
+        let written = active.file.write_all(&record).and_then(|()| {
+            match self.durability >= Durability::Data {
+                true => active.file.sync_data(),
+                false => Ok(()),
+            }
+        });
+        if let Err(err) = written {
+            // 写了一半的记录无法复用，后续写入换新的包文件
+            stat.size = PACK_SIZE;
+            stat.dead = PACK_SIZE;
+            return Err(err.into());
+        }
+        let entry = Entry {
+            pack: active.id,
+            offset: stat.size + size - len as u64,
+            len,
+        };
+        stat.size += size;
+        Ok(entry)
+    }
+
+    fn mark_dead(&self, name: &str, entry: Entry) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(stat) = state.packs.get_mut(&entry.pack) {
+            stat.dead += record_len(name, entry.len);
+        }
+    }
+
+    // 删除索引项，返回删除前的位置
+    fn remove_entry(&self, name: &str) -> anyhow::Result<Option<Entry>> {
+        let Some(value) = self.index.remove(name)? else {
+            return Ok(None);
+        };
+        let entry = Entry::decode(&value)?;
+        self.mark_dead(name, entry);
+        Ok(Some(entry))
+    }
+
+    fn read_entry(&self, entry: Entry) -> io::Result<Vec<u8>> {
+        let file = File::open(self.dir.join(pack_name(entry.pack)))?;
+        let mut data = vec![0; entry.len as usize];
+        read_exact_at(&file, &mut data, entry.offset)?;
+        Ok(data)
+    }
+
+    // 读取包文件中的分块，不在包文件中时返回 None
+    fn read(&self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let _guard = self.files.read().unwrap();
+        let Some(entry) = self.entry(name)? else {
+            return Ok(None);
+        };
+        let data = self
+            .read_entry(entry)
+            .with_context(|| format!("读取包文件 {} 中的分块 {} 失败", entry.pack, name))?;
+        Ok(Some(data))
+    }
+
+    // 把仍有索引项的分块复制到当前包文件后删除旧的包文件
+    // 复制期间分块被删除或覆盖时索引项已改变，复制的记录计为已删除
+    fn compact_pack(&self, id: u64) -> anyhow::Result<u64> {
+        let mut live = Vec::new();
+        for item in self.index.iter() {
+            let (name, value) = item?;
+            let entry = Entry::decode(&value)?;
+            if entry.pack == id {
+                live.push((String::from_utf8_lossy(&name).to_string(), entry));
+            }
+        }
+        for (name, entry) in live {
+            let data = self.read_entry(entry)?;
+            let moved = self.append(&name, &data)?;
+            let swapped = self.index.compare_and_swap(
+                name.as_bytes(),
+                Some(&entry.encode()[..]),
+                Some(&moved.encode()[..]),
+            )?;
+            if swapped.is_err() {
+                self.mark_dead(&name, moved);
+            }
+        }
+        // 删除旧的包文件前，复制的记录和新的索引项必须已落盘
+        if let Some(active) = &self.state.lock().unwrap().active {
+            active.file.sync_data()?;
+        }
+        self.index.flush()?;
+        let _guard = self.files.write().unwrap();
+        fs::remove_file(self.dir.join(pack_name(id)))?;
+        if self.durability == Durability::Full {
+            durable::sync_dir(&self.dir)?;
+        }
+        let stat = self.state.lock().unwrap().packs.remove(&id);
+        Ok(stat.map_or(0, |stat| stat.dead))
+    }
+}
+
+#[async_trait]
+impl ChunkStore for PackChunkStore {
+    async fn put(&self, name: &str, data: &[u8]) -> anyhow::Result<()> {
+        if data.len() >= self.threshold {
+            self.inner.put(name, data).await?;
+            self.packs.remove_entry(name)?;
+            return Ok(());
+        }
+        let (key, data) = (name.to_string(), data.to_vec());
+        let entry = self
+            .blocking(move |packs| packs.append(&key, &data))
+            .await?;
+        let packs = &self.packs;
+        if let Some(old) = packs.index.insert(name, &entry.encode()[..])? {
+            packs.mark_dead(name, Entry::decode(&old)?);
+        }
+        if packs.durability == Durability::Full {
+            packs.index.flush_async().await?;
+        }
+        Ok(())
+    }
+
+    async fn get(&self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let key = name.to_string();
+        match self.blocking(move |packs| packs.read(&key)).await? {
+            Some(data) => Ok(Some(data)),
+            None => self.inner.get(name).await,
+        }
+    }
+
+    async fn exists(&self, name: &str) -> bool {
+        matches!(self.packs.index.contains_key(name), Ok(true)) || self.inner.exists(name).await
+    }
+
+    async fn delete(&self, name: &str) -> anyhow::Result<()> {
+        self.packs.remove_entry(name)?;
+        self.inner.delete(name).await
+    }
+
+    async fn list(&self) -> anyhow::Result<Vec<String>> {
+        let mut names = self.inner.list().await?;
+        for name in self.packs.index.iter().keys() {
+            names.push(String::from_utf8_lossy(&name?).to_string());
+        }
+        Ok(names)
+    }
+
+    async fn shards(&self, hash: &str) -> anyhow::Result<Vec<String>> {
+        let mut names = self.inner.shards(hash).await?;
+        for name in self.packs.index.scan_prefix(format!("{}.", hash)).keys() {
+            names.push(String::from_utf8_lossy(&name?).to_string());
+        }
+        Ok(names)
+    }
+
+    // 包文件中的分块复制到隔离目录后删除索引项
+    async fn quarantine(&self, name: &str) -> anyhow::Result<Option<String>> {
+        let key = name.to_string();
+        let entry = self
+            .blocking(move |packs| {
+                let _guard = packs.files.read().unwrap();
+                Ok(packs
+                    .entry(&key)?
+                    .map(|entry| (entry, packs.read_entry(entry))))
+            })
+            .await?;
+        let Some((entry, data)) = entry else {
+            return self.inner.quarantine(name).await;
+        };
+        let quarantine = self.packs.dir.join(QUARANTINE_DIR);
+        tokio::fs::create_dir_all(&quarantine).await?;
+        let quarantine = quarantine.join(name);
+        match data {
+            Ok(data) => durable::write_async(&quarantine, &data, self.packs.durability).await?,
+            Err(err) => warn!("读取包文件 {} 中的分块 {} 失败: {}", entry.pack, name, err),
+        }
+        self.packs.remove_entry(name)?;
+        Ok(Some(quarantine.display().to_string()))
+    }
+
+    // 整理已删除数据过多的包文件，不整理正在追加的包文件
+    async fn compact(&self) -> anyhow::Result<u64> {
+        let packs: Vec<u64> = {
+            let state = self.packs.state.lock().unwrap();
+            let active = state.active.as_ref().map(|active| active.id);
+            state
+                .packs
+                .iter()
+                .filter(|(id, stat)| {
+                    Some(**id) != active && stat.dead as f64 >= stat.size as f64 * DEAD_RATIO
+                })
+                .map(|(id, _)| *id)
+                .collect()
+        };
+        let mut reclaimed = 0;
+        for id in packs {
+            reclaimed += self
+                .blocking(move |packs| packs.compact_pack(id))
+                .await
+                .map_err(|err| anyhow!("整理包文件 {} 失败: {}", id, err))?;
+        }
+        Ok(reclaimed)
+    }
+}
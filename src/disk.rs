@@ -0,0 +1,456 @@
+use crate::chunk_store::ChunkStore;
+use crate::fs;
+use crate::gc::ChunkGc;
+use crate::layout::Layout;
+use crate::util::durable::{self, Durability};
+use crate::util::codec;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use log::{error, info, warn};
+use memmap2::Mmap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// 容量按 GiB 计为权重
+const GIB: u64 = 1 << 30;
+// 已满的磁盘剩余空间恢复到这个大小后重新写入
+const MIN_FREE_SPACE: u64 = 256 << 20;
+// 隔离损坏分块的目录，位于各数据目录下
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+// 保存分块的数据目录，通常每个目录是一块磁盘
+#[derive(Debug)]
+pub struct DataDir {
+    pub path: PathBuf,
+    // 权重与磁盘容量成正比
+    pub weight: u64,
+    online: AtomicBool,
+    // 空间已满，不再写入，已有的分块仍可读取和删除
+    full: AtomicBool,
+}
+
+impl DataDir {
+    pub fn new(path: impl Into<PathBuf>, weight: u64) -> Self {
+        DataDir {
+            path: path.into(),
+            weight: weight.max(1),
+            online: AtomicBool::new(true),
+            full: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.online.load(Ordering::Relaxed)
+    }
+
+    pub fn set_online(&self, online: bool) {
+        self.online.store(online, Ordering::Relaxed);
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.full.load(Ordering::Relaxed)
+    }
+
+    pub fn set_full(&self, full: bool) {
+        self.full.store(full, Ordering::Relaxed);
+    }
+
+    // 在线且未满的目录才写入新的分块
+    pub fn is_writable(&self) -> bool {
+        self.is_online() && !self.is_full()
+    }
+
+    // 分块或分片在本目录下的路径
+    pub fn chunk_path(&self, name: &str) -> PathBuf {
+        self.path.join(relative_path(name))
+    }
+
+    // 读写出错时标记磁盘离线，文件不存在不算磁盘故障
+    // 空间不足时只标记为已满，磁盘上的分块仍可读取，回收也能继续释放空间
+    pub(crate) fn check<T>(&self, result: io::Result<T>) -> io::Result<T> {
+        match &result {
+            Err(err) if err.kind() == io::ErrorKind::StorageFull => {
+                let was_full = self.full.swap(true, Ordering::Relaxed);
+                if !was_full {
+                    warn!(
+                        "数据目录 {} 空间已满，停止写入: {}",
+                        self.path.display(),
+                        err
+                    );
+                }
+            }
+            Err(err) if err.kind() != io::ErrorKind::NotFound && self.is_online() => {
+                warn!(
+                    "数据目录 {} 读写失败，标记为离线: {}",
+                    self.path.display(),
+                    err
+                );
+                self.set_online(false);
+            }
+            _ => {}
+        }
+        result
+    }
+
+    // 移到本目录下的隔离目录
+    async fn quarantine(&self, name: &str, path: &Path) -> io::Result<PathBuf> {
+        let quarantine = self.path.join(QUARANTINE_DIR);
+        tokio::fs::create_dir_all(&quarantine).await?;
+        let quarantine = quarantine.join(name);
+        self.check(tokio::fs::rename(path, &quarantine).await)?;
+        Ok(quarantine)
+    }
+
+    // 检测离线的磁盘是否恢复，只读取目录，磁盘已满时也能通过
+    fn probe(&self) -> io::Result<()> {
+        if !std::fs::metadata(&self.path)?.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "数据目录不存在"));
+        }
+        std::fs::read_dir(&self.path)?.next().transpose()?;
+        Ok(())
+    }
+
+    // 已满的磁盘是否已释放出足够的空间
+    fn has_free_space(&self) -> bool {
+        fs2::available_space(&self.path).is_ok_and(|free| free >= MIN_FREE_SPACE)
+    }
+}
+
+// 节点的全部数据目录
+#[derive(Debug)]
+pub struct Disks {
+    dirs: Vec<DataDir>,
+    durability: Durability,
+}
+
+impl Disks {
+    pub fn new(dirs: Vec<DataDir>) -> Self {
+        Disks {
+            dirs,
+            durability: Durability::default(),
+        }
+    }
+
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    pub fn dirs(&self) -> &[DataDir] {
+        &self.dirs
+    }
+
+    pub fn online(&self) -> impl Iterator<Item = &DataDir> {
+        self.dirs.iter().filter(|dir| dir.is_online())
+    }
+
+    // 按加权最高随机权重(rendezvous)哈希排列全部数据目录，
+    // 增加目录时只有改排到新目录的分块需要移动
+    pub fn ranked(&self, name: &str) -> Vec<&DataDir> {
+        let hash = fs::chunk_of(name);
+        let mut dirs: Vec<(f64, &DataDir)> = self
+            .dirs
+            .iter()
+            .map(|dir| (score(dir, hash), dir))
+            .collect();
+        dirs.sort_by(|a, b| b.0.total_cmp(&a.0));
+        dirs.into_iter().map(|(_, dir)| dir).collect()
+    }
+
+    // 分块应写入的数据目录，即排名最高的可写目录，同一分块的分片在同一目录下
+    pub fn preferred(&self, name: &str) -> Option<&DataDir> {
+        self.ranked(name).into_iter().find(|dir| dir.is_writable())
+    }
+
+    // 查找分块所在的在线数据目录，磁盘变化后未迁移的分块可能不在首选目录
+    pub fn locate(&self, name: &str) -> Option<(&DataDir, PathBuf)> {
+        self.ranked(name)
+            .into_iter()
+            .filter(|dir| dir.is_online())
+            .map(|dir| (dir, dir.chunk_path(name)))
+            .find(|(_, path)| path.exists())
+    }
+}
+
+// 分块按名称保存在数据目录下，见 relative_path
+#[async_trait]
+impl ChunkStore for Disks {
+    // 写入首选的数据目录，写入失败时标记离线或已满，改写到下一个可写目录
+    async fn put(&self, name: &str, data: &[u8]) -> anyhow::Result<()> {
+        while let Some(dir) = self.preferred(name) {
+            let path = dir.chunk_path(name);
+            let result = match tokio::fs::create_dir_all(path.parent().unwrap()).await {
+                Ok(()) => durable::write_async(path, data, self.durability).await,
+                Err(err) => Err(err),
+            };
+            match dir.check(result) {
+                Ok(()) => return Ok(()),
+                Err(_) if dir.is_writable() => dir.set_online(false),
+                Err(_) => {}
+            }
+        }
+        Err(anyhow!("没有可写入的数据目录"))
+    }
+
+    async fn get(&self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some((dir, path)) = self.locate(name) else {
+            return Ok(None);
+        };
+        match dir.check(mmap_read_file(path).await) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn exists(&self, name: &str) -> bool {
+        self.locate(name).is_some()
+    }
+
+    // 删除各在线数据目录下的同名文件
+    async fn delete(&self, name: &str) -> anyhow::Result<()> {
+        for dir in self.online() {
+            if let Err(err) = tokio::fs::remove_file(dir.chunk_path(name)).await {
+                if err.kind() != io::ErrorKind::NotFound {
+                    dir.check(Err(err))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn list(&self) -> anyhow::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for dir in self.online() {
+            names.extend(list_chunk_files(&dir.path)?);
+        }
+        Ok(names)
+    }
+
+    // 分片与所属分块在同一目录下，只需列出这一目录
+    async fn shards(&self, hash: &str) -> anyhow::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for dir in self.online() {
+            let path = dir.chunk_path(hash);
+            let entries = match std::fs::read_dir(path.parent().unwrap()) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(dir.check(Err(err))?),
+            };
+            names.extend(
+                entries
+                    .flatten()
+                    .map(|entry| format!("{}{}", &hash[..3], entry.file_name().to_string_lossy()))
+                    .filter(|name| {
+                        fs::is_stored_name(name) && fs::chunk_of(name) == hash && name != hash
+                    }),
+            );
+        }
+        Ok(names)
+    }
+
+    // 移到所在数据目录下的隔离目录
+    async fn quarantine(&self, name: &str) -> anyhow::Result<Option<String>> {
+        let Some((dir, path)) = self.locate(name) else {
+            return Ok(None);
+        };
+        let quarantine = dir.quarantine(name, &path).await?;
+        Ok(Some(quarantine.display().to_string()))
+    }
+}
+
+// 列出一个数据目录下的全部分块和分片文件名称，忽略迁移中的临时文件和隔离的分块
+pub(crate) fn list_chunk_files(root: &Path) -> anyhow::Result<Vec<String>> {
+    let mut names = Vec::new();
+    if !root.is_dir() {
+        return Ok(names);
+    }
+    for prefix in std::fs::read_dir(root)?.flatten() {
+        if !prefix.path().is_dir() {
+            continue;
+        }
+        for subprefix in std::fs::read_dir(prefix.path())?.flatten() {
+            if !subprefix.path().is_dir() {
+                continue;
+            }
+            for suffix in std::fs::read_dir(subprefix.path())?.flatten() {
+                let name = format!(
+                    "{}{}{}",
+                    prefix.file_name().to_string_lossy(),
+                    subprefix.file_name().to_string_lossy(),
+                    suffix.file_name().to_string_lossy()
+                );
+                if fs::is_stored_name(&name) {
+                    names.push(name);
+                }
+            }
+        }
+    }
+    Ok(names)
+}
+
+async fn mmap_read_file(p: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    let file = tokio::fs::File::open(p).await?;
+    let mmap = unsafe { Mmap::map(&file)? };
+    Ok(mmap[..].to_vec())
+}
+
+// 分块在数据目录下的相对路径
+pub fn relative_path(name: &str) -> PathBuf {
+    PathBuf::from(&name[0..1])
+        .join(&name[1..3])
+        .join(&name[3..])
+}
+
+// 加权随机权重：-weight / ln(u)，u 为目录和分块名称哈希到 (0, 1] 的值
+fn score(dir: &DataDir, hash: &str) -> f64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for byte in dir
+        .path
+        .to_string_lossy()
+        .bytes()
+        .chain([0])
+        .chain(hash.bytes())
+    {
+        h ^= byte as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    // splitmix64 混合，使相近的输入分布均匀
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+    h ^= h >> 31;
+    let u = ((h >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+    -(dir.weight as f64) / u.ln()
+}
+
+// 打开节点的数据目录，权重取各目录所在磁盘的容量，目录见 Layout::chunk_dirs
+pub fn open(layout: &Layout) -> anyhow::Result<Disks> {
+    let mut dirs = Vec::new();
+    for path in &layout.chunk_dirs {
+        std::fs::create_dir_all(path)
+            .with_context(|| format!("创建数据目录 {} 失败", path.display()))?;
+        let capacity = fs2::total_space(path)
+            .with_context(|| format!("读取数据目录 {} 的容量失败", path.display()))?;
+        info!("数据目录 {}，容量 {} GiB", path.display(), capacity / GIB);
+        dirs.push(DataDir::new(path.clone(), capacity / GIB));
+    }
+    Ok(Disks::new(dirs).with_durability(layout.durability))
+}
+
+// 删除写入中断遗留的临时文件，并重新校验这些写入的目标分块
+// 旧版本直接覆盖写入分块，中断时分块可能不完整；校验失败的分块移到隔离目录，由巡检从其他节点修复
+// 加密分块和纠删码分片无法只凭名称校验，跳过
+pub(crate) async fn recover(disks: &Disks) -> anyhow::Result<()> {
+    for dir in disks.dirs() {
+        let targets = durable::clean(&dir.path)
+            .with_context(|| format!("清理数据目录 {} 的临时文件失败", dir.path.display()))?;
+        if !targets.is_empty() {
+            info!(
+                "数据目录 {} 清理了 {} 个未写完的临时文件",
+                dir.path.display(),
+                targets.len()
+            );
+        }
+        for path in targets {
+            let Some(name) = chunk_name(&dir.path, &path) else {
+                continue;
+            };
+            let Ok(data) = tokio::fs::read(&path).await else {
+                continue;
+            };
+            if !codec::is_encoded(&data) {
+                continue;
+            }
+            if let Err(err) = fs::verify_chunk(&name, &data) {
+                let quarantine = dir.quarantine(&name, &path).await?;
+                warn!(
+                    "分块 {} 未完整写入，隔离到 {}: {}",
+                    name,
+                    quarantine.display(),
+                    err
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+// 数据目录下分块文件的名称，见 relative_path
+fn chunk_name(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let name: String = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    fs::is_chunk_name(&name).then_some(name)
+}
+
+// 把不在首选目录的分块移到首选目录，返回移动的数量
+// 先复制再删除，移动过程中分块始终可读；在回收锁内移动，不与写入和回收冲突
+async fn rebalance(disks: &Disks, gc: &ChunkGc) -> anyhow::Result<usize> {
+    let mut moved = 0;
+    for dir in disks.online() {
+        for name in list_chunk_files(&dir.path)? {
+            let Some(target) = disks.preferred(&name) else {
+                continue;
+            };
+            if std::ptr::eq(target, dir) {
+                continue;
+            }
+            let _guard = gc.lock.lock().await;
+            let source = dir.chunk_path(&name);
+            if !source.exists() {
+                continue;
+            }
+            let dest = target.chunk_path(&name);
+            if !dest.exists() {
+                target.check(copy_file(&source, &dest, disks.durability).await)?;
+            }
+            dir.check(tokio::fs::remove_file(&source).await)?;
+            moved += 1;
+        }
+    }
+    Ok(moved)
+}
+
+async fn copy_file(source: &Path, dest: &Path, durability: Durability) -> io::Result<()> {
+    tokio::fs::create_dir_all(dest.parent().unwrap()).await?;
+    durable::copy(source, dest, durability).await
+}
+
+// 后台检测离线和已满的磁盘，磁盘恢复或增加后迁移分块
+pub(crate) async fn run(disks: &'static Disks, gc: ChunkGc, interval: u64) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval.max(1)));
+    let mut balanced = false;
+    loop {
+        ticker.tick().await;
+        for dir in disks.dirs().iter().filter(|dir| !dir.is_online()) {
+            if dir.probe().is_ok() {
+                info!("数据目录 {} 已恢复", dir.path.display());
+                dir.set_online(true);
+                balanced = false;
+            }
+        }
+        for dir in disks.dirs().iter().filter(|dir| dir.is_full()) {
+            if dir.has_free_space() {
+                info!("数据目录 {} 已释放空间，恢复写入", dir.path.display());
+                dir.set_full(false);
+                balanced = false;
+            }
+        }
+        if balanced {
+            continue;
+        }
+        match rebalance(disks, &gc).await {
+            Ok(moved) => {
+                balanced = true;
+                if moved > 0 {
+                    info!("迁移 {} 个分块到首选数据目录", moved);
+                }
+            }
+            Err(err) => error!("迁移分块失败: {}", err),
+        }
+    }
+}
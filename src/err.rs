@@ -1,4 +1,8 @@
+use crate::model::ErrorResp;
+use ntex::http::StatusCode;
 use ntex::web;
+use ntex::web::HttpResponse;
+use quick_xml::se::to_string;
 use thiserror::Error;
 
 // 自定义错误类型
@@ -11,6 +15,50 @@ pub enum AppError {
     NotFound,
     #[error("bad request")]
     BadRequest,
+    // 以 S3 错误码返回给客户端的错误
+    #[error("{code}: {message}")]
+    S3Error {
+        status: u16,
+        code: &'static str,
+        message: String,
+    },
 }
 
-impl web::error::WebResponseError for AppError {}
+impl AppError {
+    pub(crate) fn s3(status: u16, code: &'static str, message: impl Into<String>) -> Self {
+        AppError::S3Error {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl web::error::WebResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::S3Error { status, .. } => {
+                StatusCode::from_u16(*status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self, _: &web::HttpRequest) -> HttpResponse {
+        match self {
+            AppError::S3Error { code, message, .. } => {
+                let resp = ErrorResp {
+                    code: code.to_string(),
+                    message: message.clone(),
+                };
+                let xml = to_string(&resp).unwrap_or_default();
+                HttpResponse::build(self.status_code())
+                    .content_type("application/xml")
+                    .body(xml)
+            }
+            _ => HttpResponse::build(self.status_code())
+                .content_type("text/plain; charset=utf-8")
+                .body(self.to_string()),
+        }
+    }
+}
@@ -1,21 +1,36 @@
+use crate::cache::ChunkCache;
+use crate::chunk_store::ChunkStore;
+use crate::metrics;
+use crate::raft::network::chunk;
+use crate::raft::Node;
+use crate::util::chunker::ChunkingConfig;
+use crate::util::codec::{self, CompressionConfig};
 use crate::util::cry;
-use anyhow::Context;
+use crate::util::durable::{self, Durability};
+use crate::util::erasure::ErasureCode;
+use crate::util::keyring::Keyring;
+use anyhow::{anyhow, Context};
 use chrono::{DateTime, Utc};
-use futures::Stream;
+use futures::future::LocalBoxFuture;
+use futures::stream::FuturesOrdered;
+use futures::{ready, FutureExt, Stream, StreamExt};
 use hex::ToHex;
-use memmap2::{Mmap, MmapOptions};
-use ntex::util::{Bytes, BytesMut};
+use log::{error, warn};
+use ntex::util::Bytes;
 use rkyv::{Archive, Deserialize, Infallible, Serialize};
 use sha2::{Digest, Sha256};
-use std::fs::{self, File};
-use std::io::{self, Cursor, Read};
-use std::path::{Path, PathBuf};
-use tokio::fs::OpenOptions;
-use zstd::stream::read::Decoder;
-use zstd::zstd_safe::WriteBuf;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// 元数据明文的头部: 魔数 + 结构版本，其后为该版本结构的 rkyv 数据
+// 魔数以 0xff 开头，不会出现在旧版本无头部数据开头的字符串中
+pub const METADATA_MAGIC: &[u8; 7] = b"\xffS3META";
+// 当前 Metadata 结构的版本，结构变化时把当前结构改为 MetadataVn 并递增版本
+pub const METADATA_VERSION: u8 = 5;
 
 // 定义元数据结构
-#[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[derive(Archive, Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[archive(compare(PartialEq), check_bytes)]
 #[archive_attr(derive(Debug))]
 pub struct Metadata {
@@ -24,51 +39,369 @@ pub struct Metadata {
     pub file_type: String,
     pub time: DateTime<Utc>,
     pub chunks: Vec<String>,
+    pub encryption: Option<ObjectEncryption>,
+    pub checksum: Option<ObjectChecksum>,
+    // 分片上传对象的各分片信息，普通对象为空
+    pub parts: Vec<ObjectPart>,
+    // 通过网站访问对象时重定向到的地址(x-amz-website-redirect-location)
+    pub website_redirect_location: Option<String>,
+    // 各分块的明文长度，与 chunks 一一对应；旧版本保存的对象为空，按 chunk_lengths 推算
+    pub chunk_sizes: Vec<u64>,
+    // 小对象的数据直接保存在元数据中，此时 chunks 为空
+    pub inline: Option<InlineChunk>,
 }
 
-// 定义元数据存储路径前缀
-const PATH_PREFIX: &str = "data/file";
+// 保存在元数据中的小对象数据，已压缩(和加密)，名称与保存为单个分块时相同
+#[derive(
+    Archive, Deserialize, Serialize, serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq,
+)]
+#[archive(compare(PartialEq), check_bytes)]
+#[archive_attr(derive(Debug))]
+pub struct InlineChunk {
+    pub name: String,
+    pub data: Vec<u8>,
+}
 
-// 将sh256值解析为hash路径
-pub(crate) fn path_from_hash(hash: &str) -> PathBuf {
-    let hash_prefix = &hash[0..1];
-    let hash_subprefix = &hash[1..3];
-    let hash_suffix = &hash[3..];
+// 对象的附加校验和(x-amz-checksum-*)，分片上传对象为组合校验和，形如 {base64}-{分片数}
+#[derive(
+    Archive, Deserialize, Serialize, serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq,
+)]
+#[archive(compare(PartialEq), check_bytes)]
+#[archive_attr(derive(Debug))]
+pub struct ObjectChecksum {
+    pub algorithm: String,
+    pub value: String,
+}
 
-    PathBuf::from(PATH_PREFIX)
-        .join(hash_prefix)
-        .join(hash_subprefix)
-        .join(hash_suffix)
+// 分片上传对象的分片
+#[derive(
+    Archive, Deserialize, Serialize, serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq,
+)]
+#[archive(compare(PartialEq), check_bytes)]
+#[archive_attr(derive(Debug))]
+pub struct ObjectPart {
+    pub part_number: u32,
+    pub size: u64,
+    pub etag: String,
+    pub checksum: Option<String>,
 }
 
-#[allow(dead_code)]
-async fn mmap_read_file(p: impl AsRef<Path>) -> io::Result<Vec<u8>> {
-    let file = tokio::fs::File::open(p).await?;
-    let mmap = unsafe { Mmap::map(&file)? };
-    Ok((&mmap[..]).to_vec())
+// 对象的服务端加密信息，数据密钥由节点主密钥(SSE-S3)或客户密钥(SSE-C)包装后保存
+#[derive(
+    Archive, Deserialize, Serialize, serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq,
+)]
+#[archive(compare(PartialEq), check_bytes)]
+#[archive_attr(derive(Debug))]
+pub struct ObjectEncryption {
+    pub algorithm: String,
+    pub wrapped_key: Vec<u8>,
+    pub customer_key: Option<CustomerKeyCheck>,
 }
 
-async fn mmap_write_file(p: impl AsRef<Path>, content: &[u8]) -> io::Result<()> {
-    let file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(&p)
-        .await?;
-    file.set_len(content.len() as u64).await?;
+// SSE-C 客户密钥的加盐 HMAC，只用于校验请求中提供的密钥，不能还原密钥
+#[derive(
+    Archive, Deserialize, Serialize, serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq,
+)]
+#[archive(compare(PartialEq), check_bytes)]
+#[archive_attr(derive(Debug))]
+pub struct CustomerKeyCheck {
+    pub salt: Vec<u8>,
+    pub hmac: Vec<u8>,
+}
 
-    let mut mmap = unsafe { MmapOptions::new().map_mut(&file).unwrap() };
+// 旧版本元数据结构，仅用于读取加密功能上线前保存的元数据
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(compare(PartialEq), check_bytes)]
+#[archive_attr(derive(Debug))]
+struct MetadataV0 {
+    name: String,
+    size: u64,
+    file_type: String,
+    time: DateTime<Utc>,
+    chunks: Vec<String>,
+}
 
-    mmap.copy_from_slice(content);
-    Ok(())
+impl From<MetadataV0> for Metadata {
+    fn from(legacy: MetadataV0) -> Self {
+        Metadata {
+            name: legacy.name,
+            size: legacy.size,
+            file_type: legacy.file_type,
+            time: legacy.time,
+            chunks: legacy.chunks,
+            encryption: None,
+            checksum: None,
+            parts: vec![],
+            website_redirect_location: None,
+            chunk_sizes: vec![],
+            inline: None,
+        }
+    }
 }
 
-// 保存文件
-pub(crate) async fn save_file(hash_code: &str, data: &[u8]) -> anyhow::Result<()> {
-    let file_path = path_from_hash(hash_code);
-    tokio::fs::create_dir_all(file_path.parent().unwrap()).await?;
-    mmap_write_file(file_path, data).await?;
-    Ok(())
+// 旧版本元数据结构，仅用于读取校验和功能上线前保存的元数据
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(compare(PartialEq), check_bytes)]
+#[archive_attr(derive(Debug))]
+struct MetadataV1 {
+    name: String,
+    size: u64,
+    file_type: String,
+    time: DateTime<Utc>,
+    chunks: Vec<String>,
+    encryption: Option<ObjectEncryption>,
+}
+
+impl From<MetadataV1> for Metadata {
+    fn from(legacy: MetadataV1) -> Self {
+        Metadata {
+            name: legacy.name,
+            size: legacy.size,
+            file_type: legacy.file_type,
+            time: legacy.time,
+            chunks: legacy.chunks,
+            encryption: legacy.encryption,
+            checksum: None,
+            parts: vec![],
+            website_redirect_location: None,
+            chunk_sizes: vec![],
+            inline: None,
+        }
+    }
+}
+
+// 旧版本元数据结构，仅用于读取网站重定向功能上线前保存的元数据
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(compare(PartialEq), check_bytes)]
+#[archive_attr(derive(Debug))]
+struct MetadataV2 {
+    name: String,
+    size: u64,
+    file_type: String,
+    time: DateTime<Utc>,
+    chunks: Vec<String>,
+    encryption: Option<ObjectEncryption>,
+    checksum: Option<ObjectChecksum>,
+    parts: Vec<ObjectPart>,
+}
+
+impl From<MetadataV2> for Metadata {
+    fn from(legacy: MetadataV2) -> Self {
+        Metadata {
+            name: legacy.name,
+            size: legacy.size,
+            file_type: legacy.file_type,
+            time: legacy.time,
+            chunks: legacy.chunks,
+            encryption: legacy.encryption,
+            checksum: legacy.checksum,
+            parts: legacy.parts,
+            website_redirect_location: None,
+            chunk_sizes: vec![],
+            inline: None,
+        }
+    }
+}
+
+// 旧版本元数据结构，仅用于读取记录分块长度之前保存的元数据
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(compare(PartialEq), check_bytes)]
+#[archive_attr(derive(Debug))]
+struct MetadataV3 {
+    name: String,
+    size: u64,
+    file_type: String,
+    time: DateTime<Utc>,
+    chunks: Vec<String>,
+    encryption: Option<ObjectEncryption>,
+    checksum: Option<ObjectChecksum>,
+    parts: Vec<ObjectPart>,
+    website_redirect_location: Option<String>,
+}
+
+impl From<MetadataV3> for Metadata {
+    fn from(legacy: MetadataV3) -> Self {
+        Metadata {
+            name: legacy.name,
+            size: legacy.size,
+            file_type: legacy.file_type,
+            time: legacy.time,
+            chunks: legacy.chunks,
+            encryption: legacy.encryption,
+            checksum: legacy.checksum,
+            parts: legacy.parts,
+            website_redirect_location: legacy.website_redirect_location,
+            chunk_sizes: vec![],
+            inline: None,
+        }
+    }
+}
+
+// 旧版本元数据结构，仅用于读取支持内联小对象之前保存的元数据
+#[derive(Archive, Deserialize, Serialize, Debug, PartialEq)]
+#[archive(compare(PartialEq), check_bytes)]
+#[archive_attr(derive(Debug))]
+struct MetadataV4 {
+    name: String,
+    size: u64,
+    file_type: String,
+    time: DateTime<Utc>,
+    chunks: Vec<String>,
+    encryption: Option<ObjectEncryption>,
+    checksum: Option<ObjectChecksum>,
+    parts: Vec<ObjectPart>,
+    website_redirect_location: Option<String>,
+    chunk_sizes: Vec<u64>,
+}
+
+impl From<MetadataV4> for Metadata {
+    fn from(legacy: MetadataV4) -> Self {
+        Metadata {
+            name: legacy.name,
+            size: legacy.size,
+            file_type: legacy.file_type,
+            time: legacy.time,
+            chunks: legacy.chunks,
+            encryption: legacy.encryption,
+            checksum: legacy.checksum,
+            parts: legacy.parts,
+            website_redirect_location: legacy.website_redirect_location,
+            chunk_sizes: legacy.chunk_sizes,
+            inline: None,
+        }
+    }
+}
+
+impl Metadata {
+    // 各分块的明文长度
+    // 旧对象没有记录长度：分片上传对象每个分片是一个分块，普通对象按 CHUNK_SIZE 固定分块
+    pub fn chunk_lengths(&self) -> Vec<u64> {
+        if !self.chunk_sizes.is_empty() {
+            return self.chunk_sizes.clone();
+        }
+        if !self.parts.is_empty() {
+            return self.parts.iter().map(|part| part.size).collect();
+        }
+        let chunk_size = CHUNK_SIZE as u64;
+        (0..self.chunks.len() as u64)
+            .map(|idx| chunk_size.min(self.size.saturating_sub(idx * chunk_size)))
+            .collect()
+    }
+
+    // 对象的 ETag：普通对象为分片名的 MD5，分片上传对象为各分片 ETag 的 MD5 并附加分片数
+    pub fn etag(&self) -> String {
+        if let Some(inline) = &self.inline {
+            return cry::encrypt_by_md5(&inline.name);
+        }
+        if self.parts.is_empty() {
+            return cry::encrypt_by_md5(&self.chunks.concat());
+        }
+        let etags: String = self.parts.iter().map(|part| part.etag.as_str()).collect();
+        format!("{}-{}", cry::encrypt_by_md5(&etags), self.parts.len())
+    }
+}
+
+// 已压缩并加密的分片，名称由数据密钥派生，见 sealed_chunk_name
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SealedChunk {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+// 对象分片大小
+pub const CHUNK_SIZE: usize = 8 << 20;
+// 下载时每次输出的数据大小
+const FRAME_SIZE: usize = 256 << 10;
+
+// 分块名称为64位大写十六进制，校验来自其他节点的名称，避免路径穿越
+pub(crate) fn is_chunk_name(name: &str) -> bool {
+    name.len() == 64
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || (b'A'..=b'F').contains(&byte))
+}
+
+// 纠删码分片的名称，分片与所属分块保存在同一目录下
+pub(crate) fn shard_name(hash: &str, index: usize) -> String {
+    format!("{}.{}", hash, index)
+}
+
+// 分块或分片所属的分块名称
+pub(crate) fn chunk_of(name: &str) -> &str {
+    name.split_once('.').map_or(name, |(hash, _)| hash)
+}
+
+// 校验其他节点推送的分块或分片名称
+pub(crate) fn is_stored_name(name: &str) -> bool {
+    match name.split_once('.') {
+        Some((hash, index)) => {
+            is_chunk_name(hash)
+                && index
+                    .parse::<u8>()
+                    .is_ok_and(|value| value.to_string() == index)
+        }
+        None => is_chunk_name(name),
+    }
+}
+
+// 列出本节点保存的全部分块和分片名称
+async fn stored_names(store: &dyn ChunkStore) -> anyhow::Result<Vec<String>> {
+    store.list().await
+}
+
+// 列出已保存的全部分块，只保存了纠删码分片的分块也包括在内
+pub(crate) async fn stored_chunks(store: &dyn ChunkStore) -> anyhow::Result<Vec<String>> {
+    let mut hashes: Vec<String> = stored_names(store)
+        .await?
+        .iter()
+        .map(|name| chunk_of(name).to_string())
+        .collect();
+    hashes.sort_unstable();
+    hashes.dedup();
+    Ok(hashes)
+}
+
+// 列出本节点保存了纠删码分片的分块
+pub(crate) async fn sharded_chunks(store: &dyn ChunkStore) -> anyhow::Result<Vec<String>> {
+    let mut hashes: Vec<String> = stored_names(store)
+        .await?
+        .iter()
+        .filter(|name| name.contains('.'))
+        .map(|name| chunk_of(name).to_string())
+        .collect();
+    hashes.sort_unstable();
+    hashes.dedup();
+    Ok(hashes)
+}
+
+// 读取本节点保存的分块的全部纠删码分片
+pub(crate) async fn local_shards(
+    store: &dyn ChunkStore,
+    hash: &str,
+) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut shards = Vec::new();
+    for name in store.shards(hash).await? {
+        if let Some(shard) = store.get(&name).await? {
+            shards.push(shard);
+        }
+    }
+    Ok(shards)
+}
+
+// 删除分块及其纠删码分片，分块不存在时忽略
+pub(crate) async fn remove_chunk(store: &dyn ChunkStore, hash: &str) -> anyhow::Result<()> {
+    for name in store.shards(hash).await? {
+        store.delete(&name).await?;
+    }
+    store.delete(hash).await
+}
+
+// 读取保存的分块数据(压缩或加密后)，用于发送给其他节点
+pub(crate) async fn load_chunk(store: &dyn ChunkStore, hash: &str) -> anyhow::Result<Vec<u8>> {
+    store
+        .get(hash)
+        .await?
+        .with_context(|| format!("分块 {} 不存在", hash))
 }
 
 // 获取sha256值
@@ -80,7 +413,7 @@ fn get_sha256(data: &[u8]) -> Vec<u8> {
 }
 
 // 获取sha256字符串
-fn get_sha256_string(hash: &[u8]) -> String {
+pub(crate) fn get_sha256_string(hash: &[u8]) -> String {
     let hash_string: String = hash.encode_hex();
     hash_string.to_uppercase()
 }
@@ -91,51 +424,387 @@ pub(crate) async fn sum_sha256(data: &[u8]) -> String {
     get_sha256_string(&sha256)
 }
 
-// 压缩分片
-pub(crate) fn compress_chunk(mut reader: impl std::io::Read) -> anyhow::Result<Vec<u8>> {
-    let mut res = Vec::new();
-    zstd::stream::copy_encode(&mut reader, &mut res, 0)?;
-    Ok(res)
+// 压缩分片，分块头记录使用的编码
+pub(crate) fn compress_chunk(
+    data: &[u8],
+    compression: &CompressionConfig,
+) -> anyhow::Result<Vec<u8>> {
+    compression.compress(data)
+}
+
+// 校验未加密的分块，解压后的 sha256 应与分块名称一致
+pub(crate) fn verify_chunk(hash: &str, stored: &[u8]) -> anyhow::Result<()> {
+    open_chunk(hash, stored, None).map(|_| ())
 }
 
-// 解压分片
-fn decompress_chunk(chunk_path: impl AsRef<Path>) -> anyhow::Result<Vec<u8>> {
-    let file = File::open(chunk_path)?;
-    let chunk_file = unsafe { Mmap::map(&file)? };
-    let mut decoder = Decoder::new(&chunk_file[..])?;
-    let mut result = Vec::new();
-    decoder.read_to_end(&mut result)?;
+// 校验加密的分块，GCM 标签和由数据密钥派生的分块名称都应一致
+pub(crate) fn verify_sealed_chunk(
+    hash: &str,
+    stored: &[u8],
+    data_key: &[u8],
+) -> anyhow::Result<()> {
+    open_chunk(hash, stored, Some(data_key)).map(|_| ())
+}
+
+// 校验分块明文与名称是否一致，加密分块的名称由数据密钥和明文 sha256 派生
+fn check_chunk(hash: &str, plain: &[u8], data_key: Option<&[u8]>) -> anyhow::Result<()> {
+    let actual = get_sha256_string(&get_sha256(plain));
+    let name = match data_key {
+        Some(key) => sealed_chunk_name(key, &actual)?,
+        None => actual,
+    };
+    match name == hash {
+        true => Ok(()),
+        false => Err(anyhow!("分块 {} 的内容校验失败", hash)),
+    }
+}
+
+// 解密并解压保存在磁盘上的分块数据，并校验明文
+fn open_chunk(hash: &str, stored: &[u8], data_key: Option<&[u8]>) -> anyhow::Result<Vec<u8>> {
+    let result = match data_key {
+        Some(key) => {
+            let compressed = cry::aes_256_gcm_decrypt(key, stored, hash.as_bytes())?;
+            codec::decode(&compressed)?
+        }
+        None => codec::decode(stored)?,
+    };
+    check_chunk(hash, &result, data_key)?;
     Ok(result)
 }
 
+// 在阻塞线程池中执行分块的解压和校验，不阻塞处理请求的线程
+pub(crate) async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> anyhow::Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .context("读取分块的任务异常退出")
+}
+
+// 读取并解压本节点的完整分块，分块不存在时返回 None
+async fn read_local_chunk(
+    store: &dyn ChunkStore,
+    hash: String,
+    data_key: Option<Vec<u8>>,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(stored) = store.get(&hash).await? else {
+        return Ok(None);
+    };
+    blocking(move || open_chunk(&hash, &stored, data_key.as_deref()))
+        .await?
+        .map(Some)
+}
+
+// 读取分块明文，本节点没有完整分块或分块损坏时由纠删码分片还原或从其他节点拉取
+async fn read_chunk(
+    store: &dyn ChunkStore,
+    hash: String,
+    data_key: Option<Vec<u8>>,
+    peers: Vec<Node>,
+) -> anyhow::Result<Vec<u8>> {
+    match read_local_chunk(store, hash.clone(), data_key.clone()).await {
+        Ok(Some(data)) => return Ok(data),
+        Ok(None) => {}
+        Err(err) => {
+            warn!(
+                "本节点的分块 {} 读取失败，改从其他节点读取: {:#}",
+                hash, err
+            );
+            metrics::READ_CORRUPT.inc();
+        }
+    }
+    let result = match chunk::load_remote(store, peers, hash.clone()).await {
+        Ok(stored) => {
+            let hash = hash.clone();
+            blocking(move || open_chunk(&hash, &stored, data_key.as_deref()))
+                .await
+                .and_then(|result| result)
+        }
+        Err(err) => Err(err),
+    };
+    if let Err(err) = &result {
+        error!("分块 {} 读取失败: {:#}", hash, err);
+        metrics::READ_FAILED.inc();
+    }
+    result
+}
+
+// 读取分块明文，未加密的分块先查缓存，读出后放入缓存
+// 分块按内容命名，缓存的明文不会过期；加密分块的明文不缓存
+async fn read_cached_chunk(
+    store: &dyn ChunkStore,
+    cache: &ChunkCache,
+    hash: String,
+    data_key: Option<Vec<u8>>,
+    peers: Vec<Node>,
+) -> anyhow::Result<Bytes> {
+    if data_key.is_some() {
+        return read_chunk(store, hash, data_key, peers).await.map(Bytes::from);
+    }
+    if let Some(data) = cache.get(&hash) {
+        return Ok(data);
+    }
+    let data = Bytes::from(read_chunk(store, hash.clone(), None, peers).await?);
+    cache.insert(hash, data.clone(), data.len());
+    Ok(data)
+}
+
+// 计算加密分片的名称。
+// 加密对象不参与跨对象去重：分片以 HMAC(数据密钥, 明文sha256) 命名，
+// 只有同一数据密钥下(同一对象及其拷贝)内容相同的分片才会共用一个文件，
+// 也不会通过文件名泄露明文的 sha256。
+pub fn sealed_chunk_name(data_key: &[u8], hash: &str) -> anyhow::Result<String> {
+    let mac = cry::do_hmac_sha256(data_key, hash)?;
+    Ok(get_sha256_string(&mac))
+}
+
+// 压缩并加密单个分片，分片名称作为附加认证数据防止分片文件被替换
+pub(crate) fn seal_chunk(
+    data_key: &[u8],
+    data: &[u8],
+    compression: &CompressionConfig,
+) -> anyhow::Result<SealedChunk> {
+    let hash = get_sha256_string(&get_sha256(data));
+    let name = sealed_chunk_name(data_key, &hash)?;
+    let compressed = compress_chunk(data, compression)?;
+    let data = cry::aes_256_gcm_encrypt(data_key, &compressed, name.as_bytes())?;
+    Ok(SealedChunk { name, data })
+}
+
+impl InlineChunk {
+    // 压缩(和加密)小对象的全部数据
+    pub fn new(
+        plain: &[u8],
+        data_key: Option<&[u8]>,
+        compression: &CompressionConfig,
+    ) -> anyhow::Result<Self> {
+        let (name, data) = match data_key {
+            Some(data_key) => {
+                let sealed = seal_chunk(data_key, plain, compression)?;
+                (sealed.name, sealed.data)
+            }
+            None => (
+                get_sha256_string(&get_sha256(plain)),
+                compress_chunk(plain, compression)?,
+            ),
+        };
+        Ok(InlineChunk { name, data })
+    }
+
+    // 解密并解压，并校验明文
+    pub fn open(&self, data_key: Option<&[u8]>) -> anyhow::Result<Vec<u8>> {
+        open_chunk(&self.name, &self.data, data_key)
+    }
+}
+
 // 保存元数据
-pub(crate) fn save_metadata(meta_file_path: impl AsRef<Path>, metadata: &Metadata) -> anyhow::Result<()> {
-    let meta_data = rkyv::to_bytes::<_, 256>(metadata)?;
-    let meta_data = meta_data.as_slice();
+pub(crate) fn save_metadata(
+    meta_file_path: impl AsRef<Path>,
+    metadata: &Metadata,
+    keyring: &Keyring,
+    durability: Durability,
+) -> anyhow::Result<()> {
     fs::create_dir_all(meta_file_path.as_ref().parent().unwrap())?;
-    let meta_bytes = cry::aes_256_cbc_encrypt(meta_data)?;
-    fs::write(meta_file_path, &meta_bytes)?;
+    durable::write(
+        meta_file_path,
+        &encode_metadata(keyring, metadata)?,
+        durability,
+    )?;
     Ok(())
 }
 
 // 加载元数据
-pub(crate) fn load_metadata(meta_file_path: impl AsRef<Path>) -> anyhow::Result<Metadata> {
+pub(crate) fn load_metadata(
+    meta_file_path: impl AsRef<Path>,
+    keyring: &Keyring,
+) -> anyhow::Result<Metadata> {
     let metadata_bytes = fs::read(meta_file_path).context("元数据地址不存在")?;
-    let metadata_bytes = cry::aes_256_cbc_decrypt(&metadata_bytes)?;
-    let archived = rkyv::check_archived_root::<Metadata>(&metadata_bytes[..]).unwrap();
-    let res: Metadata = archived.deserialize(&mut Infallible)?;
-    Ok(res)
+    decode_metadata(keyring, &metadata_bytes)
+}
+
+// 序列化并用元数据密钥加密元数据，明文带版本头部
+pub(crate) fn encode_metadata(keyring: &Keyring, metadata: &Metadata) -> anyhow::Result<Vec<u8>> {
+    let archived = rkyv::to_bytes::<_, 256>(metadata)?;
+    let mut meta_data = Vec::with_capacity(METADATA_MAGIC.len() + 1 + archived.len());
+    meta_data.extend_from_slice(METADATA_MAGIC);
+    meta_data.push(METADATA_VERSION);
+    meta_data.extend_from_slice(&archived);
+    keyring.encrypt(&meta_data)
+}
+
+// 解密并解析元数据，按头部的版本选择结构
+pub(crate) fn decode_metadata(
+    keyring: &Keyring,
+    metadata_bytes: &[u8],
+) -> anyhow::Result<Metadata> {
+    let metadata_bytes = keyring.decrypt(metadata_bytes)?;
+    match metadata_bytes.strip_prefix(METADATA_MAGIC) {
+        Some([version, archived @ ..]) => decode_versioned(*version, archived),
+        _ => decode_unversioned(&metadata_bytes),
+    }
+}
+
+fn decode_versioned(version: u8, archived: &[u8]) -> anyhow::Result<Metadata> {
+    // 去掉头部后数据不再对齐，复制到对齐的缓冲区再校验
+    let mut aligned = rkyv::AlignedVec::with_capacity(archived.len());
+    aligned.extend_from_slice(archived);
+    match version {
+        METADATA_VERSION => {
+            let archived = rkyv::check_archived_root::<Metadata>(&aligned[..])
+                .map_err(|err| anyhow!("元数据格式错误: {}", err))?;
+            Ok(archived.deserialize(&mut Infallible)?)
+        }
+        version => Err(anyhow!("不支持的元数据结构版本: {}", version)),
+    }
+}
+
+// 加入版本头部之前写入的元数据，从新到旧依次尝试各版本的结构
+// 此后的结构变化通过版本号区分，不再加入这里
+fn decode_unversioned(metadata_bytes: &[u8]) -> anyhow::Result<Metadata> {
+    if let Ok(archived) = rkyv::check_archived_root::<Metadata>(metadata_bytes) {
+        let res: Metadata = archived.deserialize(&mut Infallible)?;
+        return Ok(res);
+    }
+    if let Ok(archived) = rkyv::check_archived_root::<MetadataV4>(metadata_bytes) {
+        let res: MetadataV4 = archived.deserialize(&mut Infallible)?;
+        return Ok(res.into());
+    }
+    if let Ok(archived) = rkyv::check_archived_root::<MetadataV3>(metadata_bytes) {
+        let res: MetadataV3 = archived.deserialize(&mut Infallible)?;
+        return Ok(res.into());
+    }
+    if let Ok(archived) = rkyv::check_archived_root::<MetadataV2>(metadata_bytes) {
+        let res: MetadataV2 = archived.deserialize(&mut Infallible)?;
+        return Ok(res.into());
+    }
+    if let Ok(archived) = rkyv::check_archived_root::<MetadataV1>(metadata_bytes) {
+        let res: MetadataV1 = archived.deserialize(&mut Infallible)?;
+        return Ok(res.into());
+    }
+    let archived = rkyv::check_archived_root::<MetadataV0>(metadata_bytes)
+        .map_err(|err| anyhow!("元数据格式错误: {}", err))?;
+    let res: MetadataV0 = archived.deserialize(&mut Infallible)?;
+    Ok(res.into())
+}
+
+// 分片上传中已上传分片的信息，保存在 tmp/{upload_id}/{part_number}
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub(crate) struct PartInfo {
+    pub size: u64,
+    pub etag: String,
+    pub checksum: Option<String>,
+    // 分片的各分块及其明文长度；旧版本每个分片是一个以 etag 命名的分块，不记录
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunk_sizes: Vec<u64>,
+    // 分块以纠删码分片保存时的编码方式，本节点不一定有完整分块
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub erasure: Option<ErasureCode>,
+}
+
+impl PartInfo {
+    // 分片引用的分块及其明文长度
+    pub(crate) fn chunk_list(&self) -> (Vec<String>, Vec<u64>) {
+        if !self.chunks.is_empty() {
+            return (self.chunks.clone(), self.chunk_sizes.clone());
+        }
+        // 空分片不引用分块
+        if self.etag.is_empty() || self.size == 0 {
+            return (vec![], vec![]);
+        }
+        (vec![self.etag.clone()], vec![self.size])
+    }
+}
+
+// 保存分片信息
+pub(crate) async fn save_part_info(
+    path: impl AsRef<Path>,
+    info: &PartInfo,
+    durability: Durability,
+) -> anyhow::Result<()> {
+    durable::write_async(path, &serde_json::to_vec(info)?, durability)
+        .await
+        .context("保存分片信息失败")?;
+    Ok(())
+}
+
+// 读取分片信息，兼容只记录了分片长度的旧格式
+pub(crate) fn load_part_info(path: impl AsRef<Path>) -> anyhow::Result<PartInfo> {
+    let content = fs::read_to_string(path).context("读取分片信息失败")?;
+    if let Ok(size) = content.trim().parse::<u64>() {
+        return Ok(PartInfo {
+            size,
+            etag: String::new(),
+            checksum: None,
+            chunks: vec![],
+            chunk_sizes: vec![],
+            erasure: None,
+        });
+    }
+    serde_json::from_str(&content).context("解析分片信息失败")
 }
 
 // 定义解压流
+// 分块在阻塞线程池中读取和解压，并预读之后的 read_ahead 个分块；
+// 只有响应取走数据时才继续读取，已读出的分块按帧输出
 pub(crate) struct DecompressStream {
+    store: &'static dyn ChunkStore,
+    cache: ChunkCache,
     hashes: Vec<String>,
     idx: usize,
+    data_key: Option<Vec<u8>>,
+    // 本节点没有完整分块时从这些节点读取纠删码分片或完整分块
+    peers: Vec<Node>,
+    read_ahead: usize,
+    pending: FuturesOrdered<LocalBoxFuture<'static, anyhow::Result<Bytes>>>,
+    // 当前分块尚未输出的部分
+    frame: Bytes,
+    failed: bool,
 }
 
 impl DecompressStream {
-    pub(crate) fn new(hashes: Vec<String>) -> Self {
-        DecompressStream { hashes, idx: 0 }
+    pub(crate) fn new(
+        store: &'static dyn ChunkStore,
+        cache: ChunkCache,
+        hashes: Vec<String>,
+        data_key: Option<Vec<u8>>,
+        peers: Vec<Node>,
+        read_ahead: usize,
+    ) -> Self {
+        DecompressStream {
+            store,
+            cache,
+            hashes,
+            idx: 0,
+            data_key,
+            peers,
+            read_ahead,
+            pending: FuturesOrdered::new(),
+            frame: Bytes::new(),
+            failed: false,
+        }
+    }
+
+    // 读取对象的数据流，小对象的数据在元数据中，不读取分块
+    pub(crate) fn object(
+        store: &'static dyn ChunkStore,
+        cache: ChunkCache,
+        metadata: Metadata,
+        data_key: Option<Vec<u8>>,
+        peers: Vec<Node>,
+        read_ahead: usize,
+    ) -> Self {
+        let hashes = metadata.chunks;
+        let mut stream = DecompressStream::new(store, cache, hashes, data_key, peers, read_ahead);
+        if let Some(inline) = metadata.inline {
+            let data_key = stream.data_key.clone();
+            let open = blocking(move || inline.open(data_key.as_deref()).map(Bytes::from));
+            stream
+                .pending
+                .push_back(open.map(|result| result.and_then(|result| result)).boxed_local());
+        }
+        stream
     }
 }
 
@@ -144,69 +813,65 @@ impl Stream for DecompressStream {
     type Item = io::Result<Bytes>;
 
     fn poll_next(
-        mut self: std::pin::Pin<&mut Self>,
-        _cx: &mut std::task::Context<'_>,
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        if self.idx >= self.hashes.len() {
-            std::task::Poll::Ready(None)
-        } else {
-            let x = &self.hashes[self.idx];
-            let path = path_from_hash(x);
-            if let Ok(res) = decompress_chunk(&path) {
-                self.idx += 1;
-                std::task::Poll::Ready(Some(Ok(Bytes::from(res))))
-            } else {
-                std::task::Poll::Ready(None)
+        let this = self.get_mut();
+        loop {
+            if !this.frame.is_empty() {
+                let len = this.frame.len().min(FRAME_SIZE);
+                return std::task::Poll::Ready(Some(Ok(this.frame.split_to(len))));
+            }
+            if this.failed {
+                return std::task::Poll::Ready(None);
+            }
+            // 正在输出的分块之外再预读 read_ahead 个分块
+            while this.idx < this.hashes.len() && this.pending.len() <= this.read_ahead {
+                let hash = this.hashes[this.idx].clone();
+                let (data_key, peers) = (this.data_key.clone(), this.peers.clone());
+                let cache = this.cache.clone();
+                let store = this.store;
+                let read = async move {
+                    read_cached_chunk(store, &cache, hash, data_key, peers).await
+                };
+                this.pending.push_back(read.boxed_local());
+                this.idx += 1;
+            }
+            match ready!(this.pending.poll_next_unpin(cx)) {
+                Some(Ok(data)) => this.frame = data,
+                // 返回错误使连接中断，客户端不会把截断的内容当作完整的对象
+                Some(Err(err)) => {
+                    this.failed = true;
+                    this.pending = FuturesOrdered::new();
+                    return std::task::Poll::Ready(Some(Err(io::Error::other(format!(
+                        "{:#}",
+                        err
+                    )))));
+                }
+                None => return std::task::Poll::Ready(None),
             }
         }
     }
 }
 
-// 判断路径是否存在
-#[inline]
-pub(crate) fn is_path_exist(hash: &str) -> bool {
-    let path = path_from_hash(hash);
-    path.exists()
-}
-
-// 数据分片并保存
+// 数据分块并保存，返回数据长度、各分块的哈希和明文长度
 pub(crate) async fn split_file_and_save(
+    store: &dyn ChunkStore,
     data: Vec<u8>,
-    chunk_size: usize,
-) -> anyhow::Result<(usize, Vec<String>)> {
+    chunking: &ChunkingConfig,
+    compression: &CompressionConfig,
+) -> anyhow::Result<(usize, Vec<String>, Vec<u64>)> {
     let mut chunks = Vec::new();
-    let mut size = 0;
-    let mut buffer = BytesMut::new();
-    let mut reader = Cursor::new(data);
-    loop {
-        let read = reader.read(&mut buffer)?;
-        if read > 0 {
-            size += read;
-            if buffer.len() >= chunk_size {
-                let chunk = buffer.split_to(chunk_size);
-                let hash_code = sum_sha256(chunk.as_slice()).await;
-                chunks.push(hash_code.clone());
-
-                if !is_path_exist(&hash_code) {
-                    let compressed_chunk = compress_chunk(std::io::Cursor::new(chunk))?;
-                    save_file(&hash_code, &compressed_chunk).await?;
-                }
-            }
-        }
-
-        if read == 0 {
-            if !buffer.is_empty() {
-                let chunk = buffer.as_slice();
-                let hash_code = sum_sha256(chunk).await;
-                chunks.push(hash_code.clone());
+    let mut sizes = Vec::new();
+    for chunk in chunking.split(&data) {
+        let hash_code = sum_sha256(chunk).await;
+        chunks.push(hash_code.clone());
+        sizes.push(chunk.len() as u64);
 
-                if !is_path_exist(&hash_code) {
-                    let compressed_chunk = compress_chunk(std::io::Cursor::new(chunk))?;
-                    save_file(&hash_code, &compressed_chunk).await?;
-                }
-            }
-            break;
+        if !store.exists(&hash_code).await {
+            let compressed_chunk = compress_chunk(chunk, compression)?;
+            store.put(&hash_code, &compressed_chunk).await?;
         }
     }
-    Ok((size, chunks))
+    Ok((data.len(), chunks, sizes))
 }
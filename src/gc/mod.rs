@@ -0,0 +1,120 @@
+use crate::cache::ChunkCache;
+use crate::chunk_store::ChunkStore;
+use crate::fs;
+use crate::layout::Layout;
+use crate::object_index::ObjectIndex;
+use crate::raft::app::App;
+use crate::raft::store::Request::AbortUpload;
+use crate::raft::store::{scan_chunk_refs, stale_uploads};
+use log::{error, info};
+use refs::{unix_now, ChunkRefs};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+pub mod refs;
+
+// 分块回收，引用计数和锁由状态机共享
+// 状态机应用每条日志时持有锁，回收任务在锁内再次确认分块没有引用后才删除，
+// 不会删除正被并发上传重新引用的同名分块
+#[derive(Debug, Clone)]
+pub struct ChunkGc {
+    pub refs: ChunkRefs,
+    pub lock: Arc<Mutex<()>>,
+    // 节点的分块存储
+    pub store: &'static dyn ChunkStore,
+    // 下载时读出的分块明文，对象被覆盖或删除后移出不再引用的分块
+    pub cache: ChunkCache,
+}
+
+impl ChunkGc {
+    // 保存上传或其他节点推送的分块，在锁内刷新孤儿时间，避免刚写入的同名分块被回收
+    // 纠删码分片按所属分块计数
+    pub(crate) async fn save_chunk(&self, name: &str, data: &[u8]) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().await;
+        self.store.put_if_absent(name, data).await?;
+        self.refs.touch(fs::chunk_of(name), unix_now())
+    }
+
+    // 首次运行时扫描已有对象和分片上传建立引用计数
+    async fn ensure_ready(&self, layout: &Layout, objects: &ObjectIndex) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().await;
+        if self.refs.is_ready()? {
+            return Ok(());
+        }
+        let counts = scan_chunk_refs(layout, objects)?;
+        let stored = fs::stored_chunks(self.store).await?;
+        self.refs.rebuild(&counts, &stored, unix_now())?;
+        info!(
+            "已建立分块引用计数，{} 个分块被引用，共 {} 个分块",
+            counts.len(),
+            stored.len()
+        );
+        Ok(())
+    }
+
+    // 删除超过宽限期的孤儿分块，返回删除的数量
+    // 宽限期内的分块仍可被读取中的下载流或重新上传的相同数据使用
+    async fn sweep(&self, grace: u64) -> anyhow::Result<usize> {
+        let mut removed = 0;
+        for hash in self.refs.expired(unix_now(), grace)? {
+            let _guard = self.lock.lock().await;
+            if !self.refs.is_expired(&hash, unix_now(), grace)? {
+                continue;
+            }
+            fs::remove_chunk(self.store, &hash).await?;
+            self.refs.forget(&hash)?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
+
+// 中止超过有效期仍未完成的分片上传，只由 leader 提交，有效期为0时不中止
+async fn abort_stale_uploads(app: &App, expiry: u64) -> anyhow::Result<()> {
+    if expiry == 0 || app.raft.metrics().borrow().current_leader != Some(app.id) {
+        return Ok(());
+    }
+    let stale = stale_uploads(&app.layout, Duration::from_secs(expiry))?;
+    for (bucket_name, object_key, upload_id) in stale {
+        info!(
+            "中止过期的分片上传 {}/{} {}",
+            bucket_name, object_key, upload_id
+        );
+        app.raft
+            .client_write(AbortUpload {
+                bucket_name,
+                object_key,
+                upload_id,
+            })
+            .await
+            .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+    }
+    Ok(())
+}
+
+// 后台回收任务，每个节点回收自己磁盘上的分块
+pub(crate) async fn run(app: App, gc: ChunkGc, interval: u64, grace: u64, upload_expiry: u64) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval.max(1)));
+    loop {
+        ticker.tick().await;
+        if let Err(err) = abort_stale_uploads(&app, upload_expiry).await {
+            error!("中止过期的分片上传失败: {}", err);
+        }
+        if let Err(err) = gc.ensure_ready(&app.layout, &app.objects).await {
+            error!("建立分块引用计数失败: {}", err);
+            continue;
+        }
+        match gc.sweep(grace).await {
+            Ok(0) => {}
+            Ok(removed) => info!("回收 {} 个无引用的分块", removed),
+            Err(err) => error!("回收分块失败: {}", err),
+        }
+        // 整理回收后已删除数据过多的包文件
+        match gc.store.compact().await {
+            Ok(0) => {}
+            Ok(reclaimed) => info!("整理包文件，回收 {} 字节", reclaimed),
+            Err(err) => error!("{}", err),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 引用计数表是否已根据磁盘上的数据建立
+const READY_KEY: &[u8] = b"ready";
+
+// 分块引用计数，保存在 sled 中，由状态机在应用日志时更新
+// 计数归零的分块记入孤儿表，值为归零的时间，超过宽限期后由后台任务删除
+#[derive(Debug, Clone)]
+pub struct ChunkRefs {
+    refs: sled::Tree,
+    orphans: sled::Tree,
+    state: sled::Tree,
+    // 被引用而本节点没有的分块，等待从其他节点拉取
+    missing: sled::Tree,
+}
+
+fn encode(value: u64) -> [u8; 8] {
+    value.to_be_bytes()
+}
+
+fn decode(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes.try_into().unwrap_or_default())
+}
+
+// 当前时间，单位秒
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+impl ChunkRefs {
+    pub fn open(db: &sled::Db) -> sled::Result<Self> {
+        Ok(ChunkRefs {
+            refs: db.open_tree("chunk_refs")?,
+            orphans: db.open_tree("chunk_orphans")?,
+            state: db.open_tree("chunk_gc")?,
+            missing: db.open_tree("chunk_missing")?,
+        })
+    }
+
+    pub fn count(&self, hash: &str) -> anyhow::Result<u64> {
+        Ok(self.refs.get(hash)?.map_or(0, |value| decode(&value)))
+    }
+
+    // 按操作前后引用的分块更新计数，同一分块被引用几次就计数几次
+    pub fn update(&self, before: &[String], after: &[String], now: u64) -> anyhow::Result<()> {
+        let mut delta: BTreeMap<&str, i64> = BTreeMap::new();
+        for hash in before {
+            *delta.entry(hash).or_default() -= 1;
+        }
+        for hash in after {
+            *delta.entry(hash).or_default() += 1;
+        }
+        for (hash, change) in delta {
+            if change == 0 {
+                continue;
+            }
+            let count = (self.count(hash)? as i64 + change).max(0) as u64;
+            if count > 0 {
+                self.refs.insert(hash, &encode(count))?;
+                self.orphans.remove(hash)?;
+            } else {
+                self.refs.remove(hash)?;
+                self.orphans.insert(hash, &encode(now))?;
+            }
+        }
+        Ok(())
+    }
+
+    // 新写入的分块在提交元数据前没有引用，作为孤儿并刷新时间，宽限期内不会被删除
+    pub fn touch(&self, hash: &str, now: u64) -> anyhow::Result<()> {
+        if self.count(hash)? == 0 {
+            self.orphans.insert(hash, &encode(now))?;
+        }
+        Ok(())
+    }
+
+    // 分块没有引用且已超过宽限期
+    pub fn is_expired(&self, hash: &str, now: u64, grace: u64) -> anyhow::Result<bool> {
+        let Some(since) = self.orphans.get(hash)? else {
+            return Ok(false);
+        };
+        Ok(decode(&since).saturating_add(grace) <= now && self.count(hash)? == 0)
+    }
+
+    // 可以删除的分块
+    pub fn expired(&self, now: u64, grace: u64) -> anyhow::Result<Vec<String>> {
+        let mut hashes = Vec::new();
+        for item in self.orphans.iter() {
+            let (hash, _) = item?;
+            let hash = String::from_utf8_lossy(&hash).to_string();
+            if self.is_expired(&hash, now, grace)? {
+                hashes.push(hash);
+            }
+        }
+        Ok(hashes)
+    }
+
+    // 分块已删除，移出孤儿表
+    pub fn forget(&self, hash: &str) -> anyhow::Result<()> {
+        self.orphans.remove(hash)?;
+        Ok(())
+    }
+
+    pub fn is_ready(&self) -> anyhow::Result<bool> {
+        Ok(self.state.contains_key(READY_KEY)?)
+    }
+
+    // 对象被整体替换(如安装快照)时清除就绪标记，重建完成前回收任务不删除分块
+    pub fn reset(&self) -> anyhow::Result<()> {
+        self.state.remove(READY_KEY)?;
+        self.state.flush()?;
+        Ok(())
+    }
+
+    // 记录需要从其他节点拉取的分块
+    pub fn add_missing(&self, hash: &str) -> anyhow::Result<()> {
+        self.missing.insert(hash, &[])?;
+        Ok(())
+    }
+
+    // 等待拉取的分块
+    pub fn missing(&self) -> anyhow::Result<Vec<String>> {
+        let mut hashes = Vec::new();
+        for hash in self.missing.iter().keys() {
+            hashes.push(String::from_utf8_lossy(&hash?).to_string());
+        }
+        Ok(hashes)
+    }
+
+    // 分块已拉取或不再需要拉取，移出队列
+    pub fn remove_missing(&self, hash: &str) -> anyhow::Result<()> {
+        self.missing.remove(hash)?;
+        Ok(())
+    }
+
+    // 以扫描得到的计数重建，磁盘上没有被引用的分块作为孤儿
+    pub fn rebuild(
+        &self,
+        counts: &BTreeMap<String, u64>,
+        stored: &[String],
+        now: u64,
+    ) -> anyhow::Result<()> {
+        self.refs.clear()?;
+        self.orphans.clear()?;
+        for (hash, count) in counts {
+            self.refs.insert(hash, &encode(*count))?;
+        }
+        for hash in stored {
+            if !counts.contains_key(hash) {
+                self.orphans.insert(hash, &encode(now))?;
+            }
+        }
+        self.state.insert(READY_KEY, &[])?;
+        Ok(())
+    }
+}
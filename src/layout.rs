@@ -0,0 +1,116 @@
+use crate::util::durable::Durability;
+use std::path::{Path, PathBuf};
+
+// 元数据路径的后缀，旧版本每个对象一个 {bucket}/{key}.meta 文件
+const META_SUFFIX: &str = ".meta";
+
+// 节点在本地保存数据的目录和写入这些目录的持久化模式，目录全部位于 --fs-root 之下，
+// 默认值也不依赖进程的工作目录
+// 由启动过程创建后显式传给各组件，同一进程中的多个节点各用一份，互不影响
+//
+// {root}/{id}-db          raft 日志、状态机和对象元数据索引
+// {root}/data/buckets     桶目录和分片上传的临时元数据
+// {root}/data/tmp         分片上传中各分片的信息
+// {root}/data/file        默认的分块数据目录，可用 --data-dir 指定多个
+// {root}/data/pack        --chunk-store pack 的包文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    pub root: PathBuf,
+    pub data: PathBuf,
+    pub buckets: PathBuf,
+    pub tmp: PathBuf,
+    pub raft_db: PathBuf,
+    pub chunk_dirs: Vec<PathBuf>,
+    pub pack: PathBuf,
+    pub durability: Durability,
+}
+
+impl Layout {
+    pub fn new(root: impl Into<PathBuf>, node_id: u64) -> Self {
+        let root = root.into();
+        let data = root.join("data");
+        Layout {
+            buckets: data.join("buckets"),
+            tmp: data.join("tmp"),
+            raft_db: root.join(format!("{}-db", node_id)),
+            chunk_dirs: vec![data.join("file")],
+            pack: data.join("pack"),
+            durability: Durability::default(),
+            data,
+            root,
+        }
+    }
+
+    // 分块数据目录，为空时保留默认目录
+    pub fn with_chunk_dirs(mut self, dirs: Vec<PathBuf>) -> Self {
+        if !dirs.is_empty() {
+            self.chunk_dirs = dirs;
+        }
+        self
+    }
+
+    pub fn with_pack_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.pack = dir.into();
+        self
+    }
+
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    pub fn bucket(&self, bucket_name: &str) -> PathBuf {
+        self.buckets.join(bucket_name)
+    }
+
+    // 对象在桶目录下的路径，对象本身不保存为文件，用于校验对象键和拼接元数据路径
+    pub fn object(&self, bucket_name: &str, object_key: &str) -> PathBuf {
+        self.bucket(bucket_name).join(object_key)
+    }
+
+    // 分片上传中各分片信息所在的目录
+    pub fn upload_dir(&self, upload_id: &str) -> PathBuf {
+        self.tmp.join(upload_id)
+    }
+
+    pub fn part_path(&self, upload_id: &str, part_number: &str) -> PathBuf {
+        self.upload_dir(upload_id).join(part_number)
+    }
+
+    // 分片上传的临时元数据 {bucket}/{key}.meta.{uploadId}
+    pub fn upload_metadata(&self, bucket_name: &str, object_key: &str, upload_id: &str) -> PathBuf {
+        self.resolve(&format!(
+            "{}.{}",
+            metadata_path(bucket_name, object_key),
+            upload_id
+        ))
+    }
+
+    // 日志中的路径在本节点的位置
+    // 新的日志记录相对桶根目录的路径，旧版本写入的绝对路径按原样使用
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        let path = Path::new(path);
+        match path.is_absolute() || path.starts_with(&self.buckets) {
+            true => path.to_path_buf(),
+            false => self.buckets.join(path),
+        }
+    }
+
+    // 元数据路径对应的桶名和对象键，不是对象的元数据路径时为 None
+    pub fn object_of(&self, metadata_path: &str) -> Option<(String, String)> {
+        let path = Path::new(metadata_path);
+        let relative = match path.strip_prefix(&self.buckets) {
+            Ok(relative) => relative,
+            Err(_) if path.is_relative() => path,
+            Err(_) => return None,
+        };
+        let relative = relative.to_str()?.strip_suffix(META_SUFFIX)?;
+        let (bucket_name, key) = relative.split_once('/')?;
+        Some((bucket_name.to_string(), key.to_string()))
+    }
+}
+
+// 对象的元数据路径，相对桶根目录，日志中以它表示对象
+pub fn metadata_path(bucket_name: &str, object_key: &str) -> String {
+    format!("{}/{}{}", bucket_name, object_key, META_SUFFIX)
+}
@@ -1,3 +1,6 @@
+use crate::chunk_store::{ChunkStore, MemoryChunkStore, PackChunkStore};
+use crate::disk::Disks;
+use crate::layout::Layout;
 use crate::err::AppError;
 use crate::middleware::CredentialsV4;
 use crate::raft::app::App;
@@ -5,6 +8,7 @@ use crate::raft::network::raft::Raft;
 use crate::raft::network::Network;
 use crate::raft::store::new_storage;
 use crate::raft::NodeId;
+use crate::util::durable;
 use log::info;
 use ntex::web;
 use ntex::web::HttpResponse;
@@ -13,34 +17,102 @@ use openraft::Config;
 use raft::app::NodeDesc;
 use std::collections::BTreeSet;
 use std::net::SocketAddr;
-use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 pub mod api;
+mod bucket;
+pub mod cache;
+mod checksum;
+pub mod chunk_store;
+pub mod disk;
 mod err;
 pub mod fs;
+pub mod gc;
+pub mod layout;
+mod meta_key;
 pub mod management;
+pub mod metrics;
 pub mod middleware;
 pub mod model;
+pub mod object_index;
+pub mod quota;
 mod raft;
+mod repair;
+mod scrub;
+pub mod select;
+mod sse;
 mod stream;
+mod upload;
 pub mod util;
+pub mod website;
 pub type HandlerResponse = Result<HttpResponse, AppError>;
 
-pub async fn start_example_raft_node<P>(
+pub async fn start_example_raft_node(
     node_id: NodeId,
-    dir: P,
+    layout: Layout,
     http_addr: String,
     rpc_addr: String,
-    fs_root: String,
     access_key: String,
     secret_key: String,
     leader_http_addr: Option<String>,
-) -> std::io::Result<()>
-where
-    P: AsRef<Path>,
-{
+    master_key_file: Option<String>,
+    single_node: bool,
+    metadata_key: Option<String>,
+    metadata_key_file: Option<String>,
+    metadata_legacy_cbc: bool,
+    website_addr: Option<String>,
+    website_domain: Option<String>,
+    gc_interval: u64,
+    gc_grace: u64,
+    upload_expiry: u64,
+    repair_interval: u64,
+    disk_check_interval: u64,
+    scrub_interval: u64,
+    scrub_rate: u64,
+    read_ahead: usize,
+    inline_threshold: usize,
+    cache_size: usize,
+    chunk_store: String,
+    pack_threshold: usize,
+    durability: String,
+) -> std::io::Result<()> {
+    // 单节点部署不加入其他节点的集群
+    if single_node && leader_http_addr.is_some() {
+        return Err(std::io::Error::other(
+            "--single-node 不能与 --leader-http-addr 同时使用",
+        ));
+    }
+    // 持久化模式随 Layout 传递，需在任何写入之前设置
+    let durability = durability.parse().map_err(std::io::Error::other)?;
+    let layout = layout.with_durability(durability);
+    // 分块的数据目录需在状态机应用日志之前打开
+    let open_disks = || -> std::io::Result<&'static Disks> {
+        let disks = disk::open(&layout).map_err(std::io::Error::other)?;
+        Ok(Box::leak(Box::new(disks)))
+    };
+    let (store, disks): (&'static dyn ChunkStore, _) = match chunk_store.as_str() {
+        "disk" => {
+            let disks = open_disks()?;
+            (disks, Some(disks))
+        }
+        // 小分块追加到包文件中，其余分块仍保存在数据目录下
+        "pack" => {
+            let disks = open_disks()?;
+            let store = PackChunkStore::open(&layout.pack, pack_threshold, disks, durability)
+                .map_err(std::io::Error::other)?;
+            (Box::leak(Box::new(store)), Some(disks))
+        }
+        // 分块只保存在内存中，节点重启后丢失，靠副本恢复
+        "memory" => (Box::leak(Box::<MemoryChunkStore>::default()), None),
+        other => {
+            return Err(std::io::Error::other(format!("未知的分块存储 {}", other)));
+        }
+    };
+    // 清理上次中断的写入，需在写入新的分块之前进行
+    if let Some(disks) = disks {
+        disk::recover(disks).await.map_err(std::io::Error::other)?;
+    }
     // Create a configuration for the raft instance.
     let config = Config {
         heartbeat_interval: 250,
@@ -50,9 +122,36 @@ where
 
     let config = Arc::new(config.validate().unwrap());
 
-    let (log_store, state_machine_store) = new_storage(&dir).await;
+    // 状态机应用日志时使用元数据密钥，需在创建状态机之前初始化
+    // 密钥随节点的 App 和状态机传递，同一进程中的节点可以使用不同的密钥
+    let fs_root = layout.root.to_string_lossy().to_string();
+    let master_key = sse::load_master_key(master_key_file, &fs_root, single_node, durability)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+    let (metadata_keys, keyring) = meta_key::init_metadata_key(
+        metadata_key,
+        metadata_key_file,
+        &fs_root,
+        single_node,
+        metadata_legacy_cbc,
+        durability,
+    )
+    .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+    let metadata_keys = Arc::new(metadata_keys);
+    durable::clean(&layout.data)?;
+    // 状态机在重放日志之前把旧版本的对象元数据文件导入索引
+    let layout = Arc::new(layout);
+    let (log_store, state_machine_store) = new_storage(
+        layout.clone(),
+        store,
+        cache_size,
+        keyring,
+        metadata_keys.clone(),
+    )
+    .await;
 
     let kvs = state_machine_store.data.kvs.clone();
+    let chunk_gc = state_machine_store.chunk_gc();
+    let objects = state_machine_store.objects();
 
     // Create the network layer that will connect and communicate the raft instances and
     // will be used in conjunction with the store created above.
@@ -87,6 +186,14 @@ where
         config,
         nodes: Arc::new(Mutex::new(set2)),
         node_descs: Arc::new(Mutex::new(set)),
+        chunk_gc: chunk_gc.clone(),
+        read_ahead,
+        inline_threshold,
+        layout,
+        objects,
+        master_key,
+        metadata_keys,
+        scrub: Default::default(),
     };
 
     let addr: SocketAddr = rpc_addr.parse().unwrap();
@@ -103,14 +210,49 @@ where
 
     // Create an application that will store all the instances created above, this will
     // be later used on the actix-web services.
-    api::DATA_DIR
-        .get_or_init(|| async {
-            PathBuf::from(fs_root.clone())
-                .join("data")
-                .to_string_lossy()
-                .to_string()
-        })
-        .await;
+    // 后台回收无引用的分块，需在数据目录和元数据密钥初始化之后启动
+    tokio::spawn(gc::run(
+        app.clone(),
+        chunk_gc,
+        gc_interval,
+        gc_grace,
+        upload_expiry,
+    ));
+    // 后台补齐丢失的纠删码分片
+    tokio::spawn(repair::run(app.clone(), repair_interval));
+    // 后台检测离线的磁盘，迁移不在首选数据目录的分块
+    if let Some(disks) = disks {
+        tokio::spawn(disk::run(disks, chunk_gc.clone(), disk_check_interval));
+    }
+    // 后台限速巡检分块，从其他节点修复损坏的分块
+    tokio::spawn(scrub::run(app.clone(), scrub_interval, scrub_rate));
+    // 后台用轮换后的元数据密钥重新加密已有的元数据，继续重启前未完成的轮换
+    tokio::spawn(meta_key::run(
+        app.metadata_keys.clone(),
+        app.objects.clone(),
+        app.layout.data.clone(),
+        app.chunk_gc.lock.clone(),
+    ));
+    // 静态网站服务使用单独的地址，匿名访问，不经过签名认证
+    let website_server = match website_addr {
+        Some(website_addr) => {
+            let app = app.clone();
+            let website = website::Website {
+                domain: website_domain,
+            };
+            let server = web::HttpServer::new(move || {
+                web::App::new()
+                    .state(app.clone())
+                    .state(website.clone())
+                    .wrap(ntex::web::middleware::Logger::default())
+                    .configure(website::rest)
+            })
+            .bind(&website_addr)?
+            .run();
+            Some(server)
+        }
+        None => None,
+    };
     let server_start = web::HttpServer::new(move || {
         info!("web server");
         let app = app.clone();
@@ -157,6 +299,11 @@ where
             .unwrap();
         info!("cluster init resp status {}", response.status());
     }
-    server_start.await?;
+    match website_server {
+        Some(website_server) => {
+            futures::try_join!(server_start, website_server)?;
+        }
+        None => server_start.await?,
+    }
     Ok(())
 }
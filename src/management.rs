@@ -1,19 +1,31 @@
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use log::info;
+use ntex::http::StatusCode;
 use ntex::util::BytesMut;
 use ntex::web;
 use ntex::web::types::Payload;
 use ntex::web::HttpResponse;
 use std::collections::BTreeMap;
+use std::time::Duration;
 
 use crate::raft::app::NodeDesc;
 use crate::HandlerResponse;
 use openraft::error::Infallible;
 use openraft::RaftMetrics;
 
+use crate::api::no_such_bucket;
+use crate::bucket;
+use crate::err::AppError;
+use crate::scrub;
+use crate::quota::{BucketQuota, BucketUsage};
 use crate::raft::app::App;
+use crate::raft::network::chunk;
+use crate::raft::store::Request::{RecountBucketUsage, RotateMetadataKey};
 use crate::raft::Node;
 use crate::raft::NodeId;
+use crate::util::chunker::ChunkingConfig;
+use crate::util::codec::CompressionConfig;
+use crate::util::erasure::StoragePolicy;
 
 // --- Cluster management
 
@@ -28,7 +40,67 @@ pub fn rest(cfg: &mut web::ServiceConfig) {
         web::post().to(change_membership),
     )
     .route("/cluster/init", web::post().to(init))
-    .route("/cluster/metrics", web::get().to(metrics));
+    .route("/cluster/metrics", web::get().to(metrics))
+    .route("/admin/metadata-key", web::get().to(get_metadata_key))
+    .route(
+        "/admin/rotate-metadata-key",
+        web::post().to(rotate_metadata_key),
+    )
+    .route("/admin/bucket-usage", web::get().to(list_bucket_usage))
+    .route(
+        "/admin/bucket-usage/{bucket}/recount",
+        web::post().to(recount_bucket_usage),
+    )
+    .route(
+        "/admin/bucket-quota/{bucket}",
+        web::get().to(get_bucket_quota),
+    )
+    .route(
+        "/admin/bucket-quota/{bucket}",
+        web::put().to(put_bucket_quota),
+    )
+    .route(
+        "/admin/bucket-quota/{bucket}",
+        web::delete().to(delete_bucket_quota),
+    )
+    .route(
+        "/admin/bucket-chunking/{bucket}",
+        web::get().to(get_bucket_chunking),
+    )
+    .route(
+        "/admin/bucket-chunking/{bucket}",
+        web::put().to(put_bucket_chunking),
+    )
+    .route(
+        "/admin/bucket-chunking/{bucket}",
+        web::delete().to(delete_bucket_chunking),
+    )
+    .route(
+        "/admin/bucket-storage/{bucket}",
+        web::get().to(get_bucket_storage),
+    )
+    .route(
+        "/admin/bucket-storage/{bucket}",
+        web::put().to(put_bucket_storage),
+    )
+    .route(
+        "/admin/bucket-storage/{bucket}",
+        web::delete().to(delete_bucket_storage),
+    )
+    .route(
+        "/admin/bucket-compression/{bucket}",
+        web::get().to(get_bucket_compression),
+    )
+    .route(
+        "/admin/bucket-compression/{bucket}",
+        web::put().to(put_bucket_compression),
+    )
+    .route(
+        "/admin/bucket-compression/{bucket}",
+        web::delete().to(delete_bucket_compression),
+    )
+    .route("/admin/scrub", web::get().to(scrub_status))
+    .route("/admin/metrics", web::get().to(node_metrics));
 }
 
 /// Add a node as **Learner**.
@@ -87,3 +159,392 @@ pub async fn metrics(state: web::types::State<App>) -> HandlerResponse {
     let res: Result<RaftMetrics<NodeId, Node>, Infallible> = Ok(metrics);
     Ok(HttpResponse::Ok().json(&res))
 }
+
+/// Get the metadata key of this node: the key in use, the first key of its key source,
+/// and the progress of the background re-encryption.
+pub async fn get_metadata_key(state: web::types::State<App>) -> HandlerResponse {
+    let (pending_key_id, error) = match state.metadata_keys.pending_key_id() {
+        Ok(key_id) => (Some(key_id), None),
+        Err(err) => (None, Some(format!("{:#}", err))),
+    };
+    let keyring = state.objects.keyring()?;
+    let body = serde_json::json!({
+        "node_id": state.id,
+        "active_key_id": keyring.active_key_id(),
+        "pending_key_id": pending_key_id,
+        "error": error,
+        // 尚未完成重新加密的轮换目标
+        "rotating_to": state.objects.rotation()?,
+        "rotation": state.metadata_keys.rotation(),
+    });
+    Ok(HttpResponse::Ok().json(&body))
+}
+
+// 查询其他节点密钥来源的超时时间
+const KEY_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Rotate the metadata encryption key.
+///
+/// The new key must already be the first key of the metadata key file on every node.
+/// Every member is checked first and nothing is rotated if any of them fails; the
+/// response lists the error of each node. Only the key id goes through raft, each node
+/// switches to the new key when it applies the entry and re-encrypts its local metadata
+/// in the background, see `GET /admin/metadata-key` for the progress.
+pub async fn rotate_metadata_key(state: web::types::State<App>) -> HandlerResponse {
+    let key_id = state.metadata_keys.pending_key_id()?;
+    let client = reqwest::Client::builder()
+        .timeout(KEY_CHECK_TIMEOUT)
+        .build()
+        .context("创建 HTTP 客户端失败")?;
+    let mut nodes = BTreeMap::new();
+    for (id, node) in chunk::members(&state) {
+        let pending = if id == state.id {
+            Ok(key_id.clone())
+        } else {
+            remote_pending_key_id(&client, &node).await
+        };
+        let error = match pending {
+            Ok(pending) if pending == key_id => None,
+            Ok(pending) => Some(format!(
+                "密钥来源中的当前密钥为 {}，与轮换目标 {} 不一致",
+                pending, key_id
+            )),
+            Err(err) => Some(format!("{:#}", err)),
+        };
+        nodes.insert(id, serde_json::json!({ "error": error }));
+    }
+    if nodes.values().any(|node| !node["error"].is_null()) {
+        let body = serde_json::json!({
+            "key_id": key_id,
+            "rotated": false,
+            "nodes": nodes,
+        });
+        return Ok(HttpResponse::build(StatusCode::CONFLICT).json(&body));
+    }
+    state
+        .raft
+        .client_write(RotateMetadataKey {
+            key_id: key_id.clone(),
+        })
+        .await
+        .map_err(|err| anyhow!(err.to_string()))?;
+    let body = serde_json::json!({
+        "key_id": key_id,
+        "rotated": true,
+        "nodes": nodes,
+    });
+    Ok(HttpResponse::Ok().json(&body))
+}
+
+// 读取其他节点密钥来源中的当前密钥
+async fn remote_pending_key_id(client: &reqwest::Client, node: &Node) -> anyhow::Result<String> {
+    let body: serde_json::Value = client
+        .get(format!("http://{}/admin/metadata-key", node.api_addr))
+        .send()
+        .await
+        .with_context(|| format!("查询节点 {} 的元数据密钥失败", node.api_addr))?
+        .error_for_status()?
+        .json()
+        .await?;
+    if let Some(err) = body["error"].as_str() {
+        return Err(anyhow!(err.to_string()));
+    }
+    body["pending_key_id"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("节点 {} 未返回元数据密钥", node.api_addr))
+}
+
+fn existing_bucket(state: &App, bucket_name: &str) -> Result<(), AppError> {
+    if !state.layout.bucket(bucket_name).is_dir() {
+        return Err(no_such_bucket());
+    }
+    Ok(())
+}
+
+/// Get the quota and the current usage of a bucket.
+pub async fn get_bucket_quota(
+    bucket_name: web::types::Path<String>,
+    state: web::types::State<App>,
+) -> HandlerResponse {
+    existing_bucket(&state, &bucket_name)?;
+    let quota: Option<BucketQuota> =
+        bucket::get_config(&state, &bucket_name, bucket::QUOTA_CONFIG).await?;
+    let usage = bucket::get_usage(&state, &bucket_name).await?;
+    let body = serde_json::json!({
+        "bucket": bucket_name.as_str(),
+        "quota": quota,
+        "usage": usage,
+    });
+    Ok(HttpResponse::Ok().json(&body))
+}
+
+/// Set a hard quota on a bucket, e.g. `{"max_bytes": 1073741824, "max_objects": 1000}`.
+///
+/// Either limit may be omitted. Writes that would push the usage over a limit are
+/// rejected with `QuotaExceeded`. If the bucket has no usage counter yet (it was created
+/// before quotas existed), its objects are counted first.
+pub async fn put_bucket_quota(
+    bucket_name: web::types::Path<String>,
+    mut payload: Payload,
+    state: web::types::State<App>,
+) -> HandlerResponse {
+    existing_bucket(&state, &bucket_name)?;
+    let mut bytes = BytesMut::new();
+    while let Some(item) = ntex::util::stream_recv(&mut payload).await {
+        bytes.extend_from_slice(&item.map_err(|err| anyhow!(err.to_string()))?);
+    }
+    let quota: BucketQuota = serde_json::from_slice(&bytes).map_err(|err| {
+        AppError::s3(
+            400,
+            "InvalidArgument",
+            format!("Invalid bucket quota: {}", err),
+        )
+    })?;
+    let counted = state
+        .key_values
+        .read()
+        .await
+        .contains_key(&bucket::config_key(&bucket_name, bucket::USAGE));
+    if !counted {
+        recount(&state, &bucket_name).await?;
+    }
+    bucket::put_config(&state, &bucket_name, bucket::QUOTA_CONFIG, Some(&quota)).await?;
+    Ok(HttpResponse::Ok().json(&quota))
+}
+
+/// Remove the quota of a bucket. Usage keeps being counted.
+pub async fn delete_bucket_quota(
+    bucket_name: web::types::Path<String>,
+    state: web::types::State<App>,
+) -> HandlerResponse {
+    existing_bucket(&state, &bucket_name)?;
+    bucket::put_config::<BucketQuota>(&state, &bucket_name, bucket::QUOTA_CONFIG, None).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Get how new objects of a bucket are split into chunks.
+pub async fn get_bucket_chunking(
+    bucket_name: web::types::Path<String>,
+    state: web::types::State<App>,
+) -> HandlerResponse {
+    existing_bucket(&state, &bucket_name)?;
+    let chunking = bucket::chunking(&state, &bucket_name).await?;
+    Ok(HttpResponse::Ok().json(&chunking))
+}
+
+/// Set how new objects of a bucket are split into chunks, either
+/// `{"fixed": {"size": 4194304}}` or
+/// `{"fastcdc": {"min_size": 262144, "avg_size": 1048576, "max_size": 4194304}}`.
+///
+/// Content-defined chunking keeps most chunk boundaries when data is inserted or removed,
+/// so similar objects share chunks. Existing objects keep their chunks.
+pub async fn put_bucket_chunking(
+    bucket_name: web::types::Path<String>,
+    mut payload: Payload,
+    state: web::types::State<App>,
+) -> HandlerResponse {
+    existing_bucket(&state, &bucket_name)?;
+    let mut bytes = BytesMut::new();
+    while let Some(item) = ntex::util::stream_recv(&mut payload).await {
+        bytes.extend_from_slice(&item.map_err(|err| anyhow!(err.to_string()))?);
+    }
+    let chunking: ChunkingConfig = serde_json::from_slice(&bytes)
+        .map_err(anyhow::Error::from)
+        .and_then(|chunking: ChunkingConfig| chunking.validate().map(|_| chunking))
+        .map_err(|err| {
+            AppError::s3(
+                400,
+                "InvalidArgument",
+                format!("Invalid bucket chunking: {}", err),
+            )
+        })?;
+    bucket::put_config(
+        &state,
+        &bucket_name,
+        bucket::CHUNKING_CONFIG,
+        Some(&chunking),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(&chunking))
+}
+
+/// Reset a bucket to fixed-size chunking.
+pub async fn delete_bucket_chunking(
+    bucket_name: web::types::Path<String>,
+    state: web::types::State<App>,
+) -> HandlerResponse {
+    existing_bucket(&state, &bucket_name)?;
+    bucket::put_config::<ChunkingConfig>(&state, &bucket_name, bucket::CHUNKING_CONFIG, None)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Get how the chunks of new objects of a bucket are stored.
+pub async fn get_bucket_storage(
+    bucket_name: web::types::Path<String>,
+    state: web::types::State<App>,
+) -> HandlerResponse {
+    existing_bucket(&state, &bucket_name)?;
+    let storage = bucket::storage(&state, &bucket_name).await?;
+    Ok(HttpResponse::Ok().json(&storage))
+}
+
+/// Set how the chunks of new objects of a bucket are stored, either `"replicated"` or
+/// `{"erasure": {"data_shards": 4, "parity_shards": 2}}`.
+///
+/// Erasure-coded chunks are split into data and parity shards spread over the nodes,
+/// any `data_shards` of them are enough to read the chunk. Every shard needs its own node, so
+/// the code is rejected when the cluster has fewer members than shards. Existing objects keep
+/// their chunks.
+pub async fn put_bucket_storage(
+    bucket_name: web::types::Path<String>,
+    mut payload: Payload,
+    state: web::types::State<App>,
+) -> HandlerResponse {
+    existing_bucket(&state, &bucket_name)?;
+    let mut bytes = BytesMut::new();
+    while let Some(item) = ntex::util::stream_recv(&mut payload).await {
+        bytes.extend_from_slice(&item.map_err(|err| anyhow!(err.to_string()))?);
+    }
+    let nodes = chunk::members(&state).len();
+    let storage: StoragePolicy = serde_json::from_slice(&bytes)
+        .map_err(anyhow::Error::from)
+        .and_then(|storage: StoragePolicy| {
+            storage.validate()?;
+            storage.check_placement(nodes)?;
+            Ok(storage)
+        })
+        .map_err(|err| {
+            AppError::s3(
+                400,
+                "InvalidArgument",
+                format!("Invalid bucket storage: {}", err),
+            )
+        })?;
+    bucket::put_config(&state, &bucket_name, bucket::STORAGE_CONFIG, Some(&storage)).await?;
+    Ok(HttpResponse::Ok().json(&storage))
+}
+
+/// Reset a bucket to full replicas of every chunk on every node.
+pub async fn delete_bucket_storage(
+    bucket_name: web::types::Path<String>,
+    state: web::types::State<App>,
+) -> HandlerResponse {
+    existing_bucket(&state, &bucket_name)?;
+    bucket::put_config::<StoragePolicy>(&state, &bucket_name, bucket::STORAGE_CONFIG, None).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Get how the chunks of new objects of a bucket are compressed.
+pub async fn get_bucket_compression(
+    bucket_name: web::types::Path<String>,
+    state: web::types::State<App>,
+) -> HandlerResponse {
+    existing_bucket(&state, &bucket_name)?;
+    let compression = bucket::compression(&state, &bucket_name).await?;
+    Ok(HttpResponse::Ok().json(&compression))
+}
+
+/// Set how the chunks of new objects of a bucket are compressed, e.g.
+/// `{"codec": "zstd", "level": 9, "detect": true}`. The codec is `none`, `zstd` or `lz4`.
+///
+/// With `detect`, objects whose MIME type is already compressed (images, video, archives) and
+/// chunks that do not shrink in a trial compression are stored raw. Existing chunks are kept.
+pub async fn put_bucket_compression(
+    bucket_name: web::types::Path<String>,
+    mut payload: Payload,
+    state: web::types::State<App>,
+) -> HandlerResponse {
+    existing_bucket(&state, &bucket_name)?;
+    let mut bytes = BytesMut::new();
+    while let Some(item) = ntex::util::stream_recv(&mut payload).await {
+        bytes.extend_from_slice(&item.map_err(|err| anyhow!(err.to_string()))?);
+    }
+    let compression: CompressionConfig = serde_json::from_slice(&bytes)
+        .map_err(anyhow::Error::from)
+        .and_then(|compression: CompressionConfig| compression.validate().map(|_| compression))
+        .map_err(|err| {
+            AppError::s3(
+                400,
+                "InvalidArgument",
+                format!("Invalid bucket compression: {}", err),
+            )
+        })?;
+    bucket::put_config(
+        &state,
+        &bucket_name,
+        bucket::COMPRESSION_CONFIG,
+        Some(&compression),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(&compression))
+}
+
+/// Reset a bucket to zstd at its default level, skipping incompressible chunks.
+pub async fn delete_bucket_compression(
+    bucket_name: web::types::Path<String>,
+    state: web::types::State<App>,
+) -> HandlerResponse {
+    existing_bucket(&state, &bucket_name)?;
+    bucket::put_config::<CompressionConfig>(
+        &state,
+        &bucket_name,
+        bucket::COMPRESSION_CONFIG,
+        None,
+    )
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// List the usage of every bucket that has a usage counter.
+pub async fn list_bucket_usage(state: web::types::State<App>) -> HandlerResponse {
+    let kvs = state.key_values.read().await;
+    let suffix = format!("/{}", bucket::USAGE);
+    let mut usages = BTreeMap::new();
+    for (key, value) in kvs.iter() {
+        let Some(bucket_name) = key
+            .strip_prefix("bucket/")
+            .and_then(|key| key.strip_suffix(&suffix))
+        else {
+            continue;
+        };
+        if let Ok(usage) = serde_json::from_str::<BucketUsage>(value) {
+            usages.insert(bucket_name.to_string(), usage);
+        }
+    }
+    Ok(HttpResponse::Ok().json(&usages))
+}
+
+/// Recount the usage of a bucket by scanning its objects on every node.
+pub async fn recount_bucket_usage(
+    bucket_name: web::types::Path<String>,
+    state: web::types::State<App>,
+) -> HandlerResponse {
+    existing_bucket(&state, &bucket_name)?;
+    let usage = recount(&state, &bucket_name).await?;
+    Ok(HttpResponse::Ok().json(&usage))
+}
+
+async fn recount(state: &App, bucket_name: &str) -> anyhow::Result<BucketUsage> {
+    let res = state
+        .raft
+        .client_write(RecountBucketUsage {
+            bucket_name: bucket_name.to_string(),
+        })
+        .await
+        .map_err(|err| anyhow!(err.to_string()))?;
+    let value = res.data.value.context("统计桶用量失败")?;
+    Ok(serde_json::from_str(&value)?)
+}
+
+/// Get the progress of the current or last scrub pass on this node and the corrupt chunks
+/// it found.
+pub async fn scrub_status(state: web::types::State<App>) -> HandlerResponse {
+    Ok(HttpResponse::Ok().json(&scrub::status(&state)))
+}
+
+/// Get the counters of this node, reset when the node restarts.
+pub async fn node_metrics() -> HandlerResponse {
+    Ok(HttpResponse::Ok().json(&crate::metrics::snapshot()))
+}
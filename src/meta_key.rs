@@ -0,0 +1,347 @@
+use crate::gc::refs::unix_now;
+use crate::object_index::ObjectIndex;
+use crate::util::durable::{self, Durability};
+use crate::util::keyring::{self, Keyring};
+use anyhow::{anyhow, Context};
+use log::{error, info, warn};
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+// 重新加密失败后的重试间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+// 元数据密钥来源，轮换时重新读取，由状态机和 App 共享
+#[derive(Debug)]
+pub(crate) struct MetadataKeys {
+    source: KeySource,
+    // 启动时是否升级旧版 AES-256-CBC 格式的元数据
+    legacy_cbc: bool,
+    durability: Durability,
+    rotation: Mutex<RotationStatus>,
+    // 切换密钥后唤醒后台重新加密任务
+    wake: Notify,
+}
+
+// 本节点元数据密钥轮换的进度
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RotationStatus {
+    // 当前或上一次轮换的目标密钥
+    pub key_id: Option<String>,
+    pub running: bool,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    // 已重新加密的元数据数
+    pub reencrypted: usize,
+    // 上一次重新加密失败的原因，稍后自动重试
+    pub error: Option<String>,
+}
+
+enum KeySource {
+    // 通过参数或环境变量直接提供的密钥，无法在运行时修改
+    Inline(String),
+    File(PathBuf),
+}
+
+// 不输出直接提供的密钥
+impl fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeySource::Inline(_) => f.write_str("Inline"),
+            KeySource::File(path) => f.debug_tuple("File").field(path).finish(),
+        }
+    }
+}
+
+impl KeySource {
+    fn load(&self) -> anyhow::Result<Keyring> {
+        match self {
+            KeySource::Inline(content) => Keyring::parse(content),
+            KeySource::File(path) => {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("读取元数据密钥文件失败: {}", path.display()))?;
+                Keyring::parse(&content)
+            }
+        }
+    }
+}
+
+// 加载元数据密钥：优先使用直接提供的密钥，其次是密钥文件，
+// 单节点部署时读取或生成 {fs_root}/metadata.key
+// 返回密钥来源和当前的密钥，密钥由对象元数据索引持有
+// 集群中各节点自动生成的密钥互不相同，快照中的元数据无法解密，因此集群节点必须指定密钥
+// legacy_cbc 为 true 时返回的密钥可以解密旧版格式，直到 upgrade_legacy 完成升级
+pub(crate) fn init_metadata_key(
+    key: Option<String>,
+    key_file: Option<String>,
+    fs_root: &str,
+    single_node: bool,
+    legacy_cbc: bool,
+    durability: Durability,
+) -> anyhow::Result<(MetadataKeys, Keyring)> {
+    let source = match (key, key_file) {
+        (Some(key), _) => KeySource::Inline(key),
+        (None, Some(path)) => KeySource::File(PathBuf::from(path)),
+        (None, None) => {
+            let path = PathBuf::from(fs_root).join("metadata.key");
+            if !single_node {
+                return Err(anyhow!(
+                    "未指定元数据密钥，集群内所有节点需通过 --metadata-key 或 --metadata-key-file 使用相同的密钥，单节点部署可使用 --single-node 自动生成 {}",
+                    path.display()
+                ));
+            }
+            if !path.exists() {
+                warn!("元数据密钥文件不存在，已生成 {}", path.display());
+                std::fs::create_dir_all(fs_root).context("创建文件夹失败")?;
+                let key = hex::encode(crate::util::cry::gen_aes_256_key());
+                durable::write(&path, key.as_bytes(), durability).context("保存元数据密钥失败")?;
+            }
+            KeySource::File(path)
+        }
+    };
+    let keyring = source.load()?.with_legacy_cbc(legacy_cbc);
+    info!("元数据密钥ID: {}", keyring.active_key_id());
+    let keys = MetadataKeys {
+        source,
+        legacy_cbc,
+        durability,
+        rotation: Default::default(),
+        wake: Notify::new(),
+    };
+    Ok((keys, keyring))
+}
+
+impl MetadataKeys {
+    // 重新读取密钥来源，返回其中当前密钥的ID，不替换正在使用的密钥
+    pub(crate) fn pending_key_id(&self) -> anyhow::Result<String> {
+        Ok(self.source.load()?.active_key_id())
+    }
+
+    // 切换到密钥来源中的新密钥并记录轮换目标，由后台任务重新加密已有的元数据
+    // key_id 必须与本节点密钥来源中的当前密钥一致，否则说明该节点的密钥文件尚未更新
+    pub(crate) fn switch(&self, objects: &ObjectIndex, key_id: &str) -> anyhow::Result<()> {
+        let new_keyring = self.source.load()?;
+        if new_keyring.active_key_id() != key_id {
+            return Err(anyhow!(
+                "本节点的当前元数据密钥为 {}，与轮换目标 {} 不一致",
+                new_keyring.active_key_id(),
+                key_id
+            ));
+        }
+        // 保留旧密钥用于解密，重新加密完成前未处理的元数据仍可读取
+        let current = objects.keyring()?;
+        objects.install_keyring(new_keyring.with_previous(&current));
+        objects.start_rotation(key_id)?;
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    pub(crate) fn rotation(&self) -> RotationStatus {
+        self.rotation.lock().unwrap().clone()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut RotationStatus)) {
+        f(&mut self.rotation.lock().unwrap());
+    }
+
+    // 启动时处理旧版 AES-256-CBC 格式的元数据，返回重新加密的元数据数
+    // 启用兼容时用当前密钥重新加密，否则发现旧版格式时拒绝启动；处理完成后记录标记，
+    // 此后即使启用兼容也不再接受未经认证的元数据
+    pub(crate) fn upgrade_legacy(
+        &self,
+        data_dir: &Path,
+        objects: &ObjectIndex,
+    ) -> anyhow::Result<usize> {
+        let keyring = objects.keyring()?;
+        if objects.legacy_free()? {
+            if self.legacy_cbc {
+                info!("已不存在旧版格式的元数据，忽略 --metadata-legacy-cbc");
+            }
+            objects.install_keyring(Keyring::clone(&keyring).with_legacy_cbc(false));
+            return Ok(0);
+        }
+        let upgrade = |data: &[u8]| -> anyhow::Result<Option<Vec<u8>>> {
+            if !keyring::is_legacy(data) {
+                return Ok(None);
+            }
+            if !self.legacy_cbc {
+                return Err(anyhow!(
+                    "发现旧版 AES-256-CBC 格式的元数据，需使用 --metadata-legacy-cbc 启动一次以升级"
+                ));
+            }
+            let plain = keyring.decrypt(data).context("解密旧版元数据失败")?;
+            Ok(Some(keyring.encrypt(&plain)?))
+        };
+        let count =
+            objects.reencrypt(upgrade)? + reencrypt_files(data_dir, self.durability, upgrade)?;
+        objects.mark_legacy_free()?;
+        objects.install_keyring(Keyring::clone(&keyring).with_legacy_cbc(false));
+        Ok(count)
+    }
+}
+
+// 后台重新加密元数据，启动时继续上次未完成的轮换，之后等待新的轮换
+// lock 为分块回收锁，应用日志期间持有，重新加密元数据文件时持有以免覆盖并发的写入
+pub(crate) async fn run(
+    keys: Arc<MetadataKeys>,
+    objects: ObjectIndex,
+    data_dir: PathBuf,
+    lock: Arc<tokio::sync::Mutex<()>>,
+) {
+    loop {
+        match resume(&keys, &objects, &data_dir, &lock).await {
+            Ok(()) => keys.wake.notified().await,
+            Err(err) => {
+                error!("重新加密元数据失败: {:#}", err);
+                keys.update(|status| {
+                    status.running = false;
+                    status.error = Some(format!("{:#}", err));
+                });
+                tokio::select! {
+                    _ = keys.wake.notified() => {}
+                    _ = tokio::time::sleep(RETRY_INTERVAL) => {}
+                }
+            }
+        }
+    }
+}
+
+// 继续记录的轮换，没有未完成的轮换时直接返回
+async fn resume(
+    keys: &Arc<MetadataKeys>,
+    objects: &ObjectIndex,
+    data_dir: &Path,
+    lock: &Arc<tokio::sync::Mutex<()>>,
+) -> anyhow::Result<()> {
+    let Some(key_id) = objects.rotation()? else {
+        return Ok(());
+    };
+    let keyring = objects.keyring()?;
+    if keyring.active_key_id() != key_id {
+        return Err(anyhow!(
+            "本节点的当前元数据密钥为 {}，与轮换目标 {} 不一致",
+            keyring.active_key_id(),
+            key_id
+        ));
+    }
+    keys.update(|status| {
+        *status = RotationStatus {
+            key_id: Some(key_id.clone()),
+            running: true,
+            started_at: Some(unix_now()),
+            ..Default::default()
+        }
+    });
+    info!("开始用元数据密钥 {} 重新加密元数据", key_id);
+    let (objects2, data_dir, lock) = (objects.clone(), data_dir.to_path_buf(), lock.clone());
+    let durability = keys.durability;
+    let count = tokio::task::spawn_blocking(move || {
+        reencrypt_all(&objects2, &data_dir, durability, &lock, &keyring)
+    })
+    .await??;
+    objects.finish_rotation(&key_id)?;
+    keys.update(|status| {
+        status.running = false;
+        status.finished_at = Some(unix_now());
+        status.reencrypted = count;
+    });
+    info!("已用元数据密钥 {} 重新加密 {} 份元数据", key_id, count);
+    Ok(())
+}
+
+// 用 keyring 的当前密钥重新加密对象元数据索引和 data_dir 下的所有元数据文件，
+// 返回重新加密的元数据数；已使用当前密钥的元数据跳过，中断后可重复执行
+pub fn reencrypt_all(
+    objects: &ObjectIndex,
+    data_dir: &Path,
+    durability: Durability,
+    lock: &tokio::sync::Mutex<()>,
+    keyring: &Keyring,
+) -> anyhow::Result<usize> {
+    let rotate = |data: &[u8]| reencrypt_bytes(data, keyring);
+    let mut count = objects.reencrypt(rotate)?;
+    for path in metadata_files(data_dir)? {
+        let _guard = lock.blocking_lock();
+        if reencrypt(&path, durability, rotate)? {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+// 用 f 重新加密 data_dir 下的所有元数据文件，返回修改的文件数
+fn reencrypt_files(
+    data_dir: &Path,
+    durability: Durability,
+    f: impl Fn(&[u8]) -> anyhow::Result<Option<Vec<u8>>>,
+) -> anyhow::Result<usize> {
+    let mut count = 0;
+    for path in metadata_files(data_dir)? {
+        if reencrypt(&path, durability, &f)? {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+// data_dir 下的所有元数据文件
+fn metadata_files(data_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut stack = vec![data_dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                stack.push(path);
+            } else if is_metadata_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+// 元数据文件：{object}.meta 以及分片上传的 {object}.meta.{upload_id}
+fn is_metadata_file(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy())
+        .is_some_and(|name| {
+            !durable::is_tmp(&name) && (name.ends_with(".meta") || name.contains(".meta."))
+        })
+}
+
+// 用新密钥重新加密一份元数据，已使用新密钥加密时返回 None
+fn reencrypt_bytes(data: &[u8], keyring: &Keyring) -> anyhow::Result<Option<Vec<u8>>> {
+    if !keyring.needs_rotation(data) {
+        return Ok(None);
+    }
+    let plain = keyring.decrypt(data).context("解密元数据失败")?;
+    Ok(Some(keyring.encrypt(&plain)?))
+}
+
+// 重新加密单个元数据文件，原子写入，读取方不会看到不完整的文件
+// 列出后已被删除的文件跳过
+fn reencrypt(
+    path: &Path,
+    durability: Durability,
+    f: impl Fn(&[u8]) -> anyhow::Result<Option<Vec<u8>>>,
+) -> anyhow::Result<bool> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+    let Some(data) =
+        f(&data).with_context(|| format!("重新加密元数据文件失败: {}", path.display()))?
+    else {
+        return Ok(false);
+    };
+    durable::write(path, &data, durability)?;
+    Ok(true)
+}
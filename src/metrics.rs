@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// 本节点的计数器，节点重启后归零，通过 /admin/metrics 查看
+pub struct Counter {
+    name: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    pub const fn new(name: &'static str) -> Self {
+        Counter {
+            name,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn add(&self, value: u64) {
+        self.value.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+// 巡检校验的分块数
+pub static SCRUB_CHUNKS: Counter = Counter::new("scrub_chunks_total");
+// 巡检读取的字节数
+pub static SCRUB_BYTES: Counter = Counter::new("scrub_bytes_total");
+// 巡检发现的损坏分块数
+pub static SCRUB_CORRUPT: Counter = Counter::new("scrub_corrupt_chunks_total");
+// 巡检从其他节点修复的分块数
+pub static SCRUB_REPAIRED: Counter = Counter::new("scrub_repaired_chunks_total");
+// 读取时校验失败或读不出的本节点分块数，这些分块改从其他节点读取
+pub static READ_CORRUPT: Counter = Counter::new("read_corrupt_chunks_total");
+// 本节点和其他节点都读不出的分块数，对应的下载被中断
+pub static READ_FAILED: Counter = Counter::new("read_failed_chunks_total");
+// 下载时在缓存中找到和未找到的分块数，命中率为 hits / (hits + misses)，加密分块不计入
+pub static CHUNK_CACHE_HITS: Counter = Counter::new("chunk_cache_hits_total");
+pub static CHUNK_CACHE_MISSES: Counter = Counter::new("chunk_cache_misses_total");
+// 读取对象元数据时在缓存中找到和未找到的次数
+pub static METADATA_CACHE_HITS: Counter = Counter::new("metadata_cache_hits_total");
+pub static METADATA_CACHE_MISSES: Counter = Counter::new("metadata_cache_misses_total");
+
+static COUNTERS: &[&Counter] = &[
+    &SCRUB_CHUNKS,
+    &SCRUB_BYTES,
+    &SCRUB_CORRUPT,
+    &SCRUB_REPAIRED,
+    &READ_CORRUPT,
+    &READ_FAILED,
+    &CHUNK_CACHE_HITS,
+    &CHUNK_CACHE_MISSES,
+    &METADATA_CACHE_HITS,
+    &METADATA_CACHE_MISSES,
+];
+
+// 全部计数器的当前值
+pub fn snapshot() -> BTreeMap<&'static str, u64> {
+    COUNTERS
+        .iter()
+        .map(|counter| (counter.name, counter.get()))
+        .collect()
+}
@@ -28,6 +28,14 @@ pub struct CompleteMultipartUploadResult {
     pub object_key: String,
     #[serde(rename = "ETag")]
     pub etag: String,
+    #[serde(rename = "ChecksumCRC32", skip_serializing_if = "Option::is_none")]
+    pub checksum_crc32: Option<String>,
+    #[serde(rename = "ChecksumCRC32C", skip_serializing_if = "Option::is_none")]
+    pub checksum_crc32c: Option<String>,
+    #[serde(rename = "ChecksumSHA1", skip_serializing_if = "Option::is_none")]
+    pub checksum_sha1: Option<String>,
+    #[serde(rename = "ChecksumSHA256", skip_serializing_if = "Option::is_none")]
+    pub checksum_sha256: Option<String>,
 }
 
 // 初始化分片上传请求结果
@@ -49,12 +57,27 @@ pub struct ListBucketResult {
     pub name: String,
     #[serde(rename = "Prefix")]
     pub prefix: String,
+    #[serde(rename = "Marker")]
+    pub marker: String,
+    #[serde(rename = "NextMarker", skip_serializing_if = "Option::is_none")]
+    pub next_marker: Option<String>,
+    #[serde(rename = "Delimiter", skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
     #[serde(rename = "MaxKeys")]
     pub max_keys: u32,
     #[serde(rename = "IsTruncated")]
     pub is_truncated: bool,
     #[serde(rename = "Contents")]
     pub contents: Vec<Content>,
+    #[serde(rename = "CommonPrefixes")]
+    pub common_prefixes: Vec<CommonPrefix>,
+}
+
+// 按分隔符归并的公共前缀
+#[derive(Debug, Serialize)]
+pub struct CommonPrefix {
+    #[serde(rename = "Prefix")]
+    pub prefix: String,
 }
 
 // 文件列表数据实体
@@ -91,6 +114,14 @@ pub struct PartETag {
     pub part_number: i32,
     #[serde(rename = "ETag")]
     pub etag: String,
+    #[serde(rename = "ChecksumCRC32")]
+    pub checksum_crc32: Option<String>,
+    #[serde(rename = "ChecksumCRC32C")]
+    pub checksum_crc32c: Option<String>,
+    #[serde(rename = "ChecksumSHA1")]
+    pub checksum_sha1: Option<String>,
+    #[serde(rename = "ChecksumSHA256")]
+    pub checksum_sha256: Option<String>,
 }
 
 // S3对象
@@ -138,3 +169,312 @@ pub struct ListBucketResp {
     #[serde(rename = "Buckets")]
     pub buckets: BucketWrapper,
 }
+
+// 错误响应
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename = "Error")]
+pub struct ErrorResp {
+    #[serde(rename = "Code")]
+    pub code: String,
+    #[serde(rename = "Message")]
+    pub message: String,
+}
+
+// 桶默认加密配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename = "ServerSideEncryptionConfiguration")]
+pub struct ServerSideEncryptionConfiguration {
+    #[serde(rename = "Rule")]
+    pub rules: Vec<ServerSideEncryptionRule>,
+}
+
+// 桶加密规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerSideEncryptionRule {
+    #[serde(rename = "ApplyServerSideEncryptionByDefault")]
+    pub apply_server_side_encryption_by_default: Option<ServerSideEncryptionByDefault>,
+}
+
+// 桶默认加密算法
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerSideEncryptionByDefault {
+    #[serde(rename = "SSEAlgorithm")]
+    pub sse_algorithm: String,
+}
+
+// 分片列表
+#[derive(Debug, Serialize)]
+#[serde(rename = "ListPartsResult")]
+pub struct ListPartsResult {
+    #[serde(rename = "Bucket")]
+    pub bucket: String,
+    #[serde(rename = "Key")]
+    pub key: String,
+    #[serde(rename = "UploadId")]
+    pub upload_id: String,
+    #[serde(rename = "PartNumberMarker")]
+    pub part_number_marker: u32,
+    #[serde(rename = "NextPartNumberMarker")]
+    pub next_part_number_marker: u32,
+    #[serde(rename = "MaxParts")]
+    pub max_parts: u32,
+    #[serde(rename = "IsTruncated")]
+    pub is_truncated: bool,
+    #[serde(rename = "ChecksumAlgorithm", skip_serializing_if = "Option::is_none")]
+    pub checksum_algorithm: Option<String>,
+    #[serde(rename = "Part")]
+    pub parts: Vec<Part>,
+}
+
+// 已上传的分片
+#[derive(Debug, Serialize)]
+pub struct Part {
+    #[serde(rename = "PartNumber")]
+    pub part_number: u32,
+    #[serde(rename = "LastModified")]
+    pub last_modified: DateTime<Utc>,
+    #[serde(rename = "ETag")]
+    pub etag: String,
+    #[serde(rename = "Size")]
+    pub size: u64,
+    #[serde(rename = "ChecksumCRC32", skip_serializing_if = "Option::is_none")]
+    pub checksum_crc32: Option<String>,
+    #[serde(rename = "ChecksumCRC32C", skip_serializing_if = "Option::is_none")]
+    pub checksum_crc32c: Option<String>,
+    #[serde(rename = "ChecksumSHA1", skip_serializing_if = "Option::is_none")]
+    pub checksum_sha1: Option<String>,
+    #[serde(rename = "ChecksumSHA256", skip_serializing_if = "Option::is_none")]
+    pub checksum_sha256: Option<String>,
+}
+
+// 对象属性
+#[derive(Debug, Serialize)]
+#[serde(rename = "GetObjectAttributesResponse")]
+pub struct GetObjectAttributesResponse {
+    #[serde(rename = "ETag", skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(rename = "Checksum", skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<Checksum>,
+    #[serde(rename = "ObjectParts", skip_serializing_if = "Option::is_none")]
+    pub object_parts: Option<ObjectParts>,
+    #[serde(rename = "StorageClass", skip_serializing_if = "Option::is_none")]
+    pub storage_class: Option<String>,
+    #[serde(rename = "ObjectSize", skip_serializing_if = "Option::is_none")]
+    pub object_size: Option<u64>,
+}
+
+// 对象的附加校验和
+#[derive(Debug, Serialize)]
+pub struct Checksum {
+    #[serde(rename = "ChecksumCRC32", skip_serializing_if = "Option::is_none")]
+    pub checksum_crc32: Option<String>,
+    #[serde(rename = "ChecksumCRC32C", skip_serializing_if = "Option::is_none")]
+    pub checksum_crc32c: Option<String>,
+    #[serde(rename = "ChecksumSHA1", skip_serializing_if = "Option::is_none")]
+    pub checksum_sha1: Option<String>,
+    #[serde(rename = "ChecksumSHA256", skip_serializing_if = "Option::is_none")]
+    pub checksum_sha256: Option<String>,
+    #[serde(rename = "ChecksumType")]
+    pub checksum_type: String,
+}
+
+// 分片上传对象的分片列表
+#[derive(Debug, Serialize)]
+pub struct ObjectParts {
+    #[serde(rename = "PartsCount")]
+    pub parts_count: u32,
+    #[serde(rename = "PartNumberMarker")]
+    pub part_number_marker: u32,
+    #[serde(rename = "NextPartNumberMarker")]
+    pub next_part_number_marker: u32,
+    #[serde(rename = "MaxParts")]
+    pub max_parts: u32,
+    #[serde(rename = "IsTruncated")]
+    pub is_truncated: bool,
+    #[serde(rename = "Part")]
+    pub parts: Vec<ObjectPartAttributes>,
+}
+
+// 对象的单个分片
+#[derive(Debug, Serialize)]
+pub struct ObjectPartAttributes {
+    #[serde(rename = "PartNumber")]
+    pub part_number: u32,
+    #[serde(rename = "Size")]
+    pub size: u64,
+    #[serde(rename = "ChecksumCRC32", skip_serializing_if = "Option::is_none")]
+    pub checksum_crc32: Option<String>,
+    #[serde(rename = "ChecksumCRC32C", skip_serializing_if = "Option::is_none")]
+    pub checksum_crc32c: Option<String>,
+    #[serde(rename = "ChecksumSHA1", skip_serializing_if = "Option::is_none")]
+    pub checksum_sha1: Option<String>,
+    #[serde(rename = "ChecksumSHA256", skip_serializing_if = "Option::is_none")]
+    pub checksum_sha256: Option<String>,
+}
+
+// SelectObjectContent 请求体
+#[derive(Debug, Deserialize)]
+#[serde(rename = "SelectObjectContentRequest")]
+pub struct SelectObjectContentRequest {
+    #[serde(rename = "Expression")]
+    pub expression: String,
+    #[serde(rename = "ExpressionType")]
+    pub expression_type: String,
+    #[serde(rename = "InputSerialization")]
+    pub input_serialization: InputSerialization,
+    #[serde(rename = "OutputSerialization")]
+    pub output_serialization: OutputSerialization,
+}
+
+// 查询对象的格式
+#[derive(Debug, Deserialize)]
+pub struct InputSerialization {
+    #[serde(rename = "CompressionType")]
+    pub compression_type: Option<String>,
+    #[serde(rename = "CSV")]
+    pub csv: Option<CsvInputSerialization>,
+    #[serde(rename = "JSON")]
+    pub json: Option<JsonInputSerialization>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CsvInputSerialization {
+    #[serde(rename = "FileHeaderInfo")]
+    pub file_header_info: Option<String>,
+    #[serde(rename = "Comments")]
+    pub comments: Option<String>,
+    #[serde(rename = "QuoteEscapeCharacter")]
+    pub quote_escape_character: Option<String>,
+    #[serde(rename = "RecordDelimiter")]
+    pub record_delimiter: Option<String>,
+    #[serde(rename = "FieldDelimiter")]
+    pub field_delimiter: Option<String>,
+    #[serde(rename = "QuoteCharacter")]
+    pub quote_character: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct JsonInputSerialization {
+    #[serde(rename = "Type")]
+    pub json_type: Option<String>,
+}
+
+// 查询结果的格式
+#[derive(Debug, Deserialize)]
+pub struct OutputSerialization {
+    #[serde(rename = "CSV")]
+    pub csv: Option<CsvOutputSerialization>,
+    #[serde(rename = "JSON")]
+    pub json: Option<JsonOutputSerialization>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CsvOutputSerialization {
+    #[serde(rename = "QuoteFields")]
+    pub quote_fields: Option<String>,
+    #[serde(rename = "QuoteEscapeCharacter")]
+    pub quote_escape_character: Option<String>,
+    #[serde(rename = "RecordDelimiter")]
+    pub record_delimiter: Option<String>,
+    #[serde(rename = "FieldDelimiter")]
+    pub field_delimiter: Option<String>,
+    #[serde(rename = "QuoteCharacter")]
+    pub quote_character: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct JsonOutputSerialization {
+    #[serde(rename = "RecordDelimiter")]
+    pub record_delimiter: Option<String>,
+}
+
+// SelectObjectContent 的 Stats 事件
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename = "Stats")]
+pub struct SelectStats {
+    #[serde(rename = "BytesScanned")]
+    pub bytes_scanned: u64,
+    #[serde(rename = "BytesProcessed")]
+    pub bytes_processed: u64,
+    #[serde(rename = "BytesReturned")]
+    pub bytes_returned: u64,
+}
+
+// 桶的静态网站配置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "WebsiteConfiguration")]
+pub struct WebsiteConfiguration {
+    #[serde(rename = "IndexDocument", skip_serializing_if = "Option::is_none")]
+    pub index_document: Option<IndexDocument>,
+    #[serde(rename = "ErrorDocument", skip_serializing_if = "Option::is_none")]
+    pub error_document: Option<ErrorDocument>,
+    #[serde(
+        rename = "RedirectAllRequestsTo",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub redirect_all_requests_to: Option<RedirectAllRequestsTo>,
+    #[serde(rename = "RoutingRules", skip_serializing_if = "Option::is_none")]
+    pub routing_rules: Option<RoutingRules>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexDocument {
+    #[serde(rename = "Suffix")]
+    pub suffix: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorDocument {
+    #[serde(rename = "Key")]
+    pub key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RedirectAllRequestsTo {
+    #[serde(rename = "HostName")]
+    pub host_name: String,
+    #[serde(rename = "Protocol", skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoutingRules {
+    #[serde(rename = "RoutingRule", default)]
+    pub rules: Vec<RoutingRule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoutingRule {
+    #[serde(rename = "Condition", skip_serializing_if = "Option::is_none")]
+    pub condition: Option<RoutingRuleCondition>,
+    #[serde(rename = "Redirect")]
+    pub redirect: Redirect,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoutingRuleCondition {
+    #[serde(rename = "KeyPrefixEquals", skip_serializing_if = "Option::is_none")]
+    pub key_prefix_equals: Option<String>,
+    #[serde(
+        rename = "HttpErrorCodeReturnedEquals",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub http_error_code_returned_equals: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Redirect {
+    #[serde(rename = "HostName", skip_serializing_if = "Option::is_none")]
+    pub host_name: Option<String>,
+    #[serde(rename = "HttpRedirectCode", skip_serializing_if = "Option::is_none")]
+    pub http_redirect_code: Option<String>,
+    #[serde(rename = "Protocol", skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    #[serde(
+        rename = "ReplaceKeyPrefixWith",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub replace_key_prefix_with: Option<String>,
+    #[serde(rename = "ReplaceKeyWith", skip_serializing_if = "Option::is_none")]
+    pub replace_key_with: Option<String>,
+}
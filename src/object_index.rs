@@ -0,0 +1,389 @@
+use crate::cache::{self, MetadataCache};
+use crate::fs::{self, Metadata};
+use crate::util::keyring::Keyring;
+use anyhow::Context;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+// 元数据文件的后缀，旧版本每个对象一个 {bucket}/{key}.meta 文件
+const META_SUFFIX: &str = ".meta";
+// 已导入旧版本元数据文件的标记
+const MIGRATED_KEY: &[u8] = b"migrated";
+// 已不存在旧版 AES-256-CBC 格式元数据的标记，此后不再接受未经认证的元数据
+const LEGACY_FREE_KEY: &[u8] = b"legacy_free";
+// 未完成的元数据密钥轮换的目标密钥ID
+const ROTATION_KEY: &[u8] = b"rotation";
+// 大于所有 UTF-8 字符的字节，用于跳过一个前缀下的全部键
+const MAX_BYTE: u8 = 0xFF;
+
+// 对象元数据索引，键为 {bucket}/{key}，值为加密后的元数据，按键有序
+// 由状态机在应用日志时维护，随快照复制到其他节点
+// 读取过的元数据解析后放入缓存，覆盖或删除对象时移出
+#[derive(Debug, Clone)]
+pub struct ObjectIndex {
+    objects: sled::Tree,
+    state: sled::Tree,
+    cache: MetadataCache,
+    // 节点的元数据密钥，轮换时替换，状态机和请求处理共用
+    keyring: Arc<RwLock<Option<Arc<Keyring>>>>,
+}
+
+// 列举对象的条件，与 ListObjects 的参数一致
+#[derive(Debug, Clone, Default)]
+pub struct ListQuery {
+    pub prefix: String,
+    pub delimiter: Option<String>,
+    pub marker: Option<String>,
+    pub max_keys: usize,
+}
+
+// 列举结果，对象按键有序，公共前缀只出现一次
+#[derive(Debug, Default)]
+pub struct Listing {
+    pub objects: Vec<(String, Vec<u8>)>,
+    pub common_prefixes: Vec<String>,
+    pub is_truncated: bool,
+    // 结果被截断时，下一页从这个键之后开始
+    pub next_marker: Option<String>,
+}
+
+fn object_key(bucket_name: &str, object_key: &str) -> Vec<u8> {
+    format!("{}/{}", bucket_name, object_key).into_bytes()
+}
+
+// 一个前缀下全部键之后的位置
+fn after_prefix(prefix: &[u8]) -> Vec<u8> {
+    let mut res = prefix.to_vec();
+    res.push(MAX_BYTE);
+    res
+}
+
+impl ObjectIndex {
+    pub fn open(db: &sled::Db) -> sled::Result<Self> {
+        Ok(ObjectIndex {
+            objects: db.open_tree("objects")?,
+            state: db.open_tree("object_index")?,
+            cache: cache::metadata_cache(0),
+            keyring: Arc::default(),
+        })
+    }
+
+    // 使用 keyring 加密和解密元数据
+    pub fn with_keyring(self, keyring: Keyring) -> Self {
+        self.install_keyring(keyring);
+        self
+    }
+
+    // 替换元数据密钥，共用索引的各处立即生效
+    pub fn install_keyring(&self, keyring: Keyring) {
+        *self.keyring.write().unwrap() = Some(Arc::new(keyring));
+    }
+
+    pub fn keyring(&self) -> anyhow::Result<Arc<Keyring>> {
+        self.keyring
+            .read()
+            .unwrap()
+            .clone()
+            .context("元数据密钥未初始化")
+    }
+
+    // 用当前的元数据密钥解密并解析元数据
+    pub fn decode(&self, value: &[u8]) -> anyhow::Result<Metadata> {
+        let keyring = self.keyring()?;
+        fs::decode_metadata(&keyring, value)
+    }
+
+    // 缓存最多 capacity 字节的元数据
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = cache::metadata_cache(capacity);
+        self
+    }
+
+    pub fn get(&self, bucket_name: &str, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self
+            .objects
+            .get(object_key(bucket_name, key))?
+            .map(|value| value.to_vec()))
+    }
+
+    pub fn contains(&self, bucket_name: &str, key: &str) -> anyhow::Result<bool> {
+        Ok(self.objects.contains_key(object_key(bucket_name, key))?)
+    }
+
+    pub fn put(&self, bucket_name: &str, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        let key = object_key(bucket_name, key);
+        self.objects.insert(&key, value)?;
+        self.cache.remove(&key);
+        Ok(())
+    }
+
+    pub fn remove(&self, bucket_name: &str, key: &str) -> anyhow::Result<()> {
+        let key = object_key(bucket_name, key);
+        self.objects.remove(&key)?;
+        self.cache.remove(&key);
+        Ok(())
+    }
+
+    // 读取对象的元数据，对象不存在时为 None
+    // 缓存的条目按加密后的元数据长度计算大小
+    pub fn load(&self, bucket_name: &str, key: &str) -> anyhow::Result<Option<Metadata>> {
+        let key = object_key(bucket_name, key);
+        if let Some(metadata) = self.cache.get(&key) {
+            return Ok(Some(metadata));
+        }
+        let Some(value) = self.objects.get(&key)? else {
+            return Ok(None);
+        };
+        let metadata = self.decode(&value)?;
+        let size = key.len() + value.len();
+        // 解析期间对象可能已被覆盖，索引中仍是这份元数据时才放入缓存
+        let valid = || matches!(self.objects.get(&key), Ok(Some(current)) if current == value);
+        self.cache
+            .insert_if(key.clone(), metadata.clone(), size, valid);
+        Ok(Some(metadata))
+    }
+
+    // 保存对象的元数据
+    pub fn save(&self, bucket_name: &str, key: &str, metadata: &Metadata) -> anyhow::Result<()> {
+        let keyring = self.keyring()?;
+        self.put(bucket_name, key, &fs::encode_metadata(&keyring, metadata)?)
+    }
+
+    // 桶内全部对象的键和元数据
+    pub fn scan(
+        &self,
+        bucket_name: &str,
+    ) -> impl Iterator<Item = anyhow::Result<(String, Vec<u8>)>> {
+        let prefix = object_key(bucket_name, "");
+        self.objects.scan_prefix(&prefix).map(move |item| {
+            let (key, value) = item?;
+            let key = std::str::from_utf8(&key[prefix.len()..]).context("对象键格式错误")?;
+            Ok((key.to_string(), value.to_vec()))
+        })
+    }
+
+    // 全部对象的桶名、键和元数据
+    pub fn scan_all(&self) -> impl Iterator<Item = anyhow::Result<(String, String, Vec<u8>)>> {
+        self.objects.iter().map(|item| {
+            let (key, value) = item?;
+            let key = std::str::from_utf8(&key).context("对象键格式错误")?;
+            let (bucket_name, key) = key.split_once('/').context("对象键格式错误")?;
+            Ok((bucket_name.to_string(), key.to_string(), value.to_vec()))
+        })
+    }
+
+    // 删除桶内全部对象
+    pub fn remove_bucket(&self, bucket_name: &str) -> anyhow::Result<usize> {
+        let keys: Vec<_> = self
+            .objects
+            .scan_prefix(object_key(bucket_name, ""))
+            .keys()
+            .collect::<Result<_, _>>()?;
+        for key in &keys {
+            self.objects.remove(key)?;
+            self.cache.remove(&key.to_vec());
+        }
+        Ok(keys.len())
+    }
+
+    // 按前缀和分隔符列举桶内对象，键有序，只扫描结果涉及的范围
+    // 同一公共前缀下的键整体跳过，公共前缀和对象都计入 max_keys
+    pub fn list(&self, bucket_name: &str, query: &ListQuery) -> anyhow::Result<Listing> {
+        let root = object_key(bucket_name, "");
+        let prefix = object_key(bucket_name, &query.prefix);
+        let delimiter = query.delimiter.as_deref().filter(|d| !d.is_empty());
+        let mut start = prefix.clone();
+        if let Some(marker) = &query.marker {
+            let marker = object_key(bucket_name, marker);
+            // 公共前缀作为 marker 时跳过它下面的全部键
+            let skip = match delimiter {
+                Some(delimiter) => marker.ends_with(delimiter.as_bytes()),
+                None => false,
+            };
+            let after = match skip {
+                true => after_prefix(&marker),
+                false => [&marker[..], &[0]].concat(),
+            };
+            start = start.max(after);
+        }
+        let mut listing = Listing::default();
+        let mut count = 0;
+        loop {
+            let Some(item) = self.objects.range(start.clone()..).next() else {
+                break;
+            };
+            let (key, value) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let name = std::str::from_utf8(&key[root.len()..]).context("对象键格式错误")?;
+            let common_prefix = delimiter.and_then(|delimiter| {
+                let rest = &name[query.prefix.len()..];
+                rest.find(delimiter)
+                    .map(|at| name[..query.prefix.len() + at + delimiter.len()].to_string())
+            });
+            if count == query.max_keys {
+                listing.is_truncated = true;
+                break;
+            }
+            count += 1;
+            match common_prefix {
+                Some(common_prefix) => {
+                    start = after_prefix(&object_key(bucket_name, &common_prefix));
+                    listing.next_marker = Some(common_prefix.clone());
+                    listing.common_prefixes.push(common_prefix);
+                }
+                None => {
+                    start = [&key[..], &[0]].concat();
+                    listing.next_marker = Some(name.to_string());
+                    listing.objects.push((name.to_string(), value.to_vec()));
+                }
+            }
+        }
+        if !listing.is_truncated {
+            listing.next_marker = None;
+        }
+        Ok(listing)
+    }
+
+    // 全部对象，用于生成快照
+    pub fn export(&self) -> sled::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.objects
+            .iter()
+            .map(|item| {
+                let (key, value) = item?;
+                Ok((key.to_vec(), value.to_vec()))
+            })
+            .collect()
+    }
+
+    // 用快照中的对象替换全部对象
+    pub fn import(&self, objects: Vec<(Vec<u8>, Vec<u8>)>) -> sled::Result<()> {
+        self.objects.clear()?;
+        self.cache.clear();
+        for (key, value) in objects {
+            self.objects.insert(key, value)?;
+        }
+        self.objects.flush()?;
+        Ok(())
+    }
+
+    // 用 f 重新加密全部对象的元数据，f 返回 None 时不修改，返回修改的数量
+    // 只替换未被并发修改的值，重新加密的结果落盘后返回
+    pub fn reencrypt(
+        &self,
+        f: impl Fn(&[u8]) -> anyhow::Result<Option<Vec<u8>>>,
+    ) -> anyhow::Result<usize> {
+        let mut count = 0;
+        for item in self.objects.iter() {
+            let (key, value) = item?;
+            if let Some(new) = f(&value)? {
+                if self
+                    .objects
+                    .compare_and_swap(&key, Some(value), Some(new))?
+                    .is_ok()
+                {
+                    count += 1;
+                }
+            }
+        }
+        self.objects.flush()?;
+        Ok(count)
+    }
+
+    // 记录元数据密钥轮换的目标，重新加密完成前重启后继续
+    pub fn start_rotation(&self, key_id: &str) -> anyhow::Result<()> {
+        self.state.insert(ROTATION_KEY, key_id.as_bytes())?;
+        self.state.flush()?;
+        Ok(())
+    }
+
+    // 未完成的元数据密钥轮换的目标密钥ID
+    pub fn rotation(&self) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .state
+            .get(ROTATION_KEY)?
+            .map(|id| String::from_utf8_lossy(&id).into_owned()))
+    }
+
+    // 完成到 key_id 的轮换，期间又开始了新的轮换时保留新的目标
+    pub fn finish_rotation(&self, key_id: &str) -> anyhow::Result<()> {
+        self.objects.flush()?;
+        let _ = self.state.compare_and_swap(
+            ROTATION_KEY,
+            Some(key_id.as_bytes()),
+            None as Option<&[u8]>,
+        )?;
+        self.state.flush()?;
+        Ok(())
+    }
+
+    // 是否已确认不存在旧版格式的元数据
+    pub fn legacy_free(&self) -> anyhow::Result<bool> {
+        Ok(self.state.contains_key(LEGACY_FREE_KEY)?)
+    }
+
+    // 记录已不存在旧版格式的元数据，重新加密的元数据落盘后才记录
+    pub fn mark_legacy_free(&self) -> anyhow::Result<()> {
+        self.objects.flush()?;
+        self.state.insert(LEGACY_FREE_KEY, Vec::new())?;
+        self.state.flush()?;
+        Ok(())
+    }
+
+    // 导入旧版本保存在 root 下的 .meta 文件，索引中已有的对象不覆盖，导入后删除文件
+    // 只执行一次，分片上传的临时元数据 .meta.{uploadId} 仍保存为文件
+    pub fn migrate(&self, root: &Path) -> anyhow::Result<usize> {
+        if self.state.contains_key(MIGRATED_KEY)? {
+            return Ok(0);
+        }
+        let mut count = 0;
+        let mut migrated = Vec::new();
+        let Ok(buckets) = std::fs::read_dir(root) else {
+            self.state.insert(MIGRATED_KEY, Vec::new())?;
+            return Ok(0);
+        };
+        for bucket in buckets.flatten() {
+            if !bucket.file_type()?.is_dir() {
+                continue;
+            }
+            let bucket_name = bucket.file_name().to_string_lossy().to_string();
+            let mut dirs = vec![bucket.path()];
+            while let Some(dir) = dirs.pop() {
+                for entry in std::fs::read_dir(&dir)?.flatten() {
+                    let path = entry.path();
+                    if entry.file_type()?.is_dir() {
+                        dirs.push(path);
+                        continue;
+                    }
+                    let Some(key) = path
+                        .strip_prefix(bucket.path())?
+                        .to_str()
+                        .and_then(|key| key.strip_suffix(META_SUFFIX))
+                    else {
+                        continue;
+                    };
+                    let value = std::fs::read(&path)
+                        .with_context(|| format!("读取元数据文件 {} 失败", path.display()))?;
+                    let key = object_key(&bucket_name, key);
+                    if self
+                        .objects
+                        .compare_and_swap(&key, None as Option<&[u8]>, Some(value))?
+                        .is_ok()
+                    {
+                        count += 1;
+                    }
+                    migrated.push(path);
+                }
+            }
+        }
+        // 索引落盘后才删除旧的元数据文件，中途退出时下次启动重新导入剩余的文件
+        self.objects.flush()?;
+        for path in migrated {
+            std::fs::remove_file(&path)?;
+        }
+        self.state.insert(MIGRATED_KEY, Vec::new())?;
+        self.state.flush()?;
+        Ok(count)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::bucket::{config_key, USAGE};
+use crate::object_index::ObjectIndex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// 桶配额，未设置的限制不做检查
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct BucketQuota {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_objects: Option<u64>,
+}
+
+// 桶的当前用量，由状态机在应用写入和删除时精确维护
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct BucketUsage {
+    pub bytes: u64,
+    pub objects: u64,
+}
+
+// 一次对象写入或删除对用量的影响
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UsageChange {
+    // 操作前同名对象的大小，对象不存在时为None
+    pub previous: Option<u64>,
+    // 操作后对象的大小，删除时为None
+    pub current: Option<u64>,
+}
+
+impl UsageChange {
+    // 写入对象，覆盖已有对象时扣除旧对象的大小
+    pub fn put(previous: Option<u64>, size: u64) -> Self {
+        UsageChange {
+            previous,
+            current: Some(size),
+        }
+    }
+
+    // 上传分片，分片只占用空间，不计入对象数
+    pub fn part(size: u64) -> Self {
+        UsageChange {
+            previous: Some(0),
+            current: Some(size),
+        }
+    }
+
+    pub fn delete(previous: Option<u64>) -> Self {
+        UsageChange {
+            previous,
+            current: None,
+        }
+    }
+}
+
+impl BucketUsage {
+    // 应用变化后的用量
+    pub fn after(&self, change: UsageChange) -> BucketUsage {
+        let bytes = self.bytes.saturating_sub(change.previous.unwrap_or(0));
+        let objects = self
+            .objects
+            .saturating_sub(u64::from(change.previous.is_some()));
+        BucketUsage {
+            bytes: bytes.saturating_add(change.current.unwrap_or(0)),
+            objects: objects.saturating_add(u64::from(change.current.is_some())),
+        }
+    }
+}
+
+impl BucketQuota {
+    // 变化后的用量是否在配额内
+    // 配额调低到当前用量以下时，不增加用量的写入(如删除、缩小对象)仍然允许
+    pub fn allows(&self, usage: &BucketUsage, change: UsageChange) -> bool {
+        let next = usage.after(change);
+        let within = |max: Option<u64>, before: u64, after: u64| match max {
+            Some(max) => after <= max || after <= before,
+            None => true,
+        };
+        within(self.max_bytes, usage.bytes, next.bytes)
+            && within(self.max_objects, usage.objects, next.objects)
+    }
+}
+
+// 桶用量计数保存在状态机的键值表中，只由状态机在应用日志时更新
+// 创建桶时开始计数；启用配额前创建的桶没有计数，设置配额时扫描对象统计
+
+// 读取桶用量计数，没有计数时为 None
+pub fn usage(kvs: &BTreeMap<String, String>, bucket_name: &str) -> Option<BucketUsage> {
+    kvs.get(&config_key(bucket_name, USAGE))
+        .and_then(|value| serde_json::from_str(value).ok())
+}
+
+pub fn set_usage(kvs: &mut BTreeMap<String, String>, bucket_name: &str, usage: BucketUsage) {
+    if let Ok(value) = serde_json::to_string(&usage) {
+        kvs.insert(config_key(bucket_name, USAGE), value);
+    }
+}
+
+// 按写入或删除更新桶用量，没有计数的桶不从零开始计数，以免少计已有的对象
+pub fn update_usage(kvs: &mut BTreeMap<String, String>, bucket_name: &str, change: UsageChange) {
+    if let Some(current) = usage(kvs, bucket_name) {
+        set_usage(kvs, bucket_name, current.after(change));
+    }
+}
+
+// 扫描对象元数据索引统计桶用量，进行中的分片上传不计入
+pub fn count_usage(objects: &ObjectIndex, bucket_name: &str) -> anyhow::Result<BucketUsage> {
+    let mut usage = BucketUsage::default();
+    for item in objects.scan(bucket_name) {
+        let (_, value) = item?;
+        usage.bytes += objects.decode(&value)?.size;
+        usage.objects += 1;
+    }
+    Ok(usage)
+}
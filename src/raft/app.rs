@@ -5,8 +5,13 @@ use std::sync::Arc;
 use openraft::Config;
 use tokio::sync::{Mutex, RwLock};
 
+use crate::gc::ChunkGc;
+use crate::layout::Layout;
+use crate::meta_key::MetadataKeys;
+use crate::object_index::ObjectIndex;
 use crate::raft::ExampleRaft;
 use crate::raft::NodeId;
+use crate::scrub::ScrubStatus;
 
 
 #[derive(Clone, Eq, PartialEq)]
@@ -39,4 +44,20 @@ pub struct App {
     pub config: Arc<Config>,
     pub nodes: Arc<Mutex<BTreeSet<NodeId>>>,
     pub node_descs: Arc<Mutex<BTreeSet<NodeDesc>>>,
+    // 分块回收，保存推送来的分块时刷新孤儿时间
+    pub chunk_gc: ChunkGc,
+    // 节点的本地目录
+    pub layout: Arc<Layout>,
+    // 对象元数据索引，与状态机共享
+    pub objects: ObjectIndex,
+    // 下载时预读的分块数
+    pub read_ahead: usize,
+    // 不超过该长度的对象保存在元数据中，0 表示不内联
+    pub inline_threshold: usize,
+    // 主密钥，用于包装每个对象的数据密钥，集群内所有节点必须一致
+    pub master_key: [u8; 32],
+    // 元数据密钥来源，与状态机共享
+    pub metadata_keys: Arc<MetadataKeys>,
+    // 本节点的巡检进度
+    pub scrub: Arc<std::sync::Mutex<ScrubStatus>>,
 }
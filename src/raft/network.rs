@@ -1,3 +1,4 @@
+pub mod chunk;
 pub mod raft;
 mod raft_network_impl;
 
@@ -0,0 +1,238 @@
+use crate::chunk_store::ChunkStore;
+use crate::fs;
+use crate::raft::app::App;
+use crate::raft::{Node, NodeId};
+use crate::util::erasure::{self, ErasureCode};
+use anyhow::anyhow;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use log::warn;
+use pilota::Bytes;
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::time::Duration;
+use volo_gen::rpc::raft::{
+    ChunkRequest, RaftServiceClient, RaftServiceClientBuilder, ShardRequest,
+};
+
+// 单次分块传输的超时时间
+const CHUNK_RPC_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn client(node: &Node) -> anyhow::Result<RaftServiceClient> {
+    let addr: SocketAddr = node.rpc_addr.parse()?;
+    Ok(RaftServiceClientBuilder::new("raft-service")
+        .address(addr)
+        .build())
+}
+
+// 当前成员的全部节点，按节点ID排序，各节点据此计算相同的分片位置
+pub(crate) fn members(app: &App) -> Vec<(NodeId, Node)> {
+    let membership = app.raft.metrics().borrow().membership_config.clone();
+    membership
+        .membership()
+        .nodes()
+        .map(|(id, node)| (*id, node.clone()))
+        .collect()
+}
+
+// 除本节点外的其他节点
+pub(crate) fn peers(app: &App) -> Vec<Node> {
+    members(app)
+        .into_iter()
+        .filter(|(id, _)| *id != app.id)
+        .map(|(_, node)| node)
+        .collect()
+}
+
+// 推送分块或分片到一个节点
+pub(crate) async fn push(node: Node, name: String, data: Bytes) -> anyhow::Result<()> {
+    let request = ChunkRequest {
+        name: name.into(),
+        data,
+    };
+    let reply = tokio::time::timeout(CHUNK_RPC_TIMEOUT, client(&node)?.put_chunk(request))
+        .await
+        .map_err(|_| anyhow!("推送分块超时"))?
+        .map_err(|err| anyhow!(err.to_string()))?;
+    match reply.error.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow!(reply.error.to_string())),
+    }
+}
+
+// 分块数据不经过 raft 日志，由接收请求的节点在本地保存后推送给其他节点
+// 包括本节点在内的多数投票节点保存后返回，其余节点的推送在后台继续，
+// 推送失败的节点在应用提交日志时再拉取
+pub(crate) async fn replicate(app: &App, name: &str, data: Vec<u8>) -> anyhow::Result<()> {
+    let membership = app.raft.metrics().borrow().membership_config.clone();
+    let membership = membership.membership();
+    let voters: BTreeSet<NodeId> = membership.voter_ids().collect();
+    let quorum = voters.len() / 2 + 1;
+    let mut acks = usize::from(voters.contains(&app.id));
+    let data = Bytes::from(data);
+    let mut pushes = FuturesUnordered::new();
+    for (id, node) in membership.nodes() {
+        if *id == app.id {
+            continue;
+        }
+        let id = *id;
+        let result = push(node.clone(), name.to_string(), data.clone());
+        pushes.push(tokio::spawn(async move { (id, result.await) }));
+    }
+    while acks < quorum {
+        match pushes.next().await {
+            Some(Ok((id, Ok(())))) if voters.contains(&id) => acks += 1,
+            Some(Ok((id, Err(err)))) => warn!("推送分块 {} 到节点 {} 失败: {}", name, id, err),
+            Some(_) => {}
+            None => {
+                return Err(anyhow!(
+                    "分块 {} 只写入了 {} 个节点，少于多数 {}",
+                    name,
+                    acks,
+                    quorum
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+// 从其他节点拉取分块，返回保存在磁盘上的压缩(和加密)数据
+pub(crate) async fn fetch<'a>(
+    nodes: impl IntoIterator<Item = &'a Node>,
+    name: &str,
+) -> anyhow::Result<Vec<u8>> {
+    for node in nodes {
+        let request = ChunkRequest {
+            name: name.to_string().into(),
+            data: Bytes::new(),
+        };
+        let client = match client(node) {
+            Ok(client) => client,
+            Err(err) => {
+                warn!("节点地址 {} 错误: {}", node.rpc_addr, err);
+                continue;
+            }
+        };
+        match tokio::time::timeout(CHUNK_RPC_TIMEOUT, client.get_chunk(request)).await {
+            Ok(Ok(reply)) if reply.error.is_empty() => return Ok(reply.data.to_vec()),
+            Ok(Ok(reply)) => warn!(
+                "从 {} 拉取分块 {} 失败: {}",
+                node.rpc_addr, name, reply.error
+            ),
+            Ok(Err(err)) => warn!("从 {} 拉取分块 {} 失败: {}", node.rpc_addr, name, err),
+            Err(_) => warn!("从 {} 拉取分块 {} 超时", node.rpc_addr, name),
+        }
+    }
+    Err(anyhow!("没有节点保存分块 {}", name))
+}
+
+// 读取节点保存的分块的纠删码分片，headers_only 时只返回分片头
+pub(crate) async fn get_shards(
+    node: &Node,
+    name: &str,
+    headers_only: bool,
+) -> anyhow::Result<Vec<Vec<u8>>> {
+    let request = ShardRequest {
+        name: name.to_string().into(),
+        headers_only,
+    };
+    let reply = tokio::time::timeout(CHUNK_RPC_TIMEOUT, client(node)?.get_shards(request))
+        .await
+        .map_err(|_| anyhow!("拉取分片超时"))?
+        .map_err(|err| anyhow!(err.to_string()))?;
+    match reply.error.is_empty() {
+        true => Ok(reply
+            .shards
+            .into_iter()
+            .map(|shard| shard.to_vec())
+            .collect()),
+        false => Err(anyhow!(reply.error.to_string())),
+    }
+}
+
+// 按纠删码编码分块，分片按分块名称分散保存到各节点，本节点的分片直接保存
+// 至少保存 write_quorum 个分片后返回，推送失败的分片由后台修复补齐
+pub(crate) async fn distribute(
+    app: &App,
+    name: &str,
+    data: &[u8],
+    code: ErasureCode,
+) -> anyhow::Result<()> {
+    let nodes = members(app);
+    code.check_placement(nodes.len())?;
+    let shards = code.encode(data)?;
+    let targets = erasure::placement(name, nodes.len(), shards.len());
+    let mut stored = 0;
+    let mut pushes = FuturesUnordered::new();
+    for (index, (shard, target)) in shards.into_iter().zip(targets).enumerate() {
+        let (id, node) = &nodes[target];
+        let shard_name = fs::shard_name(name, index);
+        if *id == app.id {
+            app.chunk_gc.save_chunk(&shard_name, &shard).await?;
+            stored += 1;
+        } else {
+            let id = *id;
+            let result = push(node.clone(), shard_name, Bytes::from(shard));
+            pushes.push(async move { (id, index, result.await) });
+        }
+    }
+    while let Some((id, index, result)) = pushes.next().await {
+        match result {
+            Ok(()) => stored += 1,
+            Err(err) => warn!(
+                "推送分块 {} 的分片 {} 到节点 {} 失败: {}",
+                name, index, id, err
+            ),
+        }
+    }
+    if stored < code.write_quorum() {
+        return Err(anyhow!(
+            "分块 {} 只保存了 {} 个分片，少于要求的 {} 个",
+            name,
+            stored,
+            code.write_quorum()
+        ));
+    }
+    Ok(())
+}
+
+// 读取分块保存在磁盘上的数据：本节点有完整分块时直接读取，
+// 否则收集本节点和其他节点的纠删码分片还原，没有分片时从其他节点拉取完整分块
+pub(crate) async fn load(
+    store: &dyn ChunkStore,
+    peers: Vec<Node>,
+    name: String,
+) -> anyhow::Result<Vec<u8>> {
+    if store.exists(&name).await {
+        return fs::load_chunk(store, &name).await;
+    }
+    load_remote(store, peers, name).await
+}
+
+// 不读取本节点的完整分块，用于本节点的分块损坏时
+pub(crate) async fn load_remote(
+    store: &dyn ChunkStore,
+    peers: Vec<Node>,
+    name: String,
+) -> anyhow::Result<Vec<u8>> {
+    let name = name.as_str();
+    let mut shards = fs::local_shards(store, name).await?;
+    let mut requests: FuturesUnordered<_> = peers
+        .iter()
+        .map(|node| async move { (node, get_shards(node, name, false).await) })
+        .collect();
+    while !erasure::is_recoverable(&shards) {
+        match requests.next().await {
+            Some((_, Ok(found))) => shards.extend(found),
+            Some((node, Err(err))) => {
+                warn!("从 {} 拉取分块 {} 的分片失败: {}", node.rpc_addr, name, err)
+            }
+            None => break,
+        }
+    }
+    match shards.is_empty() {
+        true => fetch(&peers, name).await,
+        false => erasure::decode(&shards),
+    }
+}
@@ -1,7 +1,10 @@
+use anyhow::anyhow;
 use log::debug;
 use std::sync::Arc;
 
+use crate::fs;
 use crate::raft::app::App;
+use crate::util::erasure;
 
 /// Raft protocol service.
 pub struct Raft {
@@ -58,4 +61,70 @@ impl volo_gen::rpc::raft::RaftService for Raft {
             error: Default::default(),
         })
     }
+    async fn put_chunk(
+        &self,
+        req: volo_gen::rpc::raft::ChunkRequest,
+    ) -> Result<volo_gen::rpc::raft::ChunkReply, volo_thrift::ServerError> {
+        debug!("handle put chunk {}", req.name);
+        let result = match fs::is_stored_name(&req.name) {
+            true => self.app.chunk_gc.save_chunk(&req.name, &req.data).await,
+            false => Err(anyhow!("分块名称错误: {}", req.name)),
+        };
+        Ok(volo_gen::rpc::raft::ChunkReply {
+            data: Default::default(),
+            error: result
+                .err()
+                .map(|err| err.to_string())
+                .unwrap_or_default()
+                .into(),
+        })
+    }
+    async fn get_chunk(
+        &self,
+        req: volo_gen::rpc::raft::ChunkRequest,
+    ) -> Result<volo_gen::rpc::raft::ChunkReply, volo_thrift::ServerError> {
+        let result = match fs::is_chunk_name(&req.name) {
+            true => fs::load_chunk(self.app.chunk_gc.store, &req.name).await,
+            false => Err(anyhow!("分块名称错误: {}", req.name)),
+        };
+        let reply = match result {
+            Ok(data) => volo_gen::rpc::raft::ChunkReply {
+                data: data.into(),
+                error: Default::default(),
+            },
+            Err(err) => volo_gen::rpc::raft::ChunkReply {
+                data: Default::default(),
+                error: err.to_string().into(),
+            },
+        };
+        Ok(reply)
+    }
+    async fn get_shards(
+        &self,
+        req: volo_gen::rpc::raft::ShardRequest,
+    ) -> Result<volo_gen::rpc::raft::ShardReply, volo_thrift::ServerError> {
+        let result = match fs::is_chunk_name(&req.name) {
+            true => fs::local_shards(self.app.chunk_gc.store, &req.name).await,
+            false => Err(anyhow!("分块名称错误: {}", req.name)),
+        };
+        let reply = match result {
+            Ok(mut shards) => {
+                // 只需要分片序号时不传输分片数据
+                if req.headers_only {
+                    for shard in &mut shards {
+                        shard.truncate(erasure::HEADER_LEN);
+                    }
+                }
+                volo_gen::rpc::raft::ShardReply {
+                    shards: shards.into_iter().map(Into::into).collect(),
+                    error: Default::default(),
+                }
+            }
+            Err(err) => volo_gen::rpc::raft::ShardReply {
+                shards: Default::default(),
+                error: err.to_string().into(),
+            },
+        };
+        Ok(reply)
+    }
 }
@@ -1,20 +1,39 @@
 use anyhow::{anyhow, Context};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Debug;
 use std::io::Cursor;
 use std::ops::RangeBounds;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::api::{BASIC_PATH_SUFFIX, DATA_DIR};
+use crate::api::parse_copy_source;
+use crate::bucket;
+use crate::cache;
+use crate::chunk_store::ChunkStore;
+use crate::meta_key::MetadataKeys;
 use crate::fs;
-use crate::fs::{save_metadata, split_file_and_save, Metadata};
-use crate::model::CompleteMultipartUpload;
+use crate::fs::{
+    save_metadata, split_file_and_save, InlineChunk, Metadata, ObjectChecksum, ObjectEncryption,
+    ObjectPart, PartInfo, SealedChunk,
+};
+use crate::gc::refs::{unix_now, ChunkRefs};
+use crate::gc::ChunkGc;
+use crate::layout::{metadata_path, Layout};
+use crate::model::{CompleteMultipartUpload, PartETag};
+use crate::object_index::ObjectIndex;
+use crate::quota::{self, UsageChange};
+use crate::util::checksum::ChecksumAlgorithm;
+use crate::util::chunker::ChunkingConfig;
+use crate::util::codec::CompressionConfig;
+use crate::util::durable::{self, Durability};
+use crate::util::erasure::ErasureCode;
+use crate::util::keyring::Keyring;
 use byteorder::BigEndian;
 use byteorder::ReadBytesExt;
 use byteorder::WriteBytesExt;
 use chrono::Utc;
-use log::{debug, info};
+use log::{debug, error, info};
 use mime_guess::MimeGuess;
 use openraft::storage::LogFlushed;
 use openraft::storage::LogState;
@@ -44,7 +63,6 @@ use crate::raft::Node;
 use crate::raft::NodeId;
 use crate::raft::SnapshotData;
 use crate::raft::TypeConfig;
-use rayon::prelude::*;
 use sled::Db;
 
 /**
@@ -65,16 +83,25 @@ pub enum Request {
         bucket_name: String,
         object_key: String,
         upload_id: String,
+        encryption: Option<ObjectEncryption>,
+        checksum_algorithm: Option<String>,
+        website_redirect_location: Option<String>,
     },
+    // 数据随日志复制，只用于应用旧版本写入的日志，新的上传使用 CommitObject/CommitPart
     UploadChunk {
         part_number: String,
         upload_id: String,
         hash: String,
         body: Vec<u8>,
+        checksum: Option<String>,
     },
+    // 数据随日志复制，只用于应用旧版本写入的日志，新的上传使用 CommitObject/CommitPart
     UploadFile {
         file_path: String,
         body: Vec<u8>,
+        checksum: Option<ObjectChecksum>,
+        website_redirect_location: Option<String>,
+        chunking: ChunkingConfig,
     },
     CombineChunk {
         bucket_name: String,
@@ -89,6 +116,80 @@ pub enum Request {
         copy_source: String,
         dest_bucket: String,
         dest_object: String,
+        // 目标对象的加密信息，为None时沿用源对象的加密信息
+        encryption: Option<ObjectEncryption>,
+        // 网站重定向地址不随对象拷贝，只使用请求中指定的值
+        website_redirect_location: Option<String>,
+    },
+    // 数据随日志复制，只用于应用旧版本写入的日志，新的上传使用 CommitObject/CommitPart
+    UploadSealedFile {
+        file_path: String,
+        size: u64,
+        chunks: Vec<SealedChunk>,
+        encryption: ObjectEncryption,
+        checksum: Option<ObjectChecksum>,
+        website_redirect_location: Option<String>,
+        // 各加密分块的明文长度
+        chunk_sizes: Vec<u64>,
+    },
+    // 数据随日志复制，只用于应用旧版本写入的日志，新的上传使用 CommitObject/CommitPart
+    UploadSealedChunk {
+        part_number: String,
+        upload_id: String,
+        len: u64,
+        chunk: SealedChunk,
+        checksum: Option<String>,
+    },
+    SetBucketConfig {
+        bucket_name: String,
+        kind: String,
+        value: Option<String>,
+    },
+    // 切换到新的元数据密钥，日志中只记录密钥ID，密钥本身由各节点从本地密钥来源读取
+    RotateMetadataKey {
+        key_id: String,
+    },
+    // 扫描桶内对象重新统计用量，用于启用配额前已有数据的桶
+    RecountBucketUsage {
+        bucket_name: String,
+    },
+    // 中止分片上传，已上传分片的分块由引用计数回收
+    AbortUpload {
+        bucket_name: String,
+        object_key: String,
+        upload_id: String,
+    },
+    // 提交流式上传的对象，日志中只有分块列表，分块数据已由接收请求的节点推送到多数节点
+    CommitObject {
+        file_path: String,
+        size: u64,
+        chunks: Vec<String>,
+        chunk_sizes: Vec<u64>,
+        encryption: Option<ObjectEncryption>,
+        checksum: Option<ObjectChecksum>,
+        website_redirect_location: Option<String>,
+        // 分块以纠删码分片分散保存时的编码方式，各节点不拉取完整分块
+        erasure: Option<ErasureCode>,
+    },
+    // 提交流式上传的分片，分块同 CommitObject
+    CommitPart {
+        upload_id: String,
+        part_number: String,
+        size: u64,
+        etag: String,
+        checksum: Option<String>,
+        chunks: Vec<String>,
+        chunk_sizes: Vec<u64>,
+        erasure: Option<ErasureCode>,
+    },
+    // 提交小对象，数据随日志复制并保存在元数据中，不写入分块
+    CommitInlineObject {
+        file_path: String,
+        size: u64,
+        inline: InlineChunk,
+        encryption: Option<ObjectEncryption>,
+        checksum: Option<ObjectChecksum>,
+        website_redirect_location: Option<String>,
     },
 }
 
@@ -112,6 +213,16 @@ pub struct StoredSnapshot {
     pub data: Vec<u8>,
 }
 
+// 快照数据的格式标记，旧版本的快照只有 kvs
+const SNAPSHOT_MAGIC: &[u8] = b"RS3SNAP2";
+
+// 快照中的状态机数据
+#[derive(Serialize, Deserialize)]
+struct SnapshotState {
+    kvs: BTreeMap<String, String>,
+    objects: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
 #[derive(Debug, Clone)]
 pub struct StateMachineStore {
     pub data: StateMachineData,
@@ -124,6 +235,18 @@ pub struct StateMachineStore {
 
     /// State machine stores snapshot in db.
     db: Arc<Db>,
+
+    // 分块引用计数，与回收任务共享
+    gc: ChunkGc,
+
+    // 对象元数据索引
+    objects: ObjectIndex,
+
+    // 节点的本地目录
+    layout: Arc<Layout>,
+
+    // 元数据密钥来源，轮换时重新读取
+    metadata_keys: Arc<MetadataKeys>,
 }
 
 #[derive(Debug, Clone)]
@@ -143,7 +266,16 @@ impl RaftSnapshotBuilder<TypeConfig> for StateMachineStore {
 
         let kv_json = {
             let kvs = self.data.kvs.read().await;
-            postcard::to_stdvec(&*kvs).map_err(|e| StorageIOError::read_state_machine(&e))?
+            let state = SnapshotState {
+                kvs: kvs.clone(),
+                objects: self
+                    .objects
+                    .export()
+                    .map_err(|e| StorageIOError::read_state_machine(&e))?,
+            };
+            let data =
+                postcard::to_stdvec(&state).map_err(|e| StorageIOError::read_state_machine(&e))?;
+            [SNAPSHOT_MAGIC, &data].concat()
         };
 
         let snapshot_id = if let Some(last) = last_applied_log {
@@ -173,7 +305,19 @@ impl RaftSnapshotBuilder<TypeConfig> for StateMachineStore {
 }
 
 impl StateMachineStore {
-    async fn new(db: Arc<Db>) -> Result<StateMachineStore, StorageError<NodeId>> {
+    async fn new(
+        db: Arc<Db>,
+        layout: Arc<Layout>,
+        store: &'static dyn ChunkStore,
+        cache_size: usize,
+        keyring: Keyring,
+        metadata_keys: Arc<MetadataKeys>,
+    ) -> Result<StateMachineStore, StorageError<NodeId>> {
+        let (chunk_cache, metadata_cache) = cache::split_budget(cache_size);
+        let objects = ObjectIndex::open(&db)
+            .map_err(|e| StorageIOError::read_state_machine(&e))?
+            .with_cache(metadata_cache)
+            .with_keyring(keyring);
         let mut sm = Self {
             data: StateMachineData {
                 last_applied_log_id: None,
@@ -181,6 +325,15 @@ impl StateMachineStore {
                 kvs: Arc::new(Default::default()),
             },
             snapshot_idx: 0,
+            gc: ChunkGc {
+                refs: ChunkRefs::open(&db).map_err(|e| StorageIOError::read_state_machine(&e))?,
+                lock: Default::default(),
+                store,
+                cache: cache::chunk_cache(chunk_cache),
+            },
+            objects,
+            layout,
+            metadata_keys,
             db,
         };
 
@@ -188,6 +341,21 @@ impl StateMachineStore {
         if let Some(snap) = snapshot {
             sm.update_state_machine_(snap).await?;
         }
+        // 旧版本的对象元数据文件需在重放日志之前导入索引
+        let count = sm.objects.migrate(&sm.layout.buckets).map_err(|e| {
+            StorageIOError::read_state_machine(AnyError::error(format!("{:#}", e)))
+        })?;
+        if count > 0 {
+            info!("已将 {} 个对象的元数据导入索引", count);
+        }
+        // 旧版格式的元数据没有认证，升级后不再接受
+        let count = sm
+            .metadata_keys
+            .upgrade_legacy(&sm.layout.data, &sm.objects)
+            .map_err(|e| StorageIOError::read_state_machine(AnyError::error(format!("{:#}", e))))?;
+        if count > 0 {
+            info!("已将 {} 份旧版格式的元数据重新加密", count);
+        }
 
         Ok(sm)
     }
@@ -196,8 +364,25 @@ impl StateMachineStore {
         &mut self,
         snapshot: StoredSnapshot,
     ) -> Result<(), StorageError<NodeId>> {
-        let kvs: BTreeMap<String, String> = postcard::from_bytes(&snapshot.data)
-            .map_err(|e| StorageIOError::read_snapshot(Some(snapshot.meta.signature()), &e))?;
+        let signature = snapshot.meta.signature();
+        let (kvs, objects) = match snapshot.data.strip_prefix(SNAPSHOT_MAGIC) {
+            Some(data) => {
+                let state: SnapshotState = postcard::from_bytes(data)
+                    .map_err(|e| StorageIOError::read_snapshot(Some(signature.clone()), &e))?;
+                (state.kvs, Some(state.objects))
+            }
+            // 旧版本的快照不含对象，对象由旧的元数据文件导入
+            None => {
+                let kvs: BTreeMap<String, String> = postcard::from_bytes(&snapshot.data)
+                    .map_err(|e| StorageIOError::read_snapshot(Some(signature.clone()), &e))?;
+                (kvs, None)
+            }
+        };
+        if let Some(objects) = objects {
+            self.objects
+                .import(objects)
+                .map_err(|e| StorageIOError::read_snapshot(Some(signature), &e))?;
+        }
 
         self.data.last_applied_log_id = snapshot.meta.last_log_id;
         self.data.last_membership = snapshot.meta.last_membership.clone();
@@ -245,6 +430,61 @@ impl StateMachineStore {
     fn store(&self) -> sled::Tree {
         self.db.open_tree("store").unwrap()
     }
+
+    pub(crate) fn chunk_gc(&self) -> ChunkGc {
+        self.gc.clone()
+    }
+
+    pub(crate) fn objects(&self) -> ObjectIndex {
+        self.objects.clone()
+    }
+
+    // 按快照导入的对象重建引用计数，被引用而本节点没有的分块记入待拉取队列，由修复任务拉取
+    // 调用方已持有回收锁
+    async fn rebuild_chunk_refs(&self) -> anyhow::Result<()> {
+        let counts = scan_chunk_refs(&self.layout, &self.objects)?;
+        let stored = fs::stored_chunks(self.gc.store).await?;
+        self.gc.refs.rebuild(&counts, &stored, unix_now())?;
+        let mut missing = 0;
+        for hash in counts.keys() {
+            if stored.binary_search(hash).is_err() {
+                self.gc.refs.add_missing(hash)?;
+                missing += 1;
+            }
+        }
+        info!(
+            "安装快照后重建分块引用计数，{} 个分块被引用，{} 个等待从其他节点拉取",
+            counts.len(),
+            missing
+        );
+        Ok(())
+    }
+
+    // 在配额内执行对象写入并更新桶用量，超出配额时不执行写入
+    // 各节点按相同顺序应用日志，判断结果一致；leader 提交前的检查无法覆盖并发写入，由这里兜底
+    async fn write_object<F>(
+        &self,
+        metadata_path: &str,
+        change: UsageChange,
+        write: F,
+    ) -> Option<String>
+    where
+        F: std::future::Future<Output = anyhow::Result<()>>,
+    {
+        let Some(bucket_name) = bucket_of(&self.layout, metadata_path) else {
+            return write.await.err().map(|err| write_failed(metadata_path, err));
+        };
+        if !bucket::quota_allows(&*self.data.kvs.read().await, &bucket_name, change) {
+            info!("桶 {} 超出配额，拒绝写入 {}", bucket_name, metadata_path);
+            return Some(bucket::QUOTA_EXCEEDED.to_string());
+        }
+        if let Err(err) = write.await {
+            return Some(write_failed(metadata_path, err));
+        }
+        let mut kvs = self.data.kvs.write().await;
+        quota::update_usage(&mut kvs, &bucket_name, change);
+        None
+    }
 }
 
 impl RaftStateMachine<TypeConfig> for StateMachineStore {
@@ -270,67 +510,376 @@ impl RaftStateMachine<TypeConfig> for StateMachineStore {
         for ent in entries {
             self.data.last_applied_log_id = Some(ent.log_id);
 
-            let resp_value = None;
+            let mut resp_value = None;
 
             match ent.payload {
                 EntryPayload::Blank => {}
-                EntryPayload::Normal(req) => match req {
-                    Request::CreateBucket { bucket_name } => {
-                        std::fs::create_dir_all(bucket_name)
-                            .context("创建桶失败")
-                            .unwrap();
-                    }
-                    Request::DeleteBucket { bucket_name } => {
-                        if std::fs::metadata(&bucket_name).is_ok() {
-                            std::fs::remove_dir_all(&bucket_name)
-                                .context("删除桶失败")
+                EntryPayload::Normal(req) => {
+                    // 持有回收锁，按应用前后引用的分块更新引用计数
+                    let _guard = self.gc.lock.clone().lock_owned().await;
+                    let layout = self.layout.clone();
+                    let (objects, store) = (&self.objects, self.gc.store);
+                    let scope = ChunkScope::of(&req, &layout, objects);
+                    let before = scope.chunks(&layout, objects);
+                    match req {
+                        // 旧版本的日志记录桶目录的完整路径，新的日志只记录桶名
+                        Request::CreateBucket { bucket_name } => {
+                            let bucket_dir = layout.resolve(&bucket_name);
+                            std::fs::create_dir_all(&bucket_dir)
+                                .context("创建桶失败")
                                 .unwrap();
+                            // 创建时开始统计用量，重新创建已有的桶时计入其中的对象
+                            if let Some(name) = bucket_dir.file_name() {
+                                let name = name.to_string_lossy();
+                                match quota::count_usage(objects, &name) {
+                                    Ok(usage) => {
+                                        let mut kvs = self.data.kvs.write().await;
+                                        quota::set_usage(&mut kvs, &name, usage);
+                                    }
+                                    Err(err) => error!("统计桶 {} 用量失败: {}", name, err),
+                                }
+                            }
+                        }
+                        Request::DeleteBucket { bucket_name } => {
+                            let bucket_dir = layout.resolve(&bucket_name);
+                            if std::fs::metadata(&bucket_dir).is_ok() {
+                                std::fs::remove_dir_all(&bucket_dir)
+                                    .context("删除桶失败")
+                                    .unwrap();
+                            }
+                            if let Some(name) = bucket_dir.file_name() {
+                                let name = name.to_string_lossy();
+                                if let Err(err) = self.objects.remove_bucket(&name) {
+                                    error!("删除桶 {} 的对象失败: {}", name, err);
+                                }
+                                let prefix = bucket::config_prefix(&name);
+                                let mut kvs = self.data.kvs.write().await;
+                                kvs.retain(|key, _| !key.starts_with(&prefix));
+                            }
+                        }
+                        // Request::Set { key, value } => {
+                        //     resp_value = Some(value.clone());
+                        //
+                        //     let mut st = self.data.kvs.write().await;
+                        //     st.insert(key, value);
+                        // }
+                        Request::InitChunk {
+                            bucket_name,
+                            object_key,
+                            upload_id,
+                            encryption,
+                            checksum_algorithm,
+                            website_redirect_location,
+                        } => {
+                            let _ = init_chunk(
+                                &layout,
+                                objects,
+                                bucket_name,
+                                object_key,
+                                upload_id,
+                                encryption,
+                                checksum_algorithm,
+                                website_redirect_location,
+                            )
+                            .await;
+                        }
+                        Request::UploadChunk {
+                            part_number,
+                            upload_id,
+                            hash,
+                            body,
+                            checksum,
+                        } => {
+                            let part_path = layout.part_path(&upload_id, &part_number);
+                            let durability = layout.durability;
+                            let _ =
+                                upload_chunk(store, part_path, durability, &hash, body, checksum)
+                                    .await;
+                        }
+                        Request::UploadFile {
+                            file_path,
+                            body,
+                            checksum,
+                            website_redirect_location,
+                            chunking,
+                        } => {
+                            let size = object_size(&layout, objects, &file_path);
+                            let change = UsageChange::put(size, body.len() as u64);
+                            resp_value = self
+                                .write_object(
+                                    &file_path,
+                                    change,
+                                    upload_file(
+                                        &layout,
+                                        objects,
+                                        store,
+                                        file_path.clone(),
+                                        body,
+                                        checksum,
+                                        website_redirect_location,
+                                        chunking,
+                                    ),
+                                )
+                                .await;
+                        }
+                        Request::CombineChunk {
+                            bucket_name,
+                            object_key,
+                            upload_id,
+                            cmu,
+                        } => {
+                            let cmu: CompleteMultipartUpload =
+                                quick_xml::de::from_str(&cmu).unwrap();
+                            let metadata_path = metadata_path(&bucket_name, &object_key);
+                            let size = load_parts(&layout, &upload_id, &cmu.part_etags)
+                                .map(|parts| parts.iter().map(|part| part.size).sum())
+                                .unwrap_or(0);
+                            let previous = object_size(&layout, objects, &metadata_path);
+                            let change = UsageChange::put(previous, size);
+                            resp_value = self
+                                .write_object(
+                                    &metadata_path,
+                                    change,
+                                    combine_chunk(
+                                        &layout,
+                                        objects,
+                                        &self.gc,
+                                        &bucket_name,
+                                        &object_key,
+                                        &upload_id,
+                                        cmu,
+                                    ),
+                                )
+                                .await;
+                        }
+                        Request::DeleteFile { file_path } => {
+                            let change =
+                                UsageChange::delete(object_size(&layout, objects, &file_path));
+                            let delete = do_delete_file(&layout, objects, file_path.clone());
+                            resp_value = self.write_object(&file_path, change, delete).await;
+                        }
+                        Request::CopyFile {
+                            copy_source,
+                            dest_bucket,
+                            dest_object,
+                            encryption,
+                            website_redirect_location,
+                        } => {
+                            let size = parse_copy_source(&copy_source)
+                                .and_then(|(bucket_name, object_key)| {
+                                    let metadata_path = metadata_path(&bucket_name, &object_key);
+                                    object_size(&layout, objects, &metadata_path)
+                                })
+                                .unwrap_or(0);
+                            let metadata_path = metadata_path(&dest_bucket, &dest_object);
+                            let previous = object_size(&layout, objects, &metadata_path);
+                            let change = UsageChange::put(previous, size);
+                            resp_value = self
+                                .write_object(
+                                    &metadata_path,
+                                    change,
+                                    copy_object(
+                                        objects,
+                                        &copy_source,
+                                        &dest_bucket,
+                                        &dest_object,
+                                        encryption,
+                                        website_redirect_location,
+                                    ),
+                                )
+                                .await;
+                        }
+                        Request::UploadSealedFile {
+                            file_path,
+                            size,
+                            chunks,
+                            encryption,
+                            checksum,
+                            website_redirect_location,
+                            chunk_sizes,
+                        } => {
+                            let change =
+                                UsageChange::put(object_size(&layout, objects, &file_path), size);
+                            resp_value = self
+                                .write_object(
+                                    &file_path,
+                                    change,
+                                    upload_sealed_file(
+                                        &layout,
+                                        objects,
+                                        store,
+                                        file_path.clone(),
+                                        size,
+                                        chunks,
+                                        encryption,
+                                        checksum,
+                                        website_redirect_location,
+                                        chunk_sizes,
+                                    ),
+                                )
+                                .await;
+                        }
+                        Request::UploadSealedChunk {
+                            part_number,
+                            upload_id,
+                            len,
+                            chunk,
+                            checksum,
+                        } => {
+                            let part_path = layout.part_path(&upload_id, &part_number);
+                            let durability = layout.durability;
+                            let _ = upload_sealed_chunk(
+                                store, part_path, durability, len, chunk, checksum,
+                            )
+                            .await;
+                        }
+                        Request::SetBucketConfig {
+                            bucket_name,
+                            kind,
+                            value,
+                        } => {
+                            let key = bucket::config_key(&bucket_name, &kind);
+                            let mut kvs = self.data.kvs.write().await;
+                            match value {
+                                Some(value) => {
+                                    kvs.insert(key, value);
+                                }
+                                None => {
+                                    kvs.remove(&key);
+                                }
+                            }
+                        }
+                        // 只切换密钥，已有的元数据由后台任务重新加密；
+                        // 切换失败时停止应用日志，更新密钥文件后重启节点继续
+                        Request::RotateMetadataKey { key_id } => {
+                            if let Err(err) = self.metadata_keys.switch(objects, &key_id) {
+                                error!("元数据密钥轮换失败: {:#}", err);
+                                return Err(StorageIOError::apply(
+                                    ent.log_id,
+                                    AnyError::error(format!("{:#}", err)),
+                                )
+                                .into());
+                            }
+                            info!("元数据密钥已切换为 {}", key_id);
+                            resp_value = Some(key_id);
+                        }
+                        Request::RecountBucketUsage { bucket_name } => {
+                            match quota::count_usage(objects, &bucket_name) {
+                                Ok(usage) => {
+                                    let mut kvs = self.data.kvs.write().await;
+                                    quota::set_usage(&mut kvs, &bucket_name, usage);
+                                    resp_value = serde_json::to_string(&usage).ok();
+                                }
+                                Err(err) => error!("统计桶 {} 用量失败: {}", bucket_name, err),
+                            }
+                        }
+                        Request::AbortUpload {
+                            bucket_name,
+                            object_key,
+                            upload_id,
+                        } => {
+                            let _ = abort_upload(&layout, &bucket_name, &object_key, &upload_id);
+                        }
+                        Request::CommitObject {
+                            file_path,
+                            size,
+                            chunks,
+                            chunk_sizes,
+                            encryption,
+                            checksum,
+                            website_redirect_location,
+                            erasure,
+                        } => {
+                            if erasure.is_none() {
+                                queue_missing_chunks(&self.gc, &chunks).await;
+                            }
+                            let change =
+                                UsageChange::put(object_size(&layout, objects, &file_path), size);
+                            let metadata = StagedObject {
+                                size,
+                                chunks,
+                                chunk_sizes,
+                                inline: None,
+                                encryption,
+                                checksum,
+                                website_redirect_location,
+                                erasure,
+                            };
+                            resp_value = self
+                                .write_object(
+                                    &file_path,
+                                    change,
+                                    commit_object(&layout, objects, &file_path, metadata),
+                                )
+                                .await;
+                        }
+                        Request::CommitPart {
+                            upload_id,
+                            part_number,
+                            size,
+                            etag,
+                            checksum,
+                            chunks,
+                            chunk_sizes,
+                            erasure,
+                        } => {
+                            if erasure.is_none() {
+                                queue_missing_chunks(&self.gc, &chunks).await;
+                            }
+                            let info = PartInfo {
+                                size,
+                                etag,
+                                checksum,
+                                chunks,
+                                chunk_sizes,
+                                erasure,
+                            };
+                            let part_path = layout.part_path(&upload_id, &part_number);
+                            let path = part_path.to_string_lossy().to_string();
+                            resp_value = fs::save_part_info(part_path, &info, layout.durability)
+                                .await
+                                .err()
+                                .map(|err| write_failed(&path, err));
+                        }
+                        Request::CommitInlineObject {
+                            file_path,
+                            size,
+                            inline,
+                            encryption,
+                            checksum,
+                            website_redirect_location,
+                        } => {
+                            let change =
+                                UsageChange::put(object_size(&layout, objects, &file_path), size);
+                            let metadata = StagedObject {
+                                size,
+                                chunks: vec![],
+                                chunk_sizes: vec![],
+                                inline: Some(inline),
+                                encryption,
+                                checksum,
+                                website_redirect_location,
+                                erasure: None,
+                            };
+                            resp_value = self
+                                .write_object(
+                                    &file_path,
+                                    change,
+                                    commit_object(&layout, objects, &file_path, metadata),
+                                )
+                                .await;
                         }
                     }
-                    // Request::Set { key, value } => {
-                    //     resp_value = Some(value.clone());
-                    //
-                    //     let mut st = self.data.kvs.write().await;
-                    //     st.insert(key, value);
-                    // }
-                    Request::InitChunk {
-                        bucket_name,
-                        object_key,
-                        upload_id,
-                    } => {
-                        let _ = init_chunk(bucket_name, object_key, upload_id).await;
-                    }
-                    Request::UploadChunk {
-                        part_number,
-                        upload_id,
-                        hash,
-                        body,
-                    } => {
-                        let _ = upload_chunk(&part_number, &upload_id, &hash, body).await;
-                    }
-                    Request::UploadFile { file_path, body } => {
-                        let _ = upload_file(file_path, body).await;
-                    }
-                    Request::CombineChunk {
-                        bucket_name,
-                        object_key,
-                        upload_id,
-                        cmu,
-                    } => {
-                        let cmu: CompleteMultipartUpload = quick_xml::de::from_str(&cmu).unwrap();
-                        let _ = combine_chunk(&bucket_name, &object_key, &upload_id, cmu).await;
-                    }
-                    Request::DeleteFile { file_path } => {
-                        let _ = do_delete_file(file_path).await;
+                    let after = scope.chunks(&layout, objects);
+                    if let Err(err) = self.gc.refs.update(&before, &after, unix_now()) {
+                        error!("更新分块引用计数失败: {}", err);
                     }
-                    Request::CopyFile {
-                        copy_source,
-                        dest_bucket,
-                        dest_object,
-                    } => {
-                        let _ = copy_object(&copy_source, &dest_bucket, &dest_object).await;
+                    // 对象被覆盖或删除后不再引用的分块移出缓存，元数据由索引在写入时移出
+                    let kept: BTreeSet<&String> = after.iter().collect();
+                    for chunk in before.iter().filter(|chunk| !kept.contains(chunk)) {
+                        self.gc.cache.remove(chunk);
                     }
-                },
+                }
                 EntryPayload::Membership(mem) => {
                     self.data.last_membership = StoredMembership::new(Some(ent.log_id), mem);
                 }
@@ -362,10 +911,25 @@ impl RaftStateMachine<TypeConfig> for StateMachineStore {
             data: snapshot.into_inner(),
         };
 
+        // 快照替换了全部对象，导入和重建引用计数期间持有回收锁；
+        // 先清除就绪标记，重建失败时由回收任务在删除分块前重新扫描
+        let gc = self.gc.clone();
+        let _guard = gc.lock.lock().await;
+        gc.refs.reset().map_err(|e| {
+            StorageIOError::write_snapshot(
+                Some(meta.signature()),
+                AnyError::error(format!("{:#}", e)),
+            )
+        })?;
+
         self.update_state_machine_(new_snapshot.clone()).await?;
 
         self.set_current_snapshot_(new_snapshot)?;
 
+        if let Err(err) = self.rebuild_chunk_refs().await {
+            error!("安装快照后重建分块引用计数失败: {:#}", err);
+        }
+
         Ok(())
     }
 
@@ -381,7 +945,16 @@ impl RaftStateMachine<TypeConfig> for StateMachineStore {
 }
 
 // 上传文件
-async fn upload_file(metainfo_file_path: String, body: Vec<u8>) -> anyhow::Result<()> {
+async fn upload_file(
+    layout: &Layout,
+    objects: &ObjectIndex,
+    store: &dyn ChunkStore,
+    metainfo_file_path: String,
+    body: Vec<u8>,
+    checksum: Option<ObjectChecksum>,
+    website_redirect_location: Option<String>,
+    chunking: ChunkingConfig,
+) -> anyhow::Result<()> {
     let file_name = PathBuf::from(&metainfo_file_path)
         .file_name()
         .context("解析文件名失败")?
@@ -392,97 +965,161 @@ async fn upload_file(metainfo_file_path: String, body: Vec<u8>) -> anyhow::Resul
         .first_or_text_plain()
         .to_string();
 
-    let (file_size, hashcodes) = split_file_and_save(body, 8 << 20).await?;
+    let compression = CompressionConfig::default().for_content_type(&file_type);
+    let (file_size, hashcodes, chunk_sizes) =
+        split_file_and_save(store, body, &chunking, &compression).await?;
     let metainfo = Metadata {
         name: file_name,
         size: file_size as u64,
         file_type: file_type.to_string(),
         time: Utc::now(),
         chunks: hashcodes,
+        encryption: None,
+        checksum,
+        parts: vec![],
+        website_redirect_location,
+        chunk_sizes,
+        inline: None,
     };
-    fs::save_metadata(&metainfo_file_path, &metainfo)?;
+    save_path(layout, objects, &metainfo_file_path, &metainfo)?;
     Ok(())
 }
 
-// 桶间拷贝对象数据
+// 上传已在接收节点加密的文件
+async fn upload_sealed_file(
+    layout: &Layout,
+    objects: &ObjectIndex,
+    store: &dyn ChunkStore,
+    metainfo_file_path: String,
+    size: u64,
+    chunks: Vec<SealedChunk>,
+    encryption: ObjectEncryption,
+    checksum: Option<ObjectChecksum>,
+    website_redirect_location: Option<String>,
+    chunk_sizes: Vec<u64>,
+) -> anyhow::Result<()> {
+    let file_name = PathBuf::from(&metainfo_file_path)
+        .file_name()
+        .context("解析文件名失败")?
+        .to_string_lossy()
+        .to_string();
+    let file_type = MimeGuess::from_path(Path::new(&file_name))
+        .first_or_text_plain()
+        .to_string();
+
+    let mut hashcodes = Vec::with_capacity(chunks.len());
+    for chunk in &chunks {
+        store.put_if_absent(&chunk.name, &chunk.data).await?;
+        hashcodes.push(chunk.name.clone());
+    }
+    let metainfo = Metadata {
+        name: file_name,
+        size,
+        file_type,
+        time: Utc::now(),
+        chunks: hashcodes,
+        encryption: Some(encryption),
+        checksum,
+        parts: vec![],
+        website_redirect_location,
+        chunk_sizes,
+        inline: None,
+    };
+    save_path(layout, objects, &metainfo_file_path, &metainfo)?;
+    Ok(())
+}
+
+// 桶间拷贝对象数据，只复制元数据，数据块由源对象和目标对象共享
 async fn copy_object(
+    objects: &ObjectIndex,
     copy_source: &str,
     dest_bucket: &str,
     dest_object: &str,
+    encryption: Option<ObjectEncryption>,
+    website_redirect_location: Option<String>,
 ) -> anyhow::Result<()> {
-    let mut copy_source = copy_source.to_string();
-    if copy_source.contains('?') {
-        copy_source = copy_source.split('?').next().unwrap().to_string();
-    }
-
-    let copy_list: Vec<&str> = copy_source.split('/').collect();
-    let copy_list = &copy_list[..copy_list.len() - 1];
-
-    let mut src_bucket_name = String::new();
-    for &it in copy_list {
-        if !it.is_empty() {
-            src_bucket_name = it.to_string();
-            break;
-        }
-    }
-
-    let mut res = String::new();
-    for i in copy_list.iter().skip(1) {
-        res.push_str(i);
-        res.push('/');
+    let (src_bucket_name, src_object) =
+        parse_copy_source(copy_source).context("解析拷贝源失败")?;
+    let mut metadata = objects
+        .load(&src_bucket_name, &src_object)?
+        .context("源对象不存在")?;
+    metadata.name = PathBuf::from(dest_object)
+        .file_name()
+        .context("解析文件名失败")?
+        .to_string_lossy()
+        .to_string();
+    metadata.time = Utc::now();
+    if encryption.is_some() {
+        metadata.encryption = encryption;
     }
-    let src_object = &res;
-    let src_metadata_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(src_bucket_name)
-        .join(src_object);
-    let dest_metadata_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(BASIC_PATH_SUFFIX)
-        .join(dest_bucket)
-        .join(dest_object);
-    std::fs::copy(src_metadata_path, dest_metadata_path).map_err(|err| anyhow!(err))?;
+    metadata.website_redirect_location = website_redirect_location;
+    objects.save(dest_bucket, dest_object, &metadata)?;
 
     Ok(())
 }
 
-// 上传分片
-pub(crate) async fn upload_chunk(
-    part_number: &str,
-    upload_id: &str,
+// 上传分片，分片信息保存到 part_path
+async fn upload_chunk(
+    store: &dyn ChunkStore,
+    part_path: PathBuf,
+    durability: Durability,
     hash: &str,
     body: Vec<u8>,
+    checksum: Option<String>,
 ) -> anyhow::Result<()> {
-    let path = fs::path_from_hash(&hash);
-    if fs::is_path_exist(&path.to_string_lossy().to_string()) {
+    let info = PartInfo {
+        size: body.len() as u64,
+        etag: hash.to_string(),
+        checksum,
+        chunks: vec![],
+        chunk_sizes: vec![],
+        erasure: None,
+    };
+    fs::save_part_info(part_path, &info, durability).await?;
+    // 相同内容的分片已存在时只记录分片信息
+    if store.exists(hash).await {
         return Ok(());
     }
-    let hash_clone = hash;
-    let len = body.len();
-    let part_path = PathBuf::from(DATA_DIR.get().unwrap())
-        .join("tmp")
-        .join(upload_id)
-        .join(part_number);
-    tokio::fs::write(part_path, format!("{}", len))
-        .await
-        .map_err(|err| anyhow!(err.to_string()))?;
-    let body = fs::compress_chunk(std::io::Cursor::new(&body))?;
-    fs::save_file(&hash_clone, &body).await?;
+    let body = fs::compress_chunk(&body, &CompressionConfig::default())?;
+    store.put(hash, &body).await?;
+    Ok(())
+}
+
+// 上传已加密的分片
+async fn upload_sealed_chunk(
+    store: &dyn ChunkStore,
+    part_path: PathBuf,
+    durability: Durability,
+    len: u64,
+    chunk: SealedChunk,
+    checksum: Option<String>,
+) -> anyhow::Result<()> {
+    let info = PartInfo {
+        size: len,
+        etag: chunk.name.clone(),
+        checksum,
+        chunks: vec![],
+        chunk_sizes: vec![],
+        erasure: None,
+    };
+    fs::save_part_info(part_path, &info, durability).await?;
+    store.put_if_absent(&chunk.name, &chunk.data).await?;
     Ok(())
 }
 
 // 初始化分片上传
-async fn init_chunk(bucket: String, object_key: String, upload_id: String) -> anyhow::Result<()> {
-    let file_size_dir = PathBuf::from(DATA_DIR.get().unwrap())
-        .join("tmp")
-        .join(&upload_id);
-    let extension = &format!(".meta.{}", &upload_id);
-    let mut tmp_dir = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(crate::api::BASIC_PATH_SUFFIX)
-        .join(&bucket)
-        .join(&object_key)
-        .to_string_lossy()
-        .to_string();
-    tmp_dir.push_str(extension);
+async fn init_chunk(
+    layout: &Layout,
+    objects: &ObjectIndex,
+    bucket: String,
+    object_key: String,
+    upload_id: String,
+    encryption: Option<ObjectEncryption>,
+    checksum_algorithm: Option<String>,
+    website_redirect_location: Option<String>,
+) -> anyhow::Result<()> {
+    let file_size_dir = layout.upload_dir(&upload_id);
+    let tmp_dir = layout.upload_metadata(&bucket, &object_key, &upload_id);
     std::fs::create_dir_all(file_size_dir).map_err(|err| anyhow!(err))?;
     let file_name = Path::new(&object_key)
         .file_name()
@@ -498,13 +1135,27 @@ async fn init_chunk(bucket: String, object_key: String, upload_id: String) -> an
         file_type,
         time: Default::default(),
         chunks: vec![],
+        encryption,
+        // 分片上传完成前只记录算法
+        checksum: checksum_algorithm.map(|algorithm| ObjectChecksum {
+            algorithm,
+            value: String::new(),
+        }),
+        parts: vec![],
+        website_redirect_location,
+        chunk_sizes: vec![],
+        inline: None,
     };
-    save_metadata(&tmp_dir, &meta_info)?;
+    let keyring = objects.keyring()?;
+    save_metadata(&tmp_dir, &meta_info, &keyring, layout.durability)?;
     Ok(())
 }
 
 // 完成分片上传
 async fn combine_chunk(
+    layout: &Layout,
+    objects: &ObjectIndex,
+    gc: &ChunkGc,
     bucket_name: &str,
     object_key: &str,
     upload_id: &str,
@@ -513,74 +1164,440 @@ async fn combine_chunk(
     info!("合并分片，uploadId: {}", upload_id);
     let mut part_etags = cmu.part_etags;
 
-    let mut check = true;
     let mut total_len: u64 = 0;
 
-    let extension = &format!(".meta.{}", &upload_id);
-    let mut tmp_metadata_dir = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(crate::api::BASIC_PATH_SUFFIX)
-        .join(bucket_name)
-        .join(object_key)
-        .to_string_lossy()
-        .to_string();
-    tmp_metadata_dir.push_str(extension);
-    let tmp_metadata_dir = PathBuf::from(tmp_metadata_dir);
+    let tmp_metadata_dir = layout.upload_metadata(bucket_name, object_key, upload_id);
     if !tmp_metadata_dir.as_path().exists() {
         info!("未初始化");
         return Err(anyhow!("未初始化".to_string()));
     }
 
-    for part_etag in &part_etags {
-        if !fs::is_path_exist(&part_etag.etag) {
-            check = false;
-            break;
-        }
-        let len_path = PathBuf::from(DATA_DIR.get().unwrap())
-            .join("tmp")
-            .join(upload_id)
-            .join(&format!("{}", part_etag.part_number));
-        let len: u64 = std::fs::read_to_string(len_path)
-            .context("读取长度文件失败")?
-            .parse()
-            .context("解析长度文件失败")?;
-        total_len += len;
-    }
-
-    if !check {
-        info!("分片不完整");
-        return Err(anyhow!("分片不完整".to_string()));
-    }
     part_etags.sort_by_key(|p| p.part_number);
-    let chunks: Vec<String> = part_etags.par_iter().map(|p| p.etag.clone()).collect();
-    let mut metadata = fs::load_metadata(tmp_metadata_dir.to_string_lossy().as_ref())?;
+    let (chunks, chunk_sizes, replicated) = part_chunks(layout, upload_id, &part_etags)?;
+    queue_missing_chunks(gc, &replicated).await;
+    let parts = load_parts(layout, upload_id, &part_etags)?;
+    for part in &parts {
+        total_len += part.size;
+    }
+    let keyring = objects.keyring()?;
+    let mut metadata = fs::load_metadata(&tmp_metadata_dir, &keyring)?;
     info!("读取临时元数据成功");
+    if let Some(checksum) = &mut metadata.checksum {
+        checksum.value = composite_checksum(&checksum.algorithm, &parts)?;
+    }
     metadata.size = total_len;
     metadata.chunks = chunks;
+    metadata.chunk_sizes = chunk_sizes;
+    metadata.parts = parts;
     metadata.time = Utc::now();
 
-    let mut metadata_dir = PathBuf::from(DATA_DIR.get().unwrap())
-        .join(crate::api::BASIC_PATH_SUFFIX)
-        .join(bucket_name)
-        .join(object_key)
-        .to_string_lossy()
-        .to_string();
-    metadata_dir.push_str(".meta");
-    save_metadata(&metadata_dir, &metadata)?;
+    objects.save(bucket_name, object_key, &metadata)?;
     info!("保存新元数据成功");
     std::fs::remove_file(tmp_metadata_dir).context("删除临时元数据失败")?;
-    std::fs::remove_dir_all(
-        PathBuf::from(DATA_DIR.get().unwrap())
-            .join("tmp")
-            .join(upload_id),
-    )
-    .context("删除临时文件夹失败")?;
+    std::fs::remove_dir_all(layout.upload_dir(upload_id)).context("删除临时文件夹失败")?;
     Ok(())
 }
 
-// 删除文件逻辑
-async fn do_delete_file(metainfo_file_path: String) -> anyhow::Result<()> {
-    if std::fs::metadata(&metainfo_file_path).is_ok() {
-        std::fs::remove_file(&metainfo_file_path).context("删除文件失败")?;
+// 读取完成分片上传时指定的各分片信息
+pub(crate) fn load_parts(
+    layout: &Layout,
+    upload_id: &str,
+    part_etags: &[PartETag],
+) -> anyhow::Result<Vec<ObjectPart>> {
+    let upload_dir = layout.upload_dir(upload_id);
+    part_etags
+        .iter()
+        .map(|part_etag| {
+            let info = fs::load_part_info(upload_dir.join(part_etag.part_number.to_string()))?;
+            if !info.etag.is_empty() && info.etag != part_etag.etag {
+                return Err(anyhow!("分片 {} 的ETag不一致", part_etag.part_number));
+            }
+            Ok(ObjectPart {
+                part_number: u32::try_from(part_etag.part_number).context("分片号错误")?,
+                size: info.size,
+                etag: part_etag.etag.clone(),
+                checksum: info.checksum,
+            })
+        })
+        .collect()
+}
+
+// 按分片顺序拼接各分片的分块及其明文长度
+// 旧版本的分片是一个以 ETag 命名的分块
+// 第三项为应在本节点保存完整分块的分块，纠删码分片保存的分块不在其中
+fn part_chunks(
+    layout: &Layout,
+    upload_id: &str,
+    part_etags: &[PartETag],
+) -> anyhow::Result<(Vec<String>, Vec<u64>, Vec<String>)> {
+    let upload_dir = layout.upload_dir(upload_id);
+    let mut chunks = Vec::new();
+    let mut chunk_sizes = Vec::new();
+    let mut replicated = Vec::new();
+    for part_etag in part_etags {
+        let info = fs::load_part_info(upload_dir.join(part_etag.part_number.to_string()))?;
+        let (names, sizes) = match info.chunk_list() {
+            (names, _) if names.is_empty() => (vec![part_etag.etag.clone()], vec![info.size]),
+            list => list,
+        };
+        if info.erasure.is_none() {
+            replicated.extend(names.iter().cloned());
+        }
+        chunks.extend(names);
+        chunk_sizes.extend(sizes);
+    }
+    Ok((chunks, chunk_sizes, replicated))
+}
+
+// 计算分片上传对象的组合校验和
+pub(crate) fn composite_checksum(algorithm: &str, parts: &[ObjectPart]) -> anyhow::Result<String> {
+    let algorithm = ChecksumAlgorithm::parse(algorithm)
+        .with_context(|| format!("不支持的校验和算法: {}", algorithm))?;
+    let checksums = parts
+        .iter()
+        .map(|part| {
+            part.checksum
+                .clone()
+                .with_context(|| format!("分片 {} 缺少校验和", part.part_number))
+        })
+        .collect::<anyhow::Result<Vec<String>>>()?;
+    algorithm.composite(&checksums)
+}
+
+// 按元数据路径读取对象的元数据，不是对象的元数据路径时为 None
+fn load_path(
+    layout: &Layout,
+    objects: &ObjectIndex,
+    metadata_path: &str,
+) -> anyhow::Result<Option<Metadata>> {
+    match layout.object_of(metadata_path) {
+        Some((bucket_name, key)) => objects.load(&bucket_name, &key),
+        None => Ok(None),
+    }
+}
+
+// 按元数据路径保存对象的元数据
+fn save_path(
+    layout: &Layout,
+    objects: &ObjectIndex,
+    metadata_path: &str,
+    metadata: &Metadata,
+) -> anyhow::Result<()> {
+    let (bucket_name, key) = layout.object_of(metadata_path).context("元数据路径错误")?;
+    objects.save(&bucket_name, &key, metadata)
+}
+
+// 写入失败时的响应值，leader 据此向客户端返回错误
+fn write_failed(metadata_path: &str, err: anyhow::Error) -> String {
+    error!("写入 {} 失败: {}", metadata_path, err);
+    format!("{}{}", bucket::WRITE_FAILED, err)
+}
+
+// 对象元数据路径所属的桶
+fn bucket_of(layout: &Layout, metadata_path: &str) -> Option<String> {
+    layout
+        .object_of(metadata_path)
+        .map(|(bucket_name, _)| bucket_name)
+}
+
+// 对象当前的大小，对象不存在时为None
+fn object_size(layout: &Layout, objects: &ObjectIndex, metadata_path: &str) -> Option<u64> {
+    load_path(layout, objects, metadata_path)
+        .ok()
+        .flatten()
+        .map(|metadata| metadata.size)
+}
+
+// 中止分片上传，删除临时元数据和已上传分片的信息
+fn abort_upload(
+    layout: &Layout,
+    bucket_name: &str,
+    object_key: &str,
+    upload_id: &str,
+) -> anyhow::Result<()> {
+    let tmp_metadata_path = layout.upload_metadata(bucket_name, object_key, upload_id);
+    if tmp_metadata_path.exists() {
+        std::fs::remove_file(&tmp_metadata_path).context("删除临时元数据失败")?;
+    }
+    let upload_dir = layout.upload_dir(upload_id);
+    if upload_dir.is_dir() {
+        std::fs::remove_dir_all(upload_dir).context("删除临时文件夹失败")?;
+    }
+    Ok(())
+}
+
+// 一条日志可能改变引用的对象和分片上传
+#[derive(Default)]
+struct ChunkScope {
+    objects: Vec<String>,
+    uploads: Vec<String>,
+}
+
+impl ChunkScope {
+    fn of(req: &Request, layout: &Layout, objects: &ObjectIndex) -> Self {
+        match req {
+            Request::UploadFile { file_path, .. }
+            | Request::UploadSealedFile { file_path, .. }
+            | Request::DeleteFile { file_path } => ChunkScope {
+                objects: vec![file_path.clone()],
+                uploads: vec![],
+            },
+            Request::CommitObject { file_path, .. }
+            | Request::CommitInlineObject { file_path, .. } => ChunkScope {
+                objects: vec![file_path.clone()],
+                uploads: vec![],
+            },
+            Request::CopyFile {
+                dest_bucket,
+                dest_object,
+                ..
+            } => ChunkScope {
+                objects: vec![metadata_path(dest_bucket, dest_object)],
+                uploads: vec![],
+            },
+            Request::CombineChunk {
+                bucket_name,
+                object_key,
+                upload_id,
+                ..
+            } => ChunkScope {
+                objects: vec![metadata_path(bucket_name, object_key)],
+                uploads: vec![upload_id.clone()],
+            },
+            Request::UploadChunk { upload_id, .. }
+            | Request::UploadSealedChunk { upload_id, .. }
+            | Request::AbortUpload { upload_id, .. }
+            | Request::CommitPart { upload_id, .. } => ChunkScope {
+                objects: vec![],
+                uploads: vec![upload_id.clone()],
+            },
+            Request::DeleteBucket { bucket_name } => ChunkScope {
+                objects: layout
+                    .resolve(bucket_name)
+                    .file_name()
+                    .map(|name| bucket_metadata_paths(objects, &name.to_string_lossy()))
+                    .unwrap_or_default(),
+                uploads: vec![],
+            },
+            _ => ChunkScope::default(),
+        }
+    }
+
+    // 当前引用的全部分块
+    fn chunks(&self, layout: &Layout, objects: &ObjectIndex) -> Vec<String> {
+        let chunks = self
+            .objects
+            .iter()
+            .flat_map(|path| object_chunks(layout, objects, path));
+        let uploads = self.uploads.iter().flat_map(|id| upload_chunks(layout, id));
+        chunks.chain(uploads).collect()
+    }
+}
+
+// 对象引用的分块，对象不存在时为空
+fn object_chunks(layout: &Layout, objects: &ObjectIndex, metadata_path: &str) -> Vec<String> {
+    load_path(layout, objects, metadata_path)
+        .ok()
+        .flatten()
+        .map(|metadata| metadata.chunks)
+        .unwrap_or_default()
+}
+
+// 分片上传中已上传分片和流式上传中尚未提交的分块
+fn upload_chunks(layout: &Layout, upload_id: &str) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(layout.upload_dir(upload_id)) else {
+        return vec![];
+    };
+    let mut chunks = Vec::new();
+    for entry in entries.flatten() {
+        if let Ok(info) = fs::load_part_info(entry.path()) {
+            chunks.extend(info.chunk_list().0);
+        }
+    }
+    chunks
+}
+
+// 桶内所有对象的元数据路径
+fn bucket_metadata_paths(objects: &ObjectIndex, bucket_name: &str) -> Vec<String> {
+    objects
+        .scan(bucket_name)
+        .flatten()
+        .map(|(key, _)| metadata_path(bucket_name, &key))
+        .collect()
+}
+
+// 所有对象的元数据
+fn all_objects(objects: &ObjectIndex) -> impl Iterator<Item = Metadata> + '_ {
+    objects
+        .scan_all()
+        .flatten()
+        .filter_map(|(_, _, value)| objects.decode(&value).ok())
+}
+
+// 扫描所有对象和进行中的分片上传，统计各分块的引用次数
+pub(crate) fn scan_chunk_refs(
+    layout: &Layout,
+    objects: &ObjectIndex,
+) -> anyhow::Result<BTreeMap<String, u64>> {
+    let mut counts = BTreeMap::new();
+    let chunks = all_objects(objects).flat_map(|metadata| metadata.chunks);
+    let upload_ids: Vec<String> = std::fs::read_dir(&layout.tmp)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    let uploads = upload_ids.iter().flat_map(|id| upload_chunks(layout, id));
+    for hash in chunks.chain(uploads) {
+        *counts.entry(hash).or_insert(0) += 1;
+    }
+    Ok(counts)
+}
+
+// 扫描所有对象引用的分块，按对象是否加密分为两组，加密分块附带所属对象的加密信息
+// 加密分块的名称由数据密钥派生，需要解包数据密钥才能校验内容
+pub(crate) fn scan_chunk_encryption(
+    objects: &ObjectIndex,
+) -> anyhow::Result<(BTreeSet<String>, BTreeMap<String, ObjectEncryption>)> {
+    let mut plain = BTreeSet::new();
+    let mut sealed = BTreeMap::new();
+    for metadata in all_objects(objects) {
+        match metadata.encryption {
+            Some(encryption) => {
+                for hash in metadata.chunks {
+                    sealed.insert(hash, encryption.clone());
+                }
+            }
+            None => plain.extend(metadata.chunks),
+        }
+    }
+    Ok((plain, sealed))
+}
+
+// 文件距上次修改的时间
+fn file_age(path: &Path) -> Duration {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .unwrap_or_default()
+}
+
+// 超过 max_age 未完成的分片上传，返回桶名、对象键和 uploadId
+// 未提交也未中止的流式上传(如接收节点在上传过程中重启)桶名和对象键为空
+pub(crate) fn stale_uploads(
+    layout: &Layout,
+    max_age: Duration,
+) -> anyhow::Result<Vec<(String, String, String)>> {
+    let root = &layout.buckets;
+    let mut uploads = Vec::new();
+    let mut dirs = vec![root.clone()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let Some((object, upload_id)) = path
+                .to_str()
+                .filter(|path| !path.ends_with(".meta") && !durable::is_tmp(path))
+                .and_then(|path| path.rsplit_once(".meta."))
+            else {
+                continue;
+            };
+            if file_age(&path) < max_age {
+                continue;
+            }
+            let Ok(relative) = Path::new(object).strip_prefix(root) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy();
+            if let Some((bucket_name, object_key)) = relative.split_once('/') {
+                uploads.push((
+                    bucket_name.to_string(),
+                    object_key.to_string(),
+                    upload_id.to_string(),
+                ));
+            }
+        }
+    }
+    Ok(uploads)
+}
+
+// 分块推送时本节点不可达或推送尚未完成，缺少的分块记入待拉取队列，由修复任务拉取
+// 提交前 leader 已确认分块写入多数节点，应用日志时不访问其他节点
+async fn queue_missing_chunks(gc: &ChunkGc, chunks: &[String]) {
+    for hash in chunks {
+        if gc.store.exists(hash).await {
+            continue;
+        }
+        if let Err(err) = gc.refs.add_missing(hash) {
+            error!("记录待拉取的分块 {} 失败: {}", hash, err);
+        }
+    }
+}
+
+// 流式上传对象的元数据
+struct StagedObject {
+    size: u64,
+    chunks: Vec<String>,
+    chunk_sizes: Vec<u64>,
+    inline: Option<InlineChunk>,
+    encryption: Option<ObjectEncryption>,
+    checksum: Option<ObjectChecksum>,
+    website_redirect_location: Option<String>,
+    erasure: Option<ErasureCode>,
+}
+
+// 提交流式上传的对象
+async fn commit_object(
+    layout: &Layout,
+    objects: &ObjectIndex,
+    metainfo_file_path: &str,
+    object: StagedObject,
+) -> anyhow::Result<()> {
+    let file_name = PathBuf::from(metainfo_file_path)
+        .file_name()
+        .context("解析文件名失败")?
+        .to_string_lossy()
+        .to_string();
+    let file_type = MimeGuess::from_path(Path::new(&file_name))
+        .first_or_text_plain()
+        .to_string();
+    let metainfo = Metadata {
+        name: file_name,
+        size: object.size,
+        file_type,
+        time: Utc::now(),
+        chunks: object.chunks,
+        encryption: object.encryption,
+        checksum: object.checksum,
+        parts: vec![],
+        website_redirect_location: object.website_redirect_location,
+        chunk_sizes: object.chunk_sizes,
+        inline: object.inline,
+    };
+    save_path(layout, objects, metainfo_file_path, &metainfo)
+}
+
+// 删除文件逻辑，同时删除尚未导入索引的旧元数据文件
+async fn do_delete_file(
+    layout: &Layout,
+    objects: &ObjectIndex,
+    metainfo_file_path: String,
+) -> anyhow::Result<()> {
+    if let Some((bucket_name, object_key)) = layout.object_of(&metainfo_file_path) {
+        objects.remove(&bucket_name, &object_key)?;
+    }
+    let legacy = layout.resolve(&metainfo_file_path);
+    if std::fs::metadata(&legacy).is_ok() {
+        std::fs::remove_file(&legacy).context("删除文件失败")?;
     }
     Ok(())
 }
@@ -588,6 +1605,8 @@ async fn do_delete_file(metainfo_file_path: String) -> anyhow::Result<()> {
 #[derive(Debug, Clone)]
 pub struct LogStore {
     db: Arc<Db>,
+    // 持久化模式，决定追加日志后是否落盘
+    durability: Durability,
 }
 type StorageResult<T> = Result<T, StorageError<NodeId>>;
 
@@ -790,6 +1809,13 @@ impl RaftLogStorage<TypeConfig> for LogStore {
                 )
                 .map_err(|e| StorageIOError::write_logs(&e))?;
         }
+        // 需要同步时，日志落盘后才通知写入完成
+        if self.durability >= Durability::Data {
+            log_tree
+                .flush_async()
+                .await
+                .map_err(|e| StorageIOError::write_logs(&e))?;
+        }
 
         callback.log_io_completed(Ok(()));
 
@@ -837,12 +1863,24 @@ impl RaftLogStorage<TypeConfig> for LogStore {
     }
 }
 
-pub(crate) async fn new_storage<P: AsRef<Path>>(db_path: P) -> (LogStore, StateMachineStore) {
-    let db = sled::open(db_path).unwrap();
+// raft 日志和状态机保存在 layout.raft_db，状态机按 layout 应用日志中的路径
+pub(crate) async fn new_storage(
+    layout: Arc<Layout>,
+    store: &'static dyn ChunkStore,
+    cache_size: usize,
+    keyring: Keyring,
+    metadata_keys: Arc<MetadataKeys>,
+) -> (LogStore, StateMachineStore) {
+    let db = sled::open(&layout.raft_db).unwrap();
     let db = Arc::new(db);
 
-    let log_store = LogStore { db: db.clone() };
-    let sm_store = StateMachineStore::new(db).await.unwrap();
+    let log_store = LogStore {
+        db: db.clone(),
+        durability: layout.durability,
+    };
+    let sm_store = StateMachineStore::new(db, layout, store, cache_size, keyring, metadata_keys)
+        .await
+        .unwrap();
 
     (log_store, sm_store)
 }
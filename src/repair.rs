@@ -0,0 +1,139 @@
+use crate::fs;
+use crate::raft::app::App;
+use crate::raft::network::chunk;
+use crate::raft::Node;
+use crate::util::erasure::{self, ShardHeader};
+use anyhow::anyhow;
+use log::{error, info, warn};
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+// 补齐分块缺少的纠删码分片，返回补齐的数量
+// 由持有最小序号分片的节点负责还原和编码，避免多个节点重复修复
+async fn repair_chunk(app: &App, name: &str) -> anyhow::Result<usize> {
+    let local = fs::local_shards(app.chunk_gc.store, name).await?;
+    let Some(first) = local.first() else {
+        return Ok(0);
+    };
+    let code = ShardHeader::parse(first)?.code;
+    let mut present: BTreeSet<usize> = erasure::shard_indexes(&local).into_iter().collect();
+    let own = present.first().copied();
+    let nodes = chunk::members(app);
+    for (id, node) in &nodes {
+        if *id == app.id {
+            continue;
+        }
+        // 不可达节点上的分片视为丢失
+        match chunk::get_shards(node, name, true).await {
+            Ok(headers) => present.extend(erasure::shard_indexes(&headers)),
+            Err(err) => warn!("查询节点 {} 上分块 {} 的分片失败: {}", id, name, err),
+        }
+    }
+    if present.len() >= code.total() || present.first().copied() != own {
+        return Ok(0);
+    }
+    if present.len() < code.data_shards {
+        return Err(anyhow!(
+            "分块 {} 只剩 {} 个分片，无法还原",
+            name,
+            present.len()
+        ));
+    }
+    let data = chunk::load(app.chunk_gc.store, chunk::peers(app), name.to_string()).await?;
+    let shards = code.encode(&data)?;
+    let targets = erasure::placement(name, nodes.len(), shards.len());
+    let mut repaired = 0;
+    for (index, shard) in shards.into_iter().enumerate() {
+        if present.contains(&index) {
+            continue;
+        }
+        let (id, node) = &nodes[targets[index]];
+        let shard_name = fs::shard_name(name, index);
+        if *id == app.id {
+            app.chunk_gc.save_chunk(&shard_name, &shard).await?;
+        } else {
+            chunk::push(node.clone(), shard_name, shard.into()).await?;
+        }
+        repaired += 1;
+    }
+    Ok(repaired)
+}
+
+// 其他节点上是否保存了分块的纠删码分片
+async fn is_sharded(peers: &[Node], name: &str) -> bool {
+    for node in peers {
+        let headers = chunk::get_shards(node, name, true).await;
+        if matches!(headers, Ok(headers) if !headers.is_empty()) {
+            return true;
+        }
+    }
+    false
+}
+
+// 拉取安装快照或应用日志时本节点缺少的分块，返回拉取的数量
+// 分块已不再被引用或只以纠删码分片保存(由分片修复负责)时移出队列，拉取失败的下一轮重试
+async fn fetch_missing(app: &App) -> anyhow::Result<usize> {
+    let refs = &app.chunk_gc.refs;
+    let mut fetched = 0;
+    for name in refs.missing()? {
+        if refs.count(&name)? == 0 || app.chunk_gc.store.exists(&name).await {
+            refs.remove_missing(&name)?;
+            continue;
+        }
+        let peers = chunk::peers(app);
+        match chunk::fetch(&peers, &name).await {
+            Ok(data) => {
+                app.chunk_gc.save_chunk(&name, &data).await?;
+                refs.remove_missing(&name)?;
+                fetched += 1;
+            }
+            Err(_) if is_sharded(&peers, &name).await => refs.remove_missing(&name)?,
+            Err(err) => warn!("拉取缺少的分块 {} 失败，稍后重试: {}", name, err),
+        }
+    }
+    Ok(fetched)
+}
+
+// 后台修复任务，拉取安装快照或应用日志时缺少的分块，节点替换后按当前成员重新编码并保存丢失的分片，
+// 间隔为0时不修复
+// 无引用的分块等待回收，不再修复
+pub(crate) async fn run(app: App, interval: u64) {
+    if interval == 0 {
+        return;
+    }
+    let period = Duration::from_secs(interval);
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    loop {
+        ticker.tick().await;
+        match fetch_missing(&app).await {
+            Ok(0) => {}
+            Ok(fetched) => info!("从其他节点拉取 {} 个缺少的分块", fetched),
+            Err(err) => error!("拉取缺少的分块失败: {}", err),
+        }
+        let chunks = match fs::sharded_chunks(app.chunk_gc.store).await {
+            Ok(chunks) => chunks,
+            Err(err) => {
+                error!("列出纠删码分块失败: {}", err);
+                continue;
+            }
+        };
+        let mut repaired = 0;
+        for name in chunks {
+            match app.chunk_gc.refs.count(&name) {
+                Ok(0) => continue,
+                Ok(_) => {}
+                Err(err) => {
+                    error!("读取分块 {} 的引用计数失败: {}", name, err);
+                    continue;
+                }
+            }
+            match repair_chunk(&app, &name).await {
+                Ok(count) => repaired += count,
+                Err(err) => error!("修复分块 {} 失败: {}", name, err),
+            }
+        }
+        if repaired > 0 {
+            info!("补齐 {} 个纠删码分片", repaired);
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use crate::fs::{self, ObjectEncryption};
+use crate::gc::refs::unix_now;
+use crate::metrics;
+use crate::raft::app::App;
+use crate::raft::network::chunk;
+use crate::raft::store::scan_chunk_encryption;
+use crate::sse;
+use crate::util::codec;
+use crate::util::erasure;
+use log::{error, info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+// 保留的最近发现数
+const MAX_FINDINGS: usize = 100;
+
+// 巡检进度和发现的损坏分块，由 App 持有
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ScrubStatus {
+    pub running: bool,
+    // 已完成的巡检轮数
+    pub passes: u64,
+    // 当前或上一轮巡检的开始和结束时间(unix 秒)
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    // 当前或上一轮巡检的进度
+    pub total: u64,
+    pub scanned: u64,
+    pub bytes: u64,
+    // SSE-C 加密的分块没有保存密钥，无法校验，跳过
+    pub skipped: u64,
+    // 读取失败的分块，不影响继续巡检
+    pub errors: u64,
+    pub corrupt: u64,
+    pub repaired: u64,
+    // 最近发现的损坏分块，最新的在后
+    pub findings: Vec<ScrubFinding>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ScrubFinding {
+    pub chunk: String,
+    // 隔离后的位置，分块存储不支持隔离时已删除
+    pub quarantine: Option<String>,
+    pub error: String,
+    pub repaired: bool,
+    pub time: u64,
+}
+
+// 当前的巡检状态
+pub fn status(app: &App) -> ScrubStatus {
+    app.scrub.lock().unwrap().clone()
+}
+
+fn update(app: &App, f: impl FnOnce(&mut ScrubStatus)) {
+    f(&mut app.scrub.lock().unwrap());
+}
+
+// 分块的校验结果
+enum Verdict {
+    Healthy,
+    Skipped,
+    Corrupt(String),
+}
+
+// 分块的校验方式，由分块所属的对象决定
+#[derive(Clone)]
+enum Check {
+    // 未加密对象的分块
+    Plain,
+    // 加密对象的分块和解包后的数据密钥，SSE-C 对象没有密钥
+    Sealed(Option<Vec<u8>>),
+    // 不属于任何对象的分块，可能是尚未提交的上传
+    Unknown,
+    // 纠删码分片和分片序号
+    Shard(usize),
+}
+
+// 按分块名称和所属对象确定校验方式
+fn check_of(
+    master_key: &[u8; 32],
+    name: &str,
+    plain: &BTreeSet<String>,
+    sealed: &BTreeMap<String, ObjectEncryption>,
+) -> Option<Check> {
+    if let Some((_, index)) = name.split_once('.') {
+        return index.parse().ok().map(Check::Shard);
+    }
+    if plain.contains(name) {
+        return Some(Check::Plain);
+    }
+    match sealed.get(name) {
+        Some(encryption) => Some(Check::Sealed(
+            sse::data_key(master_key, Some(encryption), None)
+                .ok()
+                .flatten(),
+        )),
+        None => Some(Check::Unknown),
+    }
+}
+
+// 未加密的分块解压后校验 sha256，加密的分块用数据密钥校验 GCM 标签，
+// 纠删码分片校验分片头和长度；不属于任何对象的分块没有分块头时可能是加密分块，跳过
+fn verify(name: &str, stored: &[u8], check: &Check) -> Verdict {
+    let result = match check {
+        Check::Shard(index) => erasure::verify_shard(stored, *index),
+        Check::Sealed(Some(key)) => fs::verify_sealed_chunk(name, stored, key),
+        Check::Sealed(None) => return Verdict::Skipped,
+        Check::Plain if !codec::is_encoded(stored) => {
+            return Verdict::Corrupt("分块格式错误".to_string())
+        }
+        Check::Unknown if !codec::is_encoded(stored) => return Verdict::Skipped,
+        Check::Plain | Check::Unknown => fs::verify_chunk(name, stored),
+    };
+    match result {
+        Ok(()) => Verdict::Healthy,
+        Err(err) => Verdict::Corrupt(format!("{:#}", err)),
+    }
+}
+
+// 在阻塞线程池中校验，解压和计算 sha256 不阻塞处理请求的线程
+async fn verify_blocking(name: &str, stored: Vec<u8>, check: &Check) -> anyhow::Result<Verdict> {
+    let name = name.to_string();
+    let check = check.clone();
+    fs::blocking(move || verify(&name, &stored, &check)).await
+}
+
+// 从其他节点拉取校验通过的副本，纠删码分片由修复任务用其他分片重建
+async fn repair(app: &App, name: &str, check: &Check) -> anyhow::Result<bool> {
+    if let Check::Shard(_) = check {
+        return Ok(false);
+    }
+    for node in chunk::peers(app) {
+        match chunk::fetch([&node], name).await {
+            Ok(data) => match verify_blocking(name, data.clone(), check).await? {
+                Verdict::Healthy => {
+                    app.chunk_gc.save_chunk(name, &data).await?;
+                    return Ok(true);
+                }
+                Verdict::Corrupt(err) => {
+                    warn!("节点 {} 上的分块 {} 也已损坏: {}", node.rpc_addr, name, err)
+                }
+                Verdict::Skipped => warn!("节点 {} 上的分块 {} 无法校验", node.rpc_addr, name),
+            },
+            Err(err) => warn!("从 {} 拉取分块 {} 失败: {}", node.rpc_addr, name, err),
+        }
+    }
+    Ok(false)
+}
+
+// 隔离损坏的分块并修复，隔离在回收锁内进行，拉取期间本节点的读取改从其他节点读
+async fn handle_corrupt(app: &App, name: String, check: &Check, error: String) -> ScrubFinding {
+    metrics::SCRUB_CORRUPT.inc();
+    let quarantine = {
+        let _guard = app.chunk_gc.lock.lock().await;
+        app.chunk_gc.store.quarantine(&name).await
+    };
+    let quarantine = match quarantine {
+        Ok(quarantine) => quarantine,
+        Err(err) => {
+            error!("隔离分块 {} 失败: {}", name, err);
+            return ScrubFinding {
+                chunk: name,
+                quarantine: None,
+                error,
+                repaired: false,
+                time: unix_now(),
+            };
+        }
+    };
+    warn!(
+        "分块 {} 已损坏，隔离到 {}",
+        name,
+        quarantine.as_deref().unwrap_or("(已删除)")
+    );
+    let repaired = match repair(app, &name, check).await {
+        Ok(repaired) => repaired,
+        Err(err) => {
+            error!("修复分块 {} 失败: {}", name, err);
+            false
+        }
+    };
+    if repaired {
+        metrics::SCRUB_REPAIRED.inc();
+        info!("已从其他节点修复分块 {}", name);
+    } else if let Check::Shard(_) = check {
+        info!("分片 {} 将由修复任务用其他分片重建", name);
+    }
+    ScrubFinding {
+        chunk: name,
+        quarantine,
+        error,
+        repaired,
+        time: unix_now(),
+    }
+}
+
+// 巡检一轮，rate 为每秒读取的字节数上限，0 时不限速
+async fn scrub(app: &App, rate: u64) -> anyhow::Result<()> {
+    let (plain, sealed) = scan_chunk_encryption(&app.objects)?;
+    let store = app.chunk_gc.store;
+    let files = store.list().await?;
+    update(app, |status| {
+        status.running = true;
+        status.started_at = Some(unix_now());
+        status.finished_at = None;
+        status.total = files.len() as u64;
+        status.scanned = 0;
+        status.bytes = 0;
+        status.skipped = 0;
+        status.errors = 0;
+        status.corrupt = 0;
+        status.repaired = 0;
+    });
+    let started = Instant::now();
+    let mut bytes = 0;
+    for name in files {
+        let Some(check) = check_of(&app.master_key, &name, &plain, &sealed) else {
+            update(app, |status| {
+                status.scanned += 1;
+                status.skipped += 1;
+            });
+            continue;
+        };
+        let verdict = match store.get(&name).await {
+            Ok(Some(stored)) => {
+                bytes += stored.len() as u64;
+                metrics::SCRUB_BYTES.add(stored.len() as u64);
+                metrics::SCRUB_CHUNKS.inc();
+                verify_blocking(&name, stored, &check).await
+            }
+            // 已被回收
+            Ok(None) => Ok(Verdict::Skipped),
+            Err(err) => Err(err),
+        };
+        let verdict = match verdict {
+            Ok(verdict) => verdict,
+            Err(err) => {
+                warn!("巡检分块 {} 失败: {:#}", name, err);
+                update(app, |status| {
+                    status.scanned += 1;
+                    status.errors += 1;
+                });
+                continue;
+            }
+        };
+        let skipped = matches!(verdict, Verdict::Skipped);
+        let finding = match verdict {
+            Verdict::Corrupt(error) => Some(handle_corrupt(app, name, &check, error).await),
+            _ => None,
+        };
+        update(app, |status| {
+            status.scanned += 1;
+            status.bytes = bytes;
+            status.skipped += u64::from(skipped);
+            if let Some(finding) = finding {
+                status.corrupt += 1;
+                status.repaired += u64::from(finding.repaired);
+                if status.findings.len() >= MAX_FINDINGS {
+                    status.findings.remove(0);
+                }
+                status.findings.push(finding);
+            }
+        });
+        if rate > 0 {
+            let expected = Duration::from_secs_f64(bytes as f64 / rate as f64);
+            if let Some(wait) = expected.checked_sub(started.elapsed()) {
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+    update(app, |status| {
+        status.running = false;
+        status.passes += 1;
+        status.finished_at = Some(unix_now());
+    });
+    Ok(())
+}
+
+// 后台巡检任务，每隔 interval 秒巡检一轮，间隔为0时不巡检
+pub(crate) async fn run(app: App, interval: u64, rate: u64) {
+    if interval == 0 {
+        return;
+    }
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+    loop {
+        ticker.tick().await;
+        if let Err(err) = scrub(&app, rate).await {
+            error!("巡检分块失败: {}", err);
+            update(&app, |status| status.running = false);
+            continue;
+        }
+        let status = status(&app);
+        info!(
+            "巡检完成，校验 {} 个分块，跳过 {} 个，读取失败 {} 个，损坏 {} 个，修复 {} 个",
+            status.scanned - status.skipped - status.errors,
+            status.skipped,
+            status.errors,
+            status.corrupt,
+            status.repaired
+        );
+    }
+}
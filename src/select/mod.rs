@@ -0,0 +1,506 @@
+use crate::err::AppError;
+use crate::fs::DecompressStream;
+use crate::model::{CsvInputSerialization, SelectObjectContentRequest, SelectStats};
+use crate::select::record::{
+    CsvInput, CsvOutput, FileHeaderInfo, InputFormat, JsonType, OutputFormat, QuoteFields, Record,
+    RecordReader,
+};
+use crate::select::sql::{Accumulator, Query, Row};
+use crate::util::event_stream::encode_message;
+use futures::Stream;
+use ntex::util::Bytes;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use thiserror::Error;
+
+pub mod record;
+pub mod sql;
+
+// 查询失败的原因，code 为 S3 错误码
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{code}: {message}")]
+pub struct SelectError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl SelectError {
+    pub(crate) fn new(code: &'static str, message: impl Into<String>) -> Self {
+        SelectError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<SelectError> for AppError {
+    fn from(err: SelectError) -> Self {
+        AppError::s3(400, err.code, err.message)
+    }
+}
+
+// 对象内容的压缩格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+// 解析后的 SelectObjectContent 请求
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectRequest {
+    pub query: Query,
+    pub compression: Compression,
+    pub input: InputFormat,
+    pub output: OutputFormat,
+}
+
+impl SelectRequest {
+    pub fn from_model(request: &SelectObjectContentRequest) -> Result<Self, SelectError> {
+        if !request.expression_type.eq_ignore_ascii_case("SQL") {
+            return Err(SelectError::new(
+                "InvalidExpressionType",
+                "The ExpressionType is invalid. Only SQL expressions are supported.",
+            ));
+        }
+        let query = Query::parse(&request.expression)?;
+        let input = &request.input_serialization;
+        let compression = match input
+            .compression_type
+            .as_deref()
+            .map(str::to_ascii_uppercase)
+            .as_deref()
+        {
+            None | Some("") | Some("NONE") => Compression::None,
+            Some("GZIP") => Compression::Gzip,
+            Some("ZSTD") => Compression::Zstd,
+            Some(other) => {
+                return Err(SelectError::new(
+                    "InvalidCompressionFormat",
+                    format!("The compression format {} is not supported.", other),
+                ))
+            }
+        };
+        let input_format = match (&input.csv, &input.json) {
+            (Some(csv), None) => InputFormat::Csv(csv_input(csv)?),
+            (None, Some(json)) => InputFormat::Json(
+                match json
+                    .json_type
+                    .as_deref()
+                    .map(str::to_ascii_uppercase)
+                    .as_deref()
+                {
+                    None | Some("DOCUMENT") => JsonType::Document,
+                    Some("LINES") => JsonType::Lines,
+                    Some(_) => {
+                        return Err(SelectError::new(
+                            "InvalidJsonType",
+                            "The JsonType is invalid. Only DOCUMENT and LINES are supported.",
+                        ))
+                    }
+                },
+            ),
+            _ => {
+                return Err(invalid_parameter(
+                    "Exactly one of CSV or JSON input must be specified.",
+                ))
+            }
+        };
+        let output = &request.output_serialization;
+        let output_format = match (&output.csv, &output.json) {
+            (Some(csv), None) => {
+                let defaults = CsvOutput::default();
+                let quote_fields = quote_fields(&csv.quote_fields)?;
+                OutputFormat::Csv(CsvOutput {
+                    field_delimiter: or_default(&csv.field_delimiter, defaults.field_delimiter),
+                    record_delimiter: or_default(&csv.record_delimiter, defaults.record_delimiter),
+                    quote_character: or_default(&csv.quote_character, defaults.quote_character),
+                    quote_escape_character: or_default(
+                        &csv.quote_escape_character,
+                        defaults.quote_escape_character,
+                    ),
+                    quote_fields,
+                })
+            }
+            (None, Some(json)) => {
+                OutputFormat::Json(or_default(&json.record_delimiter, "\n".to_string()))
+            }
+            _ => {
+                return Err(invalid_parameter(
+                    "Exactly one of CSV or JSON output must be specified.",
+                ))
+            }
+        };
+        Ok(SelectRequest {
+            query,
+            compression,
+            input: input_format,
+            output: output_format,
+        })
+    }
+}
+
+fn csv_input(csv: &CsvInputSerialization) -> Result<CsvInput, SelectError> {
+    let defaults = CsvInput::default();
+    let file_header_info = match csv
+        .file_header_info
+        .as_deref()
+        .map(str::to_ascii_uppercase)
+        .as_deref()
+    {
+        None | Some("") | Some("NONE") => FileHeaderInfo::None,
+        Some("USE") => FileHeaderInfo::Use,
+        Some("IGNORE") => FileHeaderInfo::Ignore,
+        Some(_) => {
+            return Err(SelectError::new(
+                "InvalidFileHeaderInfo",
+                "The FileHeaderInfo is invalid. Only NONE, USE, and IGNORE are supported.",
+            ))
+        }
+    };
+    Ok(CsvInput {
+        file_header_info,
+        field_delimiter: single_byte(&csv.field_delimiter, "FieldDelimiter")?
+            .unwrap_or(defaults.field_delimiter),
+        record_delimiter: single_byte(&csv.record_delimiter, "RecordDelimiter")?
+            .unwrap_or(defaults.record_delimiter),
+        quote_character: single_byte(&csv.quote_character, "QuoteCharacter")?
+            .unwrap_or(defaults.quote_character),
+        quote_escape_character: single_byte(&csv.quote_escape_character, "QuoteEscapeCharacter")?
+            .unwrap_or(defaults.quote_escape_character),
+        comments: single_byte(&csv.comments, "Comments")?,
+    })
+}
+
+fn quote_fields(value: &Option<String>) -> Result<QuoteFields, SelectError> {
+    match value.as_deref().map(str::to_ascii_uppercase).as_deref() {
+        None | Some("") | Some("ASNEEDED") => Ok(QuoteFields::AsNeeded),
+        Some("ALWAYS") => Ok(QuoteFields::Always),
+        Some(_) => Err(SelectError::new(
+            "InvalidQuoteFields",
+            "The QuoteFields is invalid. Only ALWAYS and ASNEEDED are supported.",
+        )),
+    }
+}
+
+// 解析单字节的分隔符，\r\n 按 \n 处理并去除行尾的 \r
+fn single_byte(value: &Option<String>, name: &str) -> Result<Option<u8>, SelectError> {
+    match value.as_deref() {
+        None | Some("") => Ok(None),
+        Some("\r\n") => Ok(Some(b'\n')),
+        Some(value) if value.len() == 1 => Ok(Some(value.as_bytes()[0])),
+        Some(value) => Err(invalid_parameter(format!(
+            "{} must be a single ASCII character: {:?}",
+            name, value
+        ))),
+    }
+}
+
+fn or_default(value: &Option<String>, default: String) -> String {
+    match value {
+        Some(value) if !value.is_empty() => value.clone(),
+        _ => default,
+    }
+}
+
+fn invalid_parameter(message: impl Into<String>) -> SelectError {
+    SelectError::new("InvalidRequestParameter", message)
+}
+
+// 解压输出的上限，防止很小的压缩对象解压出大量数据(解压炸弹)
+// 每段输入数据解压出的字节数
+const MAX_CHUNK_OUTPUT: usize = 128 << 20;
+// 整个对象解压出的字节数
+const MAX_TOTAL_OUTPUT: u64 = 64 << 30;
+
+// 解压器的输出缓冲，超出上限时写入失败，解压随之中止
+#[derive(Default)]
+struct BoundedOutput {
+    data: Vec<u8>,
+    total: u64,
+}
+
+impl BoundedOutput {
+    fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.data)
+    }
+}
+
+impl Write for BoundedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.data.len() + buf.len() > MAX_CHUNK_OUTPUT {
+            return Err(io::Error::other(format!(
+                "a chunk decompresses to more than {} bytes",
+                MAX_CHUNK_OUTPUT
+            )));
+        }
+        if self.total + buf.len() as u64 > MAX_TOTAL_OUTPUT {
+            return Err(io::Error::other(format!(
+                "the object decompresses to more than {} bytes",
+                MAX_TOTAL_OUTPUT
+            )));
+        }
+        self.data.extend_from_slice(buf);
+        self.total += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// 流式解压 gzip / zstd 压缩的对象内容
+enum Decompressor {
+    None,
+    Gzip(flate2::write::MultiGzDecoder<BoundedOutput>),
+    Zstd(zstd::stream::write::Decoder<'static, BoundedOutput>),
+}
+
+impl Decompressor {
+    fn new(compression: Compression) -> Result<Self, SelectError> {
+        Ok(match compression {
+            Compression::None => Decompressor::None,
+            Compression::Gzip => {
+                Decompressor::Gzip(flate2::write::MultiGzDecoder::new(BoundedOutput::default()))
+            }
+            Compression::Zstd => Decompressor::Zstd(
+                zstd::stream::write::Decoder::new(BoundedOutput::default())
+                    .map_err(decompress_error)?,
+            ),
+        })
+    }
+
+    // 写入一段压缩数据，返回已解压的数据
+    fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, SelectError> {
+        match self {
+            Decompressor::None => Ok(data.to_vec()),
+            Decompressor::Gzip(decoder) => {
+                decoder.write_all(data).map_err(decompress_error)?;
+                Ok(decoder.get_mut().take())
+            }
+            Decompressor::Zstd(decoder) => {
+                decoder.write_all(data).map_err(decompress_error)?;
+                decoder.flush().map_err(decompress_error)?;
+                Ok(decoder.get_mut().take())
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, SelectError> {
+        match self {
+            Decompressor::None => Ok(vec![]),
+            Decompressor::Gzip(decoder) => {
+                decoder.try_finish().map_err(decompress_error)?;
+                Ok(decoder.get_mut().take())
+            }
+            Decompressor::Zstd(decoder) => {
+                decoder.flush().map_err(decompress_error)?;
+                Ok(decoder.get_mut().take())
+            }
+        }
+    }
+}
+
+fn decompress_error(err: io::Error) -> SelectError {
+    SelectError::new(
+        "InvalidCompressionFormat",
+        format!("Failed to decompress the object: {}", err),
+    )
+}
+
+// 逐块执行查询，每块数据处理完后即可输出结果，内存占用与对象大小无关
+pub struct SelectEngine {
+    query: Query,
+    output: OutputFormat,
+    decompressor: Decompressor,
+    reader: RecordReader,
+    accumulators: Vec<Accumulator>,
+    // 已输出的记录数
+    returned: u64,
+    stats: SelectStats,
+}
+
+impl SelectEngine {
+    pub fn new(request: SelectRequest) -> Result<Self, SelectError> {
+        Ok(SelectEngine {
+            accumulators: request.query.accumulators(),
+            decompressor: Decompressor::new(request.compression)?,
+            reader: RecordReader::new(&request.input),
+            query: request.query,
+            output: request.output,
+            returned: 0,
+            stats: SelectStats::default(),
+        })
+    }
+
+    // 处理一段对象数据，返回产生的结果记录
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, SelectError> {
+        self.stats.bytes_scanned += data.len() as u64;
+        let data = self.decompressor.push(data)?;
+        self.process(&data, false)
+    }
+
+    // 对象数据读取完毕，返回剩余的结果记录，聚合查询在此输出结果
+    pub fn finish(&mut self) -> Result<Vec<u8>, SelectError> {
+        let data = self.decompressor.finish()?;
+        let mut out = self.process(&data, true)?;
+        if self.query.aggregate && self.query.limit != Some(0) {
+            let columns = self.query.aggregate_result(&self.accumulators);
+            let start = out.len();
+            self.output.write(columns, &mut out);
+            self.stats.bytes_returned += (out.len() - start) as u64;
+        }
+        Ok(out)
+    }
+
+    // 已输出 LIMIT 条记录，无需继续读取对象
+    pub fn is_done(&self) -> bool {
+        !self.query.aggregate && self.query.limit.is_some_and(|limit| self.returned >= limit)
+    }
+
+    pub fn stats(&self) -> &SelectStats {
+        &self.stats
+    }
+
+    fn process(&mut self, data: &[u8], last: bool) -> Result<Vec<u8>, SelectError> {
+        self.stats.bytes_processed += data.len() as u64;
+        let mut records = Vec::new();
+        self.reader.push(data, &mut records)?;
+        if last {
+            self.reader.finish(&mut records)?;
+        }
+        let unnest = self.query.unnest;
+        let records = records.into_iter().flat_map(|record| match record {
+            Record::Json(serde_json::Value::Array(items)) if unnest => {
+                items.into_iter().map(Record::Json).collect()
+            }
+            record => vec![record],
+        });
+        let mut out = Vec::new();
+        for record in records {
+            if self.is_done() {
+                break;
+            }
+            let row = Row {
+                record: &record,
+                header: self.reader.header(),
+            };
+            if !self.query.matches(&row)? {
+                continue;
+            }
+            if self.query.aggregate {
+                self.query.accumulate(&mut self.accumulators, &row)?;
+            } else {
+                let columns = self.query.project(&row)?;
+                self.output.write(columns, &mut out);
+                self.returned += 1;
+            }
+        }
+        self.stats.bytes_returned += out.len() as u64;
+        Ok(out)
+    }
+}
+
+// 以 AWS event stream 格式返回查询结果：若干 Records 事件，随后是 Stats 和 End 事件
+// 查询中途出错时返回错误消息并结束
+pub(crate) struct SelectStream {
+    source: DecompressStream,
+    engine: SelectEngine,
+    pending: VecDeque<Bytes>,
+    done: bool,
+}
+
+impl SelectStream {
+    pub(crate) fn new(source: DecompressStream, engine: SelectEngine) -> Self {
+        SelectStream {
+            source,
+            engine,
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    fn records(&mut self, payload: Vec<u8>) {
+        if payload.is_empty() {
+            return;
+        }
+        self.pending.push_back(Bytes::from(encode_message(
+            &[
+                (":event-type", "Records"),
+                (":content-type", "application/octet-stream"),
+                (":message-type", "event"),
+            ],
+            &payload,
+        )));
+    }
+
+    fn finish(&mut self) {
+        match self.engine.finish() {
+            Ok(payload) => {
+                self.records(payload);
+                let stats = quick_xml::se::to_string(self.engine.stats()).unwrap_or_default();
+                self.pending.push_back(Bytes::from(encode_message(
+                    &[
+                        (":event-type", "Stats"),
+                        (":content-type", "text/xml"),
+                        (":message-type", "event"),
+                    ],
+                    stats.as_bytes(),
+                )));
+                self.pending.push_back(Bytes::from(encode_message(
+                    &[(":event-type", "End"), (":message-type", "event")],
+                    &[],
+                )));
+                self.done = true;
+            }
+            Err(err) => self.fail(err),
+        }
+    }
+
+    fn fail(&mut self, err: SelectError) {
+        self.pending.push_back(Bytes::from(encode_message(
+            &[
+                (":error-code", err.code),
+                (":error-message", &err.message),
+                (":message-type", "error"),
+            ],
+            &[],
+        )));
+        self.done = true;
+    }
+}
+
+impl Stream for SelectStream {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(message) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(message)));
+            }
+            if this.done {
+                return Poll::Ready(None);
+            }
+            if this.engine.is_done() {
+                this.finish();
+                continue;
+            }
+            match Pin::new(&mut this.source).poll_next(cx) {
+                Poll::Ready(Some(Ok(data))) => match this.engine.push(&data) {
+                    Ok(payload) => this.records(payload),
+                    Err(err) => this.fail(err),
+                },
+                Poll::Ready(Some(Err(err))) => {
+                    this.fail(SelectError::new("InternalError", err.to_string()))
+                }
+                Poll::Ready(None) => this.finish(),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
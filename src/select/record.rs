@@ -0,0 +1,427 @@
+use crate::select::sql::Value;
+use crate::select::SelectError;
+
+// 单条记录的最大长度，超过时认为输入格式有误，避免无限缓存
+const MAX_RECORD_SIZE: usize = 1 << 20;
+
+// 输入记录
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    Csv(Vec<String>),
+    Json(serde_json::Value),
+}
+
+// CSV 文件头的处理方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileHeaderInfo {
+    // 首行为列名，可以按列名引用
+    Use,
+    // 跳过首行，只能按位置引用
+    Ignore,
+    // 没有文件头
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvInput {
+    pub file_header_info: FileHeaderInfo,
+    pub field_delimiter: u8,
+    pub record_delimiter: u8,
+    pub quote_character: u8,
+    pub quote_escape_character: u8,
+    pub comments: Option<u8>,
+}
+
+impl Default for CsvInput {
+    fn default() -> Self {
+        CsvInput {
+            file_header_info: FileHeaderInfo::None,
+            field_delimiter: b',',
+            record_delimiter: b'\n',
+            quote_character: b'"',
+            quote_escape_character: b'"',
+            comments: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JsonType {
+    // 每行一个 JSON 对象
+    Lines,
+    // 一个或多个连续的 JSON 文档
+    Document,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputFormat {
+    Csv(CsvInput),
+    Json(JsonType),
+}
+
+// 增量解析 CSV，字段可以跨越数据块边界
+pub(crate) struct CsvReader {
+    options: CsvInput,
+    field: Vec<u8>,
+    record: Vec<String>,
+    // 处于引号内
+    in_quotes: bool,
+    // 引号内遇到引号，可能是字段结束也可能是转义
+    quote_pending: bool,
+    // 引号内遇到转义字符
+    escape_pending: bool,
+    // 当前字段以引号开头，结尾的 \r 不去除
+    quoted: bool,
+    // 当前行是注释
+    comment: bool,
+    header_pending: bool,
+    header: Option<Vec<String>>,
+}
+
+impl CsvReader {
+    pub(crate) fn new(options: CsvInput) -> Self {
+        CsvReader {
+            header_pending: options.file_header_info != FileHeaderInfo::None,
+            options,
+            field: Vec::new(),
+            record: Vec::new(),
+            in_quotes: false,
+            quote_pending: false,
+            escape_pending: false,
+            quoted: false,
+            comment: false,
+            header: None,
+        }
+    }
+
+    pub(crate) fn header(&self) -> Option<&[String]> {
+        self.header.as_deref()
+    }
+
+    pub(crate) fn push(
+        &mut self,
+        data: &[u8],
+        records: &mut Vec<Record>,
+    ) -> Result<(), SelectError> {
+        let CsvInput {
+            field_delimiter,
+            record_delimiter,
+            quote_character,
+            quote_escape_character,
+            comments,
+            ..
+        } = self.options;
+        for &byte in data {
+            if self.comment {
+                self.comment = byte != record_delimiter;
+                continue;
+            }
+            if self.in_quotes {
+                if self.escape_pending {
+                    self.field.push(byte);
+                    self.escape_pending = false;
+                    continue;
+                }
+                if self.quote_pending {
+                    self.quote_pending = false;
+                    if byte == quote_character {
+                        self.field.push(byte);
+                        continue;
+                    }
+                    self.in_quotes = false;
+                } else if byte == quote_escape_character
+                    && quote_escape_character != quote_character
+                {
+                    self.escape_pending = true;
+                    continue;
+                } else if byte == quote_character {
+                    self.quote_pending = true;
+                    continue;
+                } else {
+                    self.field.push(byte);
+                    if self.field.len() > MAX_RECORD_SIZE {
+                        return Err(over_max_record_size());
+                    }
+                    continue;
+                }
+            }
+            if Some(byte) == comments
+                && self.record.is_empty()
+                && self.field.is_empty()
+                && !self.quoted
+            {
+                self.comment = true;
+            } else if byte == quote_character && self.field.is_empty() && !self.quoted {
+                self.in_quotes = true;
+                self.quoted = true;
+            } else if byte == field_delimiter {
+                self.end_field()?;
+            } else if byte == record_delimiter {
+                if record_delimiter == b'\n' && !self.quoted && self.field.last() == Some(&b'\r') {
+                    self.field.pop();
+                }
+                self.end_field()?;
+                self.end_record(records);
+            } else {
+                self.field.push(byte);
+                if self.field.len() > MAX_RECORD_SIZE {
+                    return Err(over_max_record_size());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 输入结束，输出最后一条没有换行结尾的记录
+    pub(crate) fn finish(&mut self, records: &mut Vec<Record>) -> Result<(), SelectError> {
+        if self.in_quotes && !self.quote_pending {
+            return Err(SelectError::new(
+                "CSVParsingError",
+                "Unterminated quoted field at end of input",
+            ));
+        }
+        self.in_quotes = false;
+        self.quote_pending = false;
+        if !self.field.is_empty() || !self.record.is_empty() || self.quoted {
+            self.end_field()?;
+            self.end_record(records);
+        }
+        Ok(())
+    }
+
+    fn end_field(&mut self) -> Result<(), SelectError> {
+        let field = String::from_utf8(std::mem::take(&mut self.field))
+            .map_err(|_| SelectError::new("CSVParsingError", "CSV field is not valid UTF-8"))?;
+        self.record.push(field);
+        self.quoted = false;
+        Ok(())
+    }
+
+    fn end_record(&mut self, records: &mut Vec<Record>) {
+        let record = std::mem::take(&mut self.record);
+        // 跳过空行
+        if record.len() == 1 && record[0].is_empty() {
+            return;
+        }
+        if self.header_pending {
+            self.header_pending = false;
+            if self.options.file_header_info == FileHeaderInfo::Use {
+                self.header = Some(record);
+            }
+            return;
+        }
+        records.push(Record::Csv(record));
+    }
+}
+
+// 增量解析 JSON 记录
+pub(crate) struct JsonReader {
+    json_type: JsonType,
+    buffer: Vec<u8>,
+}
+
+impl JsonReader {
+    pub(crate) fn new(json_type: JsonType) -> Self {
+        JsonReader {
+            json_type,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(
+        &mut self,
+        data: &[u8],
+        records: &mut Vec<Record>,
+    ) -> Result<(), SelectError> {
+        self.buffer.extend_from_slice(data);
+        match self.json_type {
+            JsonType::Lines => {
+                let Some(end) = self.buffer.iter().rposition(|byte| *byte == b'\n') else {
+                    return self.check_size();
+                };
+                let lines: Vec<u8> = self.buffer.drain(..=end).collect();
+                for line in lines.split(|byte| *byte == b'\n') {
+                    parse_line(line, records)?;
+                }
+            }
+            JsonType::Document => {
+                let mut stream = serde_json::Deserializer::from_slice(&self.buffer)
+                    .into_iter::<serde_json::Value>();
+                loop {
+                    match stream.next() {
+                        Some(Ok(value)) => records.push(Record::Json(value)),
+                        Some(Err(err)) if err.is_eof() => break,
+                        Some(Err(err)) => return Err(json_error(err)),
+                        None => break,
+                    }
+                }
+                let offset = stream.byte_offset();
+                self.buffer.drain(..offset);
+            }
+        }
+        self.check_size()
+    }
+
+    // 输入结束，解析缓存中剩余的数据
+    pub(crate) fn finish(&mut self, records: &mut Vec<Record>) -> Result<(), SelectError> {
+        let rest = std::mem::take(&mut self.buffer);
+        match self.json_type {
+            JsonType::Lines => parse_line(&rest, records),
+            JsonType::Document => {
+                for value in serde_json::Deserializer::from_slice(&rest).into_iter() {
+                    records.push(Record::Json(value.map_err(json_error)?));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn check_size(&self) -> Result<(), SelectError> {
+        if self.buffer.len() > MAX_RECORD_SIZE {
+            return Err(over_max_record_size());
+        }
+        Ok(())
+    }
+}
+
+fn parse_line(line: &[u8], records: &mut Vec<Record>) -> Result<(), SelectError> {
+    let line = line.trim_ascii();
+    if !line.is_empty() {
+        records.push(Record::Json(
+            serde_json::from_slice(line).map_err(json_error)?,
+        ));
+    }
+    Ok(())
+}
+
+fn json_error(err: serde_json::Error) -> SelectError {
+    SelectError::new(
+        "JSONParsingError",
+        format!("Error parsing JSON record: {}", err),
+    )
+}
+
+fn over_max_record_size() -> SelectError {
+    SelectError::new(
+        "OverMaxRecordSize",
+        format!("The length of a record exceeds {} bytes", MAX_RECORD_SIZE),
+    )
+}
+
+pub(crate) enum RecordReader {
+    Csv(CsvReader),
+    Json(JsonReader),
+}
+
+impl RecordReader {
+    pub(crate) fn new(format: &InputFormat) -> Self {
+        match format {
+            InputFormat::Csv(options) => RecordReader::Csv(CsvReader::new(options.clone())),
+            InputFormat::Json(json_type) => RecordReader::Json(JsonReader::new(*json_type)),
+        }
+    }
+
+    pub(crate) fn push(
+        &mut self,
+        data: &[u8],
+        records: &mut Vec<Record>,
+    ) -> Result<(), SelectError> {
+        match self {
+            RecordReader::Csv(reader) => reader.push(data, records),
+            RecordReader::Json(reader) => reader.push(data, records),
+        }
+    }
+
+    pub(crate) fn finish(&mut self, records: &mut Vec<Record>) -> Result<(), SelectError> {
+        match self {
+            RecordReader::Csv(reader) => reader.finish(records),
+            RecordReader::Json(reader) => reader.finish(records),
+        }
+    }
+
+    pub(crate) fn header(&self) -> Option<&[String]> {
+        match self {
+            RecordReader::Csv(reader) => reader.header(),
+            RecordReader::Json(_) => None,
+        }
+    }
+}
+
+// CSV 输出时字段加引号的方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuoteFields {
+    Always,
+    AsNeeded,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOutput {
+    pub field_delimiter: String,
+    pub record_delimiter: String,
+    pub quote_character: String,
+    pub quote_escape_character: String,
+    pub quote_fields: QuoteFields,
+}
+
+impl Default for CsvOutput {
+    fn default() -> Self {
+        CsvOutput {
+            field_delimiter: ",".to_string(),
+            record_delimiter: "\n".to_string(),
+            quote_character: "\"".to_string(),
+            quote_escape_character: "\"".to_string(),
+            quote_fields: QuoteFields::AsNeeded,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputFormat {
+    Csv(CsvOutput),
+    // JSON 输出的记录分隔符
+    Json(String),
+}
+
+impl OutputFormat {
+    // 序列化一条输出记录
+    pub(crate) fn write(&self, columns: Vec<(String, Value)>, out: &mut Vec<u8>) {
+        match self {
+            OutputFormat::Csv(options) => {
+                for (idx, (_, value)) in columns.iter().enumerate() {
+                    if idx > 0 {
+                        out.extend_from_slice(options.field_delimiter.as_bytes());
+                    }
+                    let text = value.to_text();
+                    let needs_quotes = options.quote_fields == QuoteFields::Always
+                        || text.contains(options.field_delimiter.as_str())
+                        || text.contains(options.quote_character.as_str())
+                        || text.contains(options.record_delimiter.as_str())
+                        || text.contains(['\r', '\n']);
+                    if needs_quotes && !options.quote_character.is_empty() {
+                        let escaped = format!(
+                            "{}{}",
+                            options.quote_escape_character, options.quote_character
+                        );
+                        out.extend_from_slice(options.quote_character.as_bytes());
+                        out.extend_from_slice(
+                            text.replace(options.quote_character.as_str(), &escaped)
+                                .as_bytes(),
+                        );
+                        out.extend_from_slice(options.quote_character.as_bytes());
+                    } else {
+                        out.extend_from_slice(text.as_bytes());
+                    }
+                }
+                out.extend_from_slice(options.record_delimiter.as_bytes());
+            }
+            OutputFormat::Json(record_delimiter) => {
+                let object: serde_json::Map<String, serde_json::Value> = columns
+                    .into_iter()
+                    .map(|(name, value)| (name, value.to_json()))
+                    .collect();
+                out.extend_from_slice(serde_json::Value::Object(object).to_string().as_bytes());
+                out.extend_from_slice(record_delimiter.as_bytes());
+            }
+        }
+    }
+}
@@ -0,0 +1,1321 @@
+use crate::select::record::Record;
+use crate::select::SelectError;
+use std::cmp::Ordering;
+
+// 不能用作列名或别名的关键字
+const RESERVED: [&str; 16] = [
+    "SELECT", "FROM", "WHERE", "LIMIT", "AND", "OR", "NOT", "LIKE", "ESCAPE", "IS", "NULL", "TRUE",
+    "FALSE", "BETWEEN", "IN", "AS",
+];
+
+// 运算符，长的放在前面以便优先匹配
+const SYMBOLS: [&str; 18] = [
+    "<>", "!=", "<=", ">=", "=", "<", ">", "(", ")", ",", ".", "*", "+", "-", "/", "%", "[", "]",
+];
+
+// SQL 中的值
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    // JSON 记录中的对象或数组
+    Json(serde_json::Value),
+}
+
+impl Value {
+    pub(crate) fn from_json(value: &serde_json::Value) -> Value {
+        match value {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(value) => Value::Bool(*value),
+            serde_json::Value::Number(number) => match number.as_i64() {
+                Some(value) => Value::Int(value),
+                None => Value::Float(number.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::String(value) => Value::String(value.clone()),
+            other => Value::Json(other.clone()),
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Null => serde_json::Value::Null,
+            Value::Bool(value) => serde_json::Value::Bool(*value),
+            Value::Int(value) => serde_json::Value::from(*value),
+            Value::Float(value) => serde_json::Number::from_f64(*value)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
+            Value::String(value) => serde_json::Value::String(value.clone()),
+            Value::Json(value) => value.clone(),
+        }
+    }
+
+    // CSV 输出的文本形式，NULL 为空字符串
+    pub(crate) fn to_text(&self) -> String {
+        match self {
+            Value::Null => String::new(),
+            Value::Bool(value) => value.to_string(),
+            Value::Int(value) => value.to_string(),
+            Value::Float(value) => value.to_string(),
+            Value::String(value) => value.clone(),
+            Value::Json(value) => value.to_string(),
+        }
+    }
+
+    // 转为数值，CSV 字段都是字符串，可以解析为数字的字符串也视为数值
+    fn to_number(&self) -> Option<Value> {
+        match self {
+            Value::Int(_) | Value::Float(_) => Some(self.clone()),
+            Value::String(value) => {
+                let value = value.trim();
+                value
+                    .parse::<i64>()
+                    .map(Value::Int)
+                    .ok()
+                    .or_else(|| value.parse::<f64>().ok().map(Value::Float))
+            }
+            _ => None,
+        }
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        match self.to_number()? {
+            Value::Int(value) => Some(value as f64),
+            Value::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    // 条件表达式的真值，NULL 为 None
+    fn truth(&self) -> Result<Option<bool>, SelectError> {
+        match self {
+            Value::Null => Ok(None),
+            Value::Bool(value) => Ok(Some(*value)),
+            Value::String(value) if value.eq_ignore_ascii_case("true") => Ok(Some(true)),
+            Value::String(value) if value.eq_ignore_ascii_case("false") => Ok(Some(false)),
+            other => Err(invalid_arguments(format!(
+                "{} is not a boolean value",
+                other.to_text()
+            ))),
+        }
+    }
+}
+
+// 比较两个值，NULL 或类型不兼容时返回 None
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+        (Value::Bool(left), Value::Bool(right)) => Some(left.cmp(right)),
+        (Value::Json(left), Value::Json(right)) => (left == right).then_some(Ordering::Equal),
+        _ => match (left.to_number()?, right.to_number()?) {
+            (Value::Int(left), Value::Int(right)) => Some(left.cmp(&right)),
+            (left, right) => left.to_f64()?.partial_cmp(&right.to_f64()?),
+        },
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CastType {
+    Int,
+    Float,
+    String,
+    Bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Function {
+    Lower,
+    Upper,
+    CharLength,
+    Trim,
+    Coalesce,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum AggregateKind {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+}
+
+// 表达式语法树
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expr {
+    Literal(Value),
+    // 列引用，CSV 的 _1、_2 按位置引用，JSON 按路径逐级引用
+    Column(Vec<String>),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Like {
+        expr: Box<Expr>,
+        pattern: Box<Expr>,
+        escape: Option<Box<Expr>>,
+        negated: bool,
+    },
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    Between {
+        expr: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        negated: bool,
+    },
+    In {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    Cast(Box<Expr>, CastType),
+    Function(Function, Vec<Expr>),
+    // COUNT(*) 的参数为 None
+    Aggregate(AggregateKind, Option<Box<Expr>>),
+}
+
+// 查询中的一行记录
+pub(crate) struct Row<'a> {
+    pub record: &'a Record,
+    // CSV 文件头中的列名
+    pub header: Option<&'a [String]>,
+}
+
+impl Row<'_> {
+    fn column(&self, path: &[String]) -> Value {
+        match self.record {
+            Record::Csv(fields) => {
+                let [name] = path else {
+                    return Value::Null;
+                };
+                let position = match name.strip_prefix('_').and_then(|n| n.parse::<usize>().ok()) {
+                    Some(position) if position > 0 => Some(position - 1),
+                    _ => self.header.and_then(|header| {
+                        header.iter().position(|column| column == name).or_else(|| {
+                            header
+                                .iter()
+                                .position(|column| column.eq_ignore_ascii_case(name))
+                        })
+                    }),
+                };
+                position
+                    .and_then(|position| fields.get(position))
+                    .map(|field| Value::String(field.clone()))
+                    .unwrap_or(Value::Null)
+            }
+            Record::Json(value) => {
+                let mut current = value;
+                for name in path {
+                    let serde_json::Value::Object(object) = current else {
+                        return Value::Null;
+                    };
+                    let child = object.get(name).or_else(|| {
+                        object
+                            .iter()
+                            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                            .map(|(_, value)| value)
+                    });
+                    match child {
+                        Some(child) => current = child,
+                        None => return Value::Null,
+                    }
+                }
+                Value::from_json(current)
+            }
+        }
+    }
+
+    // SELECT * 输出的所有列
+    fn all_columns(&self) -> Vec<(String, Value)> {
+        match self.record {
+            Record::Csv(fields) => fields
+                .iter()
+                .enumerate()
+                .map(|(idx, field)| {
+                    let name = self
+                        .header
+                        .and_then(|header| header.get(idx).cloned())
+                        .unwrap_or_else(|| format!("_{}", idx + 1));
+                    (name, Value::String(field.clone()))
+                })
+                .collect(),
+            Record::Json(serde_json::Value::Object(object)) => object
+                .iter()
+                .map(|(key, value)| (key.clone(), Value::from_json(value)))
+                .collect(),
+            Record::Json(value) => vec![("_1".to_string(), Value::from_json(value))],
+        }
+    }
+}
+
+impl Expr {
+    pub(crate) fn eval(&self, row: &Row) -> Result<Value, SelectError> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Column(path) => Ok(row.column(path)),
+            Expr::Neg(expr) => arith(BinaryOp::Sub, &Value::Int(0), &expr.eval(row)?),
+            Expr::Not(expr) => Ok(match expr.eval(row)?.truth()? {
+                Some(value) => Value::Bool(!value),
+                None => Value::Null,
+            }),
+            Expr::Binary(BinaryOp::And, left, right) => {
+                let left = left.eval(row)?.truth()?;
+                if left == Some(false) {
+                    return Ok(Value::Bool(false));
+                }
+                Ok(match (left, right.eval(row)?.truth()?) {
+                    (_, Some(false)) => Value::Bool(false),
+                    (Some(true), Some(true)) => Value::Bool(true),
+                    _ => Value::Null,
+                })
+            }
+            Expr::Binary(BinaryOp::Or, left, right) => {
+                let left = left.eval(row)?.truth()?;
+                if left == Some(true) {
+                    return Ok(Value::Bool(true));
+                }
+                Ok(match (left, right.eval(row)?.truth()?) {
+                    (_, Some(true)) => Value::Bool(true),
+                    (Some(false), Some(false)) => Value::Bool(false),
+                    _ => Value::Null,
+                })
+            }
+            Expr::Binary(op, left, right) => {
+                let left = left.eval(row)?;
+                let right = right.eval(row)?;
+                match op {
+                    BinaryOp::Add
+                    | BinaryOp::Sub
+                    | BinaryOp::Mul
+                    | BinaryOp::Div
+                    | BinaryOp::Mod => arith(*op, &left, &right),
+                    _ => Ok(match compare(&left, &right) {
+                        Some(ordering) => Value::Bool(match op {
+                            BinaryOp::Eq => ordering == Ordering::Equal,
+                            BinaryOp::Ne => ordering != Ordering::Equal,
+                            BinaryOp::Lt => ordering == Ordering::Less,
+                            BinaryOp::Le => ordering != Ordering::Greater,
+                            BinaryOp::Gt => ordering == Ordering::Greater,
+                            _ => ordering != Ordering::Less,
+                        }),
+                        None => Value::Null,
+                    }),
+                }
+            }
+            Expr::Like {
+                expr,
+                pattern,
+                escape,
+                negated,
+            } => {
+                let value = expr.eval(row)?;
+                let pattern = pattern.eval(row)?;
+                let escape = match escape {
+                    Some(escape) => match escape.eval(row)? {
+                        Value::String(escape) if escape.chars().count() == 1 => {
+                            escape.chars().next()
+                        }
+                        _ => return Err(invalid_arguments("ESCAPE must be a single character")),
+                    },
+                    None => None,
+                };
+                if value == Value::Null || pattern == Value::Null {
+                    return Ok(Value::Null);
+                }
+                let matched = like(&value.to_text(), &pattern.to_text(), escape)?;
+                Ok(Value::Bool(matched != *negated))
+            }
+            Expr::IsNull { expr, negated } => {
+                Ok(Value::Bool((expr.eval(row)? == Value::Null) != *negated))
+            }
+            Expr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => {
+                let value = expr.eval(row)?;
+                let low = compare(&value, &low.eval(row)?);
+                let high = compare(&value, &high.eval(row)?);
+                Ok(match (low, high) {
+                    (Some(low), Some(high)) => Value::Bool(
+                        (low != Ordering::Less && high != Ordering::Greater) != *negated,
+                    ),
+                    _ => Value::Null,
+                })
+            }
+            Expr::In {
+                expr,
+                list,
+                negated,
+            } => {
+                let value = expr.eval(row)?;
+                let mut unknown = false;
+                for item in list {
+                    match compare(&value, &item.eval(row)?) {
+                        Some(Ordering::Equal) => return Ok(Value::Bool(!*negated)),
+                        Some(_) => {}
+                        None => unknown = true,
+                    }
+                }
+                Ok(if unknown {
+                    Value::Null
+                } else {
+                    Value::Bool(*negated)
+                })
+            }
+            Expr::Cast(expr, cast_type) => cast(expr.eval(row)?, *cast_type),
+            Expr::Function(function, args) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(arg.eval(row)?);
+                }
+                call(*function, values)
+            }
+            Expr::Aggregate(..) => Err(SelectError::new(
+                "UnsupportedSyntax",
+                "Aggregate functions are only supported in the SELECT list",
+            )),
+        }
+    }
+
+    fn contains_aggregate(&self) -> bool {
+        match self {
+            Expr::Aggregate(..) => true,
+            Expr::Literal(_) | Expr::Column(_) => false,
+            Expr::Neg(expr) | Expr::Not(expr) | Expr::Cast(expr, _) => expr.contains_aggregate(),
+            Expr::IsNull { expr, .. } => expr.contains_aggregate(),
+            Expr::Binary(_, left, right) => left.contains_aggregate() || right.contains_aggregate(),
+            Expr::Like {
+                expr,
+                pattern,
+                escape,
+                ..
+            } => {
+                expr.contains_aggregate()
+                    || pattern.contains_aggregate()
+                    || escape
+                        .as_ref()
+                        .is_some_and(|escape| escape.contains_aggregate())
+            }
+            Expr::Between {
+                expr, low, high, ..
+            } => expr.contains_aggregate() || low.contains_aggregate() || high.contains_aggregate(),
+            Expr::In { expr, list, .. } => {
+                expr.contains_aggregate() || list.iter().any(Expr::contains_aggregate)
+            }
+            Expr::Function(_, args) => args.iter().any(Expr::contains_aggregate),
+        }
+    }
+
+    // 去掉列引用中的表别名，如 s.name -> name
+    fn strip_alias(&mut self, alias: &str) {
+        match self {
+            Expr::Column(path) => {
+                if path.len() > 1 && path[0].eq_ignore_ascii_case(alias) {
+                    path.remove(0);
+                }
+            }
+            Expr::Literal(_) => {}
+            Expr::Neg(expr) | Expr::Not(expr) | Expr::Cast(expr, _) => expr.strip_alias(alias),
+            Expr::IsNull { expr, .. } => expr.strip_alias(alias),
+            Expr::Binary(_, left, right) => {
+                left.strip_alias(alias);
+                right.strip_alias(alias);
+            }
+            Expr::Like {
+                expr,
+                pattern,
+                escape,
+                ..
+            } => {
+                expr.strip_alias(alias);
+                pattern.strip_alias(alias);
+                if let Some(escape) = escape {
+                    escape.strip_alias(alias);
+                }
+            }
+            Expr::Between {
+                expr, low, high, ..
+            } => {
+                expr.strip_alias(alias);
+                low.strip_alias(alias);
+                high.strip_alias(alias);
+            }
+            Expr::In { expr, list, .. } => {
+                expr.strip_alias(alias);
+                list.iter_mut().for_each(|item| item.strip_alias(alias));
+            }
+            Expr::Function(_, args) => args.iter_mut().for_each(|arg| arg.strip_alias(alias)),
+            Expr::Aggregate(_, arg) => {
+                if let Some(arg) = arg {
+                    arg.strip_alias(alias);
+                }
+            }
+        }
+    }
+}
+
+// 算术运算，整数溢出时转为浮点数，除数为0时结果为 NULL
+fn arith(op: BinaryOp, left: &Value, right: &Value) -> Result<Value, SelectError> {
+    if *left == Value::Null || *right == Value::Null {
+        return Ok(Value::Null);
+    }
+    let (Some(left), Some(right)) = (left.to_number(), right.to_number()) else {
+        return Err(invalid_arguments(format!(
+            "Cannot apply arithmetic to {} and {}",
+            left.to_text(),
+            right.to_text()
+        )));
+    };
+    if let (Value::Int(left), Value::Int(right)) = (&left, &right) {
+        let result = match op {
+            BinaryOp::Add => left.checked_add(*right),
+            BinaryOp::Sub => left.checked_sub(*right),
+            BinaryOp::Mul => left.checked_mul(*right),
+            BinaryOp::Div if *right == 0 => return Ok(Value::Null),
+            BinaryOp::Div => left.checked_div(*right),
+            BinaryOp::Mod if *right == 0 => return Ok(Value::Null),
+            _ => left.checked_rem(*right),
+        };
+        if let Some(result) = result {
+            return Ok(Value::Int(result));
+        }
+    }
+    let (left, right) = (
+        left.to_f64().unwrap_or(f64::NAN),
+        right.to_f64().unwrap_or(f64::NAN),
+    );
+    Ok(match op {
+        BinaryOp::Add => Value::Float(left + right),
+        BinaryOp::Sub => Value::Float(left - right),
+        BinaryOp::Mul => Value::Float(left * right),
+        _ if right == 0.0 => Value::Null,
+        BinaryOp::Div => Value::Float(left / right),
+        _ => Value::Float(left % right),
+    })
+}
+
+fn cast(value: Value, cast_type: CastType) -> Result<Value, SelectError> {
+    if value == Value::Null {
+        return Ok(Value::Null);
+    }
+    let failed = || {
+        SelectError::new(
+            "CastFailed",
+            format!(
+                "Attempt to convert from one data type to another failed: {}",
+                value.to_text()
+            ),
+        )
+    };
+    match cast_type {
+        CastType::Int => match value.to_number().ok_or_else(failed)? {
+            Value::Float(number) if number.is_finite() => Ok(Value::Int(number.trunc() as i64)),
+            Value::Int(number) => Ok(Value::Int(number)),
+            _ => Err(failed()),
+        },
+        CastType::Float => value.to_f64().map(Value::Float).ok_or_else(failed),
+        CastType::String => Ok(Value::String(value.to_text())),
+        CastType::Bool => match &value {
+            Value::Bool(_) => Ok(value),
+            Value::Int(number) => Ok(Value::Bool(*number != 0)),
+            Value::String(text) if text.eq_ignore_ascii_case("true") => Ok(Value::Bool(true)),
+            Value::String(text) if text.eq_ignore_ascii_case("false") => Ok(Value::Bool(false)),
+            _ => Err(failed()),
+        },
+    }
+}
+
+fn call(function: Function, mut args: Vec<Value>) -> Result<Value, SelectError> {
+    if function == Function::Coalesce {
+        return Ok(args
+            .into_iter()
+            .find(|value| *value != Value::Null)
+            .unwrap_or(Value::Null));
+    }
+    let value = match args.pop() {
+        Some(value) if args.is_empty() => value,
+        _ => {
+            return Err(invalid_arguments(format!(
+                "{:?} takes exactly one argument",
+                function
+            )))
+        }
+    };
+    if value == Value::Null {
+        return Ok(Value::Null);
+    }
+    let text = value.to_text();
+    Ok(match function {
+        Function::Lower => Value::String(text.to_lowercase()),
+        Function::Upper => Value::String(text.to_uppercase()),
+        Function::CharLength => Value::Int(text.chars().count() as i64),
+        _ => Value::String(text.trim().to_string()),
+    })
+}
+
+// LIKE 匹配，% 匹配任意个字符，_ 匹配单个字符
+pub(crate) fn like(text: &str, pattern: &str, escape: Option<char>) -> Result<bool, SelectError> {
+    // None 表示 %，Some(None) 表示 _，Some(Some(c)) 表示普通字符
+    let mut tokens: Vec<Option<Option<char>>> = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if Some(c) == escape {
+            let escaped = chars
+                .next()
+                .ok_or_else(|| invalid_arguments("LIKE pattern ends with the escape character"))?;
+            tokens.push(Some(Some(escaped)));
+        } else if c == '%' {
+            tokens.push(None);
+        } else if c == '_' {
+            tokens.push(Some(None));
+        } else {
+            tokens.push(Some(Some(c)));
+        }
+    }
+    let text: Vec<char> = text.chars().collect();
+    let (mut t, mut p) = (0, 0);
+    // 最近一个 % 的位置及其匹配到的文本位置，用于回溯
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match tokens.get(p) {
+            Some(None) => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(Some(expected)) if expected.is_none() || *expected == Some(text[t]) => {
+                t += 1;
+                p += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return Ok(false),
+            },
+        }
+    }
+    Ok(tokens[p..].iter().all(Option::is_none))
+}
+
+// 聚合函数的中间结果
+#[derive(Debug, Clone)]
+pub(crate) enum Accumulator {
+    Count(u64),
+    Sum(Value),
+    Min(Value),
+    Max(Value),
+    Avg(f64, u64),
+}
+
+impl Accumulator {
+    fn new(kind: AggregateKind) -> Self {
+        match kind {
+            AggregateKind::Count => Accumulator::Count(0),
+            AggregateKind::Sum => Accumulator::Sum(Value::Null),
+            AggregateKind::Min => Accumulator::Min(Value::Null),
+            AggregateKind::Max => Accumulator::Max(Value::Null),
+            AggregateKind::Avg => Accumulator::Avg(0.0, 0),
+        }
+    }
+
+    fn update(&mut self, value: Value) -> Result<(), SelectError> {
+        if value == Value::Null {
+            return Ok(());
+        }
+        // MIN 保留更小的值，MAX 保留更大的值
+        let wanted = match self {
+            Accumulator::Min(_) => Ordering::Less,
+            _ => Ordering::Greater,
+        };
+        match self {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Sum(sum) => {
+                let base = match sum {
+                    Value::Null => Value::Int(0),
+                    _ => sum.clone(),
+                };
+                *sum = arith(BinaryOp::Add, &base, &value)?;
+            }
+            Accumulator::Min(current) | Accumulator::Max(current) => {
+                let replace = match current {
+                    Value::Null => true,
+                    _ => {
+                        compare(&value, current).ok_or_else(|| {
+                            invalid_arguments(format!("Cannot compare {}", value.to_text()))
+                        })? == wanted
+                    }
+                };
+                if replace {
+                    *current = value;
+                }
+            }
+            Accumulator::Avg(sum, count) => {
+                *sum += value.to_f64().ok_or_else(|| {
+                    invalid_arguments(format!("{} is not a number", value.to_text()))
+                })?;
+                *count += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn result(&self) -> Value {
+        match self {
+            Accumulator::Count(count) => Value::Int(*count as i64),
+            Accumulator::Sum(value) | Accumulator::Min(value) | Accumulator::Max(value) => {
+                value.clone()
+            }
+            Accumulator::Avg(_, 0) => Value::Null,
+            Accumulator::Avg(sum, count) => Value::Float(*sum / *count as f64),
+        }
+    }
+}
+
+// SELECT 列表中的一项
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SelectItem {
+    pub expr: Expr,
+    pub alias: Option<String>,
+}
+
+// 解析后的查询
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    // None 表示 SELECT *
+    pub(crate) projection: Option<Vec<SelectItem>>,
+    pub(crate) filter: Option<Expr>,
+    pub(crate) limit: Option<u64>,
+    // FROM S3Object[*]：JSON 文档为数组时逐个元素作为记录
+    pub(crate) unnest: bool,
+    // SELECT 列表全部为聚合函数
+    pub(crate) aggregate: bool,
+}
+
+impl Query {
+    pub fn parse(sql: &str) -> Result<Query, SelectError> {
+        let mut parser = Parser {
+            tokens: tokenize(sql)?,
+            pos: 0,
+        };
+        let mut query = parser.parse_query()?;
+        if let Some(filter) = &query.filter {
+            if filter.contains_aggregate() {
+                return Err(SelectError::new(
+                    "UnsupportedSyntax",
+                    "Aggregate functions are not allowed in the WHERE clause",
+                ));
+            }
+        }
+        if let Some(items) = &query.projection {
+            let aggregates = items
+                .iter()
+                .filter(|item| matches!(item.expr, Expr::Aggregate(..)))
+                .count();
+            if items.iter().any(|item| {
+                !matches!(item.expr, Expr::Aggregate(..)) && item.expr.contains_aggregate()
+            }) || (aggregates > 0 && aggregates < items.len())
+            {
+                return Err(SelectError::new(
+                    "UnsupportedSyntax",
+                    "Aggregate functions cannot be mixed with other expressions in the SELECT list",
+                ));
+            }
+            query.aggregate = aggregates > 0;
+        }
+        Ok(query)
+    }
+
+    pub(crate) fn matches(&self, row: &Row) -> Result<bool, SelectError> {
+        match &self.filter {
+            Some(filter) => Ok(filter.eval(row)?.truth()? == Some(true)),
+            None => Ok(true),
+        }
+    }
+
+    // 非聚合查询的输出列
+    pub(crate) fn project(&self, row: &Row) -> Result<Vec<(String, Value)>, SelectError> {
+        let Some(items) = &self.projection else {
+            return Ok(row.all_columns());
+        };
+        let mut columns = Vec::with_capacity(items.len());
+        for (idx, item) in items.iter().enumerate() {
+            columns.push((column_name(item, idx), item.expr.eval(row)?));
+        }
+        Ok(columns)
+    }
+
+    pub(crate) fn accumulators(&self) -> Vec<Accumulator> {
+        self.projection
+            .iter()
+            .flatten()
+            .filter_map(|item| match &item.expr {
+                Expr::Aggregate(kind, _) => Some(Accumulator::new(*kind)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub(crate) fn accumulate(
+        &self,
+        accumulators: &mut [Accumulator],
+        row: &Row,
+    ) -> Result<(), SelectError> {
+        let items = self.projection.iter().flatten();
+        for (item, accumulator) in items.zip(accumulators.iter_mut()) {
+            if let Expr::Aggregate(_, arg) = &item.expr {
+                let value = match arg {
+                    Some(arg) => arg.eval(row)?,
+                    None => Value::Bool(true),
+                };
+                accumulator.update(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn aggregate_result(&self, accumulators: &[Accumulator]) -> Vec<(String, Value)> {
+        let items = self.projection.iter().flatten();
+        items
+            .zip(accumulators)
+            .enumerate()
+            .map(|(idx, (item, accumulator))| (column_name(item, idx), accumulator.result()))
+            .collect()
+    }
+}
+
+// 输出列名：别名、列名或 _{序号}
+fn column_name(item: &SelectItem, idx: usize) -> String {
+    match (&item.alias, &item.expr) {
+        (Some(alias), _) => alias.clone(),
+        (None, Expr::Column(path)) => path.last().cloned().unwrap_or_default(),
+        _ => format!("_{}", idx + 1),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    // 双引号括起来的标识符，保留大小写
+    Quoted(String),
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Symbol(&'static str),
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, SelectError> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    'outer: while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() || c == ';' {
+            i += 1;
+            continue;
+        }
+        if c == '\'' || c == '"' {
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(parse_error("Unterminated literal")),
+                    Some(&ch) if ch == c => {
+                        if chars.get(i + 1) == Some(&c) {
+                            value.push(c);
+                            i += 2;
+                        } else {
+                            i += 1;
+                            break;
+                        }
+                    }
+                    Some(&ch) => {
+                        value.push(ch);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(if c == '\'' {
+                Token::Str(value)
+            } else {
+                Token::Quoted(value)
+            });
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                i += 1;
+                if i < chars.len() && (chars[i] == '+' || chars[i] == '-') {
+                    i += 1;
+                }
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = text.parse::<i64>().map(Token::Int).or_else(|_| {
+                text.parse::<f64>()
+                    .map(Token::Float)
+                    .map_err(|_| parse_error(format!("Invalid number {}", text)))
+            })?;
+            tokens.push(number);
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            continue;
+        }
+        for symbol in SYMBOLS {
+            let len = symbol.len();
+            if i + len <= chars.len() && chars[i..i + len].iter().copied().eq(symbol.chars()) {
+                tokens.push(Token::Symbol(symbol));
+                i += len;
+                continue 'outer;
+            }
+        }
+        return Err(parse_error(format!("Unexpected character '{}'", c)));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let matched = self.peek_keyword(keyword);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), SelectError> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(keyword))
+        }
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let matched = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), SelectError> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected(symbol))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> SelectError {
+        match self.peek() {
+            Some(token) => parse_error(format!("Expected {}, found {:?}", expected, token)),
+            None => parse_error(format!("Expected {}, found end of expression", expected)),
+        }
+    }
+
+    // 读取标识符 (别名、列名等)，关键字除外
+    fn identifier(&mut self) -> Option<String> {
+        let name = match self.peek()? {
+            Token::Ident(name) if !is_reserved(name) => name.clone(),
+            Token::Quoted(name) => name.clone(),
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(name)
+    }
+
+    fn parse_query(&mut self) -> Result<Query, SelectError> {
+        self.expect_keyword("SELECT")?;
+        let projection = if self.eat_symbol("*") {
+            None
+        } else {
+            let mut items = Vec::new();
+            loop {
+                let expr = self.parse_expr()?;
+                let alias = if self.eat_keyword("AS") {
+                    Some(self.identifier().ok_or_else(|| self.unexpected("alias"))?)
+                } else {
+                    self.identifier()
+                };
+                items.push(SelectItem { expr, alias });
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+            Some(items)
+        };
+        self.expect_keyword("FROM")?;
+        if !self.eat_keyword("S3Object") {
+            return Err(self.unexpected("S3Object"));
+        }
+        let unnest = if self.eat_symbol("[") {
+            self.expect_symbol("*")?;
+            self.expect_symbol("]")?;
+            true
+        } else {
+            false
+        };
+        let alias = if self.eat_keyword("AS") {
+            Some(self.identifier().ok_or_else(|| self.unexpected("alias"))?)
+        } else {
+            self.identifier()
+        };
+        let filter = if self.eat_keyword("WHERE") {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        let limit = if self.eat_keyword("LIMIT") {
+            match self.peek() {
+                Some(Token::Int(limit)) if *limit >= 0 => {
+                    let limit = *limit as u64;
+                    self.pos += 1;
+                    Some(limit)
+                }
+                _ => return Err(self.unexpected("a non-negative integer")),
+            }
+        } else {
+            None
+        };
+        if self.peek().is_some() {
+            return Err(self.unexpected("end of expression"));
+        }
+        let mut query = Query {
+            projection,
+            filter,
+            limit,
+            unnest,
+            aggregate: false,
+        };
+        for alias in alias.iter().map(String::as_str).chain(["S3Object"]) {
+            for item in query.projection.iter_mut().flatten() {
+                item.expr.strip_alias(alias);
+            }
+            if let Some(filter) = &mut query.filter {
+                filter.strip_alias(alias);
+            }
+        }
+        Ok(query)
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, SelectError> {
+        let mut left = self.parse_and()?;
+        while self.eat_keyword("OR") {
+            let right = self.parse_and()?;
+            left = Expr::Binary(BinaryOp::Or, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, SelectError> {
+        let mut left = self.parse_not()?;
+        while self.eat_keyword("AND") {
+            let right = self.parse_not()?;
+            left = Expr::Binary(BinaryOp::And, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, SelectError> {
+        if self.eat_keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, SelectError> {
+        let left = self.parse_additive()?;
+        if self.eat_keyword("IS") {
+            let negated = self.eat_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(Expr::IsNull {
+                expr: Box::new(left),
+                negated,
+            });
+        }
+        let negated = self.eat_keyword("NOT");
+        if self.eat_keyword("LIKE") {
+            let pattern = self.parse_additive()?;
+            let escape = if self.eat_keyword("ESCAPE") {
+                Some(Box::new(self.parse_additive()?))
+            } else {
+                None
+            };
+            return Ok(Expr::Like {
+                expr: Box::new(left),
+                pattern: Box::new(pattern),
+                escape,
+                negated,
+            });
+        }
+        if self.eat_keyword("BETWEEN") {
+            let low = self.parse_additive()?;
+            self.expect_keyword("AND")?;
+            let high = self.parse_additive()?;
+            return Ok(Expr::Between {
+                expr: Box::new(left),
+                low: Box::new(low),
+                high: Box::new(high),
+                negated,
+            });
+        }
+        if self.eat_keyword("IN") {
+            self.expect_symbol("(")?;
+            let mut list = vec![self.parse_expr()?];
+            while self.eat_symbol(",") {
+                list.push(self.parse_expr()?);
+            }
+            self.expect_symbol(")")?;
+            return Ok(Expr::In {
+                expr: Box::new(left),
+                list,
+                negated,
+            });
+        }
+        if negated {
+            return Err(self.unexpected("LIKE, BETWEEN or IN"));
+        }
+        for (symbol, op) in [
+            ("=", BinaryOp::Eq),
+            ("!=", BinaryOp::Ne),
+            ("<>", BinaryOp::Ne),
+            ("<=", BinaryOp::Le),
+            (">=", BinaryOp::Ge),
+            ("<", BinaryOp::Lt),
+            (">", BinaryOp::Gt),
+        ] {
+            if self.eat_symbol(symbol) {
+                let right = self.parse_additive()?;
+                return Ok(Expr::Binary(op, Box::new(left), Box::new(right)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, SelectError> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let op = if self.eat_symbol("+") {
+                BinaryOp::Add
+            } else if self.eat_symbol("-") {
+                BinaryOp::Sub
+            } else {
+                return Ok(left);
+            };
+            let right = self.parse_multiplicative()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, SelectError> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let op = if self.eat_symbol("*") {
+                BinaryOp::Mul
+            } else if self.eat_symbol("/") {
+                BinaryOp::Div
+            } else if self.eat_symbol("%") {
+                BinaryOp::Mod
+            } else {
+                return Ok(left);
+            };
+            let right = self.parse_unary()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, SelectError> {
+        if self.eat_symbol("-") {
+            return Ok(Expr::Neg(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, SelectError> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| self.unexpected("expression"))?;
+        match token {
+            Token::Int(value) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Int(value)))
+            }
+            Token::Float(value) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Float(value)))
+            }
+            Token::Str(value) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::String(value)))
+            }
+            Token::Symbol("(") => {
+                self.pos += 1;
+                let expr = self.parse_expr()?;
+                self.expect_symbol(")")?;
+                Ok(expr)
+            }
+            Token::Ident(word) if word.eq_ignore_ascii_case("NULL") => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Null))
+            }
+            Token::Ident(word)
+                if word.eq_ignore_ascii_case("TRUE") || word.eq_ignore_ascii_case("FALSE") =>
+            {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Bool(
+                    word.eq_ignore_ascii_case("TRUE"),
+                )))
+            }
+            Token::Ident(word) if !is_reserved(&word) => {
+                self.pos += 1;
+                if self.eat_symbol("(") {
+                    return self.parse_call(&word);
+                }
+                self.parse_path(word)
+            }
+            Token::Quoted(name) => {
+                self.pos += 1;
+                self.parse_path(name)
+            }
+            _ => Err(self.unexpected("expression")),
+        }
+    }
+
+    fn parse_path(&mut self, first: String) -> Result<Expr, SelectError> {
+        let mut path = vec![first];
+        while self.eat_symbol(".") {
+            path.push(
+                self.identifier()
+                    .ok_or_else(|| self.unexpected("column name"))?,
+            );
+        }
+        Ok(Expr::Column(path))
+    }
+
+    // 解析函数调用，左括号已读取
+    fn parse_call(&mut self, name: &str) -> Result<Expr, SelectError> {
+        let name = name.to_ascii_uppercase();
+        if name == "CAST" {
+            let expr = self.parse_expr()?;
+            self.expect_keyword("AS")?;
+            let cast_type = match self.peek() {
+                Some(Token::Ident(name)) => match name.to_ascii_uppercase().as_str() {
+                    "INT" | "INTEGER" | "BIGINT" => CastType::Int,
+                    "FLOAT" | "DOUBLE" | "REAL" | "DECIMAL" | "NUMERIC" => CastType::Float,
+                    "STRING" | "VARCHAR" | "CHAR" => CastType::String,
+                    "BOOL" | "BOOLEAN" => CastType::Bool,
+                    _ => return Err(self.unexpected("data type")),
+                },
+                _ => return Err(self.unexpected("data type")),
+            };
+            self.pos += 1;
+            self.expect_symbol(")")?;
+            return Ok(Expr::Cast(Box::new(expr), cast_type));
+        }
+        let aggregate = match name.as_str() {
+            "COUNT" => Some(AggregateKind::Count),
+            "SUM" => Some(AggregateKind::Sum),
+            "MIN" => Some(AggregateKind::Min),
+            "MAX" => Some(AggregateKind::Max),
+            "AVG" => Some(AggregateKind::Avg),
+            _ => None,
+        };
+        if let Some(kind) = aggregate {
+            if kind == AggregateKind::Count && self.eat_symbol("*") {
+                self.expect_symbol(")")?;
+                return Ok(Expr::Aggregate(kind, None));
+            }
+            let arg = self.parse_expr()?;
+            self.expect_symbol(")")?;
+            return Ok(Expr::Aggregate(kind, Some(Box::new(arg))));
+        }
+        let function = match name.as_str() {
+            "LOWER" => Function::Lower,
+            "UPPER" => Function::Upper,
+            "CHAR_LENGTH" | "CHARACTER_LENGTH" => Function::CharLength,
+            "TRIM" => Function::Trim,
+            "COALESCE" => Function::Coalesce,
+            _ => {
+                return Err(SelectError::new(
+                    "UnsupportedFunction",
+                    format!("Function {} is not supported", name),
+                ))
+            }
+        };
+        let mut args = Vec::new();
+        if !self.eat_symbol(")") {
+            args.push(self.parse_expr()?);
+            while self.eat_symbol(",") {
+                args.push(self.parse_expr()?);
+            }
+            self.expect_symbol(")")?;
+        }
+        Ok(Expr::Function(function, args))
+    }
+}
+
+fn is_reserved(word: &str) -> bool {
+    RESERVED
+        .iter()
+        .any(|keyword| keyword.eq_ignore_ascii_case(word))
+}
+
+fn parse_error(message: impl Into<String>) -> SelectError {
+    SelectError::new("ParseUnexpectedToken", message)
+}
+
+fn invalid_arguments(message: impl Into<String>) -> SelectError {
+    SelectError::new("EvaluatorInvalidArguments", message)
+}
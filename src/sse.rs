@@ -0,0 +1,349 @@
+use crate::bucket;
+use crate::err::AppError;
+use crate::fs::{CustomerKeyCheck, ObjectEncryption};
+use crate::model::ServerSideEncryptionConfiguration;
+use crate::raft::app::App;
+use crate::util::cry;
+use crate::util::durable::{self, Durability};
+use anyhow::{anyhow, Context};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use crypto_hash::{digest, Algorithm};
+use log::warn;
+use ntex::web;
+use std::path::{Path, PathBuf};
+
+pub(crate) const SSE_HEADER: &str = "x-amz-server-side-encryption";
+pub(crate) const SSE_C_ALGORITHM_HEADER: &str = "x-amz-server-side-encryption-customer-algorithm";
+pub(crate) const SSE_C_KEY_MD5_HEADER: &str = "x-amz-server-side-encryption-customer-key-md5";
+pub(crate) const SSE_ALGORITHM: &str = "AES256";
+// 包装数据密钥时使用的附加认证数据
+const WRAP_AAD: &[u8] = b"sse-s3";
+const CUSTOMER_WRAP_AAD: &[u8] = b"sse-c";
+
+// 加载主密钥：使用指定的密钥文件，单节点部署时读取或生成 {fs_root}/master.key
+// 主密钥用于包装每个对象的数据密钥，集群内所有节点必须一致，由 App 持有
+// 集群中各节点自动生成的密钥互不相同，其他节点无法解密，因此集群节点必须指定密钥文件
+pub(crate) fn load_master_key(
+    key_file: Option<String>,
+    fs_root: &str,
+    single_node: bool,
+    durability: Durability,
+) -> anyhow::Result<[u8; 32]> {
+    if let Some(path) = key_file {
+        return read_key_file(path);
+    }
+    let path = PathBuf::from(fs_root).join("master.key");
+    if !single_node {
+        return Err(anyhow!(
+            "未指定主密钥文件，集群内所有节点需通过 --master-key-file 使用相同的主密钥，单节点部署可使用 --single-node 自动生成 {}",
+            path.display()
+        ));
+    }
+    if path.exists() {
+        return read_key_file(&path);
+    }
+    warn!("主密钥文件不存在，已生成 {}", path.display());
+    let key = cry::gen_aes_256_key();
+    std::fs::create_dir_all(fs_root).context("创建文件夹失败")?;
+    durable::write(&path, hex::encode(key).as_bytes(), durability).context("保存主密钥失败")?;
+    Ok(key)
+}
+
+// 读取十六进制编码的主密钥文件
+fn read_key_file(path: impl AsRef<Path>) -> anyhow::Result<[u8; 32]> {
+    let content = std::fs::read_to_string(path).context("读取主密钥文件失败")?;
+    let key = hex::decode(content.trim()).context("主密钥格式错误")?;
+    key.try_into()
+        .map_err(|_| anyhow!("主密钥长度必须为32字节"))
+}
+
+// 客户在请求中提供的密钥(SSE-C)，只在处理请求期间保存在内存中
+pub(crate) struct CustomerKey {
+    key: [u8; 32],
+    key_md5: String,
+}
+
+impl CustomerKey {
+    // 从请求头解析客户密钥，copy_source 为 true 时读取 x-amz-copy-source-* 请求头
+    pub(crate) fn from_request(
+        req: &web::HttpRequest,
+        copy_source: bool,
+    ) -> Result<Option<Self>, AppError> {
+        let prefix = if copy_source {
+            "x-amz-copy-source-"
+        } else {
+            "x-amz-"
+        };
+        let header = |name: &str| -> Result<Option<String>, AppError> {
+            match req.headers().get(format!("{}{}", prefix, name).as_str()) {
+                Some(value) => Ok(Some(
+                    value
+                        .to_str()
+                        .map_err(|_| invalid_customer_key())?
+                        .to_string(),
+                )),
+                None => Ok(None),
+            }
+        };
+        let algorithm = header("server-side-encryption-customer-algorithm")?;
+        let key = header("server-side-encryption-customer-key")?;
+        let key_md5 = header("server-side-encryption-customer-key-md5")?;
+        match (algorithm, key, key_md5) {
+            (None, None, None) => Ok(None),
+            (Some(algorithm), Some(key), Some(key_md5)) => {
+                if algorithm != SSE_ALGORITHM {
+                    return Err(AppError::s3(
+                        400,
+                        "InvalidEncryptionAlgorithmError",
+                        "The encryption request you specified is not valid. The valid value is AES256.",
+                    ));
+                }
+                let key = STANDARD.decode(key).map_err(|_| invalid_customer_key())?;
+                let key: [u8; 32] = key.try_into().map_err(|_| invalid_customer_key())?;
+                if STANDARD.encode(digest(Algorithm::MD5, &key)) != key_md5 {
+                    return Err(AppError::s3(
+                        400,
+                        "InvalidArgument",
+                        "The calculated MD5 hash of the key did not match the hash that was provided.",
+                    ));
+                }
+                Ok(Some(CustomerKey { key, key_md5 }))
+            }
+            _ => Err(AppError::s3(
+                400,
+                "InvalidArgument",
+                "Requests specifying Server Side Encryption with Customer provided keys must provide the algorithm, the key and the key MD5.",
+            )),
+        }
+    }
+
+    pub(crate) fn key(&self) -> &[u8; 32] {
+        &self.key
+    }
+
+    pub(crate) fn key_md5(&self) -> &str {
+        &self.key_md5
+    }
+}
+
+// 请求要求的加密方式
+pub(crate) enum SseRequest {
+    S3,
+    Customer(CustomerKey),
+}
+
+impl SseRequest {
+    // 用主密钥或客户密钥包装数据密钥
+    pub(crate) fn wrap(
+        &self,
+        master_key: &[u8; 32],
+        data_key: &[u8],
+    ) -> anyhow::Result<ObjectEncryption> {
+        match self {
+            SseRequest::S3 => Ok(ObjectEncryption {
+                algorithm: SSE_ALGORITHM.to_string(),
+                wrapped_key: cry::aes_256_gcm_encrypt(master_key, data_key, WRAP_AAD)?,
+                customer_key: None,
+            }),
+            SseRequest::Customer(customer_key) => {
+                let salt: [u8; 16] = rand::random();
+                Ok(ObjectEncryption {
+                    algorithm: SSE_ALGORITHM.to_string(),
+                    wrapped_key: cry::aes_256_gcm_encrypt(
+                        &customer_key.key,
+                        data_key,
+                        CUSTOMER_WRAP_AAD,
+                    )?,
+                    customer_key: Some(CustomerKeyCheck {
+                        salt: salt.to_vec(),
+                        hmac: cry::hmac_sha256(&salt, &customer_key.key)?,
+                    }),
+                })
+            }
+        }
+    }
+
+    pub(crate) fn customer_key(&self) -> Option<&[u8; 32]> {
+        match self {
+            SseRequest::S3 => None,
+            SseRequest::Customer(customer_key) => Some(&customer_key.key),
+        }
+    }
+
+    pub(crate) fn customer_key_md5(&self) -> Option<&str> {
+        match self {
+            SseRequest::S3 => None,
+            SseRequest::Customer(customer_key) => Some(&customer_key.key_md5),
+        }
+    }
+}
+
+// 新对象的加密上下文，数据密钥只在接收请求的节点内存中出现
+pub(crate) struct SseContext {
+    pub data_key: [u8; 32],
+    pub encryption: ObjectEncryption,
+    pub customer_key_md5: Option<String>,
+}
+
+// 根据请求头和桶默认配置决定新对象的加密方式
+pub(crate) async fn requested(
+    req: &web::HttpRequest,
+    state: &App,
+    bucket_name: &str,
+) -> Result<Option<SseRequest>, AppError> {
+    let customer_key = CustomerKey::from_request(req, false)?;
+    let header = match req.headers().get(SSE_HEADER) {
+        Some(value) => Some(
+            value
+                .to_str()
+                .map_err(|_| unsupported_algorithm())?
+                .to_string(),
+        ),
+        None => None,
+    };
+    if let Some(customer_key) = customer_key {
+        if header.is_some() {
+            return Err(AppError::s3(
+                400,
+                "InvalidArgument",
+                "Server Side Encryption with Customer provided key cannot be combined with x-amz-server-side-encryption",
+            ));
+        }
+        return Ok(Some(SseRequest::Customer(customer_key)));
+    }
+    let algorithm = match header {
+        Some(algorithm) => Some(algorithm),
+        None => bucket_default_algorithm(state, bucket_name).await?,
+    };
+    match algorithm.as_deref() {
+        None => Ok(None),
+        Some(SSE_ALGORITHM) => Ok(Some(SseRequest::S3)),
+        Some(_) => Err(unsupported_algorithm()),
+    }
+}
+
+// 为新对象生成数据密钥
+pub(crate) async fn from_request(
+    req: &web::HttpRequest,
+    state: &App,
+    bucket_name: &str,
+) -> Result<Option<SseContext>, AppError> {
+    match requested(req, state, bucket_name).await? {
+        Some(request) => {
+            let data_key = cry::gen_aes_256_key();
+            Ok(Some(SseContext {
+                data_key,
+                encryption: request.wrap(&state.master_key, &data_key)?,
+                customer_key_md5: request.customer_key_md5().map(str::to_string),
+            }))
+        }
+        None => Ok(None),
+    }
+}
+
+// 读取桶默认加密算法
+async fn bucket_default_algorithm(state: &App, bucket_name: &str) -> anyhow::Result<Option<String>> {
+    let config: Option<ServerSideEncryptionConfiguration> =
+        bucket::get_config(state, bucket_name, bucket::ENCRYPTION_CONFIG).await?;
+    Ok(config
+        .and_then(|config| {
+            config
+                .rules
+                .into_iter()
+                .find_map(|rule| rule.apply_server_side_encryption_by_default)
+        })
+        .map(|default| default.sse_algorithm))
+}
+
+// 校验桶加密配置，只支持 AES256
+pub(crate) fn validate_config(config: &ServerSideEncryptionConfiguration) -> Result<(), AppError> {
+    let supported = !config.rules.is_empty()
+        && config.rules.iter().all(|rule| {
+            rule.apply_server_side_encryption_by_default
+                .as_ref()
+                .is_some_and(|default| default.sse_algorithm == SSE_ALGORITHM)
+        });
+    if supported {
+        Ok(())
+    } else {
+        Err(unsupported_algorithm())
+    }
+}
+
+// 解包对象的数据密钥，SSE-C 对象需先校验请求中提供的客户密钥
+pub(crate) fn data_key(
+    master_key: &[u8; 32],
+    encryption: Option<&ObjectEncryption>,
+    customer_key: Option<&CustomerKey>,
+) -> Result<Option<Vec<u8>>, AppError> {
+    let encryption = match (encryption, customer_key) {
+        (None, None) => return Ok(None),
+        (None, Some(_)) => return Err(not_applicable()),
+        (Some(encryption), _) => encryption,
+    };
+    match (&encryption.customer_key, customer_key) {
+        (None, None) => Ok(Some(cry::aes_256_gcm_decrypt(
+            master_key,
+            &encryption.wrapped_key,
+            WRAP_AAD,
+        )?)),
+        (None, Some(_)) => Err(not_applicable()),
+        (Some(_), None) => Err(AppError::s3(
+            400,
+            "InvalidRequest",
+            "The object was stored using a form of Server Side Encryption. The correct parameters must be provided to retrieve the object.",
+        )),
+        (Some(check), Some(customer_key)) => {
+            if !cry::hmac_sha256_verify(&check.salt, &customer_key.key, &check.hmac) {
+                return Err(AppError::s3(403, "AccessDenied", "Access Denied"));
+            }
+            let data_key = cry::aes_256_gcm_decrypt(
+                &customer_key.key,
+                &encryption.wrapped_key,
+                CUSTOMER_WRAP_AAD,
+            )
+            .map_err(|_| AppError::s3(403, "AccessDenied", "Access Denied"))?;
+            Ok(Some(data_key))
+        }
+    }
+}
+
+// 加密对象需要返回的响应头
+pub(crate) fn response_headers(
+    encryption: &ObjectEncryption,
+    customer_key_md5: Option<&str>,
+) -> Vec<(&'static str, String)> {
+    match (&encryption.customer_key, customer_key_md5) {
+        (Some(_), Some(key_md5)) => vec![
+            (SSE_C_ALGORITHM_HEADER, encryption.algorithm.clone()),
+            (SSE_C_KEY_MD5_HEADER, key_md5.to_string()),
+        ],
+        (Some(_), None) => vec![(SSE_C_ALGORITHM_HEADER, encryption.algorithm.clone())],
+        (None, _) => vec![(SSE_HEADER, encryption.algorithm.clone())],
+    }
+}
+
+fn invalid_customer_key() -> AppError {
+    AppError::s3(
+        400,
+        "InvalidArgument",
+        "The secret key was invalid for the specified algorithm.",
+    )
+}
+
+fn not_applicable() -> AppError {
+    AppError::s3(
+        400,
+        "InvalidRequest",
+        "The encryption parameters are not applicable to this object.",
+    )
+}
+
+fn unsupported_algorithm() -> AppError {
+    AppError::s3(
+        400,
+        "InvalidArgument",
+        "The encryption method specified is not supported",
+    )
+}
@@ -0,0 +1,218 @@
+use crate::bucket::WritePolicy;
+use crate::checksum::ChecksumRequest;
+use crate::err::AppError;
+use crate::fs::{DecompressStream, InlineChunk, ObjectChecksum, ObjectEncryption};
+use crate::raft::app::App;
+use crate::raft::network::chunk;
+use crate::raft::store::Request;
+use crate::util::checksum::AwsChunkedDecoder;
+use crate::util::chunker::StreamChunker;
+use crate::util::codec::CompressionConfig;
+use crate::util::erasure::ErasureCode;
+use crate::{checksum, fs};
+use anyhow::anyhow;
+use futures::StreamExt;
+use ntex::web;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+
+// 已写入多数节点的数据，由 CommitObject 或 CommitPart 提交
+// 提交前分块没有引用，写入时刷新孤儿时间，提交失败的分块超过回收宽限期后删除
+pub(crate) struct StagedPayload {
+    pub size: u64,
+    // 整个请求体的 sha256，分片上传以此作为 ETag
+    pub sha256: String,
+    pub chunks: Vec<String>,
+    pub chunk_sizes: Vec<u64>,
+    // 已通过校验的附加校验和
+    pub checksum: Option<String>,
+    // 分块以纠删码分片保存时的编码方式
+    pub erasure: Option<ErasureCode>,
+    // 不超过内联阈值的对象不写入分块，数据随日志提交，chunks 为空
+    pub inline: Option<InlineChunk>,
+}
+
+impl StagedPayload {
+    // 提交对象的日志
+    pub(crate) fn commit(
+        self,
+        file_path: String,
+        encryption: Option<ObjectEncryption>,
+        checksum: Option<ObjectChecksum>,
+        website_redirect_location: Option<String>,
+    ) -> Request {
+        match self.inline {
+            Some(inline) => Request::CommitInlineObject {
+                file_path,
+                size: self.size,
+                inline,
+                encryption,
+                checksum,
+                website_redirect_location,
+            },
+            None => Request::CommitObject {
+                file_path,
+                size: self.size,
+                chunks: self.chunks,
+                chunk_sizes: self.chunk_sizes,
+                encryption,
+                checksum,
+                website_redirect_location,
+                erasure: self.erasure,
+            },
+        }
+    }
+}
+
+// 分块、压缩(和加密)后在本地保存并推送给其他节点，或编码为纠删码分片分散保存，
+// 内存占用不超过一个最大分块
+struct Stager<'a> {
+    state: &'a App,
+    data_key: Option<&'a [u8]>,
+    compression: CompressionConfig,
+    chunker: StreamChunker,
+    // 数据长度不超过内联阈值时暂存在这里，超过后交给 chunker
+    buffer: Option<Vec<u8>>,
+    inline_threshold: usize,
+    sha256: Sha256,
+    staged: StagedPayload,
+}
+
+impl<'a> Stager<'a> {
+    fn new(state: &'a App, policy: &WritePolicy, data_key: Option<&'a [u8]>) -> Self {
+        Stager {
+            state,
+            data_key,
+            compression: policy.compression,
+            chunker: StreamChunker::new(policy.chunking),
+            buffer: (policy.inline_threshold > 0).then(Vec::new),
+            inline_threshold: policy.inline_threshold,
+            sha256: Sha256::new(),
+            staged: StagedPayload {
+                size: 0,
+                sha256: String::new(),
+                chunks: vec![],
+                chunk_sizes: vec![],
+                checksum: None,
+                erasure: policy.storage.erasure(),
+                inline: None,
+            },
+        }
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), AppError> {
+        self.sha256.update(data);
+        self.staged.size += data.len() as u64;
+        match self.buffer.take() {
+            Some(mut buffer) if buffer.len() + data.len() <= self.inline_threshold => {
+                buffer.extend_from_slice(data);
+                self.buffer = Some(buffer);
+                return Ok(());
+            }
+            Some(buffer) => self.push(&buffer).await?,
+            None => {}
+        }
+        self.push(data).await
+    }
+
+    async fn push(&mut self, data: &[u8]) -> Result<(), AppError> {
+        for chunk in self.chunker.push(data) {
+            self.stage_chunk(&chunk).await?;
+        }
+        Ok(())
+    }
+
+    async fn finish(mut self) -> Result<StagedPayload, AppError> {
+        match self.buffer.take() {
+            // 空对象没有分块，也不需要内联
+            Some(buffer) if !buffer.is_empty() => {
+                let inline = InlineChunk::new(&buffer, self.data_key, &self.compression)?;
+                self.staged.inline = Some(inline);
+            }
+            _ => {
+                for chunk in self.chunker.finish() {
+                    self.stage_chunk(&chunk).await?;
+                }
+            }
+        }
+        self.staged.sha256 = fs::get_sha256_string(&self.sha256.finalize());
+        Ok(self.staged)
+    }
+
+    // 保存并推送单个分块，plain 为分块明文，加密时分块名称由数据密钥派生
+    async fn stage_chunk(&mut self, plain: &[u8]) -> Result<(), AppError> {
+        let (name, data) = match self.data_key {
+            Some(data_key) => {
+                let sealed = fs::seal_chunk(data_key, plain, &self.compression)?;
+                (sealed.name, sealed.data)
+            }
+            None => (
+                fs::sum_sha256(plain).await,
+                fs::compress_chunk(plain, &self.compression)?,
+            ),
+        };
+        match self.staged.erasure {
+            Some(code) => chunk::distribute(self.state, &name, &data, code).await?,
+            None => {
+                self.state.chunk_gc.save_chunk(&name, &data).await?;
+                chunk::replicate(self.state, &name, data).await?;
+            }
+        }
+        self.staged.chunks.push(name);
+        self.staged.chunk_sizes.push(plain.len() as u64);
+        Ok(())
+    }
+}
+
+// 边读取请求体边写入分块，aws-chunked 编码的请求体逐段解码，并校验附加校验和
+pub(crate) async fn stage_payload(
+    req: &web::HttpRequest,
+    mut body: web::types::Payload,
+    state: &App,
+    policy: &WritePolicy,
+    data_key: Option<&[u8]>,
+    checksum_request: Option<&ChecksumRequest>,
+) -> Result<StagedPayload, AppError> {
+    let mut decoder = checksum::is_aws_chunked(req).then(AwsChunkedDecoder::default);
+    let mut checksummer = checksum_request.map(ChecksumRequest::checksummer);
+    let mut stager = Stager::new(state, policy, data_key);
+    while let Some(item) = body.next().await {
+        let item = item.map_err(|err| anyhow!(err.to_string()))?;
+        let data = match &mut decoder {
+            Some(decoder) => Cow::Owned(decoder.push(&item).map_err(incomplete_body)?),
+            None => Cow::Borrowed(item.as_ref()),
+        };
+        if let Some(checksummer) = &mut checksummer {
+            checksummer.update(&data);
+        }
+        stager.write(&data).await?;
+    }
+    let trailers = match decoder {
+        Some(decoder) => decoder.finish().map_err(incomplete_body)?,
+        None => vec![],
+    };
+    let mut staged = stager.finish().await?;
+    if let (Some(request), Some(checksummer)) = (checksum_request, checksummer) {
+        staged.checksum = Some(request.verify(checksummer.finish(), &trailers)?);
+    }
+    Ok(staged)
+}
+
+// 边读取源对象边写入分块，用于需要重新加密的拷贝，内存占用与对象大小无关
+pub(crate) async fn stage_stream(
+    state: &App,
+    policy: &WritePolicy,
+    data_key: Option<&[u8]>,
+    mut source: DecompressStream,
+) -> Result<StagedPayload, AppError> {
+    let mut stager = Stager::new(state, policy, data_key);
+    while let Some(data) = source.next().await {
+        let data = data.map_err(|err| anyhow!(err))?;
+        stager.write(&data).await?;
+    }
+    stager.finish().await
+}
+
+fn incomplete_body(err: anyhow::Error) -> AppError {
+    AppError::s3(400, "IncompleteBody", err.to_string())
+}
@@ -0,0 +1,241 @@
+use anyhow::{anyhow, Context};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+
+// 计算 CRC32 (IEEE 802.3)
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+// S3 支持的附加校验和算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Crc32,
+    Crc32c,
+    Sha1,
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    pub const ALL: [ChecksumAlgorithm; 4] = [
+        ChecksumAlgorithm::Crc32,
+        ChecksumAlgorithm::Crc32c,
+        ChecksumAlgorithm::Sha1,
+        ChecksumAlgorithm::Sha256,
+    ];
+
+    // 解析 x-amz-checksum-algorithm 的值，不区分大小写
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name))
+    }
+
+    // 算法名，如 CRC32
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32 => "CRC32",
+            ChecksumAlgorithm::Crc32c => "CRC32C",
+            ChecksumAlgorithm::Sha1 => "SHA1",
+            ChecksumAlgorithm::Sha256 => "SHA256",
+        }
+    }
+
+    // 对应的请求头/响应头，如 x-amz-checksum-crc32
+    pub fn header_name(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32 => "x-amz-checksum-crc32",
+            ChecksumAlgorithm::Crc32c => "x-amz-checksum-crc32c",
+            ChecksumAlgorithm::Sha1 => "x-amz-checksum-sha1",
+            ChecksumAlgorithm::Sha256 => "x-amz-checksum-sha256",
+        }
+    }
+
+    // 计算数据的校验和，返回 base64 编码
+    pub fn checksum(&self, data: &[u8]) -> String {
+        let mut hasher = Checksummer::new(*self);
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    // 计算分片上传的组合校验和：对各分片校验和的原始字节拼接后再计算一次，并附加分片数
+    pub fn composite(&self, part_checksums: &[String]) -> anyhow::Result<String> {
+        let mut hasher = Checksummer::new(*self);
+        for checksum in part_checksums {
+            let raw = STANDARD
+                .decode(checksum)
+                .with_context(|| format!("校验和格式错误: {}", checksum))?;
+            hasher.update(&raw);
+        }
+        Ok(format!("{}-{}", hasher.finish(), part_checksums.len()))
+    }
+}
+
+// 增量计算校验和
+pub struct Checksummer {
+    state: ChecksumState,
+}
+
+enum ChecksumState {
+    Crc32(crc32fast::Hasher),
+    Crc32c(u32),
+    Sha1(crypto_hash::Hasher),
+    Sha256(Sha256),
+}
+
+impl Checksummer {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Self {
+        let state = match algorithm {
+            ChecksumAlgorithm::Crc32 => ChecksumState::Crc32(crc32fast::Hasher::new()),
+            ChecksumAlgorithm::Crc32c => ChecksumState::Crc32c(0),
+            ChecksumAlgorithm::Sha1 => {
+                ChecksumState::Sha1(crypto_hash::Hasher::new(crypto_hash::Algorithm::SHA1))
+            }
+            ChecksumAlgorithm::Sha256 => ChecksumState::Sha256(Sha256::new()),
+        };
+        Checksummer { state }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.state {
+            ChecksumState::Crc32(hasher) => hasher.update(data),
+            ChecksumState::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+            ChecksumState::Sha1(hasher) => {
+                let _ = hasher.write_all(data);
+            }
+            ChecksumState::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    // 返回 base64 编码的校验和，CRC 按大端序编码
+    pub fn finish(self) -> String {
+        let raw = match self.state {
+            ChecksumState::Crc32(hasher) => hasher.finalize().to_be_bytes().to_vec(),
+            ChecksumState::Crc32c(crc) => crc.to_be_bytes().to_vec(),
+            ChecksumState::Sha1(mut hasher) => hasher.finish(),
+            ChecksumState::Sha256(hasher) => hasher.finalize().to_vec(),
+        };
+        STANDARD.encode(raw)
+    }
+}
+
+// 解码 aws-chunked 编码的请求体，返回数据和尾部字段(trailer)
+// 格式: {十六进制长度}[;chunk-signature=...]\r\n{数据}\r\n ... 0\r\n{trailer}\r\n\r\n
+#[allow(clippy::type_complexity)]
+pub fn decode_aws_chunked(body: &[u8]) -> anyhow::Result<(Vec<u8>, Vec<(String, String)>)> {
+    let mut decoder = AwsChunkedDecoder::default();
+    let data = decoder.push(body)?;
+    Ok((data, decoder.finish()?))
+}
+
+// 分块头和尾部字段单行的最大长度
+const MAX_LINE_LEN: usize = 4096;
+
+#[derive(Debug, Default, PartialEq)]
+enum DecodeState {
+    // 等待分块头
+    #[default]
+    Header,
+    // 分块数据，值为剩余长度
+    Data(usize),
+    // 分块数据后的 \r\n
+    DataEnd,
+    Trailers,
+    Done,
+}
+
+// 增量解码 aws-chunked 请求体，请求体分段到达时逐段解码
+// 只缓存不完整的分块头和尾部字段，数据直接输出
+#[derive(Debug, Default)]
+pub struct AwsChunkedDecoder {
+    buffer: Vec<u8>,
+    state: DecodeState,
+    trailers: Vec<(String, String)>,
+}
+
+impl AwsChunkedDecoder {
+    // 解码一段请求体，返回其中的数据
+    pub fn push(&mut self, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.buffer.extend_from_slice(input);
+        let mut data = Vec::new();
+        let mut pos = 0;
+        loop {
+            match self.state {
+                DecodeState::Header => {
+                    let Some(line) = read_line(&self.buffer, &mut pos) else {
+                        break;
+                    };
+                    let size = line.split(';').next().unwrap_or_default().trim();
+                    let size = usize::from_str_radix(size, 16)
+                        .map_err(|_| anyhow!("aws-chunked 分块长度错误: {}", size))?;
+                    self.state = match size {
+                        0 => DecodeState::Trailers,
+                        size => DecodeState::Data(size),
+                    };
+                }
+                DecodeState::Data(remaining) => {
+                    let len = remaining.min(self.buffer.len() - pos);
+                    data.extend_from_slice(&self.buffer[pos..pos + len]);
+                    pos += len;
+                    if len < remaining {
+                        self.state = DecodeState::Data(remaining - len);
+                        break;
+                    }
+                    self.state = DecodeState::DataEnd;
+                }
+                DecodeState::DataEnd => {
+                    if self.buffer.len() - pos < 2 {
+                        break;
+                    }
+                    if &self.buffer[pos..pos + 2] != b"\r\n" {
+                        return Err(anyhow!("aws-chunked 分块结尾错误"));
+                    }
+                    pos += 2;
+                    self.state = DecodeState::Header;
+                }
+                DecodeState::Trailers => {
+                    let Some(line) = read_line(&self.buffer, &mut pos) else {
+                        break;
+                    };
+                    if line.is_empty() {
+                        self.state = DecodeState::Done;
+                    } else if let Some((name, value)) = line.split_once(':') {
+                        self.trailers
+                            .push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+                    }
+                }
+                DecodeState::Done => {
+                    pos = self.buffer.len();
+                    break;
+                }
+            }
+        }
+        self.buffer.drain(..pos);
+        if self.buffer.len() > MAX_LINE_LEN {
+            return Err(anyhow!("aws-chunked 分块头过长"));
+        }
+        Ok(data)
+    }
+
+    // 请求体结束，返回尾部字段
+    pub fn finish(self) -> anyhow::Result<Vec<(String, String)>> {
+        match self.state {
+            DecodeState::Trailers | DecodeState::Done => Ok(self.trailers),
+            DecodeState::Header => Err(anyhow!("aws-chunked 分块头不完整")),
+            DecodeState::Data(_) | DecodeState::DataEnd => {
+                Err(anyhow!("aws-chunked 分块数据不完整"))
+            }
+        }
+    }
+}
+
+// 读取一行，不包含 \r\n
+fn read_line<'a>(body: &'a [u8], pos: &mut usize) -> Option<&'a str> {
+    let rest = body.get(*pos..)?;
+    let end = rest.windows(2).position(|window| window == b"\r\n")?;
+    let line = std::str::from_utf8(&rest[..end]).ok()?;
+    *pos += end + 2;
+    Some(line)
+}
@@ -0,0 +1,214 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+// FastCDC 分块大小的上下限
+const MIN_CHUNK_SIZE: usize = 64;
+const MAX_CHUNK_SIZE: usize = 64 << 20;
+
+// 分块方式，按桶配置，保存在 raft 日志中以保证各节点分块结果一致
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkingConfig {
+    // 固定大小分块
+    Fixed {
+        size: usize,
+    },
+    // 基于内容的分块(FastCDC)，插入或删除数据只影响附近的分块边界
+    FastCdc {
+        min_size: usize,
+        avg_size: usize,
+        max_size: usize,
+    },
+}
+
+impl Default for ChunkingConfig {
+    fn default() -> Self {
+        ChunkingConfig::Fixed {
+            size: crate::fs::CHUNK_SIZE,
+        }
+    }
+}
+
+impl ChunkingConfig {
+    // 平均 avg_size 字节，最小 1/4、最大 4 倍
+    pub fn fastcdc(avg_size: usize) -> Self {
+        ChunkingConfig::FastCdc {
+            min_size: avg_size / 4,
+            avg_size,
+            max_size: avg_size * 4,
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match *self {
+            ChunkingConfig::Fixed { size } => {
+                if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&size) {
+                    return Err(anyhow!(
+                        "分块大小必须在 {} 到 {} 字节之间",
+                        MIN_CHUNK_SIZE,
+                        MAX_CHUNK_SIZE
+                    ));
+                }
+            }
+            ChunkingConfig::FastCdc {
+                min_size,
+                avg_size,
+                max_size,
+            } => {
+                if min_size < MIN_CHUNK_SIZE || max_size > MAX_CHUNK_SIZE {
+                    return Err(anyhow!(
+                        "分块大小必须在 {} 到 {} 字节之间",
+                        MIN_CHUNK_SIZE,
+                        MAX_CHUNK_SIZE
+                    ));
+                }
+                if !(min_size < avg_size && avg_size < max_size) {
+                    return Err(anyhow!("分块大小必须满足 min_size < avg_size < max_size"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 单个分块的最大长度
+    pub fn max_chunk_size(&self) -> usize {
+        match *self {
+            ChunkingConfig::Fixed { size } => size.max(1),
+            ChunkingConfig::FastCdc { max_size, .. } => max_size,
+        }
+    }
+
+    // 第一个分块的长度，只取决于数据的前 max_chunk_size 个字节
+    fn cut(&self, data: &[u8]) -> usize {
+        match *self {
+            ChunkingConfig::Fixed { size } => data.len().min(size.max(1)),
+            ChunkingConfig::FastCdc {
+                min_size,
+                avg_size,
+                max_size,
+            } => FastCdc::new(min_size, avg_size, max_size).cut(data),
+        }
+    }
+
+    // 将数据切分为分块
+    pub fn split<'a>(&self, data: &'a [u8]) -> Vec<&'a [u8]> {
+        let mut chunks = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let (chunk, tail) = rest.split_at(self.cut(rest));
+            chunks.push(chunk);
+            rest = tail;
+        }
+        chunks
+    }
+}
+
+// 增量分块，数据分段到达时输出边界已确定的分块，结果与对完整数据调用 split 相同
+// 缓存的数据不超过一个最大分块
+pub struct StreamChunker {
+    config: ChunkingConfig,
+    buffer: Vec<u8>,
+}
+
+impl StreamChunker {
+    pub fn new(config: ChunkingConfig) -> Self {
+        StreamChunker {
+            config,
+            buffer: Vec::new(),
+        }
+    }
+
+    // 追加数据，返回边界已确定的分块
+    pub fn push(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        self.buffer.extend_from_slice(data);
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while self.buffer.len() - start >= self.config.max_chunk_size() {
+            let len = self.config.cut(&self.buffer[start..]);
+            chunks.push(self.buffer[start..start + len].to_vec());
+            start += len;
+        }
+        self.buffer.drain(..start);
+        chunks
+    }
+
+    // 数据结束，返回剩余的分块
+    pub fn finish(self) -> Vec<Vec<u8>> {
+        self.config
+            .split(&self.buffer)
+            .into_iter()
+            .map(<[u8]>::to_vec)
+            .collect()
+    }
+}
+
+// Gear 哈希表，由固定种子的 splitmix64 生成，所有节点和版本必须一致
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x005E_ED0F_FA57_CDC0;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+// 取哈希值的高 bits 位作为判断条件，高位受最近 64 个字节影响
+fn mask(bits: u32) -> u64 {
+    let bits = bits.clamp(1, 63);
+    !0u64 << (64 - bits)
+}
+
+// FastCDC 归一化分块：平均大小之前使用更难满足的条件，之后使用更容易满足的条件，
+// 使分块大小集中在平均值附近
+struct FastCdc {
+    min_size: usize,
+    avg_size: usize,
+    max_size: usize,
+    mask_s: u64,
+    mask_l: u64,
+}
+
+impl FastCdc {
+    fn new(min_size: usize, avg_size: usize, max_size: usize) -> Self {
+        let bits = avg_size.max(2).ilog2();
+        FastCdc {
+            min_size,
+            avg_size,
+            max_size,
+            mask_s: mask(bits + 2),
+            mask_l: mask(bits.saturating_sub(2)),
+        }
+    }
+
+    // 返回第一个分块的长度
+    fn cut(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+        let end = data.len().min(self.max_size.max(1));
+        let normal = end.min(self.avg_size);
+        let mut hash: u64 = 0;
+        let mut i = self.min_size;
+        while i < normal {
+            hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+            if hash & self.mask_s == 0 {
+                return i + 1;
+            }
+            i += 1;
+        }
+        while i < end {
+            hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+            if hash & self.mask_l == 0 {
+                return i + 1;
+            }
+            i += 1;
+        }
+        end
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+// 分块头: 魔数(3) 编码(1)，没有分块头的旧分块是一个 zstd 帧
+const MAGIC: &[u8; 3] = b"CHK";
+pub const HEADER_LEN: usize = 4;
+// zstd 帧的魔数
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+// 试压缩的数据长度
+const SAMPLE_SIZE: usize = 64 << 10;
+// 试压缩后长度超过原长度的这一比例时认为数据不可压缩
+const MAX_RATIO: f64 = 0.9;
+
+// 分块的压缩编码
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    // 不压缩
+    None,
+    #[default]
+    Zstd,
+    Lz4,
+}
+
+impl Codec {
+    fn id(self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Zstd => 1,
+            Codec::Lz4 => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> anyhow::Result<Self> {
+        match id {
+            0 => Ok(Codec::None),
+            1 => Ok(Codec::Zstd),
+            2 => Ok(Codec::Lz4),
+            _ => Err(anyhow!("未知的分块编码 {}", id)),
+        }
+    }
+
+    // 保存在磁盘上的分块使用的编码，加密分块需先解密
+    pub fn of(stored: &[u8]) -> anyhow::Result<Self> {
+        match stored.strip_prefix(MAGIC) {
+            Some(rest) => Codec::from_id(*rest.first().context("分块头不完整")?),
+            None if stored.starts_with(&ZSTD_MAGIC) => Ok(Codec::Zstd),
+            None => Err(anyhow!("分块格式错误")),
+        }
+    }
+}
+
+// 分块的压缩方式，按桶配置，保存在 raft 状态机中
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CompressionConfig {
+    #[serde(default)]
+    pub codec: Codec,
+    // zstd 的压缩级别，0 为 zstd 的默认级别
+    #[serde(default)]
+    pub level: i32,
+    // 按对象的 MIME 类型和试压缩的结果跳过不可压缩的分块
+    #[serde(default = "default_detect")]
+    pub detect: bool,
+}
+
+fn default_detect() -> bool {
+    true
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            codec: Codec::Zstd,
+            level: 0,
+            detect: true,
+        }
+    }
+}
+
+impl CompressionConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let levels = zstd::compression_level_range();
+        if self.codec == Codec::Zstd && self.level != 0 && !levels.contains(&self.level) {
+            return Err(anyhow!(
+                "zstd 压缩级别必须在 {} 到 {} 之间",
+                levels.start(),
+                levels.end()
+            ));
+        }
+        Ok(())
+    }
+
+    // 对象使用的压缩方式，已压缩格式的对象不再压缩
+    pub fn for_content_type(&self, content_type: &str) -> Self {
+        match self.detect && is_incompressible_type(content_type) {
+            true => CompressionConfig {
+                codec: Codec::None,
+                ..*self
+            },
+            false => *self,
+        }
+    }
+
+    // 压缩分块并加上分块头，压缩后没有变小的分块按原样保存
+    pub fn compress(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let codec = match self.codec {
+            Codec::None => Codec::None,
+            _ if self.detect && !is_compressible(data) => Codec::None,
+            codec => codec,
+        };
+        let encoded = encode(codec, self.level, data)?;
+        match codec != Codec::None && encoded.len() >= HEADER_LEN + data.len() {
+            true => encode(Codec::None, 0, data),
+            false => Ok(encoded),
+        }
+    }
+}
+
+fn encode(codec: Codec, level: i32, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut res = Vec::with_capacity(HEADER_LEN + data.len() / 2);
+    res.extend_from_slice(MAGIC);
+    res.push(codec.id());
+    match codec {
+        Codec::None => res.extend_from_slice(data),
+        Codec::Zstd => zstd::stream::copy_encode(data, &mut res, level)?,
+        Codec::Lz4 => {
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(res);
+            encoder.write_all(data)?;
+            res = encoder.finish()?;
+        }
+    }
+    Ok(res)
+}
+
+// 还原分块明文，兼容没有分块头的 zstd 分块
+pub fn decode(stored: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let codec = Codec::of(stored)?;
+    let payload = match stored.starts_with(MAGIC) {
+        true => &stored[HEADER_LEN..],
+        false => stored,
+    };
+    let mut res = Vec::new();
+    match codec {
+        Codec::None => res.extend_from_slice(payload),
+        Codec::Zstd => {
+            zstd::stream::read::Decoder::new(payload)?
+                .read_to_end(&mut res)
+                .context("解压分块失败")?;
+        }
+        Codec::Lz4 => {
+            lz4_flex::frame::FrameDecoder::new(payload)
+                .read_to_end(&mut res)
+                .context("解压分块失败")?;
+        }
+    }
+    Ok(res)
+}
+
+// 是否是未加密的分块，加密分块以随机的 nonce 开头
+pub fn is_encoded(stored: &[u8]) -> bool {
+    stored.starts_with(MAGIC) || stored.starts_with(&ZSTD_MAGIC)
+}
+
+// 用 lz4 快速试压缩分块开头的一段数据
+fn is_compressible(data: &[u8]) -> bool {
+    let sample = &data[..data.len().min(SAMPLE_SIZE)];
+    if sample.is_empty() {
+        return false;
+    }
+    let compressed = lz4_flex::compress(sample);
+    (compressed.len() as f64) < sample.len() as f64 * MAX_RATIO
+}
+
+// 本身已压缩的格式：图片、音视频、压缩包
+pub fn is_incompressible_type(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let Some((kind, subtype)) = mime.split_once('/') else {
+        return false;
+    };
+    match kind {
+        "video" => true,
+        "audio" => !matches!(subtype, "wav" | "x-wav" | "aiff" | "x-aiff" | "midi"),
+        "image" => !matches!(subtype, "bmp" | "svg+xml" | "tiff" | "x-icon"),
+        "application" => matches!(
+            subtype,
+            "zip"
+                | "gzip"
+                | "x-gzip"
+                | "zstd"
+                | "x-bzip2"
+                | "x-xz"
+                | "x-7z-compressed"
+                | "vnd.rar"
+                | "x-rar-compressed"
+                | "java-archive"
+                | "epub+zip"
+                | "pdf"
+                | "x-lz4"
+                | "x-compress"
+                | "vnd.android.package-archive"
+        ),
+        _ => false,
+    }
+}
@@ -1,4 +1,7 @@
 use aes::Aes256;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::anyhow;
 use block_modes::block_padding::Pkcs7;
 use block_modes::{BlockMode, Cbc};
 use crypto_hash::{hex_digest, Algorithm};
@@ -8,7 +11,7 @@ use rand::seq::IndexedRandom;
 use sha2::Sha256;
 use zstd::zstd_safe::WriteBuf;
 
-// 定义一个默认的密钥常量。
+// 旧版元数据使用的固定密钥，新写入的元数据改用 keyring 中配置的密钥，这里只保留用于兼容解密。
 const DEFAULT_KEY: &str = "000102030405060708090A0B0C0D0E0F";
 
 // 使用 MD5 算法对字符串进行哈希加密的函数。
@@ -52,6 +55,39 @@ pub fn aes_256_cbc_decrypt(data: &[u8]) -> anyhow::Result<Vec<u8>> {
     Ok(cipher.decrypt_vec(&data[16..])?)
 }
 
+// AES-256-GCM 随机数长度
+const GCM_NONCE_LEN: usize = 12;
+
+// 生成随机的 256 位密钥。
+pub fn gen_aes_256_key() -> [u8; 32] {
+    rand::random()
+}
+
+// 使用 AES-256-GCM 认证加密数据的函数，输出为 随机数 + 密文(含认证标签)。
+pub fn aes_256_gcm_encrypt(key: &[u8], data: &[u8], aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let cipher = <Aes256Gcm as aes_gcm::KeyInit>::new_from_slice(key).map_err(|err| anyhow!(err))?;
+    let nonce: [u8; GCM_NONCE_LEN] = rand::random();
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: data, aad })
+        .map_err(|_| anyhow!("AES-256-GCM 加密失败"))?;
+    let mut buffer = Vec::with_capacity(GCM_NONCE_LEN + ciphertext.len());
+    buffer.extend_from_slice(&nonce);
+    buffer.extend_from_slice(&ciphertext);
+    Ok(buffer)
+}
+
+// 使用 AES-256-GCM 解密并校验数据的函数，密钥或附加数据不匹配时返回错误。
+pub fn aes_256_gcm_decrypt(key: &[u8], data: &[u8], aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if data.len() < GCM_NONCE_LEN {
+        return Err(anyhow!("密文长度不足"));
+    }
+    let cipher = <Aes256Gcm as aes_gcm::KeyInit>::new_from_slice(key).map_err(|err| anyhow!(err))?;
+    let (nonce, ciphertext) = data.split_at(GCM_NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| anyhow!("AES-256-GCM 解密失败"))
+}
+
 // 定义 HmacSha256 类型为使用 SHA256 哈希函数的 HMAC。
 type HmacSha256 = Hmac<Sha256>;
 // 对数据进行 SHA256 哈希的函数，返回十六进制字符串。
@@ -69,6 +105,24 @@ pub fn do_hmac_sha256(key: &[u8], data: &str) -> anyhow::Result<Vec<u8>> {
     Ok(Vec::from(x))
 }
 
+// 使用 HMAC-SHA256 算法对二进制数据签名的函数。
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut mac = HmacSha256::new_from_slice(key)?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+// 以常数时间校验 HMAC-SHA256 签名的函数。
+pub fn hmac_sha256_verify(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+    match HmacSha256::new_from_slice(key) {
+        Ok(mut mac) => {
+            mac.update(data);
+            mac.verify_slice(tag).is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
 // 将字节向量转换为十六进制字符串的函数。
 pub fn do_bytes_to_hex(bytes: &[u8]) -> String {
     let hex_array: [char; 16] = [
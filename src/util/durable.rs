@@ -0,0 +1,185 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+// 写入中的临时文件后缀，临时文件与目标文件在同一目录下: {name}.{uuid}.tmp
+pub const TMP_SUFFIX: &str = ".tmp";
+// 旧版本使用的临时文件后缀：迁移分块和轮换元数据密钥
+const LEGACY_SUFFIXES: [&str; 2] = [".moving", ".rotate"];
+
+// 持久化写入的模式，所有模式下文件都先写入临时文件再重命名，读取方不会看到写了一半的文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Durability {
+    // 不主动同步，断电时最近的写入可能丢失
+    None,
+    // 重命名前同步文件内容，断电后文件要么是旧内容要么是完整的新内容
+    Data,
+    // 同时同步所在目录，断电后重命名也不会丢失
+    #[default]
+    Full,
+}
+
+impl FromStr for Durability {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "none" => Ok(Durability::None),
+            "data" => Ok(Durability::Data),
+            "full" => Ok(Durability::Full),
+            other => Err(anyhow::anyhow!("未知的持久化模式 {}", other)),
+        }
+    }
+}
+
+// 目标文件的临时文件路径，并发写入同一文件时各自使用不同的临时文件
+pub fn tmp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(
+        "{}.{}{}",
+        name,
+        Uuid::new_v4().simple(),
+        TMP_SUFFIX
+    ))
+}
+
+// 是否为写入中断遗留的临时文件
+pub fn is_tmp(name: &str) -> bool {
+    name.ends_with(TMP_SUFFIX) || LEGACY_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+// 临时文件对应的目标文件
+fn target_of(tmp: &Path) -> Option<PathBuf> {
+    let name = tmp.file_name()?.to_str()?;
+    let name = match name.strip_suffix(TMP_SUFFIX) {
+        Some(name) => name.rsplit_once('.')?.0,
+        None => LEGACY_SUFFIXES
+            .iter()
+            .find_map(|suffix| name.strip_suffix(suffix))?,
+    };
+    Some(tmp.with_file_name(name))
+}
+
+// 同步目录，使目录下的创建、重命名和删除在断电后仍然有效
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => sync_dir(parent),
+        _ => sync_dir(Path::new(".")),
+    }
+}
+
+// 原子地写入文件：写入临时文件，按持久化模式同步后重命名为目标文件
+pub fn write(path: impl AsRef<Path>, data: &[u8], durability: Durability) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp = tmp_path(path);
+    let result = (|| {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+        file.write_all(data)?;
+        if durability >= Durability::Data {
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result?;
+    if durability == Durability::Full {
+        sync_parent(path)?;
+    }
+    Ok(())
+}
+
+// 异步版本的 write
+pub async fn write_async(
+    path: impl AsRef<Path>,
+    data: &[u8],
+    durability: Durability,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp = tmp_path(path);
+    let result = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp)
+            .await?;
+        file.write_all(data).await?;
+        if durability >= Durability::Data {
+            file.sync_all().await?;
+        }
+        tokio::fs::rename(&tmp, path).await
+    }
+    .await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&tmp).await;
+    }
+    result?;
+    if durability == Durability::Full {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || sync_parent(&path)).await??;
+    }
+    Ok(())
+}
+
+// 原子地复制文件，同 write
+pub async fn copy(source: &Path, dest: &Path, durability: Durability) -> io::Result<()> {
+    let tmp = tmp_path(dest);
+    let result = async {
+        tokio::fs::copy(source, &tmp).await?;
+        if durability >= Durability::Data {
+            tokio::fs::File::open(&tmp).await?.sync_all().await?;
+        }
+        tokio::fs::rename(&tmp, dest).await
+    }
+    .await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&tmp).await;
+    }
+    result?;
+    if durability == Durability::Full {
+        let dest = dest.to_path_buf();
+        tokio::task::spawn_blocking(move || sync_parent(&dest)).await??;
+    }
+    Ok(())
+}
+
+// 删除 root 下写入中断遗留的临时文件，返回这些临时文件对应的目标文件
+// 只在启动时、没有写入进行时调用
+pub fn clean(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut targets = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            if !is_tmp(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
+            fs::remove_file(&path)?;
+            if let Some(target) = target_of(&path) {
+                targets.push(target);
+            }
+        }
+    }
+    Ok(targets)
+}
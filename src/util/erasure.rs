@@ -0,0 +1,286 @@
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// 分片头: 魔数(2) 数据分片数(1) 校验分片数(1) 分片序号(1) 保留(3) 分块长度(8, 大端)
+const MAGIC: &[u8; 2] = b"EC";
+pub const HEADER_LEN: usize = 16;
+// 分片长度按 64 字节对齐，满足 reed-solomon-simd 对分片长度的要求
+const SHARD_ALIGN: usize = 64;
+// 分片总数上限
+const MAX_SHARDS: usize = 64;
+
+// 桶的分块存储方式，保存在 raft 状态机中
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StoragePolicy {
+    // 每个节点保存完整的分块
+    #[default]
+    Replicated,
+    // 分块编码为数据分片和校验分片，分散保存在不同节点
+    Erasure(ErasureCode),
+}
+
+impl StoragePolicy {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            StoragePolicy::Replicated => Ok(()),
+            StoragePolicy::Erasure(code) => code.validate(),
+        }
+    }
+
+    pub fn erasure(&self) -> Option<ErasureCode> {
+        match self {
+            StoragePolicy::Replicated => None,
+            StoragePolicy::Erasure(code) => Some(*code),
+        }
+    }
+
+    // 检查当前的 nodes 个节点能否满足存储方式
+    pub fn check_placement(&self, nodes: usize) -> anyhow::Result<()> {
+        match self {
+            StoragePolicy::Replicated => Ok(()),
+            StoragePolicy::Erasure(code) => code.check_placement(nodes),
+        }
+    }
+}
+
+// 纠删码参数，任意 data_shards 个分片即可还原分块
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ErasureCode {
+    pub data_shards: usize,
+    pub parity_shards: usize,
+}
+
+impl ErasureCode {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.data_shards == 0 || self.parity_shards == 0 {
+            return Err(anyhow!("数据分片和校验分片的数量都必须大于0"));
+        }
+        if self.total() > MAX_SHARDS {
+            return Err(anyhow!("分片总数不能超过 {}", MAX_SHARDS));
+        }
+        Ok(())
+    }
+
+    pub fn total(&self) -> usize {
+        self.data_shards + self.parity_shards
+    }
+
+    // 写入成功至少要保存的分片数：除还原所需的分片外，至少保留一半的校验分片，
+    // 提交的分块在后台修复补齐之前仍能承受节点故障
+    pub fn write_quorum(&self) -> usize {
+        self.data_shards + self.parity_shards.div_ceil(2)
+    }
+
+    // 每个分片需放在不同节点上，任一节点故障最多丢失一个分片
+    pub fn check_placement(&self, nodes: usize) -> anyhow::Result<()> {
+        if self.total() > nodes {
+            return Err(anyhow!(
+                "{} 个分片需要至少 {} 个节点，当前只有 {} 个",
+                self.total(),
+                self.total(),
+                nodes
+            ));
+        }
+        Ok(())
+    }
+
+    // 长度为 len 的分块编码后每个分片数据的长度(不含分片头)
+    fn shard_len(&self, len: u64) -> usize {
+        (len as usize)
+            .div_ceil(self.data_shards)
+            .max(1)
+            .next_multiple_of(SHARD_ALIGN)
+    }
+
+    // 编码分块，返回带分片头的全部分片，前 data_shards 个为数据分片
+    pub fn encode(&self, data: &[u8]) -> anyhow::Result<Vec<Vec<u8>>> {
+        self.validate()?;
+        let shard_len = self.shard_len(data.len() as u64);
+        let mut padded = data.to_vec();
+        padded.resize(shard_len * self.data_shards, 0);
+        let originals: Vec<&[u8]> = padded.chunks(shard_len).collect();
+        let recovery = reed_solomon_simd::encode(self.data_shards, self.parity_shards, &originals)
+            .map_err(|err| anyhow!("纠删码编码失败: {:?}", err))?;
+        let shards = originals
+            .into_iter()
+            .map(<[u8]>::to_vec)
+            .chain(recovery)
+            .enumerate()
+            .map(|(index, body)| {
+                let header = ShardHeader {
+                    code: *self,
+                    index,
+                    len: data.len() as u64,
+                };
+                let mut shard = header.to_bytes();
+                shard.extend_from_slice(&body);
+                shard
+            })
+            .collect();
+        Ok(shards)
+    }
+}
+
+// 分片头，每个分片自带还原所需的参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShardHeader {
+    pub code: ErasureCode,
+    pub index: usize,
+    // 编码前分块的长度
+    pub len: u64,
+}
+
+impl ShardHeader {
+    pub fn parse(shard: &[u8]) -> anyhow::Result<Self> {
+        if shard.len() < HEADER_LEN || &shard[..2] != MAGIC {
+            return Err(anyhow!("分片头格式错误"));
+        }
+        let header = ShardHeader {
+            code: ErasureCode {
+                data_shards: shard[2] as usize,
+                parity_shards: shard[3] as usize,
+            },
+            index: shard[4] as usize,
+            len: u64::from_be_bytes(shard[8..16].try_into()?),
+        };
+        header.code.validate()?;
+        if header.index >= header.code.total() {
+            return Err(anyhow!("分片序号 {} 超出范围", header.index));
+        }
+        Ok(header)
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(self.code.data_shards as u8);
+        bytes.push(self.code.parity_shards as u8);
+        bytes.push(self.index as u8);
+        bytes.extend_from_slice(&[0; 3]);
+        bytes.extend_from_slice(&self.len.to_be_bytes());
+        bytes
+    }
+}
+
+// 校验单个分片：分片头合法、序号与文件名一致、数据长度与分块长度相符
+// 单个分片无法校验内容，内容损坏只能在还原时发现
+pub fn verify_shard(shard: &[u8], index: usize) -> anyhow::Result<()> {
+    let header = ShardHeader::parse(shard)?;
+    if header.index != index {
+        return Err(anyhow!(
+            "分片序号 {} 与文件名 {} 不一致",
+            header.index,
+            index
+        ));
+    }
+    let expected = header.code.shard_len(header.len);
+    let actual = shard.len() - HEADER_LEN;
+    if actual != expected {
+        return Err(anyhow!("分片长度 {} 与预期的 {} 不一致", actual, expected));
+    }
+    Ok(())
+}
+
+// 分片头和按序号排列的分片数据
+type Collected<'a> = (ShardHeader, BTreeMap<usize, &'a [u8]>);
+
+// 按序号整理同一分块的分片，忽略重复的分片
+fn collect(shards: &[Vec<u8>]) -> anyhow::Result<Option<Collected<'_>>> {
+    let mut first: Option<(ShardHeader, usize)> = None;
+    let mut bodies = BTreeMap::new();
+    for shard in shards {
+        let header = ShardHeader::parse(shard)?;
+        let body = &shard[HEADER_LEN..];
+        match first {
+            Some((first, body_len))
+                if first.code != header.code
+                    || first.len != header.len
+                    || body_len != body.len() =>
+            {
+                return Err(anyhow!("分片 {} 与其他分片不一致", header.index));
+            }
+            Some(_) => {}
+            None => first = Some((header, body.len())),
+        }
+        bodies.insert(header.index, body);
+    }
+    Ok(first.map(|(header, _)| (header, bodies)))
+}
+
+// 是否已有足够的分片还原分块
+pub fn is_recoverable(shards: &[Vec<u8>]) -> bool {
+    match collect(shards) {
+        Ok(Some((header, bodies))) => bodies.len() >= header.code.data_shards,
+        _ => false,
+    }
+}
+
+// 已有分片的序号
+pub fn shard_indexes(shards: &[Vec<u8>]) -> Vec<usize> {
+    let mut indexes: Vec<usize> = shards
+        .iter()
+        .filter_map(|shard| ShardHeader::parse(shard).ok())
+        .map(|header| header.index)
+        .collect();
+    indexes.sort_unstable();
+    indexes.dedup();
+    indexes
+}
+
+// 由任意 data_shards 个分片还原分块
+pub fn decode(shards: &[Vec<u8>]) -> anyhow::Result<Vec<u8>> {
+    let (header, bodies) = collect(shards)?.context("没有可用的分片")?;
+    let ErasureCode {
+        data_shards,
+        parity_shards,
+    } = header.code;
+    if bodies.len() < data_shards {
+        return Err(anyhow!(
+            "分片不足，需要 {} 个，只有 {} 个",
+            data_shards,
+            bodies.len()
+        ));
+    }
+    let mut originals: Vec<Option<Vec<u8>>> = (0..data_shards)
+        .map(|index| bodies.get(&index).map(|body| body.to_vec()))
+        .collect();
+    if originals.iter().any(Option::is_none) {
+        let restored = reed_solomon_simd::decode(
+            data_shards,
+            parity_shards,
+            bodies
+                .iter()
+                .filter(|(index, _)| **index < data_shards)
+                .map(|(index, body)| (*index, *body)),
+            bodies
+                .iter()
+                .filter(|(index, _)| **index >= data_shards)
+                .map(|(index, body)| (*index - data_shards, *body)),
+        )
+        .map_err(|err| anyhow!("纠删码解码失败: {:?}", err))?;
+        for (index, body) in restored {
+            originals[index] = Some(body);
+        }
+    }
+    let mut data = Vec::new();
+    for body in originals {
+        data.extend(body.context("还原数据分片失败")?);
+    }
+    data.truncate(header.len as usize);
+    Ok(data)
+}
+
+// 分片所在的节点序号，第 i 个分片放在第 (起点 + i) % nodes 个节点上
+// 起点由分块名称决定，使不同分块的分片均匀分布；节点数少于分片数时一个节点保存多个分片
+pub fn placement(name: &str, nodes: usize, total: usize) -> Vec<usize> {
+    if nodes == 0 {
+        return vec![];
+    }
+    let start = name
+        .get(..2)
+        .and_then(|prefix| usize::from_str_radix(prefix, 16).ok())
+        .unwrap_or_default();
+    (0..total).map(|index| (start + index) % nodes).collect()
+}
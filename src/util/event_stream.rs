@@ -0,0 +1,79 @@
+use crate::util::checksum::crc32;
+use anyhow::{anyhow, Context};
+
+// AWS event stream 消息格式:
+// 总长度(4) + 头部长度(4) + 前导CRC(4) + 头部 + 负载 + 消息CRC(4)，整数均为大端序
+// 头部: 名称长度(1) + 名称 + 值类型(1) + 值长度(2) + 值，这里只使用字符串类型(7)
+const PRELUDE_LEN: usize = 12;
+const CRC_LEN: usize = 4;
+const STRING_TYPE: u8 = 7;
+
+// 编码一条消息
+pub fn encode_message(headers: &[(&str, &str)], payload: &[u8]) -> Vec<u8> {
+    let mut header_bytes = Vec::new();
+    for (name, value) in headers {
+        header_bytes.push(name.len() as u8);
+        header_bytes.extend_from_slice(name.as_bytes());
+        header_bytes.push(STRING_TYPE);
+        header_bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        header_bytes.extend_from_slice(value.as_bytes());
+    }
+    let total_len = PRELUDE_LEN + header_bytes.len() + payload.len() + CRC_LEN;
+    let mut message = Vec::with_capacity(total_len);
+    message.extend_from_slice(&(total_len as u32).to_be_bytes());
+    message.extend_from_slice(&(header_bytes.len() as u32).to_be_bytes());
+    let prelude_crc = crc32(&message);
+    message.extend_from_slice(&prelude_crc.to_be_bytes());
+    message.extend_from_slice(&header_bytes);
+    message.extend_from_slice(payload);
+    let message_crc = crc32(&message);
+    message.extend_from_slice(&message_crc.to_be_bytes());
+    message
+}
+
+// 解码一条消息，返回头部、负载和消息长度
+#[allow(clippy::type_complexity)]
+pub fn decode_message(data: &[u8]) -> anyhow::Result<(Vec<(String, String)>, Vec<u8>, usize)> {
+    let prelude = data.get(..PRELUDE_LEN).context("消息前导不完整")?;
+    let total_len = u32::from_be_bytes(prelude[0..4].try_into()?) as usize;
+    let headers_len = u32::from_be_bytes(prelude[4..8].try_into()?) as usize;
+    let prelude_crc = u32::from_be_bytes(prelude[8..12].try_into()?);
+    if crc32(&prelude[..8]) != prelude_crc {
+        return Err(anyhow!("消息前导校验失败"));
+    }
+    if total_len < PRELUDE_LEN + headers_len + CRC_LEN {
+        return Err(anyhow!("消息长度错误"));
+    }
+    let message = data.get(..total_len).context("消息不完整")?;
+    let (body, message_crc) = message.split_at(total_len - CRC_LEN);
+    if crc32(body) != u32::from_be_bytes(message_crc.try_into()?) {
+        return Err(anyhow!("消息校验失败"));
+    }
+    let mut headers = Vec::new();
+    let header_bytes = &body[PRELUDE_LEN..PRELUDE_LEN + headers_len];
+    let mut pos = 0;
+    while pos < header_bytes.len() {
+        let name_len = header_bytes[pos] as usize;
+        let name = header_bytes
+            .get(pos + 1..pos + 1 + name_len)
+            .context("消息头部不完整")?;
+        pos += 1 + name_len;
+        if header_bytes.get(pos) != Some(&STRING_TYPE) {
+            return Err(anyhow!("不支持的消息头部类型"));
+        }
+        let value_len = header_bytes
+            .get(pos + 1..pos + 3)
+            .context("消息头部不完整")?;
+        let value_len = u16::from_be_bytes(value_len.try_into()?) as usize;
+        let value = header_bytes
+            .get(pos + 3..pos + 3 + value_len)
+            .context("消息头部不完整")?;
+        pos += 3 + value_len;
+        headers.push((
+            String::from_utf8_lossy(name).to_string(),
+            String::from_utf8_lossy(value).to_string(),
+        ));
+    }
+    let payload = body[PRELUDE_LEN + headers_len..].to_vec();
+    Ok((headers, payload, total_len))
+}
@@ -1,4 +1,6 @@
-use crate::fs;
+use crate::layout::Layout;
+use crate::object_index::ObjectIndex;
+use anyhow::Context;
 use std::path::Path;
 
 // 根据文件路径获取文件类型。
@@ -10,9 +12,14 @@ pub fn get_file_type(file_path: &str) -> Option<&str> {
 
 //  从元信息中获取文件类型。
 #[allow(dead_code)]
-pub fn file_type_from_meta_info(file_path: &str) -> anyhow::Result<String> {
+pub fn file_type_from_meta_info(
+    layout: &Layout,
+    objects: &ObjectIndex,
+    file_path: &str,
+) -> anyhow::Result<String> {
     let mut metainfo_path = file_path.to_string();
     metainfo_path.push_str(".meta");
-    let metadata = fs::load_metadata(&metainfo_path)?;
+    let (bucket_name, key) = layout.object_of(&metainfo_path).context("对象不存在")?;
+    let metadata = objects.load(&bucket_name, &key)?.context("对象不存在")?;
     Ok(metadata.file_type)
 }
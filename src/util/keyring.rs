@@ -0,0 +1,134 @@
+use crate::util::cry;
+use anyhow::{anyhow, Context};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+// 元数据密文格式: 魔数(4) + 版本(1) + 密钥ID(8) + 随机数(12) + 密文(含认证标签)
+// 头部同时作为附加认证数据，旧版 AES-256-CBC 密文以可打印字符开头，不会与魔数冲突
+const MAGIC: &[u8; 4] = b"\0S3M";
+const VERSION: u8 = 1;
+const KEY_ID_LEN: usize = 8;
+const HEADER_LEN: usize = MAGIC.len() + 1 + KEY_ID_LEN;
+
+// 元数据密钥环，第一个密钥用于加密，其余密钥只用于解密轮换前写入的元数据
+#[derive(Clone)]
+pub struct Keyring {
+    keys: Vec<[u8; 32]>,
+    // 是否解密旧版 AES-256-CBC 格式的元数据，该格式没有认证，只在升级旧数据时启用
+    legacy_cbc: bool,
+}
+
+// 不输出密钥本身，只输出当前密钥的ID
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keyring")
+            .field("active_key_id", &self.active_key_id())
+            .field("keys", &self.keys.len())
+            .field("legacy_cbc", &self.legacy_cbc)
+            .finish()
+    }
+}
+
+impl Keyring {
+    // 解析密钥列表：每行(或以逗号分隔)一个十六进制编码的 256 位密钥，# 开头的行为注释
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let mut keys = Vec::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            for key in line.split(',').map(str::trim).filter(|key| !key.is_empty()) {
+                let key = hex::decode(key).context("元数据密钥格式错误")?;
+                let key: [u8; 32] = key
+                    .try_into()
+                    .map_err(|_| anyhow!("元数据密钥长度必须为32字节"))?;
+                keys.push(key);
+            }
+        }
+        if keys.is_empty() {
+            return Err(anyhow!("元数据密钥为空"));
+        }
+        Ok(Keyring {
+            keys,
+            legacy_cbc: false,
+        })
+    }
+
+    // 允许或禁止解密旧版 AES-256-CBC 格式的元数据
+    pub fn with_legacy_cbc(mut self, legacy_cbc: bool) -> Self {
+        self.legacy_cbc = legacy_cbc;
+        self
+    }
+
+    // 追加另一个密钥环中尚未包含的密钥，只用于解密
+    pub fn with_previous(mut self, previous: &Keyring) -> Self {
+        for key in &previous.keys {
+            if !self.keys.contains(key) {
+                self.keys.push(*key);
+            }
+        }
+        self
+    }
+
+    // 当前密钥的ID，为密钥 SHA-256 摘要的前8字节
+    pub fn active_key_id(&self) -> String {
+        hex::encode(key_id(&self.keys[0]))
+    }
+
+    // 使用当前密钥加密元数据
+    pub fn encrypt(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let key = &self.keys[0];
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.push(VERSION);
+        header.extend_from_slice(&key_id(key));
+        let ciphertext = cry::aes_256_gcm_encrypt(key, data, &header)?;
+        header.extend_from_slice(&ciphertext);
+        Ok(header)
+    }
+
+    // 根据头部的密钥ID选择密钥解密元数据，没有头部时只在启用兼容时按旧版 AES-256-CBC 格式解密
+    pub fn decrypt(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if is_legacy(data) {
+            if !self.legacy_cbc {
+                return Err(anyhow!("元数据没有认证头部，未启用旧版格式兼容"));
+            }
+            return cry::aes_256_cbc_decrypt(data);
+        }
+        if data.len() < HEADER_LEN {
+            return Err(anyhow!("元数据头部不完整"));
+        }
+        let (header, ciphertext) = data.split_at(HEADER_LEN);
+        if header[MAGIC.len()] != VERSION {
+            return Err(anyhow!("不支持的元数据版本: {}", header[MAGIC.len()]));
+        }
+        let id = &header[MAGIC.len() + 1..];
+        let key = self
+            .keys
+            .iter()
+            .find(|key| key_id(&key[..]) == id)
+            .with_context(|| format!("找不到元数据密钥: {}", hex::encode(id)))?;
+        cry::aes_256_gcm_decrypt(key, ciphertext, header).context("元数据校验失败")
+    }
+
+    // 判断元数据是否需要用当前密钥重新加密
+    pub fn needs_rotation(&self, data: &[u8]) -> bool {
+        !(data.len() >= HEADER_LEN
+            && data.starts_with(MAGIC)
+            && data[MAGIC.len()] == VERSION
+            && data[MAGIC.len() + 1..HEADER_LEN] == key_id(&self.keys[0]))
+    }
+}
+
+// 判断元数据是否为旧版 AES-256-CBC 格式
+pub fn is_legacy(data: &[u8]) -> bool {
+    !data.starts_with(MAGIC)
+}
+
+fn key_id(key: &[u8]) -> [u8; KEY_ID_LEN] {
+    let digest = Sha256::digest(key);
+    let mut id = [0u8; KEY_ID_LEN];
+    id.copy_from_slice(&digest[..KEY_ID_LEN]);
+    id
+}
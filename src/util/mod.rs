@@ -1,3 +1,10 @@
+pub mod checksum;
+pub mod chunker;
+pub mod codec;
 pub mod cry;
 pub mod date;
+pub mod durable;
+pub mod erasure;
+pub mod event_stream;
 pub mod file;
+pub mod keyring;
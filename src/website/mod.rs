@@ -0,0 +1,182 @@
+use crate::err::AppError;
+use crate::fs::{DecompressStream, Metadata};
+use crate::model::WebsiteConfiguration;
+use crate::raft::app::App;
+use crate::raft::network::chunk;
+use crate::util::date::date_format_to_second;
+use crate::{bucket, sse, HandlerResponse};
+use futures::future::ok;
+use futures::stream::once;
+use log::info;
+use ntex::http::{Method, StatusCode};
+use ntex::util::Bytes;
+use ntex::web;
+use ntex::web::HttpResponse;
+
+pub mod routing;
+
+// 对象的网站重定向地址请求头
+pub const REDIRECT_LOCATION_HEADER: &str = "x-amz-website-redirect-location";
+
+// 网站服务的设置
+#[derive(Debug, Clone, Default)]
+pub struct Website {
+    // 网站域名，请求的 Host 为 {bucket}.{domain} 时访问对应的桶
+    pub domain: Option<String>,
+}
+
+// 网站服务只接受匿名的 GET 和 HEAD 请求，路径即对象键
+pub fn rest(cfg: &mut web::ServiceConfig) {
+    cfg.route("/", web::route().to(serve))
+        .route("/{key}*", web::route().to(serve));
+}
+
+// 网站请求的错误以 HTML 页面返回
+fn error_response(status: u16, code: &str, message: &str) -> HttpResponse {
+    HttpResponse::build(StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR))
+        .content_type("text/html; charset=utf-8")
+        .body(routing::error_page(status, code, message))
+}
+
+fn redirect(status: u16, location: &str) -> HttpResponse {
+    HttpResponse::build(StatusCode::from_u16(status).unwrap_or(StatusCode::MOVED_PERMANENTLY))
+        .header("Location", location)
+        .finish()
+}
+
+pub async fn serve(
+    req: web::HttpRequest,
+    state: web::types::State<App>,
+    website: web::types::State<Website>,
+) -> HandlerResponse {
+    match do_serve(&req, &state, &website).await {
+        Ok(response) => Ok(response),
+        Err(AppError::S3Error {
+            status,
+            code,
+            message,
+        }) => Ok(error_response(status, code, &message)),
+        Err(err) => {
+            info!("网站请求失败: {}", err);
+            Ok(error_response(
+                500,
+                "InternalError",
+                "We encountered an internal error. Please try again.",
+            ))
+        }
+    }
+}
+
+async fn do_serve(req: &web::HttpRequest, state: &App, website: &Website) -> HandlerResponse {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return Err(AppError::s3(
+            405,
+            "MethodNotAllowed",
+            "The specified method is not allowed against this resource.",
+        ));
+    }
+    let host = req
+        .headers()
+        .get("host")
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or_default();
+    let no_such_bucket =
+        || AppError::s3(404, "NoSuchBucket", "The specified bucket does not exist");
+    let bucket_name =
+        routing::bucket_from_host(host, website.domain.as_deref()).ok_or_else(no_such_bucket)?;
+    if !state.layout.bucket(&bucket_name).is_dir() {
+        return Err(no_such_bucket());
+    }
+    let config: WebsiteConfiguration =
+        bucket::get_config(state, &bucket_name, bucket::WEBSITE_CONFIG)
+            .await?
+            .ok_or_else(|| {
+                AppError::s3(
+                    404,
+                    "NoSuchWebsiteConfiguration",
+                    "The specified bucket does not have a website configuration",
+                )
+            })?;
+    if let Some(to) = &config.redirect_all_requests_to {
+        let path = req.uri().path_and_query().map_or("/", |path| path.as_str());
+        return Ok(redirect(301, &routing::redirect_all(to, path)));
+    }
+    let key = routing::object_key(req.path())
+        .ok_or_else(|| AppError::s3(400, "InvalidURI", "Couldn't parse the specified URI."))?;
+    if let Some(rule) = routing::match_rule(&config, &key, None) {
+        let (status, location) = routing::rule_redirect(rule, &key, host);
+        return Ok(redirect(status, &location));
+    }
+    let suffix = config
+        .index_document
+        .as_ref()
+        .map(|index| index.suffix.as_str())
+        .unwrap_or_default();
+    if let Some(metadata) = load_object(state, &bucket_name, &routing::index_key(&key, suffix)) {
+        if let Some(location) = &metadata.website_redirect_location {
+            return Ok(redirect(301, location));
+        }
+        return object_response(req, state, metadata, StatusCode::OK);
+    }
+    // 不以 / 结尾的目录访问重定向到目录，由目录下的索引文档响应
+    if !key.is_empty()
+        && !key.ends_with('/')
+        && load_object(state, &bucket_name, &format!("{}/{}", key, suffix)).is_some()
+    {
+        return Ok(redirect(302, &format!("/{}/", key)));
+    }
+    if let Some(rule) = routing::match_rule(&config, &key, Some(404)) {
+        let (status, location) = routing::rule_redirect(rule, &key, host);
+        return Ok(redirect(status, &location));
+    }
+    let error_document = config
+        .error_document
+        .as_ref()
+        .and_then(|error| load_object(state, &bucket_name, &error.key));
+    match error_document {
+        Some(metadata) => object_response(req, state, metadata, StatusCode::NOT_FOUND),
+        None => Err(AppError::s3(
+            404,
+            "NoSuchKey",
+            "The specified key does not exist.",
+        )),
+    }
+}
+
+fn load_object(state: &App, bucket_name: &str, object_key: &str) -> Option<Metadata> {
+    if object_key.is_empty() {
+        return None;
+    }
+    state.objects.load(bucket_name, object_key).ok().flatten()
+}
+
+// 返回对象内容，使用客户密钥加密(SSE-C)的对象无法匿名访问
+fn object_response(
+    req: &web::HttpRequest,
+    state: &App,
+    metadata: Metadata,
+    status: StatusCode,
+) -> HandlerResponse {
+    let data_key = sse::data_key(&state.master_key, metadata.encryption.as_ref(), None)
+        .map_err(|_| AppError::s3(403, "AccessDenied", "Access Denied"))?;
+    let mut response = HttpResponse::build(status);
+    response
+        .content_type(metadata.file_type.as_str())
+        .header("Last-Modified", date_format_to_second(metadata.time))
+        .header("ETag", metadata.etag())
+        .content_length(metadata.size)
+        .no_chunking();
+    if req.method() == Method::HEAD {
+        return Ok(response.streaming(once(ok::<_, web::Error>(Bytes::new()))));
+    }
+    let peers = chunk::peers(state);
+    let body = DecompressStream::object(
+        state.chunk_gc.store,
+        state.chunk_gc.cache.clone(),
+        metadata,
+        data_key,
+        peers,
+        state.read_ahead,
+    );
+    Ok(response.streaming(body))
+}
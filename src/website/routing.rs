@@ -0,0 +1,250 @@
+use crate::api::percent_decode;
+use crate::err::AppError;
+use crate::model::{RedirectAllRequestsTo, RoutingRule, WebsiteConfiguration};
+
+// 重定向地址的最大长度
+const MAX_REDIRECT_LOCATION_LEN: usize = 2048;
+
+fn invalid_argument(message: impl Into<String>) -> AppError {
+    AppError::s3(400, "InvalidArgument", message)
+}
+
+fn valid_protocol(protocol: Option<&str>) -> bool {
+    matches!(protocol, None | Some("http") | Some("https"))
+}
+
+// 校验桶的网站配置
+pub fn validate_config(config: &WebsiteConfiguration) -> Result<(), AppError> {
+    if let Some(redirect) = &config.redirect_all_requests_to {
+        if config.index_document.is_some()
+            || config.error_document.is_some()
+            || config.routing_rules.is_some()
+        {
+            return Err(invalid_argument(
+                "RedirectAllRequestsTo cannot be provided in conjunction with other Routing/Redirect configurations.",
+            ));
+        }
+        if redirect.host_name.is_empty() {
+            return Err(invalid_argument("The HostName must not be empty."));
+        }
+        if !valid_protocol(redirect.protocol.as_deref()) {
+            return Err(invalid_argument("The Protocol must be http or https."));
+        }
+        return Ok(());
+    }
+    let suffix = match &config.index_document {
+        Some(index) => &index.suffix,
+        None => return Err(invalid_argument(
+            "A value for IndexDocument Suffix must be provided if RedirectAllRequestsTo is empty",
+        )),
+    };
+    if suffix.is_empty() || suffix.contains('/') {
+        return Err(invalid_argument(
+            "The IndexDocument Suffix is not well formed",
+        ));
+    }
+    if config
+        .error_document
+        .as_ref()
+        .is_some_and(|error| error.key.is_empty())
+    {
+        return Err(invalid_argument("The ErrorDocument Key must not be empty."));
+    }
+    for rule in config.routing_rules.iter().flat_map(|rules| &rules.rules) {
+        validate_rule(rule)?;
+    }
+    Ok(())
+}
+
+fn validate_rule(rule: &RoutingRule) -> Result<(), AppError> {
+    if let Some(condition) = &rule.condition {
+        if condition.key_prefix_equals.is_none()
+            && condition.http_error_code_returned_equals.is_none()
+        {
+            return Err(invalid_argument(
+                "Condition cannot be empty. To redirect all requests without a condition, the condition element shouldn't be present.",
+            ));
+        }
+        if let Some(code) = &condition.http_error_code_returned_equals {
+            if !code
+                .parse::<u16>()
+                .is_ok_and(|code| (400..600).contains(&code))
+            {
+                return Err(invalid_argument(format!(
+                    "The provided HTTP error code ({}) is not valid. Valid codes are 4XX or 5XX.",
+                    code
+                )));
+            }
+        }
+    }
+    let redirect = &rule.redirect;
+    if redirect.replace_key_with.is_some() && redirect.replace_key_prefix_with.is_some() {
+        return Err(invalid_argument(
+            "You can only define ReplaceKeyPrefix or ReplaceKey but not both.",
+        ));
+    }
+    if !valid_protocol(redirect.protocol.as_deref()) {
+        return Err(invalid_argument("The Protocol must be http or https."));
+    }
+    if let Some(code) = &redirect.http_redirect_code {
+        if !matches!(code.as_str(), "301" | "302" | "303" | "307" | "308") {
+            return Err(invalid_argument(format!(
+                "The provided HTTP redirect code ({}) is not valid. Valid codes are 3XX except 300.",
+                code
+            )));
+        }
+    }
+    Ok(())
+}
+
+// 校验对象的网站重定向地址，只能是同一网站内的绝对路径或外部 http(s) 地址
+pub fn validate_redirect_location(location: &str) -> Result<(), AppError> {
+    let valid = location.len() <= MAX_REDIRECT_LOCATION_LEN
+        && (location.starts_with('/')
+            || location.starts_with("http://")
+            || location.starts_with("https://"));
+    if !valid {
+        return Err(invalid_argument(
+            "The website redirect location must have a prefix of 'http://' or 'https://' or '/'.",
+        ));
+    }
+    Ok(())
+}
+
+// 从请求的 Host 中解析桶名：以网站域名结尾时去掉域名，否则整个主机名就是桶名(CNAME)
+pub fn bucket_from_host(host: &str, domain: Option<&str>) -> Option<String> {
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let bucket = match domain {
+        Some(domain) => match host.strip_suffix(&format!(".{}", domain.to_ascii_lowercase())) {
+            Some(bucket) => bucket.to_string(),
+            None => host,
+        },
+        None => host,
+    };
+    if bucket.is_empty() {
+        return None;
+    }
+    Some(bucket)
+}
+
+// 从请求路径解析对象键，拒绝包含 . 或 .. 路径段的键
+pub fn object_key(path: &str) -> Option<String> {
+    let key = percent_decode(path.trim_start_matches('/'))?;
+    if key
+        .split('/')
+        .any(|segment| segment == "." || segment == "..")
+    {
+        return None;
+    }
+    Some(key)
+}
+
+// 目录形式的键(为空或以 / 结尾)指向其中的索引文档
+pub fn index_key(key: &str, suffix: &str) -> String {
+    if key.is_empty() || key.ends_with('/') {
+        format!("{}{}", key, suffix)
+    } else {
+        key.to_string()
+    }
+}
+
+// 查找匹配的路由规则
+// error_code 为None时在读取对象前匹配，只考虑不带错误码条件的规则；否则只考虑错误码相同的规则
+pub fn match_rule<'a>(
+    config: &'a WebsiteConfiguration,
+    key: &str,
+    error_code: Option<u16>,
+) -> Option<&'a RoutingRule> {
+    config
+        .routing_rules
+        .iter()
+        .flat_map(|rules| &rules.rules)
+        .find(|rule| {
+            let condition = rule.condition.clone().unwrap_or_default();
+            let prefix_matches = condition
+                .key_prefix_equals
+                .as_deref()
+                .is_none_or(|prefix| key.starts_with(prefix));
+            let code_matches = match (&condition.http_error_code_returned_equals, error_code) {
+                (None, None) => true,
+                (Some(expected), Some(code)) => expected.parse::<u16>() == Ok(code),
+                _ => false,
+            };
+            prefix_matches && code_matches
+        })
+}
+
+// 按路由规则计算重定向的状态码和地址
+pub fn rule_redirect(rule: &RoutingRule, key: &str, request_host: &str) -> (u16, String) {
+    let redirect = &rule.redirect;
+    let new_key = match (
+        &redirect.replace_key_with,
+        &redirect.replace_key_prefix_with,
+    ) {
+        (Some(replacement), _) => replacement.clone(),
+        (None, Some(replacement)) => {
+            let prefix = rule
+                .condition
+                .as_ref()
+                .and_then(|condition| condition.key_prefix_equals.as_deref())
+                .unwrap_or("");
+            format!("{}{}", replacement, key.strip_prefix(prefix).unwrap_or(key))
+        }
+        (None, None) => key.to_string(),
+    };
+    let status = redirect
+        .http_redirect_code
+        .as_deref()
+        .and_then(|code| code.parse().ok())
+        .unwrap_or(301);
+    let location = match (&redirect.host_name, &redirect.protocol) {
+        (Some(host), protocol) => format!(
+            "{}://{}/{}",
+            protocol.as_deref().unwrap_or("http"),
+            host,
+            new_key
+        ),
+        (None, Some(protocol)) => format!("{}://{}/{}", protocol, request_host, new_key),
+        (None, None) => format!("/{}", new_key),
+    };
+    (status, location)
+}
+
+// 整个网站重定向到其他主机，保留请求的路径
+pub fn redirect_all(to: &RedirectAllRequestsTo, path: &str) -> String {
+    format!(
+        "{}://{}{}",
+        to.protocol.as_deref().unwrap_or("http"),
+        to.host_name,
+        path
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// 网站访问出错时返回的 HTML 页面
+pub fn error_page(status: u16, code: &str, message: &str) -> String {
+    let title = format!("{} {}", status, escape_html(code));
+    format!(
+        "<html>\n<head><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n<ul>\n<li>Code: {}</li>\n<li>Message: {}</li>\n</ul>\n<hr/>\n</body>\n</html>\n",
+        escape_html(code),
+        escape_html(message),
+    )
+}
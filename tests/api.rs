@@ -1,7 +1,12 @@
 #[cfg(test)]
 mod test {
     use quick_xml::se::to_string;
+    use rs_s3_local::api::{
+        parse_copy_source, parse_object_attributes, reuses_source_chunks, ObjectAttributes,
+    };
+    use rs_s3_local::fs::sealed_chunk_name;
     use rs_s3_local::model::{Bucket, BucketWrapper, ListBucketResp, Owner};
+    use rs_s3_local::util::cry;
     use serde::{Deserialize, Serialize};
 
     #[test]
@@ -60,4 +65,57 @@ mod test {
         let xml = to_string(&person);
         assert!(xml.is_ok(), "序列化失败");
     }
+
+    #[test]
+    fn test3() {
+        assert_eq!(
+            parse_copy_source("/bucket/dir/a%20b.txt?versionId=1"),
+            Some(("bucket".to_string(), "dir/a b.txt".to_string()))
+        );
+        assert_eq!(
+            parse_copy_source("bucket/key"),
+            Some(("bucket".to_string(), "key".to_string()))
+        );
+        assert_eq!(parse_copy_source("/bucket"), None);
+        assert_eq!(parse_copy_source("/bucket/%zz"), None);
+    }
+
+    #[test]
+    fn test4() {
+        assert_eq!(
+            parse_object_attributes("ETag, ObjectParts,ObjectSize"),
+            Some(ObjectAttributes {
+                etag: true,
+                object_parts: true,
+                object_size: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(parse_object_attributes(""), None);
+        assert_eq!(parse_object_attributes("ETag,Owner"), None);
+    }
+
+    #[test]
+    fn test5() {
+        let customer_key = cry::gen_aes_256_key();
+        let other_key = cry::gen_aes_256_key();
+        // 加密方式和密钥都相同时沿用来源的分块
+        assert!(reuses_source_chunks(None, None));
+        assert!(reuses_source_chunks(
+            Some(&customer_key),
+            Some(&customer_key)
+        ));
+        assert!(!reuses_source_chunks(Some(&customer_key), Some(&other_key)));
+        assert!(!reuses_source_chunks(Some(&customer_key), None));
+
+        // SSE-S3 复制为 SSE-C 时用新的数据密钥重新加密，目标的分块名与来源不同
+        assert!(!reuses_source_chunks(None, Some(&customer_key)));
+        let hash = "a".repeat(64);
+        let source_data_key = cry::gen_aes_256_key();
+        let dest_data_key = cry::gen_aes_256_key();
+        assert_ne!(
+            sealed_chunk_name(&source_data_key, &hash).unwrap(),
+            sealed_chunk_name(&dest_data_key, &hash).unwrap()
+        );
+    }
 }
@@ -0,0 +1,54 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::cache::{split_budget, Cache};
+    use rs_s3_local::metrics::Counter;
+
+    static HITS: Counter = Counter::new("test_cache_hits_total");
+    static MISSES: Counter = Counter::new("test_cache_misses_total");
+    static DISABLED_HITS: Counter = Counter::new("test_disabled_hits_total");
+    static DISABLED_MISSES: Counter = Counter::new("test_disabled_misses_total");
+
+    #[test]
+    fn test1() {
+        let cache: Cache<String, Vec<u8>> = Cache::new(10, &HITS, &MISSES);
+        cache.insert("a".to_string(), vec![1; 4], 4);
+        cache.insert("b".to_string(), vec![2; 4], 4);
+        assert_eq!(cache.get(&"a".to_string()), Some(vec![1; 4]));
+        // 超出容量时淘汰最久未使用的 b
+        cache.insert("c".to_string(), vec![3; 4], 4);
+        assert_eq!(cache.get(&"b".to_string()), None);
+        assert_eq!(cache.usage(), (2, 8));
+        assert_eq!(HITS.get(), 1);
+        assert_eq!(MISSES.get(), 1);
+
+        // 覆盖同一个键时按新的大小计算
+        cache.insert("a".to_string(), vec![4; 2], 2);
+        assert_eq!(cache.usage(), (2, 6));
+        // 超过整个容量的条目不缓存
+        cache.insert("d".to_string(), vec![5; 11], 11);
+        assert_eq!(cache.get(&"d".to_string()), None);
+
+        // 克隆后共享数据
+        let shared = cache.clone();
+        shared.remove(&"a".to_string());
+        assert_eq!(cache.get(&"a".to_string()), None);
+        assert_eq!(cache.usage(), (1, 4));
+        cache.insert_if("e".to_string(), vec![6], 1, || false);
+        assert_eq!(cache.get(&"e".to_string()), None);
+        shared.clear();
+        assert_eq!(cache.usage(), (0, 0));
+    }
+
+    #[test]
+    fn test2() {
+        // 容量为0时不缓存，也不计入命中率
+        let cache: Cache<String, Vec<u8>> = Cache::new(0, &DISABLED_HITS, &DISABLED_MISSES);
+        cache.insert("a".to_string(), vec![1], 1);
+        assert_eq!(cache.get(&"a".to_string()), None);
+        assert_eq!(cache.usage(), (0, 0));
+        assert_eq!(DISABLED_HITS.get() + DISABLED_MISSES.get(), 0);
+
+        assert_eq!(split_budget(256 << 20), (224 << 20, 32 << 20));
+        assert_eq!(split_budget(0), (0, 0));
+    }
+}
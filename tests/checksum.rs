@@ -0,0 +1,62 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::util::checksum::{decode_aws_chunked, AwsChunkedDecoder, ChecksumAlgorithm};
+
+    #[test]
+    fn test1() {
+        assert_eq!(ChecksumAlgorithm::Crc32.checksum(b"123456789"), "y/Q5Jg==");
+        assert_eq!(ChecksumAlgorithm::Crc32c.checksum(b"123456789"), "4waSgw==");
+        assert_eq!(
+            ChecksumAlgorithm::Sha1.checksum(b"abc"),
+            "qZk+NkcGgWq6PiVxeFDCbJzQ2J0="
+        );
+        assert_eq!(
+            ChecksumAlgorithm::Sha256.checksum(b"abc"),
+            "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0="
+        );
+        assert_eq!(ChecksumAlgorithm::parse("crc32c"), Some(ChecksumAlgorithm::Crc32c));
+        assert_eq!(ChecksumAlgorithm::parse("md5"), None);
+        // 组合校验和为各分片校验和拼接后的摘要
+        let parts = vec![
+            ChecksumAlgorithm::Sha256.checksum(b"abc"),
+            ChecksumAlgorithm::Sha256.checksum(b"def"),
+        ];
+        assert_eq!(
+            ChecksumAlgorithm::Sha256.composite(&parts).unwrap(),
+            "nATTAFe3VK8bLS1PVnV4LdYaWmWcNO5sKvR1JrZsr6Y=-2"
+        );
+    }
+
+    #[test]
+    fn test2() {
+        let body = b"5;chunk-signature=aa\r\nhello\r\n6\r\n world\r\n0\r\nx-amz-checksum-crc32:DUoRhQ==\r\n\r\n";
+        let (data, trailers) = decode_aws_chunked(body).unwrap();
+        assert_eq!(data, b"hello world");
+        assert_eq!(
+            trailers,
+            vec![("x-amz-checksum-crc32".to_string(), "DUoRhQ==".to_string())]
+        );
+        assert!(decode_aws_chunked(b"5\r\nhel").is_err());
+    }
+
+    #[test]
+    fn test3() {
+        // 请求体分段到达时逐段解码，结果与一次解码相同
+        let body = b"5;chunk-signature=aa\r\nhello\r\n6\r\n world\r\n0\r\nx-amz-checksum-crc32:DUoRhQ==\r\n\r\n";
+        let mut decoder = AwsChunkedDecoder::default();
+        let mut data = Vec::new();
+        for byte in body.iter() {
+            data.extend(decoder.push(&[*byte]).unwrap());
+        }
+        assert_eq!(data, b"hello world");
+        assert_eq!(
+            decoder.finish().unwrap(),
+            vec![("x-amz-checksum-crc32".to_string(), "DUoRhQ==".to_string())]
+        );
+
+        let mut decoder = AwsChunkedDecoder::default();
+        assert_eq!(decoder.push(b"5\r\nhel").unwrap(), b"hel");
+        assert!(decoder.finish().is_err());
+        assert!(AwsChunkedDecoder::default().push(b"zz\r\n").is_err());
+    }
+}
@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::chunk_store::{ChunkStore, MemoryChunkStore, PackChunkStore};
+    use rs_s3_local::util::durable::Durability;
+
+    #[tokio::test]
+    async fn test1() {
+        let store = MemoryChunkStore::default();
+        assert!(!store.exists("A1").await);
+        assert_eq!(store.get("A1").await.unwrap(), None);
+
+        store.put("A1", b"one").await.unwrap();
+        store.put("B2", b"two").await.unwrap();
+        assert!(store.exists("A1").await);
+        assert_eq!(store.get("A1").await.unwrap().as_deref(), Some(&b"one"[..]));
+
+        // 已存在的分块不覆盖
+        store.put_if_absent("A1", b"other").await.unwrap();
+        assert_eq!(store.get("A1").await.unwrap().as_deref(), Some(&b"one"[..]));
+
+        store.delete("A1").await.unwrap();
+        store.delete("A1").await.unwrap();
+        assert!(!store.exists("A1").await);
+        assert_eq!(store.list().await.unwrap(), vec!["B2".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test2() {
+        let store = MemoryChunkStore::default();
+        for name in ["AB", "AB.0", "AB.1", "ABC.0", "AC.0"] {
+            store.put(name, name.as_bytes()).await.unwrap();
+        }
+        // 只列出该分块的分片
+        assert_eq!(
+            store.shards("AB").await.unwrap(),
+            vec!["AB.0".to_string(), "AB.1".to_string()]
+        );
+
+        // 内存存储不支持隔离，损坏的分块直接删除
+        assert_eq!(store.quarantine("AC.0").await.unwrap(), None);
+        assert!(!store.exists("AC.0").await);
+    }
+
+    fn inner() -> &'static MemoryChunkStore {
+        Box::leak(Box::default())
+    }
+
+    // sled 的后台线程退出后才释放索引的文件锁，重新打开时稍等重试
+    fn open(
+        dir: &std::path::Path,
+        threshold: usize,
+        inner: &'static MemoryChunkStore,
+    ) -> PackChunkStore {
+        for _ in 0..100 {
+            if let Ok(store) = PackChunkStore::open(dir, threshold, inner, Durability::Full) {
+                return store;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        PackChunkStore::open(dir, threshold, inner, Durability::Full).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test3() {
+        let dir = tempfile::tempdir().unwrap();
+        let inner = inner();
+        let store = open(dir.path(), 100, inner);
+        store.put("A1", b"small").await.unwrap();
+        store.put("B2", &[7; 100]).await.unwrap();
+        // 小分块写入包文件，大分块写入内层存储
+        assert!(!inner.exists("A1").await);
+        assert!(inner.exists("B2").await);
+        assert_eq!(
+            store.get("A1").await.unwrap().as_deref(),
+            Some(&b"small"[..])
+        );
+        assert_eq!(store.get("B2").await.unwrap(), Some(vec![7; 100]));
+        let mut names = store.list().await.unwrap();
+        names.sort();
+        assert_eq!(names, vec!["A1".to_string(), "B2".to_string()]);
+
+        store.put("A1.0", b"shard").await.unwrap();
+        assert_eq!(store.shards("A1").await.unwrap(), vec!["A1.0".to_string()]);
+        store.delete("A1.0").await.unwrap();
+        assert!(!store.exists("A1.0").await);
+        drop(store);
+
+        // 重新打开后索引仍在
+        let store = open(dir.path(), 100, inner);
+        assert_eq!(
+            store.get("A1").await.unwrap().as_deref(),
+            Some(&b"small"[..])
+        );
+        assert!(!store.exists("A1.0").await);
+    }
+
+    #[tokio::test]
+    async fn test4() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open(dir.path(), 1 << 20, inner());
+        let names: Vec<String> = (0..10).map(|i| format!("{:064X}", i)).collect();
+        for name in &names {
+            store.put(name, &[1; 1000]).await.unwrap();
+        }
+        drop(store);
+        let packs = |dir: &std::path::Path| {
+            std::fs::read_dir(dir)
+                .unwrap()
+                .flatten()
+                .filter(|entry| entry.file_name().to_string_lossy().ends_with(".pack"))
+                .count()
+        };
+        assert_eq!(packs(dir.path()), 1);
+
+        // 删除过半的分块后整理，存活的分块移到新的包文件
+        let store = open(dir.path(), 1 << 20, inner());
+        for name in &names[..4] {
+            store.delete(name).await.unwrap();
+        }
+        assert_eq!(store.compact().await.unwrap(), 0);
+        store.delete(&names[4]).await.unwrap();
+        store.put(&names[5], &[2; 1000]).await.unwrap();
+        assert!(store.compact().await.unwrap() >= 6 * 1000);
+        assert_eq!(packs(dir.path()), 1);
+        for name in &names[..5] {
+            assert!(!store.exists(name).await);
+        }
+        assert_eq!(store.get(&names[5]).await.unwrap(), Some(vec![2; 1000]));
+        for name in &names[6..] {
+            assert_eq!(store.get(name).await.unwrap(), Some(vec![1; 1000]));
+        }
+    }
+
+    #[tokio::test]
+    async fn test5() {
+        let dir = tempfile::tempdir().unwrap();
+        let inner = inner();
+        let store = open(dir.path(), 100, inner);
+        store.put("A1", b"first").await.unwrap();
+        store.put("B2", b"second").await.unwrap();
+        drop(store);
+
+        // 包文件被截断后，超出末尾的索引项在打开时删除，其余分块仍可读取
+        let pack = std::fs::read_dir(dir.path())
+            .unwrap()
+            .flatten()
+            .find(|entry| entry.file_name().to_string_lossy().ends_with(".pack"))
+            .unwrap()
+            .path();
+        let len = std::fs::metadata(&pack).unwrap().len();
+        let file = std::fs::OpenOptions::new().write(true).open(&pack).unwrap();
+        file.set_len(len - 3).unwrap();
+        drop(file);
+        let store = open(dir.path(), 100, inner);
+        assert_eq!(
+            store.get("A1").await.unwrap().as_deref(),
+            Some(&b"first"[..])
+        );
+        assert!(!store.exists("B2").await);
+        assert_eq!(store.get("B2").await.unwrap(), None);
+        assert_eq!(store.list().await.unwrap(), vec!["A1".to_string()]);
+
+        // 截断的包文件中已删除的字节数不会下溢，整理后仍可读取
+        store.delete("A1").await.unwrap();
+        store.put("C3", b"third").await.unwrap();
+        store.compact().await.unwrap();
+        assert_eq!(
+            store.get("C3").await.unwrap().as_deref(),
+            Some(&b"third"[..])
+        );
+    }
+}
@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::fs::{Metadata, CHUNK_SIZE};
+    use rs_s3_local::util::chunker::{ChunkingConfig, StreamChunker};
+
+    // 可重复的伪随机数据
+    fn data(len: usize) -> Vec<u8> {
+        let mut state: u32 = 12345;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test1() {
+        let chunking = ChunkingConfig::fastcdc(4096);
+        chunking.validate().unwrap();
+        let data = data(256 * 1024);
+        let chunks = chunking.split(&data);
+        // 分块结果确定，拼接后还原数据
+        assert_eq!(chunks, chunking.split(&data));
+        assert_eq!(chunks.concat(), data);
+        assert!(chunks.len() > 1);
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(last.len() <= 4 * 4096);
+        for chunk in rest {
+            assert!(chunk.len() > 1024 && chunk.len() <= 4 * 4096);
+        }
+
+        // 在开头插入数据只影响前面少数分块
+        let mut shifted = vec![0x42];
+        shifted.extend_from_slice(&data);
+        let shifted_chunks = chunking.split(&shifted);
+        let shared = shifted_chunks
+            .iter()
+            .filter(|chunk| chunks.contains(chunk))
+            .count();
+        assert!(shared >= chunks.len() - 2);
+    }
+
+    #[test]
+    fn test2() {
+        let chunking = ChunkingConfig::Fixed { size: 100 };
+        let lens: Vec<usize> = chunking.split(&data(250)).iter().map(|c| c.len()).collect();
+        assert_eq!(lens, vec![100, 100, 50]);
+        assert!(chunking.split(&[]).is_empty());
+
+        assert_eq!(
+            serde_json::to_string(&ChunkingConfig::fastcdc(4096)).unwrap(),
+            r#"{"fastcdc":{"min_size":1024,"avg_size":4096,"max_size":16384}}"#
+        );
+        let config: ChunkingConfig = serde_json::from_str(r#"{"fixed":{"size":1024}}"#).unwrap();
+        assert_eq!(config, ChunkingConfig::Fixed { size: 1024 });
+
+        assert!(ChunkingConfig::Fixed { size: 0 }.validate().is_err());
+        assert!(ChunkingConfig::fastcdc(64).validate().is_err());
+        let invalid = ChunkingConfig::FastCdc {
+            min_size: 4096,
+            avg_size: 1024,
+            max_size: 8192,
+        };
+        assert!(invalid.validate().is_err());
+        ChunkingConfig::default().validate().unwrap();
+    }
+
+    #[test]
+    fn test3() {
+        // 旧对象没有记录分块长度，按固定大小推算
+        let mut m = Metadata {
+            name: "xxx".to_string(),
+            size: CHUNK_SIZE as u64 + 10,
+            file_type: "xxxxx".to_string(),
+            time: Default::default(),
+            chunks: vec!["a".to_string(), "b".to_string()],
+            encryption: None,
+            checksum: None,
+            parts: vec![],
+            website_redirect_location: None,
+            chunk_sizes: vec![],
+            inline: None,
+        };
+        assert_eq!(m.chunk_lengths(), vec![CHUNK_SIZE as u64, 10]);
+        m.chunk_sizes = vec![7, CHUNK_SIZE as u64 + 3];
+        assert_eq!(m.chunk_lengths(), vec![7, CHUNK_SIZE as u64 + 3]);
+    }
+
+    #[test]
+    fn test4() {
+        // 数据分段到达时增量分块，结果与对完整数据分块相同
+        let data = data(256 * 1024);
+        for chunking in [
+            ChunkingConfig::Fixed { size: 5000 },
+            ChunkingConfig::fastcdc(4096),
+        ] {
+            let expected = chunking.split(&data);
+            for piece in [1, 1000, 7777, data.len()] {
+                let mut chunker = StreamChunker::new(chunking);
+                let mut chunks = Vec::new();
+                for part in data.chunks(piece) {
+                    chunks.extend(chunker.push(part));
+                }
+                chunks.extend(chunker.finish());
+                assert_eq!(chunks, expected);
+            }
+        }
+        assert!(StreamChunker::new(ChunkingConfig::default())
+            .finish()
+            .is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::util::codec::{self, Codec, CompressionConfig, HEADER_LEN};
+
+    fn config(codec: Codec) -> CompressionConfig {
+        CompressionConfig {
+            codec,
+            ..Default::default()
+        }
+    }
+
+    // 伪随机数据，不可压缩
+    fn random(len: usize) -> Vec<u8> {
+        let mut x: u64 = 0x9E3779B97F4A7C15;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test1() {
+        let text = b"hello world, hello chunks. ".repeat(4096);
+        for codec in [Codec::None, Codec::Zstd, Codec::Lz4] {
+            let stored = config(codec).compress(&text).unwrap();
+            assert_eq!(Codec::of(&stored).unwrap(), codec);
+            assert!(codec::is_encoded(&stored));
+            assert_eq!(codec::decode(&stored).unwrap(), text);
+        }
+        let stored = CompressionConfig {
+            level: 19,
+            ..Default::default()
+        }
+        .compress(&text)
+        .unwrap();
+        assert!(stored.len() < text.len() / 10);
+
+        // 没有分块头的旧分块
+        let legacy = zstd::encode_all(&text[..], 0).unwrap();
+        assert_eq!(Codec::of(&legacy).unwrap(), Codec::Zstd);
+        assert_eq!(codec::decode(&legacy).unwrap(), text);
+        assert!(codec::decode(b"not a chunk").is_err());
+    }
+
+    #[test]
+    fn test2() {
+        // 试压缩后不可压缩的分块按原样保存
+        let data = random(1 << 20);
+        let stored = config(Codec::Zstd).compress(&data).unwrap();
+        assert_eq!(Codec::of(&stored).unwrap(), Codec::None);
+        assert_eq!(stored.len(), HEADER_LEN + data.len());
+        assert_eq!(codec::decode(&stored).unwrap(), data);
+
+        // 按 MIME 类型跳过压缩
+        let default = CompressionConfig::default();
+        assert_eq!(default.for_content_type("image/jpeg").codec, Codec::None);
+        assert_eq!(default.for_content_type("video/mp4").codec, Codec::None);
+        assert_eq!(
+            default.for_content_type("application/zip").codec,
+            Codec::None
+        );
+        assert_eq!(default.for_content_type("text/plain").codec, Codec::Zstd);
+        assert_eq!(default.for_content_type("image/svg+xml").codec, Codec::Zstd);
+        let forced = CompressionConfig {
+            detect: false,
+            ..Default::default()
+        };
+        assert_eq!(forced.for_content_type("image/jpeg").codec, Codec::Zstd);
+
+        let config: CompressionConfig = serde_json::from_str(r#"{"codec": "lz4"}"#).unwrap();
+        assert_eq!(config.codec, Codec::Lz4);
+        assert!(config.detect);
+        assert!(CompressionConfig {
+            level: 100,
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -1,6 +1,10 @@
 #[cfg(test)]
 mod test {
-    use rs_s3_local::util::cry::{aes_256_cbc_decrypt, aes_256_cbc_encrypt, do_hmac_sha256};
+    use rs_s3_local::util::cry::{
+        aes_256_cbc_decrypt, aes_256_cbc_encrypt, aes_256_gcm_decrypt, aes_256_gcm_encrypt,
+        do_hmac_sha256, gen_aes_256_key,
+    };
+    use rs_s3_local::util::keyring::{is_legacy, Keyring};
     #[test]
     fn test1() {
         let code = do_hmac_sha256(b"my secret and secure key", "input message").unwrap();
@@ -18,4 +22,63 @@ mod test {
         let de = String::from_utf8(aes_256_cbc_decrypt(&en).unwrap()).unwrap();
         assert_eq!(s, &de);
     }
+
+    #[test]
+    fn test3() {
+        let key = gen_aes_256_key();
+        let en = aes_256_gcm_encrypt(&key, b"xxxxxx", b"chunk").unwrap();
+        assert_eq!(aes_256_gcm_decrypt(&key, &en, b"chunk").unwrap(), b"xxxxxx");
+        // 附加数据或密文被篡改时解密失败
+        assert!(aes_256_gcm_decrypt(&key, &en, b"other").is_err());
+        let mut tampered = en.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(aes_256_gcm_decrypt(&key, &tampered, b"chunk").is_err());
+        assert!(aes_256_gcm_decrypt(&gen_aes_256_key(), &en, b"chunk").is_err());
+    }
+
+    #[test]
+    fn test4() {
+        let old = Keyring::parse(&hex::encode(gen_aes_256_key())).unwrap();
+        let en = old.encrypt(b"xxxxxx").unwrap();
+        assert_eq!(old.decrypt(&en).unwrap(), b"xxxxxx");
+        assert!(!old.needs_rotation(&en));
+        let mut tampered = en.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(old.decrypt(&tampered).is_err());
+        // 旧版 CBC 格式没有认证，默认拒绝，启用兼容后才能解密，并需要重新加密
+        let legacy = aes_256_cbc_encrypt(b"xxxxxx").unwrap();
+        assert!(old.decrypt(&legacy).is_err());
+        let compat = old.clone().with_legacy_cbc(true);
+        assert_eq!(compat.decrypt(&legacy).unwrap(), b"xxxxxx");
+        assert!(compat.needs_rotation(&legacy));
+        assert!(is_legacy(&legacy) && !is_legacy(&en));
+        // 新密钥环通过密钥ID找到旧密钥
+        let new = Keyring::parse(&hex::encode(gen_aes_256_key())).unwrap();
+        assert!(new.decrypt(&en).is_err());
+        let new = new.with_previous(&old);
+        assert_ne!(new.active_key_id(), old.active_key_id());
+        assert_eq!(new.decrypt(&en).unwrap(), b"xxxxxx");
+        assert!(new.needs_rotation(&en));
+        assert!(Keyring::parse("# comment\n").is_err());
+        assert!(Keyring::parse("0011").is_err());
+    }
+
+    #[test]
+    fn test5() {
+        // 任何人都能用内置密钥伪造旧版格式的元数据，未启用兼容时必须拒绝
+        let keyring = Keyring::parse(&hex::encode(gen_aes_256_key())).unwrap();
+        let forged = aes_256_cbc_encrypt(b"forged metadata").unwrap();
+        assert!(keyring.decrypt(&forged).is_err());
+        assert!(keyring
+            .clone()
+            .with_legacy_cbc(true)
+            .with_legacy_cbc(false)
+            .decrypt(&forged)
+            .is_err());
+        // 轮换后的密钥环同样拒绝
+        let rotated = Keyring::parse(&hex::encode(gen_aes_256_key()))
+            .unwrap()
+            .with_previous(&keyring);
+        assert!(rotated.decrypt(&forged).is_err());
+    }
 }
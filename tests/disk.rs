@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::disk::{relative_path, DataDir, Disks};
+    use std::path::{Path, PathBuf};
+
+    fn names(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("{:064X}", i * 7919)).collect()
+    }
+
+    fn count(disks: &Disks, names: &[String], path: &str) -> usize {
+        names
+            .iter()
+            .filter(|name| disks.preferred(name).unwrap().path == Path::new(path))
+            .count()
+    }
+
+    #[test]
+    fn test1() {
+        let disks = Disks::new(vec![
+            DataDir::new("/disk1", 1000),
+            DataDir::new("/disk2", 1000),
+            DataDir::new("/disk3", 2000),
+        ]);
+        let names = names(20000);
+        // 分块按容量比例分布
+        let disk1 = count(&disks, &names, "/disk1");
+        let disk3 = count(&disks, &names, "/disk3");
+        assert!((4000..6000).contains(&disk1), "{}", disk1);
+        assert!((9000..11000).contains(&disk3), "{}", disk3);
+
+        // 分片和所属分块在同一目录
+        let shard = format!("{}.3", names[0]);
+        assert_eq!(
+            disks.preferred(&shard).unwrap().path,
+            disks.preferred(&names[0]).unwrap().path
+        );
+        assert_eq!(
+            relative_path(&shard),
+            PathBuf::from(&names[0][..1])
+                .join(&names[0][1..3])
+                .join(format!("{}.3", &names[0][3..]))
+        );
+    }
+
+    #[test]
+    fn test2() {
+        let before = Disks::new(vec![
+            DataDir::new("/disk1", 1000),
+            DataDir::new("/disk2", 1000),
+        ]);
+        let after = Disks::new(vec![
+            DataDir::new("/disk1", 1000),
+            DataDir::new("/disk2", 1000),
+            DataDir::new("/disk3", 1000),
+        ]);
+        // 增加磁盘时只有移到新磁盘的分块需要迁移
+        for name in names(5000) {
+            let old = &before.preferred(&name).unwrap().path;
+            let new = &after.preferred(&name).unwrap().path;
+            assert!(old == new || new == &PathBuf::from("/disk3"));
+        }
+
+        // 离线的磁盘不再写入，其上的分块改写到排名次高的磁盘
+        after.dirs()[2].set_online(false);
+        for name in names(5000) {
+            let ranked = after.ranked(&name);
+            let expected = match ranked[0].path == Path::new("/disk3") {
+                true => &ranked[1].path,
+                false => &ranked[0].path,
+            };
+            assert_eq!(&after.preferred(&name).unwrap().path, expected);
+        }
+        assert_eq!(after.online().count(), 2);
+        after.dirs()[0].set_online(false);
+        after.dirs()[1].set_online(false);
+        assert!(after.preferred(&names(1)[0]).is_none());
+    }
+
+    #[test]
+    fn test3() {
+        let dir1 = tempfile::tempdir().unwrap();
+        let dir2 = tempfile::tempdir().unwrap();
+        let disks = Disks::new(vec![
+            DataDir::new(dir1.path(), 1000),
+            DataDir::new(dir2.path(), 1000),
+        ]);
+        let name = names(1).remove(0);
+        let first = disks.ranked(&name)[0];
+        let path = first.chunk_path(&name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"chunk").unwrap();
+
+        // 已满的磁盘不再写入，但其上的分块仍可找到
+        first.set_full(true);
+        assert!(first.is_online() && !first.is_writable());
+        assert_ne!(disks.preferred(&name).unwrap().path, first.path);
+        assert_eq!(disks.locate(&name).unwrap().1, path);
+        assert_eq!(disks.online().count(), 2);
+        first.set_full(false);
+        assert_eq!(disks.preferred(&name).unwrap().path, first.path);
+    }
+}
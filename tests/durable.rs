@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::util::durable::{self, Durability};
+
+    #[test]
+    fn test1() {
+        assert_eq!("none".parse::<Durability>().unwrap(), Durability::None);
+        assert_eq!("data".parse::<Durability>().unwrap(), Durability::Data);
+        assert_eq!("full".parse::<Durability>().unwrap(), Durability::Full);
+        assert!("fsync".parse::<Durability>().is_err());
+        assert_eq!(Durability::default(), Durability::Full);
+
+        // 覆盖写入后只留下目标文件
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.meta");
+        durable::write(&path, b"old", Durability::Full).unwrap();
+        durable::write(&path, b"new", Durability::Data).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let tmp = durable::tmp_path(&path);
+        assert_eq!(tmp.parent(), path.parent());
+        assert!(durable::is_tmp(&tmp.file_name().unwrap().to_string_lossy()));
+        assert!(!durable::is_tmp("a.meta"));
+    }
+
+    #[test]
+    fn test2() {
+        // 写入中断遗留的临时文件，包括旧版本的 .moving 和 .rotate
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("A").join("BC");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(sub.join("DEF"), b"chunk").unwrap();
+        std::fs::write(durable::tmp_path(&sub.join("DEF")), b"ch").unwrap();
+        std::fs::write(sub.join("GHI.moving"), b"ch").unwrap();
+        std::fs::write(dir.path().join("x.meta.rotate"), b"m").unwrap();
+        std::fs::write(dir.path().join("y.meta"), b"m").unwrap();
+
+        let mut targets = durable::clean(dir.path()).unwrap();
+        targets.sort();
+        assert_eq!(
+            targets,
+            vec![sub.join("DEF"), sub.join("GHI"), dir.path().join("x.meta")]
+        );
+        assert_eq!(std::fs::read_dir(&sub).unwrap().count(), 1);
+        assert!(dir.path().join("y.meta").exists());
+        assert!(durable::clean(dir.path()).unwrap().is_empty());
+        assert!(durable::clean(&dir.path().join("none")).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test3() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        let dest = dir.path().join("dest");
+        durable::write_async(&source, b"data", Durability::Full)
+            .await
+            .unwrap();
+        durable::copy(&source, &dest, Durability::None)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"data");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
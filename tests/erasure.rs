@@ -0,0 +1,123 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::util::erasure::{self, ErasureCode, ShardHeader, StoragePolicy, HEADER_LEN};
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test1() {
+        let code = ErasureCode {
+            data_shards: 4,
+            parity_shards: 2,
+        };
+        let data = data(100_000);
+        let shards = code.encode(&data).unwrap();
+        assert_eq!(shards.len(), 6);
+        for (index, shard) in shards.iter().enumerate() {
+            let header = ShardHeader::parse(shard).unwrap();
+            assert_eq!(header.code, code);
+            assert_eq!(header.index, index);
+            assert_eq!(header.len, data.len() as u64);
+            assert_eq!((shard.len() - HEADER_LEN) % 64, 0);
+        }
+        assert_eq!(erasure::decode(&shards).unwrap(), data);
+
+        // 任意 4 个分片都能还原，包括丢失数据分片的情况
+        for lost in [[0, 1], [2, 5], [4, 5], [0, 3]] {
+            let rest: Vec<Vec<u8>> = shards
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !lost.contains(index))
+                .map(|(_, shard)| shard.clone())
+                .collect();
+            assert!(erasure::is_recoverable(&rest));
+            assert_eq!(erasure::decode(&rest).unwrap(), data);
+        }
+
+        // 重复的分片不计数
+        let three = vec![shards[1].clone(), shards[1].clone(), shards[4].clone()];
+        assert!(!erasure::is_recoverable(&three));
+        assert!(erasure::decode(&three).is_err());
+        assert_eq!(erasure::shard_indexes(&three), vec![1, 4]);
+
+        // 空分块也能编码还原
+        let empty = code.encode(&[]).unwrap();
+        assert_eq!(erasure::decode(&empty[2..]).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test2() {
+        let code = ErasureCode {
+            data_shards: 2,
+            parity_shards: 1,
+        };
+        let shards = code.encode(b"hello").unwrap();
+        // 只有分片头时可以读取分片序号
+        let headers: Vec<Vec<u8>> = shards
+            .iter()
+            .map(|shard| shard[..HEADER_LEN].to_vec())
+            .collect();
+        assert_eq!(erasure::shard_indexes(&headers), vec![0, 1, 2]);
+        assert!(ShardHeader::parse(&shards[0][..HEADER_LEN - 1]).is_err());
+        assert!(ShardHeader::parse(b"not a shard header").is_err());
+
+        // 不同分块的分片不能混用
+        let other = code.encode(b"world!").unwrap();
+        assert!(erasure::decode(&[shards[0].clone(), other[1].clone()]).is_err());
+
+        // 单个分片校验分片头、序号和长度
+        erasure::verify_shard(&shards[1], 1).unwrap();
+        assert!(erasure::verify_shard(&shards[1], 2).is_err());
+        assert!(erasure::verify_shard(&shards[1][..shards[1].len() - 1], 1).is_err());
+        let mut longer = shards[2].clone();
+        longer.extend_from_slice(&[0; 64]);
+        assert!(erasure::verify_shard(&longer, 2).is_err());
+        assert!(erasure::verify_shard(b"not a shard", 0).is_err());
+
+        // 分片起点由名称决定，依次放在后续节点上
+        assert_eq!(erasure::placement("0A12", 4, 6), vec![2, 3, 0, 1, 2, 3]);
+        assert_eq!(erasure::placement("FF34", 8, 3), vec![7, 0, 1]);
+        assert!(erasure::placement("0A12", 0, 6).is_empty());
+    }
+
+    #[test]
+    fn test3() {
+        let policy: StoragePolicy =
+            serde_json::from_str(r#"{"erasure":{"data_shards":4,"parity_shards":2}}"#).unwrap();
+        policy.validate().unwrap();
+        assert_eq!(
+            policy.erasure(),
+            Some(ErasureCode {
+                data_shards: 4,
+                parity_shards: 2
+            })
+        );
+        let replicated: StoragePolicy = serde_json::from_str(r#""replicated""#).unwrap();
+        assert_eq!(replicated, StoragePolicy::default());
+        assert_eq!(replicated.erasure(), None);
+
+        let invalid = [
+            r#"{"erasure":{"data_shards":0,"parity_shards":2}}"#,
+            r#"{"erasure":{"data_shards":4,"parity_shards":0}}"#,
+            r#"{"erasure":{"data_shards":60,"parity_shards":8}}"#,
+        ];
+        for json in invalid {
+            let policy: StoragePolicy = serde_json::from_str(json).unwrap();
+            assert!(policy.validate().is_err());
+        }
+
+        // 写入至少保留一半的校验分片，每个分片需要单独的节点
+        let code = policy.erasure().unwrap();
+        assert_eq!(code.write_quorum(), 5);
+        let wide = ErasureCode {
+            data_shards: 4,
+            parity_shards: 4,
+        };
+        assert_eq!(wide.write_quorum(), 6);
+        assert!(policy.check_placement(6).is_ok());
+        assert!(policy.check_placement(5).is_err());
+        assert!(replicated.check_placement(1).is_ok());
+    }
+}
@@ -1,7 +1,8 @@
 #[cfg(test)]
 mod test {
     use rkyv::{Deserialize, Infallible};
-    use rs_s3_local::fs::Metadata;
+    use rs_s3_local::fs::{InlineChunk, Metadata};
+    use rs_s3_local::util::codec::CompressionConfig;
 
     #[test]
     fn test1() {
@@ -11,6 +12,12 @@ mod test {
             file_type: "xxxxx".to_string(),
             time: Default::default(),
             chunks: vec![],
+            encryption: None,
+            checksum: None,
+            parts: vec![],
+            website_redirect_location: Some("/index.html".to_string()),
+            chunk_sizes: vec![10],
+            inline: None,
         };
 
         let bytes = rkyv::to_bytes::<_, 256>(&m).unwrap();
@@ -22,4 +29,47 @@ mod test {
 
     #[test]
     fn test2() {}
+
+    #[test]
+    fn test3() {
+        let data = b"tiny object".repeat(10);
+        let compression = CompressionConfig::default();
+        let inline = InlineChunk::new(&data, None, &compression).unwrap();
+        assert_eq!(inline.open(None).unwrap(), data);
+
+        // 内联对象的 ETag 与保存为单个分块时相同
+        let mut m = Metadata {
+            name: "tiny.txt".to_string(),
+            size: data.len() as u64,
+            file_type: "text/plain".to_string(),
+            time: Default::default(),
+            chunks: vec![inline.name.clone()],
+            encryption: None,
+            checksum: None,
+            parts: vec![],
+            website_redirect_location: None,
+            chunk_sizes: vec![data.len() as u64],
+            inline: None,
+        };
+        let etag = m.etag();
+        m.chunks = vec![];
+        m.chunk_sizes = vec![];
+        m.inline = Some(inline);
+        assert_eq!(m.etag(), etag);
+        let bytes = rkyv::to_bytes::<_, 256>(&m).unwrap();
+        let archived = rkyv::check_archived_root::<Metadata>(&bytes[..]).unwrap();
+        let res: Metadata = archived.deserialize(&mut Infallible).unwrap();
+        assert_eq!(m, res);
+
+        // 加密的内联数据只能用同一数据密钥读取，内容被修改时校验失败
+        let key = [7u8; 32];
+        let mut sealed = InlineChunk::new(&data, Some(&key), &compression).unwrap();
+        assert_ne!(sealed.name, m.inline.as_ref().unwrap().name);
+        assert_eq!(sealed.open(Some(&key)).unwrap(), data);
+        assert!(sealed.open(Some(&[8u8; 32])).is_err());
+        assert!(sealed.open(None).is_err());
+        let last = sealed.data.len() - 1;
+        sealed.data[last] ^= 0xff;
+        assert!(sealed.open(Some(&key)).is_err());
+    }
 }
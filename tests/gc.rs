@@ -0,0 +1,109 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::gc::refs::ChunkRefs;
+    use std::collections::BTreeMap;
+
+    fn hashes(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test1() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let refs = ChunkRefs::open(&db).unwrap();
+
+        // 两个对象共享分块 A
+        refs.update(&[], &hashes(&["A", "B"]), 100).unwrap();
+        refs.update(&[], &hashes(&["A", "C"]), 100).unwrap();
+        assert_eq!(refs.count("A").unwrap(), 2);
+        assert_eq!(refs.count("B").unwrap(), 1);
+
+        // 覆盖第一个对象，B 不再被引用
+        refs.update(&hashes(&["A", "B"]), &hashes(&["A", "D"]), 200)
+            .unwrap();
+        assert_eq!(refs.count("A").unwrap(), 2);
+        assert_eq!(refs.count("B").unwrap(), 0);
+        assert!(!refs.is_expired("B", 200, 60).unwrap());
+        assert!(refs.is_expired("B", 260, 60).unwrap());
+        assert_eq!(refs.expired(260, 60).unwrap(), hashes(&["B"]));
+
+        // 宽限期内重新引用的分块不会被删除
+        refs.update(&[], &hashes(&["B"]), 250).unwrap();
+        assert!(refs.expired(1000, 60).unwrap().is_empty());
+
+        // 删除第二个对象，A 仍被第一个对象引用
+        refs.update(&hashes(&["A", "C"]), &[], 300).unwrap();
+        assert_eq!(refs.count("A").unwrap(), 1);
+        assert_eq!(refs.expired(1000, 60).unwrap(), hashes(&["C"]));
+        refs.forget("C").unwrap();
+        assert!(refs.expired(1000, 60).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test2() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let refs = ChunkRefs::open(&db).unwrap();
+        assert!(!refs.is_ready().unwrap());
+        refs.update(&[], &hashes(&["X"]), 0).unwrap();
+
+        // 重建时以扫描结果为准，磁盘上没有引用的分块成为孤儿
+        let counts = BTreeMap::from([("A".to_string(), 2), ("B".to_string(), 1)]);
+        refs.rebuild(&counts, &hashes(&["A", "B", "E"]), 10)
+            .unwrap();
+        assert!(refs.is_ready().unwrap());
+        assert_eq!(refs.count("X").unwrap(), 0);
+        assert_eq!(refs.count("A").unwrap(), 2);
+        assert_eq!(refs.expired(10, 0).unwrap(), hashes(&["E"]));
+
+        // 同一对象多次引用同一分块时按次数计数
+        refs.update(&[], &hashes(&["B", "B"]), 20).unwrap();
+        assert_eq!(refs.count("B").unwrap(), 3);
+        refs.update(&hashes(&["B", "B", "B"]), &[], 30).unwrap();
+        assert_eq!(refs.expired(30, 0).unwrap(), hashes(&["B", "E"]));
+    }
+
+    #[test]
+    fn test4() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let refs = ChunkRefs::open(&db).unwrap();
+        refs.rebuild(&BTreeMap::new(), &[], 0).unwrap();
+
+        // 安装快照时清除就绪标记，重建后恢复
+        refs.reset().unwrap();
+        assert!(!refs.is_ready().unwrap());
+        let counts = BTreeMap::from([("A".to_string(), 1), ("B".to_string(), 1)]);
+        refs.rebuild(&counts, &hashes(&["A"]), 10).unwrap();
+        assert!(refs.is_ready().unwrap());
+
+        // 本节点缺少的分块记入队列，拉取后移出
+        refs.add_missing("B").unwrap();
+        refs.add_missing("B").unwrap();
+        assert_eq!(refs.missing().unwrap(), hashes(&["B"]));
+        refs.remove_missing("B").unwrap();
+        assert!(refs.missing().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test3() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let refs = ChunkRefs::open(&db).unwrap();
+
+        // 推送来的分块在提交前没有引用，宽限期后才会被删除
+        refs.touch("A", 100).unwrap();
+        assert!(refs.expired(150, 60).unwrap().is_empty());
+        assert_eq!(refs.expired(160, 60).unwrap(), hashes(&["A"]));
+
+        // 再次写入同名分块时刷新时间
+        refs.touch("A", 150).unwrap();
+        assert!(refs.expired(160, 60).unwrap().is_empty());
+
+        // 已被引用的分块不受影响
+        refs.update(&[], &hashes(&["A"]), 200).unwrap();
+        refs.touch("A", 200).unwrap();
+        assert!(refs.expired(1000, 60).unwrap().is_empty());
+    }
+}
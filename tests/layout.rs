@@ -0,0 +1,70 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::layout::{metadata_path, Layout};
+    use rs_s3_local::util::durable::Durability;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test1() {
+        let layout = Layout::new("/srv/s3", 2);
+        assert_eq!(layout.data, PathBuf::from("/srv/s3/data"));
+        assert_eq!(layout.buckets, PathBuf::from("/srv/s3/data/buckets"));
+        assert_eq!(layout.tmp, PathBuf::from("/srv/s3/data/tmp"));
+        assert_eq!(layout.raft_db, PathBuf::from("/srv/s3/2-db"));
+        assert_eq!(layout.chunk_dirs, vec![PathBuf::from("/srv/s3/data/file")]);
+        assert_eq!(layout.pack, PathBuf::from("/srv/s3/data/pack"));
+        assert_eq!(
+            layout.part_path("u1", "3"),
+            PathBuf::from("/srv/s3/data/tmp/u1/3")
+        );
+        assert_eq!(
+            layout.upload_metadata("b", "dir/k", "u1"),
+            PathBuf::from("/srv/s3/data/buckets/b/dir/k.meta.u1")
+        );
+
+        // 未指定数据目录时保留默认目录
+        let layout = layout.with_chunk_dirs(vec![]).with_pack_dir("/mnt/pack");
+        assert_eq!(layout.chunk_dirs, vec![PathBuf::from("/srv/s3/data/file")]);
+        assert_eq!(layout.pack, PathBuf::from("/mnt/pack"));
+        let layout = layout.with_chunk_dirs(vec!["/mnt/a".into(), "/mnt/b".into()]);
+        assert_eq!(layout.chunk_dirs.len(), 2);
+
+        // 持久化模式默认为 full，同一进程中的节点可各自设置
+        assert_eq!(layout.durability, Durability::Full);
+        let layout = layout.with_durability(Durability::None);
+        assert_eq!(layout.durability, Durability::None);
+
+        // 同一进程中的两个节点互不共用目录
+        let other = Layout::new("/srv/s3-2", 3);
+        assert_ne!(layout.buckets, other.buckets);
+        assert_ne!(layout.raft_db, other.raft_db);
+    }
+
+    #[test]
+    fn test2() {
+        let layout = Layout::new("/srv/s3", 1);
+        let path = metadata_path("b", "dir/k.txt");
+        assert_eq!(path, "b/dir/k.txt.meta");
+        assert_eq!(
+            layout.resolve(&path),
+            PathBuf::from("/srv/s3/data/buckets/b/dir/k.txt.meta")
+        );
+        assert_eq!(
+            layout.object_of(&path),
+            Some(("b".to_string(), "dir/k.txt".to_string()))
+        );
+
+        // 旧版本的日志中是绝对路径
+        let legacy = "/srv/s3/data/buckets/b/k.meta";
+        assert_eq!(layout.resolve(legacy), PathBuf::from(legacy));
+        assert_eq!(
+            layout.object_of(legacy),
+            Some(("b".to_string(), "k".to_string()))
+        );
+        assert_eq!(layout.resolve("b"), PathBuf::from("/srv/s3/data/buckets/b"));
+
+        assert_eq!(layout.object_of("/elsewhere/b/k.meta"), None);
+        assert_eq!(layout.object_of("b/k.meta.u1"), None);
+        assert_eq!(layout.object_of("b.meta"), None);
+    }
+}
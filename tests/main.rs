@@ -1,6 +1,21 @@
 #![allow(clippy::uninlined_format_args)]
 
 mod api;
+mod cache;
+mod checksum;
+mod chunk_store;
+mod chunker;
+mod codec;
 mod crypto;
 mod date;
+mod disk;
+mod durable;
+mod erasure;
 mod fs;
+mod gc;
+mod layout;
+mod metrics;
+mod object_index;
+mod quota;
+mod select;
+mod website;
@@ -0,0 +1,22 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::metrics::{self, Counter, SCRUB_CORRUPT};
+
+    #[test]
+    fn test1() {
+        let counter = Counter::new("test_total");
+        counter.inc();
+        counter.add(41);
+        assert_eq!(counter.get(), 42);
+
+        // 快照包含全部计数器
+        SCRUB_CORRUPT.inc();
+        let snapshot = metrics::snapshot();
+        assert!(snapshot["scrub_corrupt_chunks_total"] >= 1);
+        assert!(snapshot.contains_key("scrub_chunks_total"));
+        assert!(snapshot.contains_key("scrub_repaired_chunks_total"));
+        assert!(snapshot.contains_key("read_failed_chunks_total"));
+        assert!(snapshot.contains_key("chunk_cache_hits_total"));
+        assert!(snapshot.contains_key("metadata_cache_misses_total"));
+    }
+}
@@ -0,0 +1,256 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::fs::{Metadata, METADATA_MAGIC, METADATA_VERSION};
+    use rs_s3_local::metrics::METADATA_CACHE_HITS;
+    use rs_s3_local::object_index::{ListQuery, ObjectIndex};
+    use rs_s3_local::util::keyring::Keyring;
+
+    fn query(
+        prefix: &str,
+        delimiter: Option<&str>,
+        marker: Option<&str>,
+        max_keys: usize,
+    ) -> ListQuery {
+        ListQuery {
+            prefix: prefix.to_string(),
+            delimiter: delimiter.map(|delimiter| delimiter.to_string()),
+            marker: marker.map(|marker| marker.to_string()),
+            max_keys,
+        }
+    }
+
+    fn keys(objects: &[(String, Vec<u8>)]) -> Vec<&str> {
+        objects.iter().map(|(key, _)| key.as_str()).collect()
+    }
+
+    #[test]
+    fn test1() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let index = ObjectIndex::open(&db).unwrap();
+        for key in ["a.txt", "docs/b.txt", "docs/c/d.txt", "docs/e.txt", "z.txt"] {
+            index.put("bucket", key, key.as_bytes()).unwrap();
+        }
+        // 其他桶中前缀相同的对象不会出现在结果中
+        index.put("bucket2", "a.txt", b"other").unwrap();
+        index.put("bucket-a", "a.txt", b"other").unwrap();
+
+        let listing = index.list("bucket", &query("", None, None, 1000)).unwrap();
+        assert_eq!(
+            keys(&listing.objects),
+            vec!["a.txt", "docs/b.txt", "docs/c/d.txt", "docs/e.txt", "z.txt"]
+        );
+        assert!(!listing.is_truncated);
+        assert_eq!(listing.next_marker, None);
+
+        // 分隔符归并公共前缀
+        let listing = index
+            .list("bucket", &query("", Some("/"), None, 1000))
+            .unwrap();
+        assert_eq!(keys(&listing.objects), vec!["a.txt", "z.txt"]);
+        assert_eq!(listing.common_prefixes, vec!["docs/"]);
+
+        let listing = index
+            .list("bucket", &query("docs/", Some("/"), None, 1000))
+            .unwrap();
+        assert_eq!(keys(&listing.objects), vec!["docs/b.txt", "docs/e.txt"]);
+        assert_eq!(listing.common_prefixes, vec!["docs/c/"]);
+        assert_eq!(listing.objects[0].1, b"docs/b.txt");
+
+        // 公共前缀计入 max_keys，按 next_marker 翻页
+        let listing = index
+            .list("bucket", &query("", Some("/"), None, 2))
+            .unwrap();
+        assert_eq!(keys(&listing.objects), vec!["a.txt"]);
+        assert_eq!(listing.common_prefixes, vec!["docs/"]);
+        assert!(listing.is_truncated);
+        assert_eq!(listing.next_marker.as_deref(), Some("docs/"));
+        let listing = index
+            .list("bucket", &query("", Some("/"), Some("docs/"), 2))
+            .unwrap();
+        assert_eq!(keys(&listing.objects), vec!["z.txt"]);
+        assert!(listing.common_prefixes.is_empty());
+        assert!(!listing.is_truncated);
+
+        let listing = index
+            .list("bucket", &query("docs/", None, Some("docs/b.txt"), 1))
+            .unwrap();
+        assert_eq!(keys(&listing.objects), vec!["docs/c/d.txt"]);
+        assert!(listing.is_truncated);
+        assert_eq!(listing.next_marker.as_deref(), Some("docs/c/d.txt"));
+
+        let listing = index
+            .list("bucket", &query("none/", None, None, 1000))
+            .unwrap();
+        assert!(listing.objects.is_empty());
+
+        // 删除对象和桶
+        index.remove("bucket", "z.txt").unwrap();
+        assert!(!index.contains("bucket", "z.txt").unwrap());
+        assert_eq!(
+            index.get("bucket", "a.txt").unwrap(),
+            Some(b"a.txt".to_vec())
+        );
+        assert_eq!(index.remove_bucket("bucket").unwrap(), 4);
+        assert!(index.scan("bucket").next().is_none());
+        assert_eq!(index.scan_all().count(), 2);
+    }
+
+    #[test]
+    fn test2() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path().join("db")).unwrap();
+        let index = ObjectIndex::open(&db).unwrap();
+        index.put("bucket", "exists", b"new").unwrap();
+
+        // 旧版本每个对象一个 .meta 文件，分片上传的临时元数据不导入
+        let root = dir.path().join("buckets");
+        std::fs::create_dir_all(root.join("bucket/dir")).unwrap();
+        std::fs::write(root.join("bucket/a.meta"), b"a").unwrap();
+        std::fs::write(root.join("bucket/dir/b.meta"), b"b").unwrap();
+        std::fs::write(root.join("bucket/exists.meta"), b"old").unwrap();
+        std::fs::write(root.join("bucket/c.meta.upload"), b"c").unwrap();
+
+        assert_eq!(index.migrate(&root).unwrap(), 2);
+        assert_eq!(index.get("bucket", "a").unwrap(), Some(b"a".to_vec()));
+        assert_eq!(index.get("bucket", "dir/b").unwrap(), Some(b"b".to_vec()));
+        assert_eq!(
+            index.get("bucket", "exists").unwrap(),
+            Some(b"new".to_vec())
+        );
+        assert!(!root.join("bucket/a.meta").exists());
+        assert!(root.join("bucket/c.meta.upload").exists());
+
+        // 只导入一次
+        std::fs::write(root.join("bucket/d.meta"), b"d").unwrap();
+        assert_eq!(index.migrate(&root).unwrap(), 0);
+        assert!(!index.contains("bucket", "d").unwrap());
+
+        // 快照导出后在另一个节点导入，替换原有对象
+        let other = ObjectIndex::open(&sled::open(dir.path().join("other")).unwrap()).unwrap();
+        other.put("bucket", "stale", b"stale").unwrap();
+        other.import(index.export().unwrap()).unwrap();
+        assert!(!other.contains("bucket", "stale").unwrap());
+        assert_eq!(other.scan("bucket").count(), 3);
+
+        // 重新加密只修改返回 Some 的对象
+        let count = index
+            .reencrypt(|value| Ok((value == b"a").then(|| b"A".to_vec())))
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(index.get("bucket", "a").unwrap(), Some(b"A".to_vec()));
+
+        // 不存在旧版格式元数据的标记持久保存，快照导入不影响
+        assert!(!index.legacy_free().unwrap());
+        index.mark_legacy_free().unwrap();
+        assert!(index.legacy_free().unwrap());
+        index.import(other.export().unwrap()).unwrap();
+        assert!(index.legacy_free().unwrap());
+        assert!(!other.legacy_free().unwrap());
+
+        // 未完成的轮换目标持久保存，只清除仍为同一目标的记录
+        assert_eq!(index.rotation().unwrap(), None);
+        index.start_rotation("k1").unwrap();
+        index.start_rotation("k2").unwrap();
+        index.finish_rotation("k1").unwrap();
+        assert_eq!(index.rotation().unwrap(), Some("k2".to_string()));
+        index.import(other.export().unwrap()).unwrap();
+        assert_eq!(index.rotation().unwrap(), Some("k2".to_string()));
+        index.finish_rotation("k2").unwrap();
+        assert_eq!(index.rotation().unwrap(), None);
+    }
+
+    #[test]
+    fn test3() {
+        let keyring = Keyring::parse(&hex::encode([5u8; 32])).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let index = ObjectIndex::open(&db)
+            .unwrap()
+            .with_cache(1 << 20)
+            .with_keyring(keyring);
+        let mut metadata = Metadata {
+            name: "a.txt".to_string(),
+            size: 1,
+            file_type: "text/plain".to_string(),
+            time: Default::default(),
+            chunks: vec!["A".repeat(64)],
+            encryption: None,
+            checksum: None,
+            parts: vec![],
+            website_redirect_location: None,
+            chunk_sizes: vec![1],
+            inline: None,
+        };
+        index.save("bucket", "a.txt", &metadata).unwrap();
+        assert_eq!(
+            index.load("bucket", "a.txt").unwrap().as_ref(),
+            Some(&metadata)
+        );
+        let hits = METADATA_CACHE_HITS.get();
+        assert_eq!(
+            index.load("bucket", "a.txt").unwrap().as_ref(),
+            Some(&metadata)
+        );
+        assert!(METADATA_CACHE_HITS.get() > hits);
+
+        // 覆盖和删除对象后不再返回缓存的元数据
+        metadata.size = 2;
+        index.save("bucket", "a.txt", &metadata).unwrap();
+        assert_eq!(index.load("bucket", "a.txt").unwrap().unwrap().size, 2);
+        index.remove("bucket", "a.txt").unwrap();
+        assert!(index.load("bucket", "a.txt").unwrap().is_none());
+        index.save("bucket", "b.txt", &metadata).unwrap();
+        assert!(index.load("bucket", "b.txt").unwrap().is_some());
+        index.remove_bucket("bucket").unwrap();
+        assert!(index.load("bucket", "b.txt").unwrap().is_none());
+    }
+
+    #[test]
+    fn test4() {
+        let keyring = Keyring::parse(&hex::encode([5u8; 32])).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let index = ObjectIndex::open(&db)
+            .unwrap()
+            .with_keyring(keyring.clone());
+        let metadata = Metadata {
+            name: "a.txt".to_string(),
+            size: 1,
+            file_type: "text/plain".to_string(),
+            time: Default::default(),
+            chunks: vec!["A".repeat(64)],
+            encryption: None,
+            checksum: None,
+            parts: vec![],
+            website_redirect_location: None,
+            chunk_sizes: vec![1],
+            inline: None,
+        };
+        // 元数据明文以魔数和结构版本开头
+        index.save("bucket", "a.txt", &metadata).unwrap();
+        let plain = keyring
+            .decrypt(&index.get("bucket", "a.txt").unwrap().unwrap())
+            .unwrap();
+        assert!(plain.starts_with(METADATA_MAGIC));
+        assert_eq!(plain[METADATA_MAGIC.len()], METADATA_VERSION);
+
+        // 密钥由各自的索引持有，使用其他密钥的索引无法解密
+        let other = Keyring::parse(&hex::encode([6u8; 32])).unwrap();
+        let other = ObjectIndex::open(&db).unwrap().with_keyring(other);
+        assert!(other.load("bucket", "a.txt").is_err());
+
+        // 未知的结构版本不按其他版本猜测解析
+        let mut unknown = plain.clone();
+        unknown[METADATA_MAGIC.len()] = METADATA_VERSION + 1;
+        let value = keyring.encrypt(&unknown).unwrap();
+        index.put("bucket", "b.txt", &value).unwrap();
+        assert!(index.load("bucket", "b.txt").is_err());
+
+        // 没有版本头部的旧数据仍可读取
+        let archived = rkyv::to_bytes::<_, 256>(&metadata).unwrap();
+        let value = keyring.encrypt(&archived).unwrap();
+        index.put("bucket", "c.txt", &value).unwrap();
+        assert_eq!(index.load("bucket", "c.txt").unwrap(), Some(metadata));
+    }
+}
@@ -0,0 +1,139 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::fs::Metadata;
+    use rs_s3_local::object_index::ObjectIndex;
+    use rs_s3_local::quota::{self, BucketQuota, BucketUsage, UsageChange};
+    use rs_s3_local::util::keyring::Keyring;
+    use std::collections::BTreeMap;
+
+    fn metadata(size: u64) -> Metadata {
+        Metadata {
+            name: "a.txt".to_string(),
+            size,
+            file_type: "text/plain".to_string(),
+            time: Default::default(),
+            chunks: vec![],
+            encryption: None,
+            checksum: None,
+            parts: vec![],
+            website_redirect_location: None,
+            chunk_sizes: vec![],
+            inline: None,
+        }
+    }
+
+    #[test]
+    fn test1() {
+        let usage = BucketUsage {
+            bytes: 100,
+            objects: 2,
+        };
+        // 新对象计入大小和数量
+        assert_eq!(
+            usage.after(UsageChange::put(None, 50)),
+            BucketUsage {
+                bytes: 150,
+                objects: 3
+            }
+        );
+        // 覆盖只计入大小的差值
+        assert_eq!(
+            usage.after(UsageChange::put(Some(40), 10)),
+            BucketUsage {
+                bytes: 70,
+                objects: 2
+            }
+        );
+        assert_eq!(
+            usage.after(UsageChange::delete(Some(40))),
+            BucketUsage {
+                bytes: 60,
+                objects: 1
+            }
+        );
+        // 删除不存在的对象不影响用量
+        assert_eq!(usage.after(UsageChange::delete(None)), usage);
+        // 分片只计入大小
+        assert_eq!(
+            usage.after(UsageChange::part(30)),
+            BucketUsage {
+                bytes: 130,
+                objects: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test2() {
+        let usage = BucketUsage {
+            bytes: 100,
+            objects: 2,
+        };
+        let quota = BucketQuota {
+            max_bytes: Some(150),
+            max_objects: Some(3),
+        };
+        assert!(quota.allows(&usage, UsageChange::put(None, 50)));
+        assert!(!quota.allows(&usage, UsageChange::put(None, 51)));
+        assert!(quota.allows(&usage, UsageChange::put(Some(20), 70)));
+        let full = usage.after(UsageChange::put(None, 1));
+        assert!(!quota.allows(&full, UsageChange::put(None, 0)));
+        assert!(quota.allows(&full, UsageChange::put(Some(1), 1)));
+        assert!(quota.allows(&full, UsageChange::part(49)));
+        assert!(!quota.allows(&full, UsageChange::part(50)));
+        // 配额调低到当前用量以下后，只允许不增加用量的写入
+        let quota = BucketQuota {
+            max_bytes: Some(10),
+            max_objects: None,
+        };
+        assert!(quota.allows(&usage, UsageChange::delete(Some(30))));
+        assert!(quota.allows(&usage, UsageChange::put(Some(30), 20)));
+        assert!(!quota.allows(&usage, UsageChange::put(Some(30), 31)));
+        assert!(BucketQuota::default().allows(&usage, UsageChange::put(None, u64::MAX)));
+        let quota: BucketQuota = serde_json::from_str(r#"{"max_objects":5}"#).unwrap();
+        assert_eq!(quota.max_bytes, None);
+        assert_eq!(serde_json::to_string(&quota).unwrap(), r#"{"max_objects":5}"#);
+    }
+
+    #[test]
+    fn test3() {
+        let keyring = Keyring::parse(&hex::encode([5u8; 32])).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let index = ObjectIndex::open(&db).unwrap().with_keyring(keyring);
+        let mut kvs = BTreeMap::new();
+
+        // 启用配额前写入的对象没有计数
+        index.save("old", "a.txt", &metadata(100)).unwrap();
+        index.save("old", "b.txt", &metadata(20)).unwrap();
+        assert_eq!(quota::usage(&kvs, "old"), None);
+
+        // 之后的写入不从零开始计数
+        index.save("old", "c.txt", &metadata(3)).unwrap();
+        quota::update_usage(&mut kvs, "old", UsageChange::put(None, 3));
+        assert_eq!(quota::usage(&kvs, "old"), None);
+
+        // 首次设置配额时统计全部对象
+        let usage = quota::count_usage(&index, "old").unwrap();
+        quota::set_usage(&mut kvs, "old", usage);
+        let total = BucketUsage {
+            bytes: 123,
+            objects: 3,
+        };
+        assert_eq!(quota::usage(&kvs, "old"), Some(total));
+
+        // 有计数的桶按写入更新，其他桶不受影响
+        quota::update_usage(&mut kvs, "old", UsageChange::delete(Some(3)));
+        assert_eq!(
+            quota::usage(&kvs, "old"),
+            Some(BucketUsage {
+                bytes: 120,
+                objects: 2
+            })
+        );
+        assert_eq!(
+            quota::count_usage(&index, "new").unwrap(),
+            BucketUsage::default()
+        );
+    }
+}
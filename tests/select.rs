@@ -0,0 +1,169 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::model::SelectObjectContentRequest;
+    use rs_s3_local::select::sql::Query;
+    use rs_s3_local::select::{SelectEngine, SelectRequest};
+    use rs_s3_local::util::event_stream::{decode_message, encode_message};
+    use std::io::Write;
+
+    fn select_request(expression: &str, input: &str, output: &str) -> SelectRequest {
+        let xml = format!(
+            "<SelectObjectContentRequest><Expression>{}</Expression><ExpressionType>SQL</ExpressionType>\
+             <InputSerialization>{}</InputSerialization><OutputSerialization>{}</OutputSerialization>\
+             </SelectObjectContentRequest>",
+            expression, input, output
+        );
+        let request: SelectObjectContentRequest = quick_xml::de::from_str(&xml).unwrap();
+        SelectRequest::from_model(&request).unwrap()
+    }
+
+    // 按固定大小分块输入，模拟逐个读取数据块
+    fn run(request: SelectRequest, data: &[u8], chunk_size: usize) -> String {
+        let mut engine = SelectEngine::new(request).unwrap();
+        let mut out = Vec::new();
+        for chunk in data.chunks(chunk_size) {
+            out.extend(engine.push(chunk).unwrap());
+        }
+        out.extend(engine.finish().unwrap());
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test1() {
+        let csv = "name,city,age\r\n\
+                   \"Smith, John\",Berlin,42\r\n\
+                   # comment line\r\n\
+                   Jane,\"Paris\",31\r\n\
+                   \"Bob \"\"the\"\" Builder\",London,57\r\n\
+                   Alice,Paris,19";
+        let input = "<CSV><FileHeaderInfo>USE</FileHeaderInfo><Comments>#</Comments></CSV>";
+        let request = select_request(
+            "SELECT s.name, age FROM S3Object s WHERE CAST(s.age AS INT) &gt; 30 AND city NOT LIKE 'Par%'",
+            input,
+            "<CSV/>",
+        );
+        assert_eq!(
+            run(request.clone(), csv.as_bytes(), 5),
+            "\"Smith, John\",42\n\"Bob \"\"the\"\" Builder\",57\n"
+        );
+        assert_eq!(
+            run(request, csv.as_bytes(), csv.len()),
+            "\"Smith, John\",42\n\"Bob \"\"the\"\" Builder\",57\n"
+        );
+
+        let request = select_request(
+            "SELECT _1, UPPER(_2) AS city FROM S3Object WHERE _3 BETWEEN 20 AND 50 LIMIT 1",
+            input,
+            "<JSON/>",
+        );
+        assert_eq!(
+            run(request, csv.as_bytes(), 3),
+            "{\"_1\":\"Smith, John\",\"city\":\"BERLIN\"}\n"
+        );
+    }
+
+    #[test]
+    fn test2() {
+        let mut lines = String::new();
+        for i in 1..=100 {
+            lines.push_str(&format!(
+                "{{\"id\":{},\"user\":{{\"name\":\"u{}\"}},\"score\":{}}}\n",
+                i,
+                i,
+                if i % 10 == 0 {
+                    "null".to_string()
+                } else {
+                    (i * 2).to_string()
+                }
+            ));
+        }
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(lines.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let input = "<CompressionType>GZIP</CompressionType><JSON><Type>LINES</Type></JSON>";
+
+        let request = select_request(
+            "SELECT COUNT(*), COUNT(s.score) AS scored, SUM(s.score), MIN(s.score), MAX(s.score), AVG(s.id) \
+             FROM S3Object s WHERE s.id &lt;= 20",
+            input,
+            "<JSON/>",
+        );
+        assert_eq!(
+            run(request, &compressed, 64),
+            "{\"_1\":20,\"_3\":360,\"_4\":2,\"_5\":38,\"_6\":10.5,\"scored\":18}\n"
+        );
+
+        let request = select_request(
+            "SELECT s.user.name FROM S3Object s WHERE s.id IN (3, 97) OR s.score IS NULL AND s.id &gt; 90",
+            input,
+            "<CSV/>",
+        );
+        assert_eq!(run(request, &compressed, 100), "u3\nu97\nu100\n");
+
+        let zstd = zstd::encode_all(lines.as_bytes(), 0).unwrap();
+        let input = "<CompressionType>ZSTD</CompressionType><JSON><Type>DOCUMENT</Type></JSON>";
+        let request = select_request("SELECT * FROM S3Object LIMIT 2", input, "<JSON/>");
+        let mut engine = SelectEngine::new(request).unwrap();
+        let out = engine.push(&zstd).unwrap();
+        assert!(engine.is_done());
+        assert_eq!(
+            out.split(|b| *b == b'\n').filter(|l| !l.is_empty()).count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test3() {
+        let message = encode_message(
+            &[(":event-type", "Records"), (":message-type", "event")],
+            b"a,b\n",
+        );
+        let (headers, payload, len) = decode_message(&message).unwrap();
+        assert_eq!(len, message.len());
+        assert_eq!(payload, b"a,b\n");
+        assert_eq!(
+            headers,
+            vec![
+                (":event-type".to_string(), "Records".to_string()),
+                (":message-type".to_string(), "event".to_string())
+            ]
+        );
+        let mut corrupted = message.clone();
+        corrupted[len - 6] ^= 1;
+        assert!(decode_message(&corrupted).is_err());
+    }
+
+    #[test]
+    fn test4() {
+        assert!(Query::parse("SELECT * FROM S3Object s WHERE s.a = 'x''y' LIMIT 5").is_ok());
+        let err = Query::parse("SELECT COUNT(*) FROM S3Object WHERE SUM(_1) > 1").unwrap_err();
+        assert_eq!(err.code, "UnsupportedSyntax");
+        let err = Query::parse("SELECT _1, COUNT(*) FROM S3Object").unwrap_err();
+        assert_eq!(err.code, "UnsupportedSyntax");
+        assert!(Query::parse("SELECT _1 FROM S3Object WHERE").is_err());
+        assert!(Query::parse("SELECT _1 FROM table").is_err());
+        assert!(Query::parse("SELECT 'abc FROM S3Object").is_err());
+    }
+
+    #[test]
+    fn test5() {
+        // 数据量很小但解压后超过单段输出上限的 gzip / zstd 对象
+        let zeros = vec![0u8; 1 << 20];
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        let mut zstd = zstd::stream::write::Encoder::new(Vec::new(), 3).unwrap();
+        for _ in 0..129 {
+            gzip.write_all(&zeros).unwrap();
+            zstd.write_all(&zeros).unwrap();
+        }
+        let gzip = gzip.finish().unwrap();
+        let zstd = zstd.finish().unwrap();
+        assert!(gzip.len() < 1 << 20 && zstd.len() < 1 << 20);
+        for (compression, data) in [("GZIP", gzip), ("ZSTD", zstd)] {
+            let input = format!("<CompressionType>{}</CompressionType><CSV/>", compression);
+            let request = select_request("SELECT * FROM S3Object", &input, "<CSV/>");
+            let mut engine = SelectEngine::new(request).unwrap();
+            let err = engine.push(&data).unwrap_err();
+            assert_eq!(err.code, "InvalidCompressionFormat");
+        }
+    }
+}
@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod test {
+    use rs_s3_local::model::WebsiteConfiguration;
+    use rs_s3_local::website::routing::{
+        bucket_from_host, error_page, index_key, match_rule, object_key, redirect_all,
+        rule_redirect, validate_config, validate_redirect_location,
+    };
+
+    const CONFIG: &str = r#"<WebsiteConfiguration>
+        <IndexDocument><Suffix>index.html</Suffix></IndexDocument>
+        <ErrorDocument><Key>404.html</Key></ErrorDocument>
+        <RoutingRules>
+            <RoutingRule>
+                <Condition><KeyPrefixEquals>docs/</KeyPrefixEquals></Condition>
+                <Redirect><ReplaceKeyPrefixWith>documents/</ReplaceKeyPrefixWith></Redirect>
+            </RoutingRule>
+            <RoutingRule>
+                <Condition><HttpErrorCodeReturnedEquals>404</HttpErrorCodeReturnedEquals></Condition>
+                <Redirect>
+                    <HostName>example.com</HostName>
+                    <Protocol>https</Protocol>
+                    <ReplaceKeyWith>missing.html</ReplaceKeyWith>
+                    <HttpRedirectCode>302</HttpRedirectCode>
+                </Redirect>
+            </RoutingRule>
+        </RoutingRules>
+    </WebsiteConfiguration>"#;
+
+    #[test]
+    fn test1() {
+        let config: WebsiteConfiguration = quick_xml::de::from_str(CONFIG).unwrap();
+        assert!(validate_config(&config).is_ok());
+        assert_eq!(config.index_document.as_ref().unwrap().suffix, "index.html");
+        let rules = &config.routing_rules.as_ref().unwrap().rules;
+        assert_eq!(rules.len(), 2);
+        // 配置以 JSON 保存在状态机中
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            serde_json::from_str::<WebsiteConfiguration>(&json).unwrap(),
+            config
+        );
+
+        // 读取对象前只匹配不带错误码条件的规则
+        let rule = match_rule(&config, "docs/a/b.html", None).unwrap();
+        assert_eq!(
+            rule_redirect(rule, "docs/a/b.html", "site.local"),
+            (301, "/documents/a/b.html".to_string())
+        );
+        assert!(match_rule(&config, "blog/post.html", None).is_none());
+        let rule = match_rule(&config, "blog/post.html", Some(404)).unwrap();
+        assert_eq!(
+            rule_redirect(rule, "blog/post.html", "site.local"),
+            (302, "https://example.com/missing.html".to_string())
+        );
+        assert!(match_rule(&config, "blog/post.html", Some(403)).is_none());
+
+        assert_eq!(index_key("", "index.html"), "index.html");
+        assert_eq!(index_key("guide/", "index.html"), "guide/index.html");
+        assert_eq!(index_key("guide", "index.html"), "guide");
+    }
+
+    #[test]
+    fn test2() {
+        let invalid = [
+            // 缺少索引文档
+            "<WebsiteConfiguration><ErrorDocument><Key>e.html</Key></ErrorDocument></WebsiteConfiguration>",
+            "<WebsiteConfiguration><IndexDocument><Suffix>a/index.html</Suffix></IndexDocument></WebsiteConfiguration>",
+            "<WebsiteConfiguration><RedirectAllRequestsTo><HostName>a.com</HostName></RedirectAllRequestsTo><IndexDocument><Suffix>index.html</Suffix></IndexDocument></WebsiteConfiguration>",
+            "<WebsiteConfiguration><IndexDocument><Suffix>index.html</Suffix></IndexDocument><RoutingRules><RoutingRule><Redirect><HttpRedirectCode>200</HttpRedirectCode></Redirect></RoutingRule></RoutingRules></WebsiteConfiguration>",
+            "<WebsiteConfiguration><IndexDocument><Suffix>index.html</Suffix></IndexDocument><RoutingRules><RoutingRule><Redirect><ReplaceKeyWith>a</ReplaceKeyWith><ReplaceKeyPrefixWith>b</ReplaceKeyPrefixWith></Redirect></RoutingRule></RoutingRules></WebsiteConfiguration>",
+        ];
+        for xml in invalid {
+            let config: WebsiteConfiguration = quick_xml::de::from_str(xml).unwrap();
+            assert!(validate_config(&config).is_err(), "{}", xml);
+        }
+        let config: WebsiteConfiguration = quick_xml::de::from_str(
+            "<WebsiteConfiguration><RedirectAllRequestsTo><HostName>new.example.com</HostName><Protocol>https</Protocol></RedirectAllRequestsTo></WebsiteConfiguration>",
+        )
+        .unwrap();
+        assert!(validate_config(&config).is_ok());
+        assert_eq!(
+            redirect_all(config.redirect_all_requests_to.as_ref().unwrap(), "/a/b?c=d"),
+            "https://new.example.com/a/b?c=d"
+        );
+
+        assert!(validate_redirect_location("/other.html").is_ok());
+        assert!(validate_redirect_location("https://example.com/").is_ok());
+        assert!(validate_redirect_location("ftp://example.com/").is_err());
+        assert!(validate_redirect_location("other.html").is_err());
+    }
+
+    #[test]
+    fn test3() {
+        assert_eq!(
+            bucket_from_host("docs.website.local:8080", Some("website.local")),
+            Some("docs".to_string())
+        );
+        assert_eq!(
+            bucket_from_host("Docs.Example.com", Some("website.local")),
+            Some("docs.example.com".to_string())
+        );
+        assert_eq!(bucket_from_host("", None), None);
+        assert_eq!(object_key("/a%20b/c.html"), Some("a b/c.html".to_string()));
+        assert_eq!(object_key("/"), Some(String::new()));
+        assert_eq!(object_key("/a/../../secret"), None);
+        let page = error_page(404, "NoSuchKey", "<script>");
+        assert!(page.contains("404 NoSuchKey"));
+        assert!(page.contains("&lt;script&gt;"));
+    }
+}